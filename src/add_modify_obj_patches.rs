@@ -5,16 +5,18 @@ use resource_info_table::resource_info;
 use structs::{res_id, ResId, SclyPropertyData};
 
 use crate::{
+    clone_objects::ClonedObject,
     door_meta::DoorType,
     mlvl_wrapper,
     patch_config::{
         ActorKeyframeConfig, ActorRotateConfig, BallTriggerConfig, BlockConfig, BombSlotConfig,
         CameraConfig, CameraFilterKeyframeConfig, CameraHintTriggerConfig, CameraWaypointConfig,
-        ControllerActionConfig, CounterConfig, DamageType, FilterShape, FilterType, FogConfig,
-        GenericTexture, HudmemoConfig, InitialSplinePosition, LockOnPoint, NewCameraHintConfig,
-        PathCameraConfig, PlatformConfig, PlatformType, PlayerActorConfig, PlayerHintConfig,
-        RelayConfig, SpawnPointConfig, SpecialFunctionConfig, StreamedAudioConfig, SwitchConfig,
-        TimerConfig, TriggerConfig, WaterConfig, WaypointConfig, WorldLightFaderConfig,
+        CloneObjectConfig, ControllerActionConfig, CounterConfig, DamageType, FilterShape,
+        FilterType, FogConfig, GenericTexture, HudmemoConfig, InitialSplinePosition, LayerId,
+        LockOnPoint, NewCameraHintConfig, PathCameraConfig, PlatformConfig, PlatformType,
+        PlayerActorConfig, PlayerHintConfig, RelayConfig, SpawnPointConfig, SpecialFunctionConfig,
+        StreamedAudioConfig, SwitchConfig, TimerConfig, TriggerConfig, WaterConfig, WaypointConfig,
+        WorldLightFaderConfig,
     },
     patcher::PatcherState,
     patches::{string_to_cstr, WaterType},
@@ -25,7 +27,7 @@ macro_rules! add_edit_obj_helper {
     ($area:expr, $id:expr, $requested_layer_id:expr, $object_type:ident, $new_property_data:ident, $update_property_data:ident) => {
         let area = $area;
         let id = $id;
-        let requested_layer_id = $requested_layer_id;
+        let requested_layer_id = area.resolve_layer($requested_layer_id.as_ref())?;
         let mrea_id = area.mlvl_area.mrea.to_u32().clone();
 
         // add more layers as needed
@@ -257,7 +259,7 @@ pub fn patch_add_liquid<'r>(
 
     {
         let id = config.id;
-        let requested_layer_id = config.layer;
+        let requested_layer_id = area.resolve_layer(config.layer.as_ref())?;
         let mrea_id = area.mlvl_area.mrea.to_u32();
 
         // add more layers as needed
@@ -675,6 +677,22 @@ pub fn patch_add_special_fn(
     };
     let item_id = pickup_type as u32;
 
+    // Names of layers in other rooms are resolved before patching begins
+    let layer_change_layer_id = match config.layer_change_layer_id.as_ref() {
+        Some(LayerId::Name(name))
+            if config
+                .layer_change_room_id
+                .is_some_and(|room_id| room_id != area.mlvl_area.internal_id) =>
+        {
+            return Err(format!(
+                "Failed to find layer '{}' in room 0x{:X}",
+                name,
+                config.layer_change_room_id.unwrap()
+            ));
+        }
+        layer => area.resolve_layer(layer)?,
+    };
+
     macro_rules! new {
         () => {
             structs::SpecialFunction {
@@ -687,7 +705,7 @@ pub fn patch_add_special_fn(
                 value_param2: config.value_param2.unwrap_or_default(),
                 value_param3: config.value_param3.unwrap_or_default(),
                 layer_change_room_id: config.layer_change_room_id.unwrap_or(0xFFFFFFFF),
-                layer_change_layer_id: config.layer_change_layer_id.unwrap_or(0xFFFFFFFF),
+                layer_change_layer_id: layer_change_layer_id.unwrap_or(0xFFFFFFFF),
                 item_id,
                 active: config.active.unwrap_or(true) as u8,
                 value_param4: config.value_param4.unwrap_or_default(),
@@ -728,7 +746,7 @@ pub fn patch_add_special_fn(
             if let Some(layer_change_room_id) = config.layer_change_room_id {
                 property_data.layer_change_room_id = layer_change_room_id
             }
-            if let Some(layer_change_layer_id) = config.layer_change_layer_id {
+            if let Some(layer_change_layer_id) = layer_change_layer_id {
                 property_data.layer_change_layer_id = layer_change_layer_id
             }
             if let Some(item_id) = config.item_id {
@@ -1247,13 +1265,33 @@ pub fn relative_offset(position: [f32; 3], rotation: [f32; 3], offset: [f32; 3])
     adjusted_position.into()
 }
 
+/// Row-major 3x4 transform placing something at `position` with `rotation` (in degrees)
+pub fn transform_matrix(position: [f32; 3], rotation: [f32; 3]) -> [f32; 12] {
+    let rotations = [Yaw(rotation[2]), Roll(rotation[1]), Pitch(rotation[0])];
+    let r = rotation_matrix(rotations);
+    [
+        r[(0, 0)],
+        r[(0, 1)],
+        r[(0, 2)],
+        position[0],
+        r[(1, 0)],
+        r[(1, 1)],
+        r[(1, 2)],
+        position[1],
+        r[(2, 0)],
+        r[(2, 1)],
+        r[(2, 2)],
+        position[2],
+    ]
+}
+
 pub fn patch_add_bomb_slot<'r>(
     _ps: &mut PatcherState,
     area: &mut mlvl_wrapper::MlvlArea<'r, '_, '_, '_>,
     game_resources: &HashMap<(u32, FourCC), structs::Resource<'r>>,
     config: BombSlotConfig,
 ) -> Result<(), String> {
-    let layer = match config.layer {
+    let layer = match area.resolve_layer(config.layer.as_ref())? {
         Some(layer) => {
             while area.layer_flags.layer_count <= layer {
                 area.add_layer(b"New Layer\0".as_cstr());
@@ -2661,7 +2699,7 @@ pub fn patch_add_platform<'r>(
     let ids = match platform_type {
         PlatformType::BombBox => {
             let mut ids = vec![];
-            let layer = config.layer.unwrap_or(0) as usize;
+            let layer = area.resolve_layer(config.layer.as_ref())?.unwrap_or(0) as usize;
             for _ in 0..8 {
                 ids.push(area.new_object_id_from_layer_id(layer));
             }
@@ -2672,7 +2710,9 @@ pub fn patch_add_platform<'r>(
 
     let undamaged_block_id = match config.id {
         Some(id) => id,
-        None => area.new_object_id_from_layer_id(config.layer.unwrap_or(0) as usize),
+        None => area.new_object_id_from_layer_id(
+            area.resolve_layer(config.layer.as_ref())?.unwrap_or(0) as usize,
+        ),
     };
 
     let vulnerability = match platform_type {
@@ -2908,7 +2948,7 @@ pub fn patch_add_platform<'r>(
     }
 
     if platform_type == PlatformType::BombBox {
-        let layer_id = config.layer.unwrap_or(0) as usize;
+        let layer_id = area.resolve_layer(config.layer.as_ref())?.unwrap_or(0) as usize;
         while area.layer_flags.layer_count <= layer_id as u32 {
             area.add_layer(b"New Layer\0".as_cstr());
         }
@@ -3208,7 +3248,7 @@ pub fn patch_add_platform<'r>(
     }
 
     let id = config.id;
-    let requested_layer_id = config.layer;
+    let requested_layer_id = area.resolve_layer(config.layer.as_ref())?;
     let mrea_id = area.mlvl_area.mrea.to_u32();
 
     // add more layers as needed
@@ -3337,6 +3377,7 @@ pub fn patch_add_block<'r>(
     });
     area.add_dependencies(game_resources, 0, deps_iter);
 
+    let layer = area.resolve_layer(config.layer.as_ref())?;
     add_block(
         area,
         config.id,
@@ -3344,7 +3385,7 @@ pub fn patch_add_block<'r>(
         config.scale.unwrap_or([1.0, 1.0, 1.0]),
         texture,
         1,
-        config.layer,
+        layer,
         config.active.unwrap_or(true),
         old_scale,
         config.thermal_hot.unwrap_or(false),
@@ -3485,7 +3526,7 @@ pub fn patch_lock_on_point<'r>(
     let is_grapple = config.is_grapple.unwrap_or(false);
     let no_lock = config.no_lock.unwrap_or(false);
     let position = config.position;
-    let layer = config.layer.unwrap_or(0) as usize;
+    let layer = area.resolve_layer(config.layer.as_ref())?.unwrap_or(0) as usize;
 
     if is_grapple {
         let deps = [
@@ -3794,11 +3835,11 @@ pub fn patch_add_camera_hint(
     camera_pos: [f32; 3],
     camera_rot: [f32; 3],
     behavior: u32,
-    layer: u32,
+    layer: Option<&LayerId>,
     camera_id: Option<u32>,
     trigger_id: Option<u32>,
 ) -> Result<(), String> {
-    let layer = layer as usize;
+    let layer = area.resolve_layer(layer)?.unwrap_or(0) as usize;
     let camear_hint_id = camera_id.unwrap_or(area.new_object_id_from_layer_id(layer));
     let camera_hint_trigger_id = trigger_id.unwrap_or(area.new_object_id_from_layer_id(layer));
 
@@ -4095,3 +4136,77 @@ pub fn patch_add_escape_sequence(
 
     Ok(())
 }
+
+pub fn patch_clone_object<'r>(
+    _ps: &mut PatcherState,
+    area: &mut mlvl_wrapper::MlvlArea<'r, '_, '_, '_>,
+    game_resources: &HashMap<(u32, FourCC), structs::Resource<'r>>,
+    source: &ClonedObject<'r>,
+    config: CloneObjectConfig,
+) -> Result<(), String> {
+    let layer = area.resolve_layer(config.layer.as_ref())?.unwrap_or(0);
+    while area.layer_flags.layer_count <= layer {
+        area.add_layer(b"New Layer\0".as_cstr());
+    }
+
+    let id = match config.id {
+        Some(id) => {
+            let exists = area.mrea().scly_section().layers.iter().any(|layer| {
+                layer
+                    .objects
+                    .iter()
+                    .any(|obj| obj.instance_id & 0x00FFFFFF == id & 0x00FFFFFF)
+            });
+            if exists {
+                return Err(format!(
+                    "Cannot clone 0x{:X} into room 0x{:X} as 0x{:X}, the id is already in use",
+                    config.instance_id,
+                    area.mlvl_area.mrea.to_u32(),
+                    id
+                ));
+            }
+            (id & 0x03FFFFFF) | (layer << 26)
+        }
+        None => area.new_object_id_from_layer_id(layer as usize),
+    };
+
+    let mut property_data = source.object.property_data.clone();
+
+    macro_rules! transform {
+        ($value:expr, $supports:ident, $set:ident, $property:expr) => {
+            if let Some(value) = $value {
+                if !property_data.$supports() {
+                    return Err(format!(
+                        "Cloned object 0x{:X} does not have a {}",
+                        config.instance_id, $property
+                    ));
+                }
+                property_data.$set(value);
+            }
+        };
+    }
+
+    transform!(config.active, supports_active, set_active, "active state");
+    transform!(config.position, supports_position, set_position, "position");
+    transform!(config.rotation, supports_rotation, set_rotation, "rotation");
+    transform!(config.scale, supports_scale, set_scale, "scale");
+
+    // Already everything the object needs, see `collect_cloned_objects`
+    area.add_dependencies(
+        game_resources,
+        layer as usize,
+        source.dependencies.iter().cloned(),
+    );
+
+    // Connections are dropped as they refer to objects in the source room
+    area.mrea().scly_section_mut().layers.as_mut_vec()[layer as usize]
+        .objects
+        .as_mut_vec()
+        .push(structs::SclyObject {
+            instance_id: id,
+            property_data,
+            connections: vec![].into(),
+        });
+
+    Ok(())
+}
@@ -0,0 +1,955 @@
+use std::{
+    borrow::Cow,
+    collections::{HashMap, HashSet},
+    ffi::CString,
+};
+
+use reader_writer::{CStr, FourCC, Reader, Writable};
+use structs::{
+    Ancs, AnimTime, Dependency, EffectEvent, EventBase, Evnt, MetaAnimation, Resource,
+    ResourceKind, SoundEvent, UserEvent,
+};
+
+use crate::{
+    mlvl_wrapper::{self, RoomsListing},
+    patch_config::{AncsEditConfig, AnimEventConfig, AnimEventKind, AnimEventsConfig, PatchConfig},
+    patcher::PrimePatcher,
+    pickup_meta, GcDiscLookupExtensions, ResourceData,
+};
+
+// POI node types of the events
+const EFFECT_EVENT_TYPE: u16 = 5;
+const USER_EVENT_TYPE: u16 = 6;
+const SOUND_EVENT_TYPE: u16 = 8;
+
+// CCharAnimTime types
+const ANIM_TIME_NON_ZERO: u32 = 0;
+const ANIM_TIME_ZERO_STEADY: u32 = 2;
+
+const PARTICLE_TYPES: [&[u8; 4]; 3] = [b"PART", b"ELSC", b"SWHC"];
+
+// Every pak a character set can be in
+fn character_paks() -> Vec<&'static str> {
+    pickup_meta::ROOM_INFO
+        .iter()
+        .map(|(pak_name, _)| *pak_name)
+        .chain(["NoARAM.pak", "MiscData.pak", "TestAnim.Pak", "SamusGun.pak"])
+        .collect()
+}
+
+fn parse_ancs_id(key: &str) -> Result<u32, String> {
+    key.parse::<u32>()
+        .map_err(|_| format!("{} is not a valid ANCS identifier", key))
+}
+
+// Particles the edits make a character use, which may be new to the rooms it appears in
+fn new_particle_ids(edit: &AncsEditConfig) -> impl Iterator<Item = u32> + '_ {
+    let added = edit.animations.values().flat_map(|animation| {
+        animation
+            .add
+            .iter()
+            .flatten()
+            .filter(|event| event.kind == AnimEventKind::Effect)
+            .filter_map(|event| event.effect_id)
+    });
+    edit.particle_swaps.values().copied().chain(added)
+}
+
+fn particle_dependency(
+    resources: &HashMap<(u32, FourCC), Resource>,
+    id: u32,
+) -> Result<Dependency, String> {
+    PARTICLE_TYPES
+        .iter()
+        .map(|fourcc| FourCC::from_bytes(fourcc))
+        .find(|fourcc| resources.contains_key(&(id, *fourcc)))
+        .map(|asset_type| Dependency {
+            asset_id: id,
+            asset_type,
+        })
+        .ok_or_else(|| format!("ancsEdits: 0x{:08X} is not a particle in the game", id))
+}
+
+/// Adds the edited character sets to `resources`, along with the particles the edits introduce
+/// and everything those need in turn
+pub fn collect_ancs_edit_resources<'r>(
+    gc_disc: &structs::GcDisc<'r>,
+    config: &PatchConfig,
+    resources: &mut HashMap<(u32, FourCC), Resource<'r>>,
+) -> Result<(), String> {
+    if config.ancs_edits.is_empty() {
+        return Ok(());
+    }
+
+    let mut pak_resources: HashMap<(u32, FourCC), Resource<'r>> = HashMap::new();
+    for pak_name in character_paks() {
+        let file_entry = match gc_disc.find_file(pak_name) {
+            Some(file_entry) => file_entry,
+            None => continue,
+        };
+        let pak = match *file_entry.file().unwrap() {
+            structs::FstEntryFile::Pak(ref pak) => Cow::Borrowed(pak),
+            structs::FstEntryFile::Unknown(ref reader) => Cow::Owned(reader.clone().read(())),
+            _ => panic!(),
+        };
+        for res in pak.resources.iter() {
+            pak_resources
+                .entry((res.file_id, res.fourcc()))
+                .or_insert_with(|| res.into_owned());
+        }
+    }
+
+    let mut roots = vec![];
+    for (key, edit) in &config.ancs_edits {
+        let id = parse_ancs_id(key)?;
+        let ancs = pak_resources
+            .get(&(id, b"ANCS".into()))
+            .ok_or_else(|| format!("ancsEdits: 0x{:08X} is not a character in the game", id))?;
+        resources.insert((id, b"ANCS".into()), ancs.clone());
+
+        for particle in new_particle_ids(edit) {
+            roots.push(particle_dependency(&pak_resources, particle)?);
+        }
+    }
+
+    let closure = structs::dependency_closure(roots, |dep| {
+        pak_resources
+            .get(&(dep.asset_id, dep.asset_type))
+            .map(|res| ResourceData::new(res).decompress())
+    });
+    for dep in closure {
+        let key = (dep.asset_id, dep.asset_type);
+        if let Some(res) = pak_resources.get(&key) {
+            resources.entry(key).or_insert_with(|| res.clone());
+        }
+    }
+
+    Ok(())
+}
+
+/// The rooms that load the edited characters which are given new particles
+pub fn collect_ancs_rooms(
+    gc_disc: &structs::GcDisc,
+    config: &PatchConfig,
+) -> Result<RoomsListing, String> {
+    let mut ancs_ids = HashSet::new();
+    for (key, edit) in &config.ancs_edits {
+        if new_particle_ids(edit).next().is_some() {
+            ancs_ids.insert((parse_ancs_id(key)?, FourCC::from_bytes(b"ANCS")));
+        }
+    }
+    mlvl_wrapper::rooms_listing(gc_disc, &ancs_ids)
+}
+
+fn decompressed_bytes(res: &Resource) -> Vec<u8> {
+    let res_data = match &res.kind {
+        ResourceKind::External(_, _) => ResourceData::new_external(res),
+        _ => ResourceData::new(res),
+    };
+    res_data.decompress().into_owned()
+}
+
+fn collect_anims(meta: &MetaAnimation, anims: &mut Vec<u32>) {
+    match meta {
+        MetaAnimation::Play(play) => anims.push(play.get().anim.to_u32()),
+        MetaAnimation::Blend(blend) | MetaAnimation::PhaseBlend(blend) => {
+            let blend = blend.get();
+            collect_anims(&blend.anim_a, anims);
+            collect_anims(&blend.anim_b, anims);
+        }
+        MetaAnimation::Random(random) => {
+            for pair in random.get().anims.iter() {
+                collect_anims(&pair.meta, anims);
+            }
+        }
+        MetaAnimation::Sequence(sequence) => {
+            for meta in sequence.get().anims.iter() {
+                collect_anims(&meta, anims);
+            }
+        }
+    }
+}
+
+/// The EVNTs of the animations called `name`, or of every animation for "*"
+fn animation_evnts(ancs: &Ancs, ancs_id: u32, name: &str) -> Result<Vec<u32>, String> {
+    let mut anims = vec![];
+    for animation in ancs.anim_set.animations.iter() {
+        if name == "*" || animation.name.to_bytes() == name.as_bytes() {
+            collect_anims(&animation.meta, &mut anims);
+        }
+    }
+    if anims.is_empty() {
+        return Err(format!(
+            "ANCS 0x{:08X} has no animation named '{}'",
+            ancs_id, name
+        ));
+    }
+
+    let resources = ancs
+        .anim_set
+        .animation_resources
+        .as_ref()
+        .ok_or_else(|| format!("ANCS 0x{:08X} doesn't list its EVNTs", ancs_id))?;
+    let mut evnts: Vec<u32> = resources
+        .iter()
+        .filter(|res| anims.contains(&res.anim.to_u32()) && res.evnt.to_u32() != u32::MAX)
+        .map(|res| res.evnt.to_u32())
+        .collect();
+    evnts.sort_unstable();
+    evnts.dedup();
+    Ok(evnts)
+}
+
+pub fn add_ancs_edit_patches<'r, 's>(
+    patcher: &mut PrimePatcher<'r, 's>,
+    config: &'s PatchConfig,
+    game_resources: &'s HashMap<(u32, FourCC), Resource<'r>>,
+    ancs_rooms: &RoomsListing,
+) -> Result<(), String> {
+    let paks = character_paks();
+
+    for (key, edit) in &config.ancs_edits {
+        let ancs_id = parse_ancs_id(key)?;
+        let ancs_bytes = decompressed_bytes(&game_resources[&(ancs_id, b"ANCS".into())]);
+        let ancs: Ancs = Reader::new(&ancs_bytes[..]).read(());
+
+        let mut particle_swaps: HashMap<u32, Dependency> = HashMap::new();
+        for (original, replacement) in &edit.particle_swaps {
+            particle_swaps.insert(
+                *original,
+                particle_dependency(game_resources, *replacement)?,
+            );
+        }
+        let new_particles = new_particle_ids(edit)
+            .map(|id| particle_dependency(game_resources, id))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        // Swapped particles can be attached by any animation
+        let mut evnt_edits: HashMap<u32, Vec<&'s AnimEventsConfig>> = HashMap::new();
+        if !particle_swaps.is_empty() {
+            for evnt in animation_evnts(&ancs, ancs_id, "*")? {
+                evnt_edits.entry(evnt).or_default();
+            }
+        }
+        for (name, events) in &edit.animations {
+            for evnt in animation_evnts(&ancs, ancs_id, name)? {
+                evnt_edits.entry(evnt).or_default().push(events);
+            }
+        }
+
+        for pak in paks.iter() {
+            let ancs_swaps = particle_swaps.clone();
+            let ancs_particles = new_particles.clone();
+            patcher.add_resource_patch(
+                (&[pak.as_bytes()], ancs_id, FourCC::from_bytes(b"ANCS")),
+                move |res| patch_ancs(res, &ancs_swaps, &ancs_particles),
+            );
+
+            for (evnt, events) in &evnt_edits {
+                let particle_swaps = particle_swaps.clone();
+                let new_particles = new_particles.clone();
+                let events = events.clone();
+                patcher.add_resource_patch(
+                    (&[pak.as_bytes()], *evnt, FourCC::from_bytes(b"EVNT")),
+                    move |res| patch_evnt(res, &particle_swaps, &new_particles, &events),
+                );
+            }
+        }
+
+        if new_particles.is_empty() {
+            continue;
+        }
+        let ancs_dep = Dependency {
+            asset_id: ancs_id,
+            asset_type: b"ANCS".into(),
+        };
+        for (pak_name, room_id) in ancs_rooms
+            .get(&(ancs_id, ancs_dep.asset_type))
+            .into_iter()
+            .flatten()
+        {
+            let ancs_dep = ancs_dep.clone();
+            let new_particles = new_particles.clone();
+            patcher.add_scly_patch((pak_name.as_bytes(), *room_id), move |_ps, area| {
+                if let Some(layer_num) = area.dependency_layer(&ancs_dep) {
+                    area.add_dependencies_with_closure(
+                        game_resources,
+                        layer_num,
+                        new_particles.iter().cloned(),
+                    );
+                }
+                Ok(())
+            });
+        }
+    }
+
+    Ok(())
+}
+
+// Makes sure the character preloads `particle`
+fn list_particle(
+    particles: &mut structs::ParticleResData,
+    particle: &Dependency,
+) -> Result<(), String> {
+    let id = particle.asset_id;
+    match particle.asset_type.as_bytes() {
+        b"PART" => {
+            let parts = particles.part_assets.as_mut_vec();
+            if !parts.contains(&id) {
+                parts.push(id);
+            }
+        }
+        b"SWHC" => {
+            let swhcs = particles.swhc_assets.as_mut_vec();
+            if !swhcs.iter().any(|swhc| swhc.to_u32() == id) {
+                swhcs.push(structs::ResId::new(id));
+            }
+        }
+        _ => {
+            let elscs = particles
+                .elsc_assets
+                .as_mut()
+                .ok_or_else(|| format!("Character can't use electric particle 0x{:08X}", id))?
+                .as_mut_vec();
+            if !elscs.iter().any(|elsc| elsc.to_u32() == id) {
+                elscs.push(structs::ResId::new(id));
+            }
+        }
+    }
+    Ok(())
+}
+
+fn patch_ancs(
+    res: &mut Resource,
+    particle_swaps: &HashMap<u32, Dependency>,
+    new_particles: &[Dependency],
+) -> Result<(), String> {
+    let bytes = decompressed_bytes(res);
+    let mut ancs: Ancs = Reader::new(&bytes[..]).read(());
+
+    for char_info in ancs.char_set.char_info.as_mut_vec() {
+        let particles = &mut char_info.particles;
+        for (original, replacement) in particle_swaps {
+            let parts = particles.part_assets.as_mut_vec();
+            let swhcs = particles.swhc_assets.as_mut_vec();
+            let listed = parts.contains(original)
+                || swhcs.iter().any(|swhc| swhc.to_u32() == *original)
+                || particles
+                    .elsc_assets
+                    .iter()
+                    .flat_map(|elscs| elscs.iter())
+                    .any(|elsc| elsc.to_u32() == *original);
+            if !listed {
+                continue;
+            }
+            parts.retain(|part| part != original);
+            swhcs.retain(|swhc| swhc.to_u32() != *original);
+            if let Some(elscs) = particles.elsc_assets.as_mut() {
+                elscs.as_mut_vec().retain(|elsc| elsc.to_u32() != *original);
+            }
+            list_particle(particles, replacement)?;
+        }
+        for particle in new_particles {
+            list_particle(particles, particle)?;
+        }
+
+        for effect in char_info.effects.iter_mut().flat_map(|e| e.as_mut_vec()) {
+            for component in effect.components.as_mut_vec() {
+                if let Some(replacement) = particle_swaps.get(&component.file_id) {
+                    component.file_id = replacement.asset_id;
+                    component.type_ = replacement.asset_type;
+                }
+            }
+        }
+    }
+
+    let mut bytes = vec![];
+    ancs.write_to(&mut bytes).unwrap();
+    res.kind = ResourceKind::External(bytes, b"ANCS".into());
+    res.compressed = false;
+    Ok(())
+}
+
+fn set_time(time: &mut AnimTime, seconds: f32) {
+    time.timestamp = seconds;
+    time.differential_state = if seconds == 0.0 {
+        ANIM_TIME_ZERO_STEADY
+    } else {
+        ANIM_TIME_NON_ZERO
+    };
+}
+
+fn c_string<'r>(field: &str, value: &str) -> Result<CStr<'r>, String> {
+    CString::new(value)
+        .map(Cow::Owned)
+        .map_err(|_| format!("The {} '{}' can't contain NUL characters", field, value))
+}
+
+fn update_event_base(base: &mut EventBase, event: &AnimEventConfig) -> Result<(), String> {
+    if let Some(time) = event.time {
+        set_time(&mut base.timestamp, time);
+    }
+    if let Some(name) = &event.name {
+        base.name = c_string("name", name)?;
+    }
+    if let Some(character_index) = event.character_index {
+        base.character_index = character_index;
+    }
+    if let Some(weight) = event.weight {
+        base.weight = weight;
+    }
+    Ok(())
+}
+
+fn update_effect_event(effect: &mut EffectEvent, event: &AnimEventConfig) -> Result<(), String> {
+    if let Some(effect_id) = event.effect_id {
+        effect.effect_file_id = effect_id;
+    }
+    if let Some(effect_type) = &event.effect_type {
+        if !PARTICLE_TYPES
+            .iter()
+            .any(|t| t[..] == *effect_type.as_bytes())
+        {
+            return Err(format!("{} is not a particle type", effect_type));
+        }
+        effect.effect_type = FourCC::from_bytes(effect_type.as_bytes().try_into().unwrap());
+    }
+    if let Some(bone_name) = &event.bone_name {
+        effect.bone_name = c_string("boneName", bone_name)?;
+    }
+    if let Some(scale) = event.scale {
+        effect.scale = scale.to_bits();
+    }
+    if let Some(parent_mode) = event.parent_mode {
+        effect.transform_type = parent_mode;
+    }
+    if let Some(frame_count) = event.frame_count {
+        effect.frame_count = frame_count;
+    }
+    Ok(())
+}
+
+fn update_sound_event(sound: &mut SoundEvent, event: &AnimEventConfig) {
+    if let Some(sound_id) = event.sound_id {
+        sound.sound_id = sound_id;
+    }
+    if let Some(reference_amplitude) = event.reference_amplitude {
+        sound.reference_amplitude = reference_amplitude;
+    }
+    if let Some(reference_distance) = event.reference_distance {
+        sound.reference_distance = reference_distance;
+    }
+}
+
+fn update_user_event(user: &mut UserEvent, event: &AnimEventConfig) -> Result<(), String> {
+    if let Some(user_event_type) = event.user_event_type {
+        user.event_type = user_event_type;
+    }
+    if let Some(bone_name) = &event.bone_name {
+        user.bone_name = c_string("boneName", bone_name)?;
+    }
+    Ok(())
+}
+
+// New events start as a copy of an existing one, so the fields nobody configures keep values the
+// game is known to accept
+fn new_event_base<'r>(evnt: &Evnt<'r>, event_type: u16, index: usize) -> EventBase<'r> {
+    let existing = evnt
+        .effect_events
+        .iter()
+        .map(|e| e.base.clone())
+        .chain(evnt.user_events.iter().map(|e| e.base.clone()))
+        .chain(
+            evnt.sound_events
+                .iter()
+                .flat_map(|s| s.iter().map(|e| e.base.clone())),
+        )
+        .chain(evnt.loop_events.iter().map(|e| e.base))
+        .next();
+    let mut base = existing.unwrap_or(EventBase {
+        unknown0: 2,
+        name: Cow::Owned(CString::default()),
+        event_type,
+        timestamp: AnimTime {
+            timestamp: 0.0,
+            differential_state: ANIM_TIME_ZERO_STEADY,
+        },
+        event_index: 0,
+        unknown1: 1,
+        weight: 1.0,
+        character_index: -1,
+        unknown2: 0,
+    });
+    base.event_type = event_type;
+    base.event_index = index as u32;
+    base.name = Cow::Owned(CString::default());
+    set_time(&mut base.timestamp, 0.0);
+    base
+}
+
+fn add_event(
+    evnt: &mut Evnt,
+    event: &AnimEventConfig,
+    evnt_id: u32,
+    new_particles: &[Dependency],
+) -> Result<(), String> {
+    match event.kind {
+        AnimEventKind::Sound => {
+            let index = evnt.sound_events.as_ref().map(|s| s.len()).unwrap_or(0);
+            let template = evnt
+                .sound_events
+                .iter()
+                .flat_map(|s| s.iter())
+                .next()
+                .map(|s| (s.reference_amplitude, s.reference_distance));
+            let (reference_amplitude, reference_distance) =
+                match (event.reference_amplitude, event.reference_distance, template) {
+                    (Some(amplitude), Some(distance), _) => (amplitude, distance),
+                    (amplitude, distance, Some((default_amplitude, default_distance))) => (
+                        amplitude.unwrap_or(default_amplitude),
+                        distance.unwrap_or(default_distance),
+                    ),
+                    _ => {
+                        return Err(format!(
+                            "EVNT 0x{:08X} has no sounds to copy from, new sounds need a referenceAmplitude and referenceDistance",
+                            evnt_id
+                        ))
+                    }
+                };
+            let mut sound = SoundEvent {
+                base: new_event_base(evnt, SOUND_EVENT_TYPE, index),
+                sound_id: event
+                    .sound_id
+                    .ok_or_else(|| "New sound events need a soundId".to_string())?,
+                reference_amplitude,
+                reference_distance,
+            };
+            update_event_base(&mut sound.base, event)?;
+            evnt.sound_events
+                .get_or_insert_with(|| vec![].into())
+                .as_mut_vec()
+                .push(sound);
+        }
+        AnimEventKind::Effect => {
+            let index = evnt.effect_events.len();
+            let effect_id = event
+                .effect_id
+                .ok_or_else(|| "New effect events need an effectId".to_string())?;
+            // The type of the particle as found in the game, unless configured otherwise
+            let effect_type = new_particles
+                .iter()
+                .find(|particle| particle.asset_id == effect_id)
+                .map(|particle| particle.asset_type)
+                .unwrap_or(FourCC::from_bytes(b"PART"));
+            let mut effect = EffectEvent {
+                base: new_event_base(evnt, EFFECT_EVENT_TYPE, index),
+                frame_count: 0,
+                effect_type,
+                effect_file_id: effect_id,
+                bone_name: c_string(
+                    "boneName",
+                    event
+                        .bone_name
+                        .as_ref()
+                        .ok_or_else(|| "New effect events need a boneName".to_string())?,
+                )?,
+                scale: 1.0f32.to_bits(),
+                transform_type: 0,
+            };
+            update_event_base(&mut effect.base, event)?;
+            update_effect_event(&mut effect, event)?;
+            evnt.effect_events.as_mut_vec().push(effect);
+        }
+        AnimEventKind::User => {
+            let index = evnt.user_events.len();
+            let mut user = UserEvent {
+                base: new_event_base(evnt, USER_EVENT_TYPE, index),
+                event_type: event
+                    .user_event_type
+                    .ok_or_else(|| "New user events need a userEventType".to_string())?,
+                bone_name: Cow::Owned(CString::default()),
+            };
+            update_event_base(&mut user.base, event)?;
+            update_user_event(&mut user, event)?;
+            evnt.user_events.as_mut_vec().push(user);
+        }
+    }
+    Ok(())
+}
+
+fn edit_event(evnt: &mut Evnt, event: &AnimEventConfig, evnt_id: u32) -> Result<(), String> {
+    let index = event
+        .index
+        .ok_or_else(|| "Editing an animation event requires an index".to_string())?
+        as usize;
+    let missing = || {
+        format!(
+            "EVNT 0x{:08X} has no {:?} event {}",
+            evnt_id, event.kind, index
+        )
+    };
+    match event.kind {
+        AnimEventKind::Sound => {
+            let sound = evnt
+                .sound_events
+                .as_mut()
+                .and_then(|s| s.as_mut_vec().get_mut(index))
+                .ok_or_else(missing)?;
+            update_event_base(&mut sound.base, event)?;
+            update_sound_event(sound, event);
+        }
+        AnimEventKind::Effect => {
+            let effect = evnt
+                .effect_events
+                .as_mut_vec()
+                .get_mut(index)
+                .ok_or_else(missing)?;
+            update_event_base(&mut effect.base, event)?;
+            update_effect_event(effect, event)?;
+        }
+        AnimEventKind::User => {
+            let user = evnt
+                .user_events
+                .as_mut_vec()
+                .get_mut(index)
+                .ok_or_else(missing)?;
+            update_event_base(&mut user.base, event)?;
+            update_user_event(user, event)?;
+        }
+    }
+    Ok(())
+}
+
+fn renumber_events(evnt: &mut Evnt, kind: AnimEventKind) {
+    let bases: Vec<&mut EventBase> = match kind {
+        AnimEventKind::Sound => evnt
+            .sound_events
+            .iter_mut()
+            .flat_map(|s| s.as_mut_vec())
+            .map(|e| &mut e.base)
+            .collect(),
+        AnimEventKind::Effect => evnt
+            .effect_events
+            .as_mut_vec()
+            .iter_mut()
+            .map(|e| &mut e.base)
+            .collect(),
+        AnimEventKind::User => evnt
+            .user_events
+            .as_mut_vec()
+            .iter_mut()
+            .map(|e| &mut e.base)
+            .collect(),
+    };
+    for (index, base) in bases.into_iter().enumerate() {
+        base.event_index = index as u32;
+    }
+}
+
+fn patch_evnt(
+    res: &mut Resource,
+    particle_swaps: &HashMap<u32, Dependency>,
+    new_particles: &[Dependency],
+    edits: &[&AnimEventsConfig],
+) -> Result<(), String> {
+    let evnt_id = res.file_id;
+    let bytes = decompressed_bytes(res);
+    let mut evnt: Evnt = Reader::new(&bytes[..]).read(());
+
+    for effect in evnt.effect_events.as_mut_vec() {
+        if let Some(replacement) = particle_swaps.get(&effect.effect_file_id) {
+            effect.effect_file_id = replacement.asset_id;
+            effect.effect_type = replacement.asset_type;
+        }
+    }
+
+    for edit in edits {
+        for event in edit.edit.iter().flatten() {
+            edit_event(&mut evnt, event, evnt_id)?;
+        }
+
+        // Remove in descending order so earlier removals don't shift the later indices
+        let mut removals: Vec<(AnimEventKind, usize)> = edit
+            .remove
+            .iter()
+            .flatten()
+            .map(|id| (id.kind, id.index as usize))
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .collect();
+        removals.sort_by_key(|(_, index)| std::cmp::Reverse(*index));
+        for &(kind, index) in &removals {
+            let len = match kind {
+                AnimEventKind::Sound => evnt.sound_events.as_ref().map(|s| s.len()).unwrap_or(0),
+                AnimEventKind::Effect => evnt.effect_events.len(),
+                AnimEventKind::User => evnt.user_events.len(),
+            };
+            if index >= len {
+                return Err(format!(
+                    "EVNT 0x{:08X} has no {:?} event {}",
+                    evnt_id, kind, index
+                ));
+            }
+            match kind {
+                AnimEventKind::Sound => {
+                    evnt.sound_events
+                        .as_mut()
+                        .unwrap()
+                        .as_mut_vec()
+                        .remove(index);
+                }
+                AnimEventKind::Effect => {
+                    evnt.effect_events.as_mut_vec().remove(index);
+                }
+                AnimEventKind::User => {
+                    evnt.user_events.as_mut_vec().remove(index);
+                }
+            }
+        }
+        // Events are numbered by their position, which is also how new events are numbered
+        for kind in removals
+            .iter()
+            .map(|(kind, _)| *kind)
+            .collect::<HashSet<_>>()
+        {
+            renumber_events(&mut evnt, kind);
+        }
+
+        if edit.mute_sounds.unwrap_or(false) {
+            if let Some(sounds) = evnt.sound_events.as_mut() {
+                sounds.as_mut_vec().clear();
+            }
+        }
+
+        for event in edit.add.iter().flatten() {
+            add_event(&mut evnt, event, evnt_id, new_particles)?;
+        }
+    }
+
+    let mut new_bytes = vec![];
+    evnt.write_to(&mut new_bytes).unwrap();
+    res.kind = ResourceKind::External(new_bytes, b"EVNT".into());
+    res.compressed = false;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::custom_assets::build_resource_raw;
+
+    const EVNT_ID: u32 = 0xE0E0E0E0;
+    const PARTICLE: u32 = 0x11111111;
+    const NEW_PARTICLE: u32 = 0x22222222;
+
+    fn event_base(name: String, event_type: u16, index: u32) -> EventBase<'static> {
+        EventBase {
+            unknown0: 2,
+            name: Cow::Owned(CString::new(name).unwrap()),
+            event_type,
+            timestamp: AnimTime {
+                timestamp: index as f32,
+                differential_state: ANIM_TIME_NON_ZERO,
+            },
+            event_index: index,
+            unknown1: 1,
+            weight: 1.0,
+            character_index: -1,
+            unknown2: 0,
+        }
+    }
+
+    // Three effects, named effect0 to effect2, and two sounds
+    fn evnt_resource() -> Resource<'static> {
+        let effects: Vec<EffectEvent> = (0..3)
+            .map(|i| EffectEvent {
+                base: event_base(format!("effect{}", i), EFFECT_EVENT_TYPE, i),
+                frame_count: 0,
+                effect_type: b"PART".into(),
+                effect_file_id: PARTICLE + i,
+                bone_name: Cow::Owned(CString::new("Head").unwrap()),
+                scale: 1.0f32.to_bits(),
+                transform_type: 0,
+            })
+            .collect();
+        let sounds: Vec<SoundEvent> = (0..2)
+            .map(|i| SoundEvent {
+                base: event_base(format!("sound{}", i), SOUND_EVENT_TYPE, i),
+                sound_id: 0x100 + i,
+                reference_amplitude: 0.5,
+                reference_distance: 20.0,
+            })
+            .collect();
+        let evnt = Evnt {
+            loop_events: Reader::new(&[]).read((0, ())),
+            user_events: vec![].into(),
+            effect_events: effects.into(),
+            sound_events: Some(sounds.into()),
+        };
+
+        let mut bytes = vec![];
+        evnt.write_to(&mut bytes).unwrap();
+        build_resource_raw(EVNT_ID, ResourceKind::External(bytes, b"EVNT".into()))
+    }
+
+    fn patched_evnt(
+        particle_swaps: &HashMap<u32, Dependency>,
+        edits: &str,
+    ) -> Result<Vec<u8>, String> {
+        let edits: AnimEventsConfig = serde_json::from_str(edits).unwrap();
+        let new_particles = [Dependency {
+            asset_id: NEW_PARTICLE,
+            asset_type: b"ELSC".into(),
+        }];
+        let mut res = evnt_resource();
+        patch_evnt(&mut res, particle_swaps, &new_particles, &[&edits])?;
+        Ok(decompressed_bytes(&res))
+    }
+
+    fn effects(evnt: &Evnt) -> Vec<(String, u32, u32)> {
+        evnt.effect_events
+            .iter()
+            .map(|e| {
+                let name = e.base.name.to_str().unwrap().to_string();
+                (name, e.base.event_index, e.effect_file_id)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn events_are_edited_then_removed_then_added() {
+        let bytes = patched_evnt(
+            &HashMap::new(),
+            r#"{
+                "edit": [{"kind": "Effect", "index": 1, "name": "edited", "time": 0}],
+                "remove": [
+                    {"kind": "Effect", "index": 0},
+                    {"kind": "Effect", "index": 2},
+                    {"kind": "Effect", "index": 0}
+                ],
+                "add": [{"kind": "Effect", "effectId": 572662306, "boneName": "L_hand"}]
+            }"#,
+        )
+        .unwrap();
+        let evnt: Evnt = Reader::new(&bytes[..]).read(());
+
+        assert_eq!(
+            effects(&evnt),
+            [
+                ("edited".to_string(), 0, PARTICLE + 1),
+                (String::new(), 1, NEW_PARTICLE),
+            ]
+        );
+        let edited = evnt.effect_events.iter().next().unwrap();
+        assert_eq!(edited.base.timestamp.timestamp, 0.0);
+        assert_eq!(
+            edited.base.timestamp.differential_state,
+            ANIM_TIME_ZERO_STEADY
+        );
+        let added = evnt.effect_events.iter().nth(1).unwrap();
+        assert_eq!(added.effect_type, b"ELSC".into());
+        assert_eq!(added.bone_name.to_str().unwrap(), "L_hand");
+        // The other kinds keep their numbering
+        let sounds: Vec<u32> = evnt
+            .sound_events
+            .as_ref()
+            .unwrap()
+            .iter()
+            .map(|s| s.base.event_index)
+            .collect();
+        assert_eq!(sounds, [0, 1]);
+    }
+
+    #[test]
+    fn muted_sounds_make_way_for_new_ones() {
+        let bytes = patched_evnt(
+            &HashMap::new(),
+            r#"{
+                "muteSounds": true,
+                "add": [{
+                    "kind": "Sound",
+                    "soundId": 7,
+                    "time": 0.5,
+                    "referenceAmplitude": 0.25,
+                    "referenceDistance": 10
+                }]
+            }"#,
+        )
+        .unwrap();
+        let evnt: Evnt = Reader::new(&bytes[..]).read(());
+
+        let sounds: Vec<_> = evnt.sound_events.as_ref().unwrap().iter().collect();
+        assert_eq!(sounds.len(), 1);
+        assert_eq!(sounds[0].sound_id, 7);
+        assert_eq!(sounds[0].base.event_index, 0);
+        assert_eq!(sounds[0].base.timestamp.timestamp, 0.5);
+        assert_eq!(sounds[0].reference_distance, 10.0);
+        assert_eq!(evnt.effect_events.len(), 3);
+
+        // Without sounds left, there is nothing to copy the reference values from
+        let muted = patched_evnt(
+            &HashMap::new(),
+            r#"{"muteSounds": true, "add": [{"kind": "Sound", "soundId": 7}]}"#,
+        );
+        assert!(muted.is_err());
+    }
+
+    #[test]
+    fn new_sounds_copy_the_reference_values() {
+        let bytes = patched_evnt(
+            &HashMap::new(),
+            r#"{"add": [{"kind": "Sound", "soundId": 7, "referenceAmplitude": 0.25}]}"#,
+        )
+        .unwrap();
+        let evnt: Evnt = Reader::new(&bytes[..]).read(());
+
+        let added = evnt.sound_events.as_ref().unwrap().iter().nth(2).unwrap();
+        assert_eq!(added.base.event_index, 2);
+        assert_eq!(added.reference_amplitude, 0.25);
+        assert_eq!(added.reference_distance, 20.0);
+    }
+
+    #[test]
+    fn particles_are_swapped() {
+        let swaps = HashMap::from([(
+            PARTICLE + 2,
+            Dependency {
+                asset_id: NEW_PARTICLE,
+                asset_type: b"SWHC".into(),
+            },
+        )]);
+        let bytes = patched_evnt(&swaps, "{}").unwrap();
+        let evnt: Evnt = Reader::new(&bytes[..]).read(());
+
+        let swapped: Vec<_> = evnt
+            .effect_events
+            .iter()
+            .map(|e| (e.effect_file_id, e.effect_type))
+            .collect();
+        assert_eq!(
+            swapped,
+            [
+                (PARTICLE, b"PART".into()),
+                (PARTICLE + 1, b"PART".into()),
+                (NEW_PARTICLE, b"SWHC".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn bad_event_edits_are_rejected() {
+        let swaps = HashMap::new();
+        for edits in [
+            r#"{"remove": [{"kind": "Effect", "index": 3}]}"#,
+            r#"{"remove": [{"kind": "User", "index": 0}]}"#,
+            r#"{"edit": [{"kind": "Sound", "name": "no index"}]}"#,
+            r#"{"edit": [{"kind": "Effect", "index": 0, "name": "nul\u0000"}]}"#,
+            r#"{"edit": [{"kind": "Effect", "index": 0, "effectType": "TXTR"}]}"#,
+            r#"{"add": [{"kind": "Effect", "effectId": 1, "boneName": "nul\u0000"}]}"#,
+            r#"{"add": [{"kind": "Effect", "effectId": 1}]}"#,
+            r#"{"add": [{"kind": "Sound"}]}"#,
+        ] {
+            assert!(patched_evnt(&swaps, edits).is_err(), "{}", edits);
+        }
+    }
+}
@@ -0,0 +1,165 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use clap::{clap_app, crate_authors, crate_version};
+use randomprime::dsp_adpcm::{read_wav, resample, write_wav, DspAudio};
+
+// Stereo streams are a pair of files, which the game loads from names like
+// "/audio/songL.dsp|/audio/songR.dsp"
+fn output_paths(output: &Path, channel_count: usize) -> Vec<PathBuf> {
+    let stem = output.with_extension("");
+    let stem = stem.to_string_lossy();
+    if channel_count == 1 {
+        vec![PathBuf::from(format!("{}.dsp", stem))]
+    } else {
+        vec![
+            PathBuf::from(format!("{}L.dsp", stem)),
+            PathBuf::from(format!("{}R.dsp", stem)),
+        ]
+    }
+}
+
+fn wav2dsp(
+    input: &Path,
+    output: &Path,
+    sample_rate: Option<u32>,
+    loop_start: Option<usize>,
+    loop_end: Option<usize>,
+) -> Result<(), String> {
+    let (channels, wav_sample_rate) = read_wav(input)?;
+    if channels.is_empty() || channels.len() > 2 {
+        Err(format!(
+            "{} has {} channels, only mono and stereo are supported",
+            input.display(),
+            channels.len()
+        ))?;
+    }
+
+    let sample_rate = sample_rate.unwrap_or(wav_sample_rate);
+    let channels: Vec<_> = channels
+        .iter()
+        .map(|samples| resample(samples, wav_sample_rate, sample_rate))
+        .collect();
+
+    let channel_count = channels.len();
+    let sample_count = channels[0].len();
+    let loop_range = (loop_start.is_some() || loop_end.is_some())
+        .then(|| loop_start.unwrap_or(0)..loop_end.unwrap_or(sample_count));
+
+    for (samples, path) in channels
+        .into_iter()
+        .zip(output_paths(output, channel_count))
+    {
+        let dsp = DspAudio {
+            samples,
+            sample_rate,
+            loop_range: loop_range.clone(),
+        }
+        .encode()?;
+        fs::write(&path, dsp).map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+        println!("Wrote {}", path.display());
+    }
+
+    Ok(())
+}
+
+fn dsp2wav(inputs: &[&Path], output: &Path) -> Result<(), String> {
+    let mut channels = vec![];
+    let mut sample_rate = None;
+    for input in inputs {
+        let bytes =
+            fs::read(input).map_err(|e| format!("Failed to read {}: {}", input.display(), e))?;
+        let dsp = DspAudio::decode(&bytes)
+            .map_err(|e| format!("Failed to decode {}: {}", input.display(), e))?;
+        if *sample_rate.get_or_insert(dsp.sample_rate) != dsp.sample_rate {
+            Err("The left and right channels have different sample rates")?;
+        }
+        if let Some(loop_range) = &dsp.loop_range {
+            println!(
+                "{} loops samples {} to {}",
+                input.display(),
+                loop_range.start,
+                loop_range.end
+            );
+        }
+        channels.push(dsp.samples);
+    }
+
+    // Pad the shorter channel, if any, so they can be interleaved
+    let sample_count = channels.iter().map(|c| c.len()).max().unwrap();
+    for channel in &mut channels {
+        channel.resize(sample_count, 0);
+    }
+
+    write_wav(output, &channels, sample_rate.unwrap())
+}
+
+fn main() {
+    let app = clap_app!(app =>
+        (version: crate_version!())
+        (author: crate_authors!())
+        (about: "Converts WAVs to/from the DSP ADPCM .dsp files streamed by the game.")
+        (@setting ArgRequiredElseHelp)
+        (@subcommand wav2dsp =>
+            (about: "Converts a mono WAV to a .dsp, or a stereo WAV to a pair of L.dsp/R.dsp.")
+            (@arg input: -i --input +takes_value +required
+                "Input WAV file to convert.")
+            (@arg output: -o --output +takes_value +required
+                "Output path of the .dsp file. Stereo WAVs are written to two files with an L \
+                 and R suffix added, e.g. song.dsp becomes songL.dsp and songR.dsp.")
+            (@arg sample_rate: -s --sample_rate +takes_value
+                { |s| s.parse::<u32>()
+                    .map(|_| ())
+                    .map_err(|_| "Expected integer for sample rate".into()) }
+                "Sample rate to resample the audio to. Defaults to the rate of the WAV, the \
+                 game's own music is 32000Hz.")
+            (@arg loop: -l --loop "Loop the whole file.")
+            (@arg loop_start: --loop_start +takes_value
+                { |s| s.parse::<usize>()
+                    .map(|_| ())
+                    .map_err(|_| "Expected integer for loop start".into()) }
+                "Sample the loop starts at, after any resampling. Implies --loop.")
+            (@arg loop_end: --loop_end +takes_value
+                { |s| s.parse::<usize>()
+                    .map(|_| ())
+                    .map_err(|_| "Expected integer for loop end".into()) }
+                "Sample the loop ends before, after any resampling. Defaults to the end of the \
+                 file. Implies --loop.")
+        )
+        (@subcommand dsp2wav =>
+            (about: "Converts a .dsp, or a pair of L.dsp/R.dsp, to a WAV.")
+            (@arg input: -i --input +takes_value +required
+                "Input .dsp file, or the left channel of a stereo stream.")
+            (@arg right: -r --right +takes_value
+                "Right channel of a stereo stream.")
+            (@arg output: -o --output +takes_value +required
+                "Output path to write the WAV file to.")
+        )
+    );
+    let matches = app.get_matches();
+
+    let res = match matches.subcommand() {
+        ("wav2dsp", Some(matches)) => {
+            let input = Path::new(matches.value_of("input").unwrap());
+            let output = Path::new(matches.value_of("output").unwrap());
+            let sample_rate = matches.value_of("sample_rate").map(|s| s.parse().unwrap());
+            let loop_start = matches.value_of("loop_start").map(|s| s.parse().unwrap());
+            let loop_end = matches.value_of("loop_end").map(|s| s.parse().unwrap());
+            let loop_start = loop_start.or(matches.is_present("loop").then_some(0));
+            wav2dsp(input, output, sample_rate, loop_start, loop_end)
+        }
+        ("dsp2wav", Some(matches)) => {
+            let mut inputs = vec![Path::new(matches.value_of("input").unwrap())];
+            inputs.extend(matches.value_of("right").map(Path::new));
+            let output = Path::new(matches.value_of("output").unwrap());
+            dsp2wav(&inputs, output)
+        }
+        _ => return,
+    };
+    if let Err(s) = res {
+        eprintln!("{} {}", clap::Format::Error("error:"), s);
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,1327 @@
+use std::{
+    collections::{HashMap, HashSet},
+    ffi::CString,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use clap::{clap_app, crate_authors, crate_version};
+use nalgebra::{Matrix4, Quaternion, UnitQuaternion, Vector3, Vector4};
+use randomprime::{
+    extern_assets::{PACKAGE_MANIFEST, PACKAGE_SCHEMA_VERSION, RESERVED_ASSET_IDS},
+    txtr_conversions::{png_to_cmpr_txtr, CmprQuality},
+};
+use reader_writer::{generic_array::GenericArray, Uncached, Writable};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use structs::{
+    Ancs, Animation, AnimationAABB, AnimationName, AnimationResource, AnimationSet, CharacterInfo,
+    CharacterSet, Cmdl, CmdlGeometry, CmdlMaterial, CmdlMaterialSet, CmdlNormals, CmdlPrimitive,
+    CmdlSurface, CmdlTevStage, CmdlTevStageInput, CmdlVertex, GxPrimitiveKind, MetaAnimation,
+    MetaAnimationPlay, MetaTransition, ParticleResData, PasDatabase, ResId,
+};
+
+// Skeleton and idle animation of the Gravity Suit pickup. Every custom model built into the
+// patcher is animated with a copy of its ANCS, so converted models are rigged the same way. The
+// patcher already collects these resources for the vanilla pickup models.
+const SUIT_CSKR: u32 = 0xFEBBC197;
+const SUIT_CINF: u32 = 0xAF9DEFBE;
+const SUIT_ANIM: u32 = 0x394D3877;
+const ANIMATION_NAME: &str = "idle";
+
+// The model and material setup shared by the models in extra_assets: a single texture modulated
+// by the lit vertex color, with the position, normal and UV of each vertex as 16-bit indices
+const CMDL_FLAGS: u32 = 0x1;
+const MATERIAL_FLAGS: u32 = 0x11083;
+const MATERIAL_VERTEX_ATTRIBUTES: u32 = 0x3 | (0x3 << 2) | (0x3 << 8);
+
+// Keeps each surface's display list under the 64KiB it can address
+const MAX_SURFACE_TRIANGLES: usize = 3600;
+
+#[derive(Debug, Clone, Copy)]
+struct MeshVertex {
+    position: usize,
+    normal: usize,
+    tex_coord: usize,
+}
+
+#[derive(Debug, Clone)]
+struct MeshGroup {
+    // Index into the mesh's textures
+    texture: Option<usize>,
+    triangles: Vec<[MeshVertex; 3]>,
+}
+
+/// A triangulated mesh, with one group per material. Positions and normals are in the input's
+/// coordinate system until converted, texture coordinates have their origin at the bottom left
+/// of the image.
+#[derive(Debug, Clone, Default)]
+struct Mesh {
+    positions: Vec<[f32; 3]>,
+    normals: Vec<[f32; 3]>,
+    tex_coords: Vec<[f32; 2]>,
+    // PNG files
+    textures: Vec<Vec<u8>>,
+    groups: Vec<MeshGroup>,
+}
+
+impl Mesh {
+    fn face_normal(&self, positions: [usize; 3]) -> [f32; 3] {
+        let [a, b, c] = positions.map(|i| Vector3::from(self.positions[i]));
+        let normal = (b - a).cross(&(c - a));
+        normal.try_normalize(0.0).unwrap_or(normal).into()
+    }
+
+    fn add_texture(&mut self, path: &Path) -> Result<usize, String> {
+        let bytes = fs::read(path)
+            .map_err(|e| format!("Failed to read texture {}: {}", path.display(), e))?;
+        self.textures.push(bytes);
+        Ok(self.textures.len() - 1)
+    }
+
+    fn convert_to_z_up(&mut self) {
+        for [_, y, z] in self.positions.iter_mut().chain(self.normals.iter_mut()) {
+            (*y, *z) = (-*z, *y);
+        }
+    }
+}
+
+fn parse_floats<'a, const N: usize>(mut words: impl Iterator<Item = &'a str>) -> Option<[f32; N]> {
+    let mut values = [0.0; N];
+    for value in values.iter_mut() {
+        *value = words.next()?.parse().ok()?;
+    }
+    Some(values)
+}
+
+fn obj_index(word: Option<&str>, len: usize) -> Result<Option<usize>, String> {
+    let word = match word {
+        Some(word) if !word.is_empty() => word,
+        _ => return Ok(None),
+    };
+    let index: i64 = word
+        .parse()
+        .map_err(|_| format!("Invalid index \"{}\"", word))?;
+    let resolved = if index < 0 {
+        len as i64 + index
+    } else {
+        index - 1
+    };
+    if resolved < 0 || resolved >= len as i64 {
+        Err(format!("Index {} is out of range", index))?
+    }
+    Ok(Some(resolved as usize))
+}
+
+// The last word of a statement is its file name, anything before it are options
+fn obj_file_name(line: &str) -> Option<&str> {
+    line.split_whitespace().last()
+}
+
+fn load_mtl(path: &Path) -> Result<HashMap<String, PathBuf>, String> {
+    let text = fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    let dir = path.parent().unwrap_or_else(|| Path::new(""));
+
+    let mut textures = HashMap::new();
+    let mut material = None;
+    for line in text.lines() {
+        let line = line.trim();
+        if let Some(name) = line.strip_prefix("newmtl ") {
+            material = Some(name.trim().to_string());
+        } else if let Some(args) = line.strip_prefix("map_Kd ") {
+            if let (Some(material), Some(file_name)) = (material.as_ref(), obj_file_name(args)) {
+                textures.insert(material.clone(), dir.join(file_name));
+            }
+        }
+    }
+
+    Ok(textures)
+}
+
+fn load_obj(path: &Path) -> Result<Mesh, String> {
+    let text = fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    let dir = path.parent().unwrap_or_else(|| Path::new(""));
+
+    let mut mesh = Mesh::default();
+    let mut material_textures = HashMap::new();
+    let mut texture_indices: HashMap<PathBuf, usize> = HashMap::new();
+    let mut group_indices: HashMap<Option<String>, usize> = HashMap::new();
+    let mut material: Option<String> = None;
+    let mut zero_tex_coord = None;
+
+    for (line_index, line) in text.lines().enumerate() {
+        let err = |msg: String| format!("{}:{}: {}", path.display(), line_index + 1, msg);
+        let line = line.trim();
+        let mut words = line.split_whitespace();
+        match words.next() {
+            Some("v") => mesh.positions.push(
+                parse_floats(words).ok_or_else(|| err("Invalid vertex position".to_string()))?,
+            ),
+            Some("vn") => mesh
+                .normals
+                .push(parse_floats(words).ok_or_else(|| err("Invalid vertex normal".to_string()))?),
+            Some("vt") => mesh.tex_coords.push(
+                parse_floats(words).ok_or_else(|| err("Invalid texture coordinate".to_string()))?,
+            ),
+            Some("mtllib") => {
+                let file_name = obj_file_name(&line["mtllib".len()..])
+                    .ok_or_else(|| err("Missing material library".to_string()))?;
+                material_textures.extend(load_mtl(&dir.join(file_name))?);
+            }
+            Some("usemtl") => material = Some(line["usemtl".len()..].trim().to_string()),
+            Some("f") => {
+                let mut vertices = vec![];
+                for word in words {
+                    let mut indices = word.split('/');
+                    let position = obj_index(indices.next(), mesh.positions.len())
+                        .map_err(err)?
+                        .ok_or_else(|| err("Face vertex without a position".to_string()))?;
+                    let tex_coord =
+                        obj_index(indices.next(), mesh.tex_coords.len()).map_err(err)?;
+                    let normal = obj_index(indices.next(), mesh.normals.len()).map_err(err)?;
+                    vertices.push((position, normal, tex_coord));
+                }
+                if vertices.len() < 3 {
+                    Err(err("Face with less than 3 vertices".to_string()))?
+                }
+
+                let group = match group_indices.get(&material) {
+                    Some(group) => *group,
+                    None => {
+                        let texture = match material
+                            .as_ref()
+                            .and_then(|material| material_textures.get(material))
+                        {
+                            Some(texture_path) => Some(match texture_indices.get(texture_path) {
+                                Some(texture) => *texture,
+                                None => {
+                                    let texture = mesh.add_texture(texture_path)?;
+                                    texture_indices.insert(texture_path.clone(), texture);
+                                    texture
+                                }
+                            }),
+                            None => None,
+                        };
+                        mesh.groups.push(MeshGroup {
+                            texture,
+                            triangles: vec![],
+                        });
+                        group_indices.insert(material.clone(), mesh.groups.len() - 1);
+                        mesh.groups.len() - 1
+                    }
+                };
+
+                // Faces are convex polygons, split them into a fan
+                for i in 2..vertices.len() {
+                    let corners = [vertices[0], vertices[i - 1], vertices[i]];
+                    let mut face_normal = None;
+                    let mut triangle = [MeshVertex {
+                        position: 0,
+                        normal: 0,
+                        tex_coord: 0,
+                    }; 3];
+                    for (vertex, (position, normal, tex_coord)) in triangle.iter_mut().zip(corners)
+                    {
+                        vertex.position = position;
+                        vertex.normal = match normal {
+                            Some(normal) => normal,
+                            None => *face_normal.get_or_insert_with(|| {
+                                let normal = mesh.face_normal(corners.map(|c| c.0));
+                                mesh.normals.push(normal);
+                                mesh.normals.len() - 1
+                            }),
+                        };
+                        vertex.tex_coord = match tex_coord {
+                            Some(tex_coord) => tex_coord,
+                            None => *zero_tex_coord.get_or_insert_with(|| {
+                                mesh.tex_coords.push([0.0, 0.0]);
+                                mesh.tex_coords.len() - 1
+                            }),
+                        };
+                    }
+                    mesh.groups[group].triangles.push(triangle);
+                }
+            }
+            _ => (),
+        }
+    }
+
+    Ok(mesh)
+}
+
+/* Structs for the parts of the glTF format that are used */
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+struct GltfJson {
+    scene: Option<usize>,
+    #[serde(default)]
+    scenes: Vec<GltfScene>,
+    #[serde(default)]
+    nodes: Vec<GltfNode>,
+    #[serde(default)]
+    meshes: Vec<GltfMesh>,
+    #[serde(default)]
+    accessors: Vec<GltfAccessor>,
+    #[serde(default)]
+    buffer_views: Vec<GltfBufferView>,
+    #[serde(default)]
+    buffers: Vec<GltfBuffer>,
+    #[serde(default)]
+    materials: Vec<GltfMaterial>,
+    #[serde(default)]
+    textures: Vec<GltfTexture>,
+    #[serde(default)]
+    images: Vec<GltfImage>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+struct GltfScene {
+    #[serde(default)]
+    nodes: Vec<usize>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+struct GltfNode {
+    #[serde(default)]
+    children: Vec<usize>,
+    mesh: Option<usize>,
+    matrix: Option<[f32; 16]>,
+    translation: Option<[f32; 3]>,
+    rotation: Option<[f32; 4]>,
+    scale: Option<[f32; 3]>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+struct GltfMesh {
+    primitives: Vec<GltfPrimitive>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+struct GltfPrimitive {
+    attributes: HashMap<String, usize>,
+    indices: Option<usize>,
+    material: Option<usize>,
+    mode: Option<u32>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+struct GltfAccessor {
+    buffer_view: Option<usize>,
+    #[serde(default)]
+    byte_offset: usize,
+    component_type: u32,
+    count: usize,
+    #[serde(rename = "type")]
+    type_: String,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+struct GltfBufferView {
+    buffer: usize,
+    #[serde(default)]
+    byte_offset: usize,
+    byte_length: usize,
+    byte_stride: Option<usize>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+struct GltfBuffer {
+    uri: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+struct GltfMaterial {
+    pbr_metallic_roughness: Option<GltfPbrMetallicRoughness>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+struct GltfPbrMetallicRoughness {
+    base_color_texture: Option<GltfTextureInfo>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+struct GltfTextureInfo {
+    index: usize,
+}
+
+#[derive(Deserialize, Debug, Default)]
+struct GltfTexture {
+    source: Option<usize>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+struct GltfImage {
+    uri: Option<String>,
+    buffer_view: Option<usize>,
+}
+
+const GLTF_MODE_TRIANGLES: u32 = 4;
+const GLTF_COMPONENT_U8: u32 = 5121;
+const GLTF_COMPONENT_U16: u32 = 5123;
+const GLTF_COMPONENT_U32: u32 = 5125;
+const GLTF_COMPONENT_F32: u32 = 5126;
+
+fn decode_base64(data: &str) -> Result<Vec<u8>, String> {
+    let mut bytes = vec![];
+    let mut bits = 0u32;
+    let mut bit_count = 0;
+    for c in data.bytes().filter(|c| *c != b'=') {
+        let value = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            _ => Err(format!("Invalid base64 character '{}'", c as char))?,
+        };
+        bits = (bits << 6) | value as u32;
+        bit_count += 6;
+        if bit_count >= 8 {
+            bit_count -= 8;
+            bytes.push((bits >> bit_count) as u8);
+        }
+    }
+    Ok(bytes)
+}
+
+fn decode_percent_escapes(uri: &str) -> String {
+    let bytes = uri.as_bytes();
+    let mut decoded = vec![];
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = bytes
+            .get(i + 1..i + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[i], escaped) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+fn load_gltf_uri(dir: &Path, uri: &str) -> Result<Vec<u8>, String> {
+    if let Some(data) = uri.strip_prefix("data:") {
+        let (_, data) = data
+            .split_once(";base64,")
+            .ok_or_else(|| "Only base64 data URIs are supported".to_string())?;
+        decode_base64(data)
+    } else {
+        let path = dir.join(decode_percent_escapes(uri));
+        fs::read(&path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))
+    }
+}
+
+/// Splits a binary glTF file into its JSON and binary chunks
+fn parse_glb(bytes: &[u8]) -> Result<(&[u8], Option<&[u8]>), String> {
+    let read_u32 = |offset: usize| {
+        bytes
+            .get(offset..offset + 4)
+            .map(|b| u32::from_le_bytes(b.try_into().unwrap()))
+            .ok_or_else(|| "Truncated GLB file".to_string())
+    };
+    if read_u32(4)? != 2 {
+        Err("Only version 2 GLB files are supported")?
+    }
+
+    let mut json = None;
+    let mut bin = None;
+    let mut offset = 12;
+    while offset < bytes.len() {
+        let length = read_u32(offset)? as usize;
+        let kind = read_u32(offset + 4)?;
+        let data = bytes
+            .get(offset + 8..offset + 8 + length)
+            .ok_or_else(|| "Truncated GLB file".to_string())?;
+        match &kind.to_le_bytes() {
+            b"JSON" => json = Some(data),
+            b"BIN\0" => bin = Some(data),
+            _ => (),
+        }
+        offset += 8 + length;
+    }
+
+    Ok((
+        json.ok_or_else(|| "GLB file has no JSON chunk".to_string())?,
+        bin,
+    ))
+}
+
+struct GltfLoader<'a> {
+    gltf: GltfJson,
+    buffers: Vec<Vec<u8>>,
+    dir: &'a Path,
+    // Maps glTF images to the mesh's textures, and glTF materials to its groups
+    texture_indices: HashMap<usize, usize>,
+    group_indices: HashMap<Option<usize>, usize>,
+    mesh: Mesh,
+}
+
+impl GltfLoader<'_> {
+    fn buffer_view(&self, index: usize) -> Result<&[u8], String> {
+        let view = self
+            .gltf
+            .buffer_views
+            .get(index)
+            .ok_or_else(|| format!("Missing buffer view {}", index))?;
+        self.buffers
+            .get(view.buffer)
+            .and_then(|buffer| buffer.get(view.byte_offset..view.byte_offset + view.byte_length))
+            .ok_or_else(|| format!("Buffer view {} is out of range", index))
+    }
+
+    /// Returns the bytes of each element of an accessor along with its component type
+    fn accessor(&self, index: usize, expected_type: &str) -> Result<(u32, Vec<&[u8]>), String> {
+        let accessor = self
+            .gltf
+            .accessors
+            .get(index)
+            .ok_or_else(|| format!("Missing accessor {}", index))?;
+        if accessor.type_ != expected_type {
+            Err(format!(
+                "Accessor {} is a {}, expected a {}",
+                index, accessor.type_, expected_type
+            ))?
+        }
+        let view_index = accessor
+            .buffer_view
+            .ok_or_else(|| format!("Accessor {} has no buffer view", index))?;
+        let view = self.buffer_view(view_index)?;
+
+        let component_size = match accessor.component_type {
+            GLTF_COMPONENT_U8 => 1,
+            GLTF_COMPONENT_U16 => 2,
+            GLTF_COMPONENT_U32 | GLTF_COMPONENT_F32 => 4,
+            other => Err(format!(
+                "Accessor {} has unsupported component type {}",
+                index, other
+            ))?,
+        };
+        let component_count = match expected_type {
+            "SCALAR" => 1,
+            "VEC2" => 2,
+            _ => 3,
+        };
+        let element_size = component_size * component_count;
+        let stride = self.gltf.buffer_views[view_index]
+            .byte_stride
+            .unwrap_or(element_size);
+
+        let elements = (0..accessor.count)
+            .map(|i| {
+                let start = accessor.byte_offset + i * stride;
+                view.get(start..start + element_size)
+                    .ok_or_else(|| format!("Accessor {} is out of range", index))
+            })
+            .collect::<Result<_, _>>()?;
+        Ok((accessor.component_type, elements))
+    }
+
+    fn float_accessor<const N: usize>(&self, index: usize) -> Result<Vec<[f32; N]>, String> {
+        let expected_type = if N == 2 { "VEC2" } else { "VEC3" };
+        let (component_type, elements) = self.accessor(index, expected_type)?;
+        if component_type != GLTF_COMPONENT_F32 {
+            Err(format!("Accessor {} must contain floats", index))?
+        }
+        Ok(elements
+            .into_iter()
+            .map(|element| {
+                let mut values = [0.0; N];
+                for (value, bytes) in values.iter_mut().zip(element.chunks_exact(4)) {
+                    *value = f32::from_le_bytes(bytes.try_into().unwrap());
+                }
+                values
+            })
+            .collect())
+    }
+
+    fn index_accessor(&self, index: usize) -> Result<Vec<usize>, String> {
+        let (component_type, elements) = self.accessor(index, "SCALAR")?;
+        Ok(elements
+            .into_iter()
+            .map(|bytes| match component_type {
+                GLTF_COMPONENT_U8 => bytes[0] as usize,
+                GLTF_COMPONENT_U16 => u16::from_le_bytes(bytes.try_into().unwrap()) as usize,
+                _ => u32::from_le_bytes(bytes.try_into().unwrap()) as usize,
+            })
+            .collect())
+    }
+
+    fn texture(&mut self, material: Option<usize>) -> Result<Option<usize>, String> {
+        let image_index = material
+            .and_then(|material| self.gltf.materials.get(material))
+            .and_then(|material| material.pbr_metallic_roughness.as_ref())
+            .and_then(|pbr| pbr.base_color_texture.as_ref())
+            .and_then(|info| self.gltf.textures.get(info.index))
+            .and_then(|texture| texture.source);
+        let image_index = match image_index {
+            Some(image_index) => image_index,
+            None => return Ok(None),
+        };
+        if let Some(texture) = self.texture_indices.get(&image_index) {
+            return Ok(Some(*texture));
+        }
+
+        let image = self
+            .gltf
+            .images
+            .get(image_index)
+            .ok_or_else(|| format!("Missing image {}", image_index))?;
+        let bytes = match (&image.uri, image.buffer_view) {
+            (Some(uri), _) => load_gltf_uri(self.dir, uri)?,
+            (None, Some(view)) => self.buffer_view(view)?.to_vec(),
+            (None, None) => Err(format!("Image {} has no data", image_index))?,
+        };
+        self.mesh.textures.push(bytes);
+        let texture = self.mesh.textures.len() - 1;
+        self.texture_indices.insert(image_index, texture);
+        Ok(Some(texture))
+    }
+
+    fn add_mesh(&mut self, mesh_index: usize, transform: &Matrix4<f32>) -> Result<(), String> {
+        let normal_transform = transform
+            .fixed_view::<3, 3>(0, 0)
+            .try_inverse()
+            .ok_or_else(|| format!("Mesh {} has a degenerate transform", mesh_index))?
+            .transpose();
+        // Mirroring transforms flip the winding order
+        let mirrored = transform.fixed_view::<3, 3>(0, 0).determinant() < 0.0;
+
+        let primitive_count = self
+            .gltf
+            .meshes
+            .get(mesh_index)
+            .ok_or_else(|| format!("Missing mesh {}", mesh_index))?
+            .primitives
+            .len();
+        for primitive_index in 0..primitive_count {
+            let primitive = &self.gltf.meshes[mesh_index].primitives[primitive_index];
+            if primitive.mode.unwrap_or(GLTF_MODE_TRIANGLES) != GLTF_MODE_TRIANGLES {
+                Err(format!(
+                    "Mesh {} contains primitives which aren't triangle lists",
+                    mesh_index
+                ))?
+            }
+            let material = primitive.material;
+            let position_accessor = *primitive
+                .attributes
+                .get("POSITION")
+                .ok_or_else(|| format!("Mesh {} has no positions", mesh_index))?;
+            let normal_accessor = primitive.attributes.get("NORMAL").copied();
+            let tex_coord_accessor = primitive.attributes.get("TEXCOORD_0").copied();
+            let index_accessor = primitive.indices;
+
+            let positions = self.float_accessor::<3>(position_accessor)?;
+            let normals = normal_accessor
+                .map(|accessor| self.float_accessor::<3>(accessor))
+                .transpose()?;
+            let tex_coords = tex_coord_accessor
+                .map(|accessor| self.float_accessor::<2>(accessor))
+                .transpose()?;
+            let indices = match index_accessor {
+                Some(accessor) => self.index_accessor(accessor)?,
+                None => (0..positions.len()).collect(),
+            };
+            if indices.iter().any(|i| *i >= positions.len())
+                || normals.as_ref().is_some_and(|n| n.len() < positions.len())
+                || tex_coords
+                    .as_ref()
+                    .is_some_and(|t| t.len() < positions.len())
+            {
+                Err(format!("Mesh {} has out of range indices", mesh_index))?
+            }
+
+            let texture = self.texture(material)?;
+            let group = match self.group_indices.get(&material) {
+                Some(group) => *group,
+                None => {
+                    self.mesh.groups.push(MeshGroup {
+                        texture,
+                        triangles: vec![],
+                    });
+                    self.group_indices
+                        .insert(material, self.mesh.groups.len() - 1);
+                    self.mesh.groups.len() - 1
+                }
+            };
+
+            let mesh = &mut self.mesh;
+            let first_position = mesh.positions.len();
+            mesh.positions.extend(positions.iter().map(|p| {
+                let p = transform * Vector4::new(p[0], p[1], p[2], 1.0);
+                [p.x, p.y, p.z]
+            }));
+            let first_normal = mesh.normals.len();
+            if let Some(normals) = normals.as_ref() {
+                mesh.normals.extend(normals.iter().map(|n| {
+                    let n = normal_transform * Vector3::from(*n);
+                    let n = n.try_normalize(0.0).unwrap_or(n);
+                    [n.x, n.y, n.z]
+                }));
+            }
+            // glTF puts the origin of texture coordinates at the top left
+            let first_tex_coord = mesh.tex_coords.len();
+            match tex_coords.as_ref() {
+                Some(tex_coords) => mesh
+                    .tex_coords
+                    .extend(tex_coords.iter().map(|[u, v]| [*u, 1.0 - v])),
+                None => mesh.tex_coords.push([0.0, 0.0]),
+            }
+
+            for triangle in indices.chunks_exact(3) {
+                let mut triangle = [triangle[0], triangle[1], triangle[2]];
+                if mirrored {
+                    triangle.swap(1, 2);
+                }
+                let normal = match normals {
+                    Some(_) => None,
+                    None => {
+                        let normal = mesh.face_normal(triangle.map(|i| first_position + i));
+                        mesh.normals.push(normal);
+                        Some(mesh.normals.len() - 1)
+                    }
+                };
+                mesh.groups[group]
+                    .triangles
+                    .push(triangle.map(|i| MeshVertex {
+                        position: first_position + i,
+                        normal: normal.unwrap_or(first_normal + i),
+                        tex_coord: match tex_coords {
+                            Some(_) => first_tex_coord + i,
+                            None => first_tex_coord,
+                        },
+                    }));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn gltf_node_transform(node: &GltfNode) -> Matrix4<f32> {
+    if let Some(matrix) = node.matrix {
+        return Matrix4::from_column_slice(&matrix);
+    }
+    let [x, y, z] = node.translation.unwrap_or([0.0; 3]);
+    let [qx, qy, qz, qw] = node.rotation.unwrap_or([0.0, 0.0, 0.0, 1.0]);
+    let scale = node.scale.unwrap_or([1.0; 3]);
+    Matrix4::new_translation(&Vector3::new(x, y, z))
+        * UnitQuaternion::from_quaternion(Quaternion::new(qw, qx, qy, qz)).to_homogeneous()
+        * Matrix4::new_nonuniform_scaling(&Vector3::from(scale))
+}
+
+fn load_gltf(path: &Path) -> Result<Mesh, String> {
+    let bytes = fs::read(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    let dir = path.parent().unwrap_or_else(|| Path::new(""));
+
+    let (json, bin) = if bytes.starts_with(b"glTF") {
+        parse_glb(&bytes)?
+    } else {
+        (&bytes[..], None)
+    };
+    let gltf: GltfJson =
+        serde_json::from_slice(json).map_err(|e| format!("Failed to parse glTF: {}", e))?;
+
+    let buffers = gltf
+        .buffers
+        .iter()
+        .enumerate()
+        .map(|(i, buffer)| match (&buffer.uri, bin) {
+            (Some(uri), _) => load_gltf_uri(dir, uri),
+            // A GLB file's binary chunk is the first buffer
+            (None, Some(bin)) if i == 0 => Ok(bin.to_vec()),
+            (None, _) => Err(format!("Buffer {} has no data", i)),
+        })
+        .collect::<Result<_, _>>()?;
+
+    let roots = match gltf.scenes.get(gltf.scene.unwrap_or(0)) {
+        Some(scene) => scene.nodes.clone(),
+        None => {
+            let children: HashSet<usize> = gltf
+                .nodes
+                .iter()
+                .flat_map(|node| node.children.iter().copied())
+                .collect();
+            (0..gltf.nodes.len())
+                .filter(|i| !children.contains(i))
+                .collect()
+        }
+    };
+
+    let mut loader = GltfLoader {
+        gltf,
+        buffers,
+        dir,
+        texture_indices: HashMap::new(),
+        group_indices: HashMap::new(),
+        mesh: Mesh::default(),
+    };
+    let mut nodes: Vec<(usize, Matrix4<f32>)> = roots
+        .into_iter()
+        .map(|node| (node, Matrix4::identity()))
+        .collect();
+    while let Some((node_index, parent_transform)) = nodes.pop() {
+        let node = loader
+            .gltf
+            .nodes
+            .get(node_index)
+            .ok_or_else(|| format!("Missing node {}", node_index))?;
+        let transform = parent_transform * gltf_node_transform(node);
+        nodes.extend(node.children.iter().map(|child| (*child, transform)));
+        if let Some(mesh_index) = node.mesh {
+            loader.add_mesh(mesh_index, &transform)?;
+        }
+    }
+
+    Ok(loader.mesh)
+}
+
+/// Merges identical values, returning the unique values and the new index of each value
+fn dedup<const N: usize>(values: &[[f32; N]]) -> (Vec<[f32; N]>, Vec<usize>) {
+    let mut unique = vec![];
+    let mut indices: HashMap<[u32; N], usize> = HashMap::new();
+    let remap = values
+        .iter()
+        .map(|value| {
+            *indices.entry(value.map(f32::to_bits)).or_insert_with(|| {
+                unique.push(*value);
+                unique.len() - 1
+            })
+        })
+        .collect();
+    (unique, remap)
+}
+
+fn bounds<'a>(positions: impl Iterator<Item = &'a [f32; 3]>) -> ([f32; 3], [f32; 3]) {
+    let mut min = [f32::MAX; 3];
+    let mut max = [f32::MIN; 3];
+    for position in positions {
+        for axis in 0..3 {
+            min[axis] = min[axis].min(position[axis]);
+            max[axis] = max[axis].max(position[axis]);
+        }
+    }
+    (min, max)
+}
+
+fn material<'r>(texture: u32) -> CmdlMaterial<'r> {
+    CmdlMaterial {
+        flags: MATERIAL_FLAGS,
+        textures: vec![texture].into(),
+        vertex_attributes: MATERIAL_VERTEX_ATTRIBUTES,
+        group_index: 0,
+        konst_colors: None,
+        blend_dst_factor: 0,
+        blend_src_factor: 1,
+        indirect_texture: None,
+        color_channels: vec![0x3000].into(),
+        tev_stages: vec![CmdlTevStage {
+            color_input_flags: 0x7A14F,
+            alpha_input_flags: 0x21CE7,
+            color_combine_flags: 0x100,
+            alpha_combine_flags: 0x100,
+            padding: 0,
+            konst_alpha_input: 0,
+            konst_color_input: 0,
+            rasterized_color_input: 4,
+        }]
+        .into(),
+        tev_stage_inputs: vec![CmdlTevStageInput {
+            padding: 0,
+            texture: 0,
+            tex_coord: 0,
+        }]
+        .into(),
+        tex_gens: vec![0x1EBC40].into(),
+        uv_animations: vec![].into(),
+    }
+}
+
+fn build_cmdl<'r>(mesh: &Mesh, texture_ids: &[u32]) -> Result<Cmdl<'r>, String> {
+    let (positions, position_indices) = dedup(&mesh.positions);
+    let (normals, normal_indices) = dedup(&mesh.normals);
+    let (tex_coords, tex_coord_indices) = dedup(&mesh.tex_coords);
+    for (name, count) in [
+        ("positions", positions.len()),
+        ("normals", normals.len()),
+        ("texture coordinates", tex_coords.len()),
+    ] {
+        if count > u16::MAX as usize + 1 {
+            Err(format!(
+                "The mesh has {} unique {}, the most a CMDL can hold is {}",
+                count,
+                name,
+                u16::MAX as usize + 1
+            ))?
+        }
+    }
+
+    let mut surfaces = vec![];
+    for (material_index, group) in mesh.groups.iter().enumerate() {
+        for triangles in group.triangles.chunks(MAX_SURFACE_TRIANGLES) {
+            let (min, max) = bounds(
+                triangles
+                    .iter()
+                    .flatten()
+                    .map(|v| &mesh.positions[v.position]),
+            );
+            let normal: Vector3<f32> = triangles
+                .iter()
+                .map(|t| Vector3::from(mesh.face_normal(t.map(|v| v.position))))
+                .sum();
+            let vertices = triangles
+                .iter()
+                .flatten()
+                .map(|vertex| CmdlVertex {
+                    position: Some(position_indices[vertex.position] as u16),
+                    normal: Some(normal_indices[vertex.normal] as u16),
+                    tex_coords: [
+                        Some(tex_coord_indices[vertex.tex_coord] as u16),
+                        None,
+                        None,
+                        None,
+                        None,
+                        None,
+                        None,
+                        None,
+                    ],
+                    ..CmdlVertex::default()
+                })
+                .collect();
+
+            let mut surface = CmdlSurface {
+                center: [0, 1, 2].map(|axis| (min[axis] + max[axis]) / 2.0),
+                material_index: material_index as u32,
+                mantissa: 0x8000,
+                parent_model_pointer: 0,
+                next_surface_pointer: 0,
+                normal: normal.try_normalize(0.0).unwrap_or(normal).into(),
+                extra_data: vec![],
+                display_list: vec![],
+            };
+            surface.set_primitives(
+                MATERIAL_VERTEX_ATTRIBUTES,
+                &[CmdlPrimitive {
+                    kind: GxPrimitiveKind::Triangles,
+                    vertex_format: 0,
+                    vertices,
+                }],
+            )?;
+            surfaces.push(surface);
+        }
+    }
+
+    let (min, max) = bounds(positions.iter());
+    let mut cmdl = Cmdl {
+        flags: CMDL_FLAGS,
+        maab: GenericArray::clone_from_slice(&[min[0], min[1], min[2], max[0], max[1], max[2]]),
+        material_sets: vec![CmdlMaterialSet {
+            texture_ids: texture_ids
+                .iter()
+                .map(|id| ResId::new(*id))
+                .collect::<Vec<_>>()
+                .into(),
+            materials: mesh
+                .groups
+                .iter()
+                .map(|group| material(group.texture.unwrap() as u32))
+                .collect::<Vec<_>>()
+                .into(),
+        }]
+        .into(),
+        data_sections: vec![].into(),
+    };
+    cmdl.set_geometry(&CmdlGeometry {
+        positions,
+        normals: CmdlNormals::Float(normals),
+        colors: vec![],
+        tex_coords,
+        short_tex_coords: None,
+        surfaces,
+    });
+
+    Ok(cmdl)
+}
+
+fn build_ancs<'r>(name: &str, cmdl_id: u32, maab: [f32; 6]) -> Result<Ancs<'r>, String> {
+    let name = CString::new(name).map_err(|_| "The name can't contain NUL characters")?;
+    let animation_name = CString::new(ANIMATION_NAME).unwrap();
+    let empty = CString::new("").unwrap();
+
+    let char_info = CharacterInfo {
+        id: 0,
+        info_type_count: 6,
+        name: name.into(),
+        cmdl: ResId::new(cmdl_id),
+        cskr: ResId::new(SUIT_CSKR),
+        cinf: ResId::new(SUIT_CINF),
+        animation_names: vec![AnimationName {
+            index: 0,
+            unknown: Some(empty.clone().into()),
+            name: animation_name.clone().into(),
+        }]
+        .into(),
+        pas_database: PasDatabase {
+            default_state: 0,
+            anim_states: vec![].into(),
+        },
+        particles: ParticleResData {
+            part_assets: vec![].into(),
+            swhc_assets: vec![].into(),
+            unknowns: vec![].into(),
+            elsc_assets: Some(vec![].into()),
+        },
+        unknown0: 0,
+        unknown1: None,
+        unknown2: None,
+        animation_aabbs: Some(
+            vec![AnimationAABB {
+                name: animation_name.clone().into(),
+                aabb: GenericArray::clone_from_slice(&maab),
+            }]
+            .into(),
+        ),
+        effects: Some(vec![].into()),
+        overlay_cmdl: Some(ResId::invalid()),
+        overlay_cskr: Some(ResId::invalid()),
+        animation_indices: Some(vec![].into()),
+        unknown3: None,
+        unknown4: None,
+        animation_indexed_aabbs: None,
+    };
+
+    let anim_set = AnimationSet {
+        info_count: 4,
+        animations: vec![Animation {
+            name: animation_name.clone().into(),
+            meta: MetaAnimation::Play(Uncached::Owned(Box::new(MetaAnimationPlay {
+                anim: ResId::new(SUIT_ANIM),
+                index: 0,
+                name: animation_name.into(),
+                unknown0: 1.0,
+                unknown1: 0,
+            }))),
+        }]
+        .into(),
+        transitions: vec![].into(),
+        default_transition: MetaTransition::NoTransition,
+        additive_animations: vec![].into(),
+        fade_in: 0.0,
+        fade_out: 0.0,
+        half_transitions: Some(vec![].into()),
+        animation_resources: Some(
+            vec![AnimationResource {
+                anim: ResId::new(SUIT_ANIM),
+                evnt: ResId::invalid(),
+            }]
+            .into(),
+        ),
+    };
+
+    Ok(Ancs {
+        char_set: CharacterSet {
+            char_info: vec![char_info].into(),
+        },
+        anim_set,
+    })
+}
+
+struct NewAsset {
+    id: u32,
+    fourcc: &'static str,
+    dependencies: Vec<(u32, &'static str)>,
+    bytes: Vec<u8>,
+}
+
+impl NewAsset {
+    fn new<T: Writable>(
+        id: u32,
+        fourcc: &'static str,
+        dependencies: Vec<(u32, &'static str)>,
+        resource: &T,
+    ) -> Result<Self, String> {
+        let mut bytes = vec![];
+        resource
+            .write_to(&mut bytes)
+            .map_err(|e| format!("Error writing {}: {}", fourcc, e))?;
+        bytes.resize(reader_writer::align_byte_count(32, bytes.len()), 0);
+        Ok(NewAsset {
+            id,
+            fourcc,
+            dependencies,
+            bytes,
+        })
+    }
+
+    fn file_name(&self) -> String {
+        format!("{}.{}", self.id, self.fourcc)
+    }
+}
+
+/// Every asset id used by the items in `manifest`, except for the item called `name`
+fn ids_used_by_other_items(manifest: &Value, name: &str) -> HashMap<u64, String> {
+    let mut used = HashMap::new();
+    let items = manifest["items"].as_object().into_iter().flatten();
+    for (item_name, item) in items.filter(|(item_name, _)| *item_name != name) {
+        let mut ids: Vec<u64> = ["ancs", "cmdl"]
+            .iter()
+            .filter_map(|key| item[key].as_u64())
+            .collect();
+        while let Some(id) = ids.pop() {
+            if used.insert(id, item_name.clone()).is_some() {
+                continue;
+            }
+            let assets = manifest["assets"].as_array().into_iter().flatten();
+            for asset in assets.filter(|asset| asset["id"].as_u64() == Some(id)) {
+                let dependencies = asset["dependencies"].as_array().into_iter().flatten();
+                ids.extend(dependencies.filter_map(|dep| dep["id"].as_u64()));
+            }
+        }
+    }
+    used
+}
+
+fn convert(
+    input: &Path,
+    output: &Path,
+    name: &str,
+    first_id: u32,
+    scale: f32,
+    fallback_texture: Option<&Path>,
+    z_up: bool,
+) -> Result<(), String> {
+    let extension = input
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_ascii_lowercase());
+    let mut mesh = match extension.as_deref() {
+        Some("obj") => load_obj(input)?,
+        Some("gltf") | Some("glb") => load_gltf(input)?,
+        _ => Err("The input must be an OBJ, glTF or GLB file")?,
+    };
+    if !z_up {
+        mesh.convert_to_z_up();
+    }
+
+    mesh.groups.retain(|group| !group.triangles.is_empty());
+    if mesh.groups.is_empty() {
+        Err("The mesh contains no triangles")?
+    }
+    if mesh.groups.iter().any(|group| group.texture.is_none()) {
+        let path = fallback_texture.ok_or(
+            "Parts of the mesh have no texture, specify one to use for them with --texture",
+        )?;
+        let texture = mesh.add_texture(path)?;
+        for group in mesh.groups.iter_mut() {
+            group.texture.get_or_insert(texture);
+        }
+    }
+
+    // Only keep the textures that are used, in the order they're first used
+    let mut used_textures: Vec<usize> = vec![];
+    for group in mesh.groups.iter_mut() {
+        let texture = group.texture.unwrap();
+        let slot = match used_textures.iter().position(|t| *t == texture) {
+            Some(slot) => slot,
+            None => {
+                used_textures.push(texture);
+                used_textures.len() - 1
+            }
+        };
+        group.texture = Some(slot);
+    }
+
+    let id_count = used_textures.len() as u32 + 2;
+    let ids = match first_id.checked_add(id_count) {
+        Some(end) => first_id..end,
+        None => Err(format!("--id is too large to fit {} assets", id_count))?,
+    };
+    if ids.start < RESERVED_ASSET_IDS.end && RESERVED_ASSET_IDS.start < ids.end {
+        Err(format!(
+            "Asset ids 0x{:08X} to 0x{:08X} are reserved for the patcher, choose a different --id",
+            RESERVED_ASSET_IDS.start,
+            RESERVED_ASSET_IDS.end - 1
+        ))?
+    }
+    let texture_ids: Vec<u32> = (first_id..).take(used_textures.len()).collect();
+    let cmdl_id = first_id + used_textures.len() as u32;
+    let ancs_id = cmdl_id + 1;
+
+    let mut assets = vec![];
+    for (id, texture) in texture_ids.iter().zip(used_textures.iter()) {
+        let txtr = png_to_cmpr_txtr(&mesh.textures[*texture], CmprQuality::Best)
+            .map_err(|e| format!("Texture {}: {}", texture, e))?;
+        assets.push(NewAsset::new(*id, "TXTR", vec![], &txtr)?);
+    }
+    let cmdl = build_cmdl(&mesh, &texture_ids)?;
+    let maab: Vec<f32> = cmdl.maab.iter().copied().collect();
+    assets.push(NewAsset::new(
+        cmdl_id,
+        "CMDL",
+        texture_ids.iter().map(|id| (*id, "TXTR")).collect(),
+        &cmdl,
+    )?);
+    let ancs = build_ancs(name, cmdl_id, maab.try_into().unwrap())?;
+    assets.push(NewAsset::new(
+        ancs_id,
+        "ANCS",
+        vec![(cmdl_id, "CMDL")],
+        &ancs,
+    )?);
+
+    fs::create_dir_all(output)
+        .map_err(|e| format!("Failed to create {}: {}", output.display(), e))?;
+    let manifest_path = output.join(PACKAGE_MANIFEST);
+    let mut manifest = if manifest_path.exists() {
+        let text = fs::read_to_string(&manifest_path)
+            .map_err(|e| format!("Failed to read {}: {}", manifest_path.display(), e))?;
+        serde_json::from_str(&text)
+            .map_err(|e| format!("Failed to parse {}: {}", manifest_path.display(), e))?
+    } else if output.join("meta.json").exists() {
+        Err(format!(
+            "{} holds extern assets in the legacy meta.json format, write the model to a new \
+             directory instead",
+            output.display()
+        ))?
+    } else {
+        json!({ "schema_version": PACKAGE_SCHEMA_VERSION, "items": {}, "assets": [] })
+    };
+    match manifest["schema_version"].as_u64() {
+        Some(version) if version <= PACKAGE_SCHEMA_VERSION as u64 => (),
+        _ => Err(format!(
+            "{} has a schema version this converter doesn't support",
+            manifest_path.display()
+        ))?,
+    }
+
+    let used_ids = ids_used_by_other_items(&manifest, name);
+    for asset in assets.iter() {
+        if let Some(item_name) = used_ids.get(&(asset.id as u64)) {
+            Err(format!(
+                "Asset id {} is already used by '{}', choose a different --id",
+                asset.id, item_name
+            ))?
+        }
+    }
+
+    for asset in assets.iter() {
+        let path = output.join(asset.file_name());
+        fs::write(&path, &asset.bytes)
+            .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+    }
+
+    if !manifest["items"].is_object() {
+        manifest["items"] = json!({});
+    }
+    manifest["items"][name] = json!({
+        "ancs": ancs_id,
+        "cmdl": cmdl_id,
+        "scale": scale,
+        "character": 0,
+    });
+    let mut manifest_assets: Vec<Value> = manifest["assets"]
+        .as_array()
+        .cloned()
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|asset| {
+            !assets
+                .iter()
+                .any(|new| asset["id"].as_u64() == Some(new.id as u64))
+        })
+        .collect();
+    manifest_assets.extend(assets.iter().map(|asset| {
+        json!({
+            "id": asset.id,
+            "type": asset.fourcc,
+            "file": asset.file_name(),
+            "dependencies": asset
+                .dependencies
+                .iter()
+                .map(|(id, fourcc)| json!({ "type": fourcc, "id": id }))
+                .collect::<Vec<_>>(),
+        })
+    }));
+    manifest["assets"] = Value::Array(manifest_assets);
+
+    let text = serde_json::to_string_pretty(&manifest)
+        .map_err(|e| format!("Failed to serialize {}: {}", PACKAGE_MANIFEST, e))?;
+    fs::write(&manifest_path, text)
+        .map_err(|e| format!("Failed to write {}: {}", manifest_path.display(), e))?;
+
+    Ok(())
+}
+
+fn parse_id(s: &str) -> Result<u32, String> {
+    match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => u32::from_str_radix(hex, 16),
+        None => s.parse(),
+    }
+    .map_err(|_| format!("Invalid asset id \"{}\"", s))
+}
+
+fn main() {
+    let app = clap_app!(app =>
+        (version: crate_version!())
+        (author: crate_authors!())
+        (about: "Converts an OBJ or glTF model to the CMDL, TXTR and ANCS files of an extern \
+                 pickup model, and adds it to the asset package in the directory.")
+        (@setting ArgRequiredElseHelp)
+        (@arg input: -i --input +takes_value +required
+            "Input model to convert. Accepted formats are OBJ (.obj), glTF (.gltf) and binary \
+             glTF (.glb). Textures must be PNG images with power of two dimensions.")
+        (@arg output: -o --output +takes_value +required
+            "Directory of the extern asset package to write the model to. It is created, along \
+             with its manifest.json, if it doesn't exist.")
+        (@arg name: -n --name +takes_value +required
+            "Name of the model, which pickups use as their `model`.")
+        (@arg id: --id +takes_value +required
+            { |s| parse_id(&s).map(|_| ()) }
+            "Asset id of the first texture, the other assets use the ids following it. \
+             Accepts decimal and 0x-prefixed hexadecimal values.")
+        (@arg scale: -s --scale +takes_value
+            { |s| s.parse::<f32>()
+                .map(|_| ())
+                    .map_err(|_| "Expected a number for scale".into()) }
+            "Scale the model is displayed at. Defaults to 1."
+        )
+        (@arg texture: -t --texture +takes_value
+            "PNG texture for the parts of the model without one of their own.")
+        (@arg z_up: --z_up
+            "The model is Z-up, like the game. By default it is assumed to be Y-up, which is \
+             what most tools export.")
+    );
+    let matches = app.get_matches();
+
+    let res = convert(
+        matches.value_of("input").unwrap().as_ref(),
+        matches.value_of("output").unwrap().as_ref(),
+        matches.value_of("name").unwrap(),
+        parse_id(matches.value_of("id").unwrap()).unwrap(),
+        matches
+            .value_of("scale")
+            .map(|s| s.parse().unwrap())
+            .unwrap_or(1.0),
+        matches.value_of("texture").map(Path::new),
+        matches.is_present("z_up"),
+    );
+    if let Err(s) = res {
+        eprintln!("{} {}", clap::Format::Error("error:"), s);
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,798 @@
+use std::{borrow::Cow, collections::HashMap, fs, path::Path};
+
+use clap::{clap_app, crate_authors, crate_version};
+use image::codecs::png::PngEncoder;
+use nalgebra::UnitQuaternion;
+use randomprime::{
+    elevators::World,
+    room_lookup::ROOM_BY_NAME,
+    txtr_conversions::{decode_txtr_mipmap, TxtrFormatExt},
+    GcDiscLookupExtensions, ResourceData,
+};
+use reader_writer::{Reader, Writable};
+use serde_json::{json, Value};
+use structs::{
+    Cmdl, CmdlGeometry, CmdlMaterial, CmdlMaterialSet, CmdlNormals, CmdlVertex, Mrea, Txtr,
+    CMDL_FLAG_SHORT_NORMALS, CMDL_FLAG_SHORT_UVS,
+};
+
+// Material flags for how a material is blended, whether its first texture is a lightmap, and
+// whether its first texture coordinates are read from the geometry's short UVs
+const MATERIAL_FLAG_TRANSPARENT: u32 = 0x10;
+const MATERIAL_FLAG_PUNCHTHROUGH: u32 = 0x20;
+const MATERIAL_FLAG_LIGHTMAP: u32 = 0x800;
+const MATERIAL_FLAG_SHORT_UVS: u32 = 0x2000;
+
+// Tex gen sources 4 to 11 are the vertex texture coordinates, the others are generated from the
+// position or normal
+const TEX_GEN_SOURCE_TEX0: u32 = 4;
+const TEX_GEN_SOURCE_TEX7: u32 = 11;
+
+// Size of the octahedron drawn at each script object
+const MARKER_RADIUS: f32 = 0.5;
+
+const GLTF_FLOAT: u32 = 5126;
+const GLTF_UNSIGNED_INT: u32 = 5125;
+const GLTF_ARRAY_BUFFER: u32 = 34962;
+const GLTF_ELEMENT_ARRAY_BUFFER: u32 = 34963;
+
+/// Builds a glTF document with all its binary data in a single buffer
+#[derive(Default)]
+struct GltfBuilder {
+    nodes: Vec<Value>,
+    meshes: Vec<Value>,
+    materials: Vec<Value>,
+    textures: Vec<Value>,
+    images: Vec<Value>,
+    accessors: Vec<Value>,
+    buffer_views: Vec<Value>,
+    buffer: Vec<u8>,
+
+    // Materials are shared between meshes, keyed by TXTR id and whether they are blended
+    textured_materials: HashMap<(u32, bool), usize>,
+    untextured_material: Option<usize>,
+    marker_mesh: Option<usize>,
+}
+
+impl GltfBuilder {
+    fn add_buffer_view(&mut self, data: &[u8], target: Option<u32>) -> usize {
+        self.buffer.resize((self.buffer.len() + 3) & !3, 0);
+        let mut view = json!({
+            "buffer": 0,
+            "byteOffset": self.buffer.len(),
+            "byteLength": data.len(),
+        });
+        if let Some(target) = target {
+            view["target"] = json!(target);
+        }
+        self.buffer.extend_from_slice(data);
+        self.buffer_views.push(view);
+        self.buffer_views.len() - 1
+    }
+
+    fn add_float_accessor<const N: usize>(&mut self, items: &[[f32; N]], bounds: bool) -> usize {
+        let data: Vec<u8> = items
+            .iter()
+            .flatten()
+            .flat_map(|f| f.to_le_bytes())
+            .collect();
+        let view = self.add_buffer_view(&data, Some(GLTF_ARRAY_BUFFER));
+        let mut accessor = json!({
+            "bufferView": view,
+            "componentType": GLTF_FLOAT,
+            "count": items.len(),
+            "type": format!("VEC{}", N),
+        });
+        if bounds {
+            let mut min = [f32::MAX; N];
+            let mut max = [f32::MIN; N];
+            for item in items {
+                for i in 0..N {
+                    min[i] = min[i].min(item[i]);
+                    max[i] = max[i].max(item[i]);
+                }
+            }
+            accessor["min"] = json!(min.to_vec());
+            accessor["max"] = json!(max.to_vec());
+        }
+        self.accessors.push(accessor);
+        self.accessors.len() - 1
+    }
+
+    fn add_index_accessor(&mut self, indices: &[u32]) -> usize {
+        let data: Vec<u8> = indices.iter().flat_map(|i| i.to_le_bytes()).collect();
+        let view = self.add_buffer_view(&data, Some(GLTF_ELEMENT_ARRAY_BUFFER));
+        self.accessors.push(json!({
+            "bufferView": view,
+            "componentType": GLTF_UNSIGNED_INT,
+            "count": indices.len(),
+            "type": "SCALAR",
+        }));
+        self.accessors.len() - 1
+    }
+
+    fn add_node(&mut self, node: Value) -> usize {
+        self.nodes.push(node);
+        self.nodes.len() - 1
+    }
+
+    /// The material for surfaces showing `txtr_id`. Textures which can't be found are replaced
+    /// by a plain white material, so the geometry can still be inspected.
+    fn material(
+        &mut self,
+        txtr_id: Option<u32>,
+        alpha_mode: &str,
+        textures: &TextureLookup,
+    ) -> Result<usize, String> {
+        let txtr_id = match txtr_id {
+            Some(txtr_id) if textures.contains_key(&txtr_id) => txtr_id,
+            _ => {
+                if let Some(material) = self.untextured_material {
+                    return Ok(material);
+                }
+                self.materials.push(json!({
+                    "name": "untextured",
+                    "pbrMetallicRoughness": { "metallicFactor": 0.0 },
+                }));
+                self.untextured_material = Some(self.materials.len() - 1);
+                return Ok(self.materials.len() - 1);
+            }
+        };
+        let blend = alpha_mode != "OPAQUE";
+        if let Some(material) = self.textured_materials.get(&(txtr_id, blend)) {
+            return Ok(*material);
+        }
+
+        let txtr: Txtr = Reader::new(&textures[&txtr_id]).read(());
+        let pixels = decode_txtr_mipmap(&txtr, 0);
+        let mut png = vec![];
+        PngEncoder::new(&mut png)
+            .encode(
+                &pixels,
+                txtr.width as u32,
+                txtr.height as u32,
+                txtr.format.color_type(),
+            )
+            .map_err(|e| format!("Failed to encode texture 0x{:08X}: {}", txtr_id, e))?;
+        let view = self.add_buffer_view(&png, None);
+        self.images.push(json!({
+            "name": format!("{:08X}.TXTR", txtr_id),
+            "bufferView": view,
+            "mimeType": "image/png",
+        }));
+        self.textures
+            .push(json!({ "source": self.images.len() - 1 }));
+        self.materials.push(json!({
+            "name": format!("{:08X}", txtr_id),
+            "pbrMetallicRoughness": {
+                "baseColorTexture": { "index": self.textures.len() - 1 },
+                "metallicFactor": 0.0,
+            },
+            "alphaMode": alpha_mode,
+        }));
+        self.textured_materials
+            .insert((txtr_id, blend), self.materials.len() - 1);
+        Ok(self.materials.len() - 1)
+    }
+
+    /// A small octahedron marking where a script object is
+    fn marker_mesh(&mut self) -> usize {
+        if let Some(mesh) = self.marker_mesh {
+            return mesh;
+        }
+
+        let r = MARKER_RADIUS;
+        let positions = [
+            [r, 0.0, 0.0],
+            [-r, 0.0, 0.0],
+            [0.0, r, 0.0],
+            [0.0, -r, 0.0],
+            [0.0, 0.0, r],
+            [0.0, 0.0, -r],
+        ];
+        let indices = [
+            0, 2, 4, 2, 1, 4, 1, 3, 4, 3, 0, 4, 2, 0, 5, 1, 2, 5, 3, 1, 5, 0, 3, 5,
+        ];
+        let positions = self.add_float_accessor(&positions, true);
+        let indices = self.add_index_accessor(&indices);
+        self.materials.push(json!({
+            "name": "script object",
+            "pbrMetallicRoughness": {
+                "baseColorFactor": [1.0, 0.5, 0.0, 1.0],
+                "metallicFactor": 0.0,
+            },
+        }));
+        self.meshes.push(json!({
+            "name": "script object",
+            "primitives": [{
+                "attributes": { "POSITION": positions },
+                "indices": indices,
+                "material": self.materials.len() - 1,
+            }],
+        }));
+        self.marker_mesh = Some(self.meshes.len() - 1);
+        self.meshes.len() - 1
+    }
+
+    /// Adds a mesh with a primitive for each material used by `geometry`'s surfaces. Returns
+    /// `None` when there are no triangles to draw.
+    fn add_mesh(
+        &mut self,
+        name: &str,
+        geometry: &CmdlGeometry,
+        material_set: &CmdlMaterialSet,
+        textures: &TextureLookup,
+    ) -> Result<Option<usize>, String> {
+        let materials: Vec<CmdlMaterial> = material_set
+            .materials
+            .iter()
+            .map(|material| material.into_owned())
+            .collect();
+        let texture_ids: Vec<u32> = material_set
+            .texture_ids
+            .iter()
+            .map(|id| id.to_u32())
+            .collect();
+
+        let mut builders: Vec<(u32, PrimitiveBuilder)> = vec![];
+        for surface in geometry.surfaces.iter() {
+            let material = materials
+                .get(surface.material_index as usize)
+                .ok_or_else(|| {
+                    format!(
+                        "{} has a surface using material {}, but there are only {}",
+                        name,
+                        surface.material_index,
+                        materials.len()
+                    )
+                })?;
+            let primitives = surface
+                .primitives(material.vertex_attributes)
+                .map_err(|e| format!("Failed to decode a surface of {}: {}", name, e))?;
+
+            let index = match builders
+                .iter()
+                .position(|(index, _)| *index == surface.material_index)
+            {
+                Some(index) => index,
+                None => {
+                    builders.push((surface.material_index, PrimitiveBuilder::default()));
+                    builders.len() - 1
+                }
+            };
+            let builder = &mut builders[index].1;
+            let uv_source = material_texture(material).map(|(_, uv)| uv);
+            let short_uvs = material.flags & MATERIAL_FLAG_SHORT_UVS != 0;
+            for primitive in primitives.iter() {
+                for triangle in primitive.triangles() {
+                    for vertex in triangle {
+                        builder
+                            .add_vertex(geometry, vertex, uv_source, short_uvs)
+                            .map_err(|e| format!("Invalid surface in {}: {}", name, e))?;
+                    }
+                }
+            }
+        }
+
+        let mut primitives = vec![];
+        for (material_index, builder) in builders {
+            if builder.indices.is_empty() {
+                continue;
+            }
+            let material = &materials[material_index as usize];
+            let txtr_id = material_texture(material)
+                .and_then(|(texture, _)| material.textures.iter().nth(texture))
+                .and_then(|texture| texture_ids.get(*texture as usize).copied());
+            let alpha_mode = if material.flags & MATERIAL_FLAG_TRANSPARENT != 0 {
+                "BLEND"
+            } else if material.flags & MATERIAL_FLAG_PUNCHTHROUGH != 0 {
+                "MASK"
+            } else {
+                "OPAQUE"
+            };
+
+            let mut attributes = json!({
+                "POSITION": self.add_float_accessor(&builder.positions, true),
+            });
+            if let Some(normals) = &builder.normals {
+                attributes["NORMAL"] = json!(self.add_float_accessor(normals, false));
+            }
+            if txtr_id.is_some() {
+                attributes["TEXCOORD_0"] =
+                    json!(self.add_float_accessor(&builder.tex_coords, false));
+            }
+            primitives.push(json!({
+                "attributes": attributes,
+                "indices": self.add_index_accessor(&builder.indices),
+                "material": self.material(txtr_id, alpha_mode, textures)?,
+            }));
+        }
+
+        if primitives.is_empty() {
+            return Ok(None);
+        }
+        self.meshes.push(json!({
+            "name": name,
+            "primitives": primitives,
+        }));
+        Ok(Some(self.meshes.len() - 1))
+    }
+
+    fn write(self, path: &Path, root_nodes: Vec<usize>) -> Result<(), String> {
+        let binary = path
+            .extension()
+            .map(|ext| ext.eq_ignore_ascii_case("glb"))
+            .unwrap_or(false);
+        let bin_path = path.with_extension("bin");
+
+        let mut buffer = json!({ "byteLength": self.buffer.len() });
+        if !binary {
+            buffer["uri"] = json!(bin_path.file_name().unwrap().to_str().unwrap());
+        }
+        let mut document = json!({
+            "asset": { "version": "2.0", "generator": "randomprime model_exporter" },
+            "scene": 0,
+            "scenes": [{ "nodes": root_nodes }],
+            "nodes": self.nodes,
+            "meshes": self.meshes,
+            "materials": self.materials,
+            "accessors": self.accessors,
+            "bufferViews": self.buffer_views,
+            "buffers": [buffer],
+        });
+        // glTF doesn't allow empty arrays
+        if !self.images.is_empty() {
+            document["images"] = json!(self.images);
+            document["textures"] = json!(self.textures);
+        }
+        let json = serde_json::to_vec(&document).unwrap();
+
+        if binary {
+            let mut json = json;
+            json.resize((json.len() + 3) & !3, b' ');
+            let mut bin = self.buffer;
+            bin.resize((bin.len() + 3) & !3, 0);
+
+            let mut glb = vec![];
+            glb.extend_from_slice(b"glTF");
+            glb.extend_from_slice(&2u32.to_le_bytes());
+            glb.extend_from_slice(
+                &(12 + 8 + json.len() as u32 + 8 + bin.len() as u32).to_le_bytes(),
+            );
+            glb.extend_from_slice(&(json.len() as u32).to_le_bytes());
+            glb.extend_from_slice(b"JSON");
+            glb.extend_from_slice(&json);
+            glb.extend_from_slice(&(bin.len() as u32).to_le_bytes());
+            glb.extend_from_slice(b"BIN\0");
+            glb.extend_from_slice(&bin);
+            fs::write(path, glb)
+                .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+        } else {
+            fs::write(path, json)
+                .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+            fs::write(&bin_path, self.buffer)
+                .map_err(|e| format!("Failed to write {}: {}", bin_path.display(), e))?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Vertices of one glTF primitive, deduplicated by the attribute indices they are built from
+#[derive(Default)]
+struct PrimitiveBuilder {
+    positions: Vec<[f32; 3]>,
+    // None once a vertex without a normal is added
+    normals: Option<Vec<[f32; 3]>>,
+    tex_coords: Vec<[f32; 2]>,
+    indices: Vec<u32>,
+    vertices: HashMap<(u16, Option<u16>, Option<u16>), u32>,
+}
+
+impl PrimitiveBuilder {
+    fn add_vertex(
+        &mut self,
+        geometry: &CmdlGeometry,
+        vertex: &CmdlVertex,
+        uv_source: Option<usize>,
+        short_uvs: bool,
+    ) -> Result<(), String> {
+        let position = vertex
+            .position
+            .ok_or_else(|| "vertex without a position".to_string())?;
+        let tex_coord = uv_source.and_then(|uv| vertex.tex_coords[uv]);
+        let key = (position, vertex.normal, tex_coord);
+        if let Some(index) = self.vertices.get(&key) {
+            self.indices.push(*index);
+            return Ok(());
+        }
+
+        let out_of_range = |kind: &str, index: u16| format!("{} {} is out of range", kind, index);
+        let position = *geometry
+            .positions
+            .get(position as usize)
+            .ok_or_else(|| out_of_range("position", position))?;
+        let normal = match vertex.normal {
+            Some(index) => Some(
+                match &geometry.normals {
+                    CmdlNormals::Float(normals) => normals.get(index as usize).copied(),
+                    CmdlNormals::Short(normals) => normals
+                        .get(index as usize)
+                        .map(|n| n.map(|f| f as f32 / 32768.0)),
+                }
+                .ok_or_else(|| out_of_range("normal", index))?,
+            ),
+            None => None,
+        };
+        let tex_coord = match (uv_source, tex_coord) {
+            // Only a material's first texture coordinates can use the short UVs
+            (Some(0), Some(index)) if short_uvs && geometry.short_tex_coords.is_some() => geometry
+                .short_tex_coords
+                .as_ref()
+                .unwrap()
+                .get(index as usize)
+                .map(|uv| uv.map(|f| f as f32 / 32768.0)),
+            (_, Some(index)) => geometry.tex_coords.get(index as usize).copied(),
+            (_, None) => Some([0.0, 0.0]),
+        }
+        .ok_or_else(|| out_of_range("texture coordinate", tex_coord.unwrap()))?;
+
+        if self.positions.is_empty() {
+            self.normals = normal.map(|_| vec![]);
+        }
+        match (&mut self.normals, normal) {
+            (Some(normals), Some(normal)) => {
+                let len = normal.iter().map(|f| f * f).sum::<f32>().sqrt();
+                normals.push(if len > 0.0 {
+                    normal.map(|f| f / len)
+                } else {
+                    [0.0, 0.0, 1.0]
+                });
+            }
+            (normals, _) => *normals = None,
+        }
+        self.positions.push(position);
+        // TXTRs are stored bottom row first, glTF textures top row first
+        self.tex_coords.push([tex_coord[0], 1.0 - tex_coord[1]]);
+
+        let index = self.positions.len() as u32 - 1;
+        self.vertices.insert(key, index);
+        self.indices.push(index);
+        Ok(())
+    }
+}
+
+/// Picks the texture shown for a material, as an index into its textures along with the
+/// vertex texture coordinates it is mapped with. Lightmaps and textures mapped from the
+/// position or normal, like reflections, are skipped.
+fn material_texture(material: &CmdlMaterial) -> Option<(usize, usize)> {
+    let tex_gens: Vec<u32> = material.tex_gens.iter().map(|t| *t).collect();
+    let mut skip_lightmap = material.flags & MATERIAL_FLAG_LIGHTMAP != 0;
+    for input in material.tev_stage_inputs.iter() {
+        if input.texture == 0xFF {
+            continue;
+        }
+        // The lightmap is always sampled first
+        if skip_lightmap {
+            skip_lightmap = false;
+            continue;
+        }
+        let source = match tex_gens.get(input.tex_coord as usize) {
+            Some(tex_gen) => (tex_gen >> 4) & 0x1F,
+            None => continue,
+        };
+        if (TEX_GEN_SOURCE_TEX0..=TEX_GEN_SOURCE_TEX7).contains(&source) {
+            return Some((
+                input.texture as usize,
+                (source - TEX_GEN_SOURCE_TEX0) as usize,
+            ));
+        }
+    }
+    None
+}
+
+type TextureLookup<'a> = HashMap<u32, Cow<'a, [u8]>>;
+
+fn pak_textures<'a>(pak: &structs::Pak<'a>) -> TextureLookup<'a> {
+    pak.resources
+        .iter()
+        .filter(|res| res.fourcc() == b"TXTR".into())
+        .map(|res| {
+            (
+                res.file_id,
+                ResourceData::new(&res).decompress().into_owned().into(),
+            )
+        })
+        .collect()
+}
+
+fn read_pak<'a>(entry: &structs::FstEntry<'a>) -> Option<structs::Pak<'a>> {
+    match entry.file()? {
+        structs::FstEntryFile::Pak(pak) => Some(pak.clone()),
+        structs::FstEntryFile::Unknown(reader) => Some(reader.clone().read(())),
+        _ => None,
+    }
+}
+
+fn read_disc(input: &Path) -> Result<memmap::Mmap, String> {
+    let file =
+        fs::File::open(input).map_err(|e| format!("Failed to open {}: {}", input.display(), e))?;
+    unsafe { memmap::Mmap::map(&file) }
+        .map_err(|e| format!("Failed to map {}: {}", input.display(), e))
+}
+
+fn export_cmdl(input: &Path, output: &Path, cmdl_id: u32) -> Result<(), String> {
+    let mmap = read_disc(input)?;
+    let gc_disc: structs::GcDisc = Reader::new(&mmap[..]).read(());
+
+    let pak_entries = gc_disc.file_system_root.dir_entries().unwrap();
+    for entry in pak_entries {
+        let name = entry.name().to_str().unwrap().to_lowercase();
+        if !name.ends_with(".pak") {
+            continue;
+        }
+        let pak = match read_pak(entry) {
+            Some(pak) => pak,
+            None => continue,
+        };
+        let res = match pak
+            .resources
+            .iter()
+            .find(|res| res.fourcc() == b"CMDL".into() && res.file_id == cmdl_id)
+        {
+            Some(res) => res.into_owned(),
+            None => continue,
+        };
+
+        let bytes = ResourceData::new(&res).decompress();
+        let cmdl: Cmdl = Reader::new(&bytes).read(());
+        let material_set = cmdl
+            .material_sets
+            .iter()
+            .next()
+            .ok_or_else(|| format!("CMDL 0x{:08X} has no materials", cmdl_id))?;
+        let textures = pak_textures(&pak);
+
+        let mut gltf = GltfBuilder::default();
+        let name = format!("{:08X}.CMDL", cmdl_id);
+        let mesh = gltf
+            .add_mesh(&name, &cmdl.geometry(), &material_set, &textures)?
+            .ok_or_else(|| format!("CMDL 0x{:08X} has no triangles", cmdl_id))?;
+        let node = gltf.add_node(json!({ "name": name, "mesh": mesh }));
+        let root = gltf.add_node(z_up_root(vec![node]));
+        return gltf.write(output, vec![root]);
+    }
+
+    Err(format!("CMDL 0x{:08X} is not in any pak", cmdl_id))
+}
+
+/// The node converting the game's Z-up coordinates to glTF's Y-up ones. Its descendants'
+/// transforms are left in game coordinates.
+fn z_up_root(children: Vec<usize>) -> Value {
+    let half = std::f32::consts::FRAC_1_SQRT_2;
+    json!({
+        "name": "Z-up",
+        "rotation": [-half, 0.0, 0.0, half],
+        "children": children,
+    })
+}
+
+fn export_room(
+    input: &Path,
+    output: &Path,
+    world_name: &str,
+    room_name: &str,
+    export_objects: bool,
+) -> Result<(), String> {
+    let room = ROOM_BY_NAME
+        .get(&(world_name.to_string(), room_name.to_string()))
+        .ok_or_else(|| format!("'{}' is not a room in '{}'", room_name, world_name))?;
+    let pak_name = World::from_json_key(room.region_name).to_pak_str();
+
+    let mmap = read_disc(input)?;
+    let gc_disc: structs::GcDisc = Reader::new(&mmap[..]).read(());
+    let pak = gc_disc
+        .find_file(pak_name)
+        .and_then(read_pak)
+        .ok_or_else(|| format!("Failed to find {}", pak_name))?;
+
+    let mrea_res = pak
+        .resources
+        .iter()
+        .find(|res| res.fourcc() == b"MREA".into() && res.file_id == room.mrea_id)
+        .ok_or_else(|| format!("Failed to find the MREA of {}", room_name))?
+        .into_owned();
+    let mrea_bytes = ResourceData::new(&mrea_res).decompress();
+    let mrea: Mrea = Reader::new(&mrea_bytes).read(());
+
+    let mut gltf = GltfBuilder::default();
+    let textures = pak_textures(&pak);
+    let mut root_children = vec![];
+
+    // The geometry starts with the material set shared by every world model, then each model is
+    // a header followed by the same sections as a CMDL's geometry
+    let sections: Vec<Vec<u8>> = mrea
+        .sections
+        .iter()
+        .map(|section| {
+            let mut data = vec![];
+            section.write_to(&mut data).unwrap();
+            data
+        })
+        .collect();
+    let mut sections = sections[mrea.world_geometry_section_idx as usize..]
+        .iter()
+        .map(|section| &section[..]);
+    let material_set_section = sections
+        .next()
+        .ok_or_else(|| "Missing world geometry".to_string())?;
+    let material_set: CmdlMaterialSet =
+        Reader::new(material_set_section).read(material_set_section.len() as u32);
+
+    let mut model_nodes = vec![];
+    for model_index in 0..mrea.world_model_count {
+        let mut header = Reader::new(
+            sections
+                .next()
+                .ok_or_else(|| "Missing world geometry".to_string())?,
+        );
+        let _visor_flags: u32 = header.read(());
+        let transform: Vec<f32> = (0..12).map(|_| header.read::<f32>(())).collect();
+        let geometry = CmdlGeometry::read_sections(
+            CMDL_FLAG_SHORT_NORMALS | CMDL_FLAG_SHORT_UVS,
+            &mut sections,
+        );
+
+        let name = format!("world model {}", model_index);
+        if let Some(mesh) = gltf.add_mesh(&name, &geometry, &material_set, &textures)? {
+            // The header's transform is a row-major 3x4 matrix, glTF wants column-major 4x4
+            let mut matrix = [0.0; 16];
+            for row in 0..3 {
+                for col in 0..4 {
+                    matrix[col * 4 + row] = transform[row * 4 + col];
+                }
+            }
+            matrix[15] = 1.0;
+            model_nodes.push(gltf.add_node(json!({
+                "name": name,
+                "mesh": mesh,
+                "matrix": matrix,
+            })));
+        }
+    }
+    root_children.push(gltf.add_node(json!({
+        "name": "world geometry",
+        "children": model_nodes,
+    })));
+
+    if export_objects {
+        let mlvl = pak
+            .resources
+            .iter()
+            .find(|res| res.fourcc() == b"MLVL".into())
+            .unwrap()
+            .kind
+            .as_mlvl()
+            .unwrap()
+            .into_owned();
+        let area_index = mlvl
+            .areas
+            .iter()
+            .position(|area| area.mrea.to_u32() == room.mrea_id)
+            .ok_or_else(|| format!("{} is not in {}", room_name, pak_name))?;
+        let layer_names: Vec<String> = mlvl
+            .area_layer_names
+            .names_for_area(area_index)
+            .map(|names| {
+                names
+                    .iter()
+                    .map(|name| name.to_str().unwrap().to_string())
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        let marker = gltf.marker_mesh();
+        let scly = mrea.scly_section();
+        for (layer_index, layer) in scly.layers.iter().enumerate() {
+            let layer_name = layer_names
+                .get(layer_index)
+                .cloned()
+                .unwrap_or_else(|| format!("layer {}", layer_index));
+
+            let mut object_nodes = vec![];
+            for obj in layer.objects.iter() {
+                let mut property_data = obj.property_data.clone();
+                if !property_data.supports_position() {
+                    continue;
+                }
+                let type_name = property_data.object_type_name();
+                let object_name = property_data.get_name().to_str().unwrap().to_string();
+                let position = property_data.get_position();
+                let mut node = json!({
+                    "name": format!("{} 0x{:08X} {}", type_name, obj.instance_id, object_name),
+                    "mesh": marker,
+                    "translation": position,
+                    "extras": {
+                        "instanceId": format!("0x{:08X}", obj.instance_id),
+                        "type": type_name,
+                        "name": object_name,
+                        "layer": layer_name,
+                        "position": position,
+                    },
+                });
+                if property_data.supports_rotation() {
+                    // Rotations are in degrees, applied around X, then Y, then Z
+                    let [x, y, z] = property_data.get_rotation().map(f32::to_radians);
+                    let q = UnitQuaternion::from_euler_angles(x, y, z);
+                    node["rotation"] = json!([q.i, q.j, q.k, q.w]);
+                    node["extras"]["rotation"] = json!(property_data.get_rotation());
+                }
+                if property_data.supports_scale() {
+                    node["extras"]["scale"] = json!(property_data.get_scale());
+                }
+                object_nodes.push(gltf.add_node(node));
+            }
+
+            root_children.push(gltf.add_node(json!({
+                "name": format!("layer {}: {}", layer_index, layer_name),
+                "children": object_nodes,
+            })));
+        }
+    }
+
+    let root = gltf.add_node(z_up_root(root_children));
+    gltf.write(output, vec![root])
+}
+
+fn parse_id(s: &str) -> Result<u32, String> {
+    let parsed = match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => u32::from_str_radix(hex, 16),
+        None => s.parse::<u32>(),
+    };
+    parsed.map_err(|_| format!("Expected an asset id, got \"{}\"", s))
+}
+
+fn main() {
+    let app = clap_app!(app =>
+        (version: crate_version!())
+        (author: crate_authors!())
+        (about: "Exports a CMDL, or the geometry and script objects of a room, to glTF. The \
+                 scene is rotated to be Y-up by its root node, below which positions are in \
+                 game coordinates.")
+        (@setting ArgRequiredElseHelp)
+        (@arg input: -i --input +takes_value +required "Path to the game's ISO.")
+        (@arg output: -o --output +takes_value +required
+            "Path to write the glTF file to. A .glb extension writes binary glTF, otherwise the \
+             binary data is written next to it with a .bin extension.")
+        (@arg cmdl: --cmdl +takes_value
+            { |s| parse_id(&s).map(|_| ()) }
+            "Asset id of a CMDL to export. Accepts decimal and 0x-prefixed hexadecimal values.")
+        (@arg world: -w --world +takes_value
+            "World of the room to export, as in the config's levelData.")
+        (@arg room: -r --room +takes_value "Name of the room to export.")
+        (@arg no_objects: --no_objects "Leave the room's script objects out.")
+    );
+    let matches = app.get_matches();
+
+    let input = Path::new(matches.value_of("input").unwrap());
+    let output = Path::new(matches.value_of("output").unwrap());
+    let res = match (
+        matches.value_of("cmdl"),
+        matches.value_of("world"),
+        matches.value_of("room"),
+    ) {
+        (Some(cmdl), None, None) => export_cmdl(input, output, parse_id(cmdl).unwrap()),
+        (None, Some(world), Some(room)) => export_room(
+            input,
+            output,
+            world,
+            room,
+            !matches.is_present("no_objects"),
+        ),
+        _ => Err("Either --cmdl, or both --world and --room, must be given".to_string()),
+    };
+    if let Err(s) = res {
+        eprintln!("{} {}", clap::Format::Error("error:"), s);
+        std::process::exit(1);
+    }
+}
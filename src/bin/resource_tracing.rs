@@ -124,31 +124,8 @@ impl<'r> ResourceDb<'r> {
                 }
             } else if key.fourcc == b"PART".into() {
                 let buf = data.decompress();
-                let buf: &[u8] = &buf;
-                // We're cheating here. We're going to find the sub-string ICTSCNST
-                // and then using the next word as the id of a PART.
-                for i in 0..(buf.len() - 8) {
-                    if &buf[i..(i + 8)] == b"ICTSCNST" {
-                        let id: u32 = Reader::new(&buf[(i + 8)..(i + 12)]).read(());
-                        if id != 0 {
-                            extend_deps(id, b"PART");
-                        }
-                        // TODO: IITS and IDTS too?
-                    } else if &buf[i..(i + 4)] == b"TEXR" {
-                        if &buf[(i + 4)..(i + 8)] == b"ATEX" {
-                            let id: u32 = Reader::new(&buf[(i + 12)..(i + 16)]).read(());
-                            if id != 0 {
-                                extend_deps(id, b"TXTR");
-                            }
-                        }
-                    } else if &buf[i..(i + 4)] == b"KSSM" && &buf[(i + 4)..(i + 8)] != b"NONE" {
-                        let kssm: structs::Kssm = Reader::new(&buf[(i + 8)..]).read(());
-                        for list in kssm.lists.iter() {
-                            for item in list.items.iter() {
-                                extend_deps(item.part.to_u32(), b"PART");
-                            }
-                        }
-                    }
+                for dep in structs::direct_dependencies(key.fourcc, &buf) {
+                    extend_deps(dep.asset_id, dep.asset_type.as_bytes());
                 }
             } else if key.fourcc == b"CMDL".into() {
                 let buf = data.decompress();
@@ -0,0 +1,98 @@
+use std::{fs, path::Path};
+
+use clap::{clap_app, crate_authors, crate_version};
+use randomprime::thp_conversions::{encode_thp, ThpAudio};
+
+fn encode(
+    frames_dir: &Path,
+    output: &Path,
+    audio: Option<&Path>,
+    quality: u8,
+) -> Result<(), String> {
+    // Frames play in the order of their file names
+    let mut paths = fs::read_dir(frames_dir)
+        .map_err(|e| format!("Failed to read {}: {}", frames_dir.display(), e))?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("Failed to read {}: {}", frames_dir.display(), e))?;
+    paths.retain(|path| {
+        path.extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("png"))
+    });
+    paths.sort();
+    if paths.is_empty() {
+        Err(format!("{} has no PNG frames", frames_dir.display()))?;
+    }
+
+    let mut frames = vec![];
+    let mut dimensions = None;
+    for path in &paths {
+        let image = image::open(path)
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?
+            .into_rgb8();
+        let (width, height) = image.dimensions();
+        if *dimensions.get_or_insert((width, height)) != (width, height) {
+            Err(format!(
+                "{} is {}x{}, unlike the previous frames",
+                path.display(),
+                width,
+                height
+            ))?;
+        }
+        frames.push(image.into_raw());
+    }
+    let (width, height) = dimensions.unwrap();
+
+    let audio = audio.map(ThpAudio::from_wav).transpose()?;
+    let thp = encode_thp(&frames, width, height, audio.as_ref(), quality)?;
+    fs::write(output, thp).map_err(|e| format!("Failed to write {}: {}", output.display(), e))?;
+
+    println!(
+        "Encoded {} frames of {}x{} to {}",
+        frames.len(),
+        width,
+        height,
+        output.display()
+    );
+    Ok(())
+}
+
+fn main() {
+    let app = clap_app!(app =>
+        (version: crate_version!())
+        (author: crate_authors!())
+        (about: "Encodes a directory of PNG frames, and optionally a WAV soundtrack, to a THP \
+                 video playing at 29.97 frames per second.")
+        (@setting ArgRequiredElseHelp)
+        (@arg input: -i --input +takes_value +required
+            "Directory of .png frames, played in the order of their file names. Their width \
+             and height must be multiples of 16.")
+        (@arg output: -o --output +takes_value +required
+            "Output path to write the THP file to.")
+        (@arg audio: -a --audio +takes_value
+            "Mono or stereo WAV file to play alongside the video. It is resampled to 32kHz, \
+             and cut or padded with silence to the length of the video.")
+        (@arg quality: -q --quality +takes_value
+            { |s| s.parse::<u8>()
+                .ok()
+                .filter(|q| (1..=100).contains(q))
+                .map(|_| ())
+                .ok_or_else(|| "Expected an integer from 1 to 100 for quality".into()) }
+            "JPEG quality of the frames, from 1 to 100. Defaults to 90.")
+    );
+    let matches = app.get_matches();
+
+    let input = Path::new(matches.value_of("input").unwrap());
+    let output = Path::new(matches.value_of("output").unwrap());
+    let audio = matches.value_of("audio").map(Path::new);
+    let quality = matches
+        .value_of("quality")
+        .unwrap_or("90")
+        .parse::<u8>()
+        .unwrap();
+
+    if let Err(s) = encode(input, output, audio, quality) {
+        eprintln!("{} {}", clap::Format::Error("error:"), s);
+        std::process::exit(1);
+    }
+}
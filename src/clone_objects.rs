@@ -0,0 +1,203 @@
+use std::{
+    borrow::Cow,
+    collections::{HashMap, HashSet},
+    io,
+};
+
+use reader_writer::{FourCC, Writable};
+use structs::{Dependency, Resource, SclyObject, SclyProperty};
+
+use crate::{
+    elevators::SpawnRoomData, patch_config::LevelConfig, GcDiscLookupExtensions, ResourceData,
+};
+
+pub struct ClonedObject<'r> {
+    pub object: SclyObject<'r>,
+    pub dependencies: Vec<Dependency>,
+}
+
+// (source mrea, source instance id & 0x00FFFFFF)
+pub type ClonedObjects<'r> = HashMap<(u32, u32), ClonedObject<'r>>;
+
+// Records the value of every 4-byte field as a property is written
+struct FieldWords(Vec<u32>);
+
+impl io::Write for FieldWords {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if let Ok(bytes) = <[u8; 4]>::try_from(buf) {
+            self.0.push(u32::from_be_bytes(bytes));
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// Returns every id in `candidates` that one of the property's 4-byte fields holds. Properties
+// without a definition are only raw bytes, so every 4-byte window of them is searched instead.
+fn referenced_ids(
+    property: &SclyProperty,
+    candidates: &HashMap<u32, FourCC>,
+) -> Vec<(u32, FourCC)> {
+    let words = match property {
+        SclyProperty::Unknown { data, .. } => data
+            .windows(4)
+            .map(|window| u32::from_be_bytes([window[0], window[1], window[2], window[3]]))
+            .collect(),
+        _ => {
+            let mut words = FieldWords(vec![]);
+            property.write_to(&mut words).unwrap();
+            words.0
+        }
+    };
+
+    let mut found = Vec::new();
+    for id in words {
+        if let Some(fourcc) = candidates.get(&id) {
+            if !found.iter().any(|(found_id, _)| *found_id == id) {
+                found.push((id, *fourcc));
+            }
+        }
+    }
+    found
+}
+
+/// Reads every object referenced by a `cloneObjects` entry out of its source room. The assets an
+/// object uses directly are found by matching its property fields against the ids of assets the
+/// source area depends on, and everything those need in turn is found with `structs::dependency_closure`.
+/// The resources themselves are added to `resources` so they can later be given to the target area.
+pub fn collect_cloned_objects<'r>(
+    gc_disc: &structs::GcDisc<'r>,
+    level_data: &HashMap<String, LevelConfig>,
+    resources: &mut HashMap<(u32, FourCC), Resource<'r>>,
+) -> Result<ClonedObjects<'r>, String> {
+    // Group the requested objects by source room
+    let mut requests: HashMap<(&'static str, u32), HashSet<u32>> = HashMap::new();
+    for level in level_data.values() {
+        for room in level.rooms.values() {
+            for config in room.clone_objects.iter().flatten() {
+                let source = SpawnRoomData::try_from_str(&config.source_room)
+                    .map_err(|e| format!("cloneObjects: {}", e))?;
+                requests
+                    .entry((source.pak_name, source.mrea))
+                    .or_default()
+                    .insert(config.instance_id & 0x00FFFFFF);
+            }
+        }
+    }
+
+    let mut cloned_objects = HashMap::new();
+    for ((pak_name, mrea_id), instance_ids) in requests {
+        let file_entry = gc_disc
+            .find_file(pak_name)
+            .ok_or_else(|| format!("Failed to find {}", pak_name))?;
+        let pak = match *file_entry.file().unwrap() {
+            structs::FstEntryFile::Pak(ref pak) => Cow::Borrowed(pak),
+            structs::FstEntryFile::Unknown(ref reader) => Cow::Owned(reader.clone().read(())),
+            _ => panic!(),
+        };
+
+        // Everything the source area depends on, regardless of layer
+        let area_deps: HashMap<u32, FourCC> = {
+            let mlvl = pak
+                .resources
+                .iter()
+                .find(|res| res.fourcc() == b"MLVL".into())
+                .unwrap()
+                .kind
+                .as_mlvl()
+                .unwrap()
+                .into_owned();
+            let area = mlvl
+                .areas
+                .iter()
+                .find(|area| area.mrea.to_u32() == mrea_id)
+                .ok_or_else(|| format!("Room 0x{:X} is not in {}", mrea_id, pak_name))?;
+            area.dependencies
+                .deps
+                .iter()
+                .flat_map(|layer| {
+                    layer
+                        .iter()
+                        .map(|dep| (dep.asset_id, dep.asset_type))
+                        .collect::<Vec<_>>()
+                })
+                .collect()
+        };
+
+        let pak_resources: HashMap<(u32, FourCC), Resource<'r>> = pak
+            .resources
+            .iter()
+            .filter(|res| area_deps.get(&res.file_id) == Some(&res.fourcc()))
+            .map(|res| ((res.file_id, res.fourcc()), res.into_owned()))
+            .collect();
+
+        let mrea = pak
+            .resources
+            .iter()
+            .find(|res| res.file_id == mrea_id && res.fourcc() == b"MREA".into())
+            .unwrap()
+            .kind
+            .as_mrea()
+            .unwrap()
+            .into_owned();
+        let scly = mrea.scly_section();
+
+        for instance_id in instance_ids {
+            let object = scly
+                .layers
+                .iter()
+                .find_map(|layer| {
+                    layer
+                        .objects
+                        .iter()
+                        .find(|obj| obj.instance_id & 0x00FFFFFF == instance_id)
+                        .map(|obj| obj.into_owned())
+                })
+                .ok_or_else(|| {
+                    format!(
+                        "Failed to find object 0x{:X} to clone in room 0x{:X}",
+                        instance_id, mrea_id
+                    )
+                })?;
+
+            let roots = referenced_ids(&object.property_data, &area_deps)
+                .into_iter()
+                .map(|(asset_id, asset_type)| Dependency {
+                    asset_id,
+                    asset_type,
+                });
+            let dependencies: Vec<Dependency> = structs::dependency_closure(roots, |dep| {
+                pak_resources
+                    .get(&(dep.asset_id, dep.asset_type))
+                    .map(|res| ResourceData::new(res).decompress())
+            })
+            .into_iter()
+            .filter(|dep| area_deps.get(&dep.asset_id) == Some(&dep.asset_type))
+            .collect();
+
+            for dep in dependencies.iter() {
+                let key = (dep.asset_id, dep.asset_type);
+                let res = pak_resources.get(&key).ok_or_else(|| {
+                    format!(
+                        "Failed to find dependency 0x{:X} ({}) of cloned object 0x{:X}",
+                        dep.asset_id, dep.asset_type, instance_id
+                    )
+                })?;
+                resources.entry(key).or_insert_with(|| res.clone());
+            }
+
+            cloned_objects.insert(
+                (mrea_id, instance_id),
+                ClonedObject {
+                    object,
+                    dependencies,
+                },
+            );
+        }
+    }
+
+    Ok(cloned_objects)
+}
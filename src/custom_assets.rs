@@ -11,15 +11,16 @@ use crate::{
     door_meta::{BlastShieldType, DoorType},
     elevators::{SpawnRoomData, World},
     extern_assets::ExternPickupModel,
-    patch_config::{GenericTexture, PatchConfig, ScannableParametersConfig, Version},
+    font_glyphs,
+    patch_config::{GenericTexture, Localized, PatchConfig, ScannableParametersConfig, Version},
     patches::WaterType,
     pickup_meta::{self, PickupModel, PickupType},
-    sorted_by_key, GcDiscLookupExtensions, ResourceData,
+    sorted_by_key, texture_swaps, GcDiscLookupExtensions, ResourceData,
 };
 
 #[derive(Debug, Clone, Default, Hash, PartialEq, Eq)]
 struct ScanStrgSpec {
-    strings: Vec<String>,
+    strings: Localized<Vec<String>>,
     is_important: u8,
     logbook_category: u32,
     scan_speed: u32,
@@ -81,7 +82,7 @@ fn scan_strg_spec(config: &ScannableParametersConfig) -> ScanStrgSpec {
     ];
 
     ScanStrgSpec {
-        strings,
+        strings: strings.into(),
         is_important: config.critical as u8,
         logbook_category: config.logbook_category as u32,
         scan_speed: config.slow_scan as u32,
@@ -405,6 +406,10 @@ pub mod custom_asset_ids {
 
         EXTRA_IDS_START: STRG = DEFAULT_PICKUP_HUDMEMO_STRGS.to_u32() + 50,
     }
+
+    /// End of the block of ids kept for the patcher's own assets. Ids after `EXTRA_IDS_START` are
+    /// handed out while patching, so the whole block is kept rather than just the ids above.
+    pub const EXTRA_IDS_END: u32 = PHAZON_SUIT_TXTR1.to_u32() + 0x10000;
 }
 
 pub fn custom_asset_filename(resource_info: structs::ResourceInfo) -> String {
@@ -413,6 +418,37 @@ pub fn custom_asset_filename(resource_info: structs::ResourceInfo) -> String {
     format!("{}.{}", base_name, resource_info.fourcc)
 }
 
+// Builds a STRG with a table for each language the version's game can be played in. The JPN
+// release sets all of its tables in the JPN font, as the game's own STRGs do.
+pub fn build_localized_strg<'r>(
+    strings: &Localized<Vec<String>>,
+    version: Version,
+) -> structs::Strg<'r> {
+    let languages: &[&[u8; 4]] = match version {
+        Version::Pal => &[b"ENGL", b"FREN", b"GERM", b"SPAN", b"ITAL", b"JAPN"],
+        Version::NtscJ => &[b"ENGL", b"JAPN"],
+        _ => &[b"ENGL"],
+    };
+
+    let tables = languages
+        .iter()
+        .map(|lang| {
+            let lang = FourCC::from_bytes(lang);
+            let strings = strings
+                .get(lang)
+                .iter()
+                .map(|string| match version {
+                    Version::NtscJ => format!("{}{}", structs::JPN_FONT_PREFIX, string),
+                    _ => string.clone(),
+                })
+                .collect();
+            (lang, strings)
+        })
+        .collect();
+
+    structs::Strg::from_string_tables(tables)
+}
+
 pub fn build_resource<K>(file_id: ResId<K>, kind: ResourceKind) -> Resource
 where
     K: res_id::ResIdKind,
@@ -442,10 +478,14 @@ pub fn build_resource_raw(file_id: u32, kind: ResourceKind) -> Resource {
 
 // Assets defined in an external file at RUNTIME
 fn extern_assets_runtime<'r>(
-    extern_assets_dir: Option<String>,
+    extern_assets_dir: &str,
+    version: Version,
+    game_asset_ids: &HashSet<u32>,
 ) -> Result<(Vec<Resource<'r>>, HashMap<String, ExternPickupModel>), String> {
     let (extern_models, extern_assets) =
-        ExternPickupModel::parse(&extern_assets_dir.clone().unwrap())?;
+        ExternPickupModel::parse(extern_assets_dir, version, &|id| {
+            game_asset_ids.contains(&id)
+        })?;
 
     let mut resources = Vec::<Resource<'r>>::new();
     for (id, asset) in sorted_by_key(&extern_assets) {
@@ -664,10 +704,11 @@ fn extern_assets_compile_time<'r>() -> Vec<Resource<'r>> {
 }
 
 // Assets not found in the base game
-#[allow(clippy::type_complexity)]
+#[allow(clippy::type_complexity, clippy::too_many_arguments)]
 pub fn custom_assets<'r>(
     resources: &HashMap<(u32, FourCC), structs::Resource<'r>>,
-    starting_memo: Option<&str>,
+    game_asset_ids: &HashSet<u32>,
+    starting_memo: Option<&Localized<String>>,
     pickup_hudmemos: &mut HashMap<PickupHashKey, ResId<res_id::STRG>>,
     pickup_scans: &mut HashMap<PickupHashKey, (ResId<res_id::SCAN>, ResId<res_id::STRG>)>,
     extra_scans: &mut HashMap<PickupHashKey, (ResId<res_id::SCAN>, ResId<res_id::STRG>)>,
@@ -712,8 +753,9 @@ pub fn custom_assets<'r>(
 
     // External assets
     let mut assets = extern_assets_compile_time();
-    let extern_models = if config.extern_assets_dir.is_some() {
-        let (more_assets, extern_models) = extern_assets_runtime(config.extern_assets_dir.clone())?;
+    let extern_models = if let Some(extern_assets_dir) = &config.extern_assets_dir {
+        let (more_assets, extern_models) =
+            extern_assets_runtime(extern_assets_dir, config.version, game_asset_ids)?;
         assets.extend_from_slice(&more_assets);
         extern_models // HashMap of extern models available for use
     } else {
@@ -795,7 +837,7 @@ pub fn custom_assets<'r>(
                 "Toaster's Champions: Awp82, DiggleWrath, Yeti2000, freak532486, AlphaRage, Csabi,\0".to_string(),
                 "\0".to_string(),
                 "BajaBlood, hammergoboom, Firemetroid, Lokir, MeriKatt, Cosmonawt, Haldadrin, RXM, Schwartz, Samuel, Miguel, chliu, JeffGainsNGames\0".to_string(),
-            ],
+            ].into(),
             is_important: 1,
             ..Default::default()
         },
@@ -810,7 +852,7 @@ pub fn custom_assets<'r>(
                 "Chozo script translated.\0".to_string(),
                 "Racing\0".to_string(),
                 "As we have done for millennia, we Chozo work constantly on our speed. Our fastest are our sentinels; They are, and have always been, repositories for our most precious secrets and strongest powers.\n\n2025 - Samuel6710\n2024 (Mentor Tournament) - Belokuikuini\n2023 (CGC) - TheGingerChris + BajaBlood\n2023 - Cosmonawt\n2022 (CGC) - Cosmo + Cestrion\n2021 - Dinopony\n2020 - Interslice\n2019 - TheWeakestLink64\0".to_string(),
-            ],
+            ].into(),
             is_important: 1,
             ..Default::default()
         },
@@ -819,13 +861,11 @@ pub fn custom_assets<'r>(
     local_savw_scans_to_add[World::TallonOverworld as usize]
         .push(custom_asset_ids::TOURNEY_WINNERS_SCAN);
 
-    if starting_memo.is_some() {
+    if let Some(starting_memo) = starting_memo {
+        let strings = starting_memo.map(|memo| vec![format!("&just=center;{}\0", memo)]);
         assets.push(build_resource(
             custom_asset_ids::STARTING_ITEMS_HUDMEMO_STRG,
-            structs::ResourceKind::Strg(structs::Strg::from_strings(vec![format!(
-                "&just=center;{}\0",
-                starting_memo.unwrap()
-            )])),
+            structs::ResourceKind::Strg(build_localized_strg(&strings, config.version)),
         ));
     }
 
@@ -836,7 +876,7 @@ pub fn custom_assets<'r>(
             pt.scan(),
             pt.scan_strg(),
             &ScanStrgSpec {
-                strings: vec![format!("{}\0", name)],
+                strings: vec![format!("{}\0", name)].into(),
                 is_important: 1,
                 ..Default::default()
             },
@@ -853,8 +893,12 @@ pub fn custom_assets<'r>(
         ));
     }
 
+    // Textures converted from PNGs for `textureSwaps` take the first extra IDs //
+    let png_txtrs = texture_swaps::png_txtrs(config)?;
+    let mut custom_asset_offset = png_txtrs.len() as u32;
+    assets.extend(png_txtrs);
+
     // Create user-defined hudmemo and scan strings and map to locations //
-    let mut custom_asset_offset = 0;
     {
         let mut scan_allocator = ScanStrgAllocator {
             assets: &mut assets,
@@ -872,7 +916,7 @@ pub fn custom_assets<'r>(
                 let mut extra_scans_idx = 0;
 
                 for custom_scan in room.extra_scans.iter().flatten() {
-                    let mut logbook_title = String::new();
+                    let mut logbook_title = Localized::default();
 
                     if custom_scan.logbook_title.is_some() || custom_scan.logbook_category.is_some()
                     {
@@ -885,7 +929,9 @@ pub fn custom_assets<'r>(
                     }
 
                     let spec = ScanStrgSpec {
-                        strings: vec![custom_scan.text.clone() + "\0", logbook_title + "\0"],
+                        strings: custom_scan.text.zip(&logbook_title, |text, title| {
+                            vec![format!("{}\0", text), format!("{}\0", title)]
+                        }),
                         is_important: custom_scan.is_red.unwrap_or(false) as u8,
                         logbook_category: custom_scan.logbook_category.unwrap_or(0),
                         ..Default::default()
@@ -902,7 +948,7 @@ pub fn custom_assets<'r>(
                     };
 
                     let spec = ScanStrgSpec {
-                        strings: vec![destination.room_name.clone() + "\0"],
+                        strings: vec![destination.room_name.clone() + "\0"].into(),
                         ..Default::default()
                     };
 
@@ -914,7 +960,7 @@ pub fn custom_assets<'r>(
                 for hudmemo_config in room.hudmemos.iter().flatten() {
                     if let Some(text) = hudmemo_config.text.as_ref() {
                         let spec = ScanStrgSpec {
-                            strings: vec![format!("{}\0", text)],
+                            strings: text.map(|text| vec![format!("{}\0", text)]),
                             ..Default::default()
                         };
 
@@ -947,14 +993,12 @@ pub fn custom_assets<'r>(
                         *scan_allocator.next_offset += 1;
 
                         // Build resource //
-                        let strg = structs::ResourceKind::Strg(structs::Strg {
-                            string_tables: vec![structs::StrgStringTable {
-                                lang: b"ENGL".into(),
-                                strings: vec![format!("&just=center;{}\u{0}", hudmemo_text).into()]
-                                    .into(),
-                            }]
-                            .into(),
-                        });
+                        let strings =
+                            hudmemo_text.map(|text| vec![format!("&just=center;{}\u{0}", text)]);
+                        let strg = structs::ResourceKind::Strg(build_localized_strg(
+                            &strings,
+                            scan_allocator.version,
+                        ));
                         scan_allocator.assets.push(build_resource(strg_id, strg));
 
                         // Map for easy lookup when patching //
@@ -965,7 +1009,7 @@ pub fn custom_assets<'r>(
                     // Custom scan string
                     if let Some(scan_text) = pickup.scan_text.as_ref() {
                         let spec = ScanStrgSpec {
-                            strings: vec![format!("{}\0", scan_text)],
+                            strings: scan_text.map(|text| vec![format!("{}\0", text)]),
                             // the research core scan is red because it goes on the terminal
                             is_important: (room_name.trim().to_lowercase() == "research core")
                                 as u8,
@@ -1044,7 +1088,7 @@ pub fn custom_assets<'r>(
                     door_type.scan(),
                     door_type.strg(),
                     &ScanStrgSpec {
-                        strings: door_type.scan_text(),
+                        strings: door_type.scan_text().into(),
                         is_important: 1,
                         ..Default::default()
                     },
@@ -1078,7 +1122,7 @@ pub fn custom_assets<'r>(
                     blast_shield.scan(),
                     blast_shield.strg(),
                     &ScanStrgSpec {
-                        strings: blast_shield.scan_text(),
+                        strings: blast_shield.scan_text().into(),
                         is_important: 1,
                         ..Default::default()
                     },
@@ -1127,7 +1171,7 @@ pub fn custom_assets<'r>(
 #[allow(clippy::type_complexity)]
 pub fn collect_game_resources<'r>(
     gc_disc: &structs::GcDisc<'r>,
-    starting_memo: Option<&str>,
+    starting_memo: Option<&Localized<String>>,
     config: &PatchConfig,
 ) -> Result<
     (
@@ -1260,8 +1304,18 @@ pub fn collect_game_resources<'r>(
     ];
     looking_for.extend(player_freeze_deps);
 
+    let texture_swaps = texture_swaps::game_txtrs(config);
+    looking_for.extend(texture_swaps.iter().copied());
+
+    looking_for.extend(
+        font_glyphs::GAME_FONTS
+            .iter()
+            .flat_map(|(font, txtr)| -> [(u32, FourCC); 2] { [(*font).into(), (*txtr).into()] }),
+    );
+
     // Dependencies read from paks and custom assets will go here //
     let mut found = HashMap::with_capacity(looking_for.len());
+    let mut game_asset_ids = HashSet::new();
 
     // Iterate through every level Pak //
     let mut paks: Vec<&str> = Vec::new();
@@ -1282,6 +1336,8 @@ pub fn collect_game_resources<'r>(
 
         // Iterate through all resources in level Pak //
         for res in pak.resources.iter() {
+            game_asset_ids.insert(res.file_id);
+
             // If this resource is a dependency needed by the patcher, add the resource to the output list //
             let key = (res.file_id, res.fourcc());
             if looking_for.remove(&key) {
@@ -1310,6 +1366,7 @@ pub fn collect_game_resources<'r>(
         extern_models,
     ) = custom_assets(
         &found,
+        &game_asset_ids,
         starting_memo,
         &mut pickup_hudmemos,
         &mut pickup_scans,
@@ -1323,6 +1380,13 @@ pub fn collect_game_resources<'r>(
         found.insert(key, res.to_owned());
     }
 
+    if let Some((id, _)) = texture_swaps.iter().find(|key| looking_for.contains(key)) {
+        return Err(format!(
+            "textureSwaps: 0x{:X} is not a texture in the game",
+            id
+        ));
+    }
+
     if !looking_for.is_empty() {
         panic!("error - still looking for {:?}", looking_for);
     }
@@ -1869,7 +1933,7 @@ fn create_item_scan_strg_pair<'r>(
     version: Version,
 ) -> [structs::Resource<'r>; 2] {
     let spec = ScanStrgSpec {
-        strings: vec![contents],
+        strings: vec![contents].into(),
         ..Default::default()
     };
 
@@ -1934,15 +1998,7 @@ fn create_item_scan_strg_pair_2<'r>(
         }),
     );
 
-    let contents = spec.strings.clone();
-    let kind = if version == Version::Pal {
-        structs::ResourceKind::Strg(structs::Strg::from_strings_pal(contents))
-    } else if version == Version::NtscJ {
-        structs::ResourceKind::Strg(structs::Strg::from_strings_jpn(contents))
-    } else {
-        structs::ResourceKind::Strg(structs::Strg::from_strings(contents))
-    };
-
+    let kind = structs::ResourceKind::Strg(build_localized_strg(&spec.strings, version));
     let strg = build_resource(new_strg, kind);
 
     [scan, strg]
@@ -13,7 +13,7 @@ use crate::patch_config::{
     DifficultyBehavior, PatchConfig, PhazonDamageModifier, SuitDamageReduction, Version, Visor,
 };
 use crate::pickup_meta::PickupType;
-use crate::txtr_conversions::{huerotate_color, huerotate_matrix};
+use crate::txtr_conversions::{recolor_color, RecolorParams};
 
 fn patch_rel_loader(
     dol_patcher: &mut DolPatcher<'_>,
@@ -2838,13 +2838,13 @@ fn patch_ball_glow_normalized(
         .as_ref()
         .map(|s| {
             (
-                s.power_deg.unwrap_or(0),
-                s.gravity_deg.unwrap_or(0),
-                s.varia_deg.unwrap_or(0),
-                s.phazon_deg.unwrap_or(0),
+                s.power_recolor().params(),
+                s.gravity_recolor().params(),
+                s.varia_recolor().params(),
+                s.phazon_recolor().params(),
             )
         })
-        .unwrap_or((0, 0, 0, 0));
+        .unwrap_or_default();
 
     // Retail contents of each glow table (9 slots x RGB), preserved verbatim.
     let retail: [[u8; 27]; 7] = [
@@ -2885,11 +2885,11 @@ fn patch_ball_glow_normalized(
         ],
     ];
 
-    // Owning suit of each color slot, all keeping their retail color and rotated by the owner's deg:
-    // idx0 Power, idx1 Varia non-spider, idx2 Varia spider, idx3 Gravity, idx4 Phazon. Fusion slots:
-    // idx5 Power+F, idx6 Varia+F, idx7 Gravity+F, idx8 Phazon+F.
-    let slot_deg = [
-        power, varia, varia, gravity, phazon, power, varia, gravity, phazon,
+    // Owning suit of each color slot, all keeping their retail color and recolored as the owner's
+    // glow: idx0 Power, idx1 Varia non-spider, idx2 Varia spider, idx3 Gravity, idx4 Phazon. Fusion
+    // slots: idx5 Power+F, idx6 Varia+F, idx7 Gravity+F, idx8 Phazon+F.
+    let slot_params = [
+        &power, &varia, &varia, &gravity, &phazon, &power, &varia, &gravity, &phazon,
     ];
 
     let addrs = [
@@ -2903,16 +2903,14 @@ fn patch_ball_glow_normalized(
     ];
     for (addr, base) in addrs.iter().zip(retail.iter()) {
         let mut table = *base;
-        for (slot, &deg) in slot_deg.iter().enumerate() {
-            let deg = deg.rem_euclid(360);
-            if deg == 0 {
+        for (slot, params) in slot_params.iter().enumerate() {
+            if **params == RecolorParams::default() {
                 continue;
             }
-            let rgb = huerotate_color(
-                huerotate_matrix(deg as f32),
-                table[slot * 3],
-                table[slot * 3 + 1],
-                table[slot * 3 + 2],
+            let rgb = recolor_color(
+                params,
+                [table[slot * 3], table[slot * 3 + 1], table[slot * 3 + 2]],
+                [0.0, 0.0, 1.0],
             );
             table[slot * 3..slot * 3 + 3].copy_from_slice(&rgb);
         }
@@ -0,0 +1,830 @@
+use std::{ops::Range, path::Path};
+
+use reader_writer::{Readable, Reader, Writable};
+use structs::DspHeader;
+
+// Nintendo's DSP ADPCM, as used by .dsp streams and THP audio. Every frame of 8 bytes holds 14
+// samples, predicted from the previous two with one of 8 coefficient pairs picked per frame.
+// The coefficient computation follows the algorithm of Nintendo's DSPADPCM encoder.
+
+pub const SAMPLES_PER_FRAME: usize = 14;
+pub const BYTES_PER_FRAME: usize = 8;
+
+// The sample rate of the game's own music streams
+pub const STREAM_SAMPLE_RATE: u32 = 32000;
+
+// Samples are split into blocks of this many when gathering the coefficient candidates
+const CORRELATE_BLOCK_SAMPLES: usize = 0x3800;
+
+type Vec3 = [f64; 3];
+
+pub fn frame_count(sample_count: usize) -> usize {
+    sample_count.div_ceil(SAMPLES_PER_FRAME)
+}
+
+pub fn byte_count(sample_count: usize) -> usize {
+    frame_count(sample_count) * BYTES_PER_FRAME
+}
+
+// Addresses count the nibbles of every frame, including the two of its header byte
+pub fn nibble_address(sample: usize) -> u32 {
+    ((sample / SAMPLES_PER_FRAME) * BYTES_PER_FRAME * 2 + 2 + sample % SAMPLES_PER_FRAME) as u32
+}
+
+fn address_sample(address: u32) -> usize {
+    let nibbles_per_frame = BYTES_PER_FRAME * 2;
+    let address = address as usize;
+    (address / nibbles_per_frame) * SAMPLES_PER_FRAME + (address % nibbles_per_frame).max(2) - 2
+}
+
+fn clamp_i16(value: i32) -> i16 {
+    value.clamp(i16::MIN as i32, i16::MAX as i32) as i16
+}
+
+/* Coefficients */
+
+// `pcm` holds the previous frame followed by the current one, which starts at index 14
+fn inner_product_merge(pcm: &[i16; 28]) -> Vec3 {
+    let mut out = [0.0; 3];
+    for (i, value) in out.iter_mut().enumerate() {
+        for x in 14..28 {
+            *value -= pcm[x - i] as f64 * pcm[x] as f64;
+        }
+    }
+    out
+}
+
+fn outer_product_merge(pcm: &[i16; 28]) -> [Vec3; 3] {
+    let mut out = [[0.0; 3]; 3];
+    for x in 1..=2 {
+        for y in 1..=2 {
+            for z in 14..28 {
+                out[x][y] += pcm[z - x] as f64 * pcm[z - y] as f64;
+            }
+        }
+    }
+    out
+}
+
+// LU decomposition with partial pivoting, returns None if the matrix is near singular
+fn analyze_ranges(mtx: &mut [Vec3; 3]) -> Option<[usize; 3]> {
+    let mut recips = [0.0; 3];
+    for x in 1..=2 {
+        let val = mtx[x][1].abs().max(mtx[x][2].abs());
+        if val < f64::EPSILON {
+            return None;
+        }
+        recips[x] = 1.0 / val;
+    }
+
+    let mut indices = [0; 3];
+    let mut max_index = 0;
+    for i in 1..=2 {
+        for x in 1..i {
+            let mut tmp = mtx[x][i];
+            for y in 1..x {
+                tmp -= mtx[x][y] * mtx[y][i];
+            }
+            mtx[x][i] = tmp;
+        }
+
+        let mut val = 0.0;
+        for x in i..=2 {
+            let mut tmp = mtx[x][i];
+            for y in 1..i {
+                tmp -= mtx[x][y] * mtx[y][i];
+            }
+            mtx[x][i] = tmp;
+            let tmp = tmp.abs() * recips[x];
+            if tmp >= val {
+                val = tmp;
+                max_index = x;
+            }
+        }
+
+        if max_index != i {
+            mtx.swap(max_index, i);
+            recips[max_index] = recips[i];
+        }
+        indices[i] = max_index;
+
+        if mtx[i][i] == 0.0 {
+            return None;
+        }
+        if i != 2 {
+            let tmp = 1.0 / mtx[i][i];
+            for row in mtx.iter_mut().skip(i + 1) {
+                row[i] *= tmp;
+            }
+        }
+    }
+
+    let (mut min, mut max) = (1.0e10, 0.0);
+    for (i, row) in mtx.iter().enumerate().skip(1) {
+        let tmp = row[i].abs();
+        if tmp < min {
+            min = tmp;
+        }
+        if tmp > max {
+            max = tmp;
+        }
+    }
+    if min / max < 1.0e-10 {
+        return None;
+    }
+
+    Some(indices)
+}
+
+fn bidirectional_filter(mtx: &[Vec3; 3], indices: &[usize; 3], vec: &mut Vec3) {
+    let mut x = 0;
+    for i in 1..=2 {
+        let index = indices[i];
+        let mut tmp = vec[index];
+        vec[index] = vec[i];
+        if x != 0 {
+            tmp -= (x..i).map(|y| vec[y] * mtx[i][y]).sum::<f64>();
+        } else if tmp != 0.0 {
+            x = i;
+        }
+        vec[i] = tmp;
+    }
+
+    for i in (1..=2).rev() {
+        let tmp = vec[i] - (i + 1..=2).map(|y| vec[y] * mtx[i][y]).sum::<f64>();
+        vec[i] = tmp / mtx[i][i];
+    }
+
+    vec[0] = 1.0;
+}
+
+// Returns false if the filter would be unstable
+fn quadratic_merge(vec: &mut Vec3) -> bool {
+    let v2 = vec[2];
+    let tmp = 1.0 - v2 * v2;
+    if tmp == 0.0 {
+        return false;
+    }
+
+    let v0 = (vec[0] - v2 * v2) / tmp;
+    let v1 = (vec[1] - vec[1] * v2) / tmp;
+    vec[0] = v0;
+    vec[1] = v1;
+
+    v1.abs() <= 1.0
+}
+
+fn finish_record(mut vec: Vec3) -> Vec3 {
+    for value in &mut vec[1..] {
+        *value = value.clamp(-0.9999999999, 0.9999999999);
+    }
+    [1.0, vec[2] * vec[1] + vec[1], vec[2]]
+}
+
+fn matrix_filter(src: &Vec3) -> Vec3 {
+    let mut mtx = [[0.0; 3]; 3];
+    mtx[2] = [1.0, -src[1], -src[2]];
+
+    for i in (1..=2).rev() {
+        let val = 1.0 - mtx[i][i] * mtx[i][i];
+        for y in 1..=i {
+            mtx[i - 1][y] = (mtx[i][i] * mtx[i][y] + mtx[i][y]) / val;
+        }
+    }
+
+    let mut dst = [1.0, 0.0, 0.0];
+    for i in 1..=2 {
+        for y in 1..=i {
+            dst[i] += mtx[i][y] * dst[i - y];
+        }
+    }
+    dst
+}
+
+fn merge_finish_record(src: &Vec3) -> Vec3 {
+    let mut dst = [1.0, 0.0, 0.0];
+    let mut tmp = [0.0; 3];
+    let mut val = src[0];
+
+    for i in 1..=2 {
+        let mut v2 = 0.0;
+        for y in 1..i {
+            v2 += dst[y] * src[i - y];
+        }
+
+        dst[i] = if val > 0.0 { -(v2 + src[i]) / val } else { 0.0 };
+        tmp[i] = dst[i];
+
+        for y in 1..i {
+            dst[y] += dst[i] * dst[i - y];
+        }
+
+        val *= 1.0 - dst[i] * dst[i];
+    }
+
+    finish_record(tmp)
+}
+
+fn contrast_vectors(source1: &Vec3, source2: &Vec3) -> f64 {
+    let val = (source2[2] * source2[1] - source2[1]) / (1.0 - source2[2] * source2[2]);
+    let val1 = source1[0] * source1[0] + source1[1] * source1[1] + source1[2] * source1[2];
+    let val2 = source1[0] * source1[1] + source1[1] * source1[2];
+    let val3 = source1[0] * source1[2];
+    val1 + 2.0 * val * val2 + 2.0 * (-source2[1] * val - source2[2]) * val3
+}
+
+// Refines the best candidates by clustering the records around them
+fn filter_records(best: &mut [Vec3; 8], count: usize, records: &[Vec3]) {
+    for _ in 0..2 {
+        let mut sums = [[0.0; 3]; 8];
+        let mut counts = [0; 8];
+
+        for record in records {
+            let mut index = 0;
+            let mut value = 1.0e30;
+            for (i, candidate) in best.iter().enumerate().take(count) {
+                let tmp = contrast_vectors(candidate, record);
+                if tmp < value {
+                    value = tmp;
+                    index = i;
+                }
+            }
+            counts[index] += 1;
+            let filtered = matrix_filter(record);
+            for (sum, value) in sums[index].iter_mut().zip(filtered) {
+                *sum += value;
+            }
+        }
+
+        for i in 0..count {
+            if counts[i] > 0 {
+                for sum in &mut sums[i] {
+                    *sum /= counts[i] as f64;
+                }
+            }
+            best[i] = merge_finish_record(&sums[i]);
+        }
+    }
+}
+
+/// Computes the 8 coefficient pairs that best predict the samples
+pub fn correlate_coefs(samples: &[i16]) -> [[i16; 2]; 8] {
+    let mut records = vec![];
+    let mut pcm = [0i16; 28];
+
+    for block in samples.chunks(CORRELATE_BLOCK_SAMPLES) {
+        for frame in block.chunks(SAMPLES_PER_FRAME) {
+            pcm.copy_within(14.., 0);
+            pcm[14..].fill(0);
+            pcm[14..14 + frame.len()].copy_from_slice(frame);
+
+            let mut vec = inner_product_merge(&pcm);
+            if vec[0].abs() <= 10.0 {
+                continue;
+            }
+            let mut mtx = outer_product_merge(&pcm);
+            let Some(indices) = analyze_ranges(&mut mtx) else {
+                continue;
+            };
+            bidirectional_filter(&mtx, &indices, &mut vec);
+            if quadratic_merge(&mut vec) {
+                records.push(finish_record(vec));
+            }
+        }
+    }
+
+    let mut best = [[0.0; 3]; 8];
+    let mut vec = [1.0, 0.0, 0.0];
+    for record in &records {
+        let filtered = matrix_filter(record);
+        vec[1] += filtered[1];
+        vec[2] += filtered[2];
+    }
+    if !records.is_empty() {
+        vec[1] /= records.len() as f64;
+        vec[2] /= records.len() as f64;
+    }
+    best[0] = merge_finish_record(&vec);
+
+    // Split every candidate in two and refine them, up to 8
+    let mut count = 1;
+    while count < 8 {
+        for i in 0..count {
+            best[count + i] = [best[i][0], best[i][1] - 0.01, best[i][2]];
+        }
+        count *= 2;
+        filter_records(&mut best, count, &records);
+    }
+
+    best.map(|vec| {
+        [
+            (-vec[1] * 2048.0).round().clamp(-32768.0, 32767.0) as i16,
+            (-vec[2] * 2048.0).round().clamp(-32768.0, 32767.0) as i16,
+        ]
+    })
+}
+
+/* Encoding */
+
+/// Encodes a stream of samples, carrying the decoder history over between calls
+#[derive(Debug, Clone)]
+pub struct DspEncoder {
+    pub coefs: [[i16; 2]; 8],
+    // The last two decoded samples, most recent first
+    pub history: [i16; 2],
+}
+
+impl DspEncoder {
+    pub fn new(coefs: [[i16; 2]; 8]) -> Self {
+        DspEncoder {
+            coefs,
+            history: [0, 0],
+        }
+    }
+
+    // Picks the coefficients and scale with the least error for up to 14 samples
+    fn encode_frame(&mut self, samples: &[i16]) -> [u8; BYTES_PER_FRAME] {
+        let count = samples.len();
+        let mut pcm = [0i32; 16];
+        pcm[0] = self.history[1] as i32;
+        pcm[1] = self.history[0] as i32;
+        for (dst, &src) in pcm[2..].iter_mut().zip(samples) {
+            *dst = src as i32;
+        }
+
+        let mut decoded = [[0i32; 16]; 8];
+        let mut nibbles = [[0i32; SAMPLES_PER_FRAME]; 8];
+        let mut scales = [0i32; 8];
+        let mut errors = [0.0f64; 8];
+
+        for (i, &[coef1, coef2]) in self.coefs.iter().enumerate() {
+            let (coef1, coef2) = (coef1 as i32, coef2 as i32);
+            decoded[i][0] = pcm[0];
+            decoded[i][1] = pcm[1];
+
+            let mut distance = 0i32;
+            for s in 0..count {
+                let predicted = (pcm[s] * coef2 + pcm[s + 1] * coef1) / 2048;
+                decoded[i][s + 2] = predicted;
+                let delta = clamp_i16(pcm[s + 2] - predicted) as i32;
+                if delta.abs() > distance.abs() {
+                    distance = delta;
+                }
+            }
+
+            let mut scale = 0;
+            while scale <= 12 && !(-8..=7).contains(&distance) {
+                scale += 1;
+                distance /= 2;
+            }
+            scales[i] = if scale <= 1 { -1 } else { scale - 2 };
+
+            loop {
+                scales[i] += 1;
+                errors[i] = 0.0;
+                let mut overflow = 0;
+
+                for s in 0..count {
+                    let predicted = decoded[i][s] * coef2 + decoded[i][s + 1] * coef1;
+                    let delta = (pcm[s + 2] << 11) - predicted;
+                    let scaled = delta as f64 / (1 << scales[i]) as f64 / 2048.0;
+                    let mut nibble = if delta > 0 {
+                        (scaled + 0.4999999) as i32
+                    } else {
+                        (scaled - 0.4999999) as i32
+                    };
+
+                    if nibble < -8 {
+                        overflow = overflow.max(-8 - nibble);
+                        nibble = -8;
+                    } else if nibble > 7 {
+                        overflow = overflow.max(nibble - 7);
+                        nibble = 7;
+                    }
+                    nibbles[i][s] = nibble;
+
+                    let sample = (predicted + ((nibble * (1 << scales[i])) << 11) + 1024) >> 11;
+                    let sample = clamp_i16(sample) as i32;
+                    decoded[i][s + 2] = sample;
+                    let error = (pcm[s + 2] - sample) as f64;
+                    errors[i] += error * error;
+                }
+
+                let mut x = overflow + 8;
+                while x > 256 {
+                    scales[i] += 1;
+                    if scales[i] >= 12 {
+                        scales[i] = 11;
+                    }
+                    x >>= 1;
+                }
+
+                if scales[i] >= 12 || overflow <= 1 {
+                    break;
+                }
+            }
+        }
+
+        let best = (0..8)
+            .min_by(|&a, &b| errors[a].total_cmp(&errors[b]))
+            .unwrap();
+
+        if count > 0 {
+            self.history = [decoded[best][count + 1] as i16, decoded[best][count] as i16];
+        }
+
+        let mut frame = [0u8; BYTES_PER_FRAME];
+        frame[0] = ((best as u8) << 4) | (scales[best] as u8 & 0xF);
+        for (i, pair) in nibbles[best][..count].chunks(2).enumerate() {
+            let low = pair.get(1).copied().unwrap_or(0);
+            frame[i + 1] = ((pair[0] << 4) | (low & 0xF)) as u8;
+        }
+        frame
+    }
+
+    pub fn encode(&mut self, samples: &[i16]) -> Vec<u8> {
+        let mut data = Vec::with_capacity(byte_count(samples.len()));
+        for frame in samples.chunks(SAMPLES_PER_FRAME) {
+            data.extend(self.encode_frame(frame));
+        }
+        data
+    }
+}
+
+/* Decoding */
+
+/// Decodes `sample_count` samples, starting with the given history, most recent first
+pub fn decode(
+    data: &[u8],
+    coefs: &[[i16; 2]; 8],
+    history: [i16; 2],
+    sample_count: usize,
+) -> Vec<i16> {
+    let [mut hist1, mut hist2] = history.map(|h| h as i32);
+    let mut samples = Vec::with_capacity(sample_count);
+    for frame in data.chunks(BYTES_PER_FRAME) {
+        let scale = 1 << (frame[0] & 0xF);
+        let [coef1, coef2] = coefs[(frame[0] >> 4) as usize & 0x7].map(|c| c as i32);
+        for i in 0..SAMPLES_PER_FRAME {
+            if samples.len() == sample_count {
+                return samples;
+            }
+            let byte = frame.get(1 + i / 2).copied().unwrap_or(0);
+            let nibble = if i % 2 == 0 { byte >> 4 } else { byte & 0xF };
+            // Sign extend
+            let nibble = ((nibble << 4) as i8 >> 4) as i32;
+
+            let sample = ((nibble * scale) << 11) + 1024 + coef1 * hist1 + coef2 * hist2;
+            let sample = clamp_i16(sample >> 11);
+            hist2 = hist1;
+            hist1 = sample as i32;
+            samples.push(sample);
+        }
+    }
+    samples
+}
+
+/* .dsp files */
+
+/// A single channel of audio, as stored by a .dsp file. Stereo streams are made of two of them,
+/// usually named with an L and R suffix.
+#[derive(Debug, Clone)]
+pub struct DspAudio {
+    pub samples: Vec<i16>,
+    pub sample_rate: u32,
+    // Samples played again once the end of the range is reached
+    pub loop_range: Option<Range<usize>>,
+}
+
+impl DspAudio {
+    pub fn encode(&self) -> Result<Vec<u8>, String> {
+        if self.samples.is_empty() {
+            return Err("There are no samples to encode".to_string());
+        }
+        if let Some(loop_range) = &self.loop_range {
+            if loop_range.is_empty() || loop_range.end > self.samples.len() {
+                return Err(format!(
+                    "The loop {}..{} isn't inside of the {} samples",
+                    loop_range.start,
+                    loop_range.end,
+                    self.samples.len()
+                ));
+            }
+        }
+
+        let coefs = correlate_coefs(&self.samples);
+        let data = DspEncoder::new(coefs).encode(&self.samples);
+
+        let (loop_start, loop_end) = self
+            .loop_range
+            .as_ref()
+            .map_or((0, self.samples.len()), |range| (range.start, range.end));
+        // The decoder state when jumping back to the start of the loop
+        let loop_frame = loop_start / SAMPLES_PER_FRAME;
+        let decoded = decode(&data, &coefs, [0, 0], loop_start);
+        let loop_history = [
+            decoded.last().copied().unwrap_or(0),
+            decoded.iter().rev().nth(1).copied().unwrap_or(0),
+        ];
+
+        let header = DspHeader {
+            sample_count: self.samples.len() as u32,
+            nibble_count: nibble_address(self.samples.len() - 1) + 1,
+            sample_rate: self.sample_rate,
+            loop_flag: self.loop_range.is_some() as u16,
+            loop_start_address: nibble_address(loop_start),
+            loop_end_address: nibble_address(loop_end - 1),
+            current_address: nibble_address(0),
+            coefs: coefs.as_flattened().iter().copied().collect(),
+            gain: 0,
+            initial_predictor_scale: data[0] as u16,
+            initial_history: [0, 0].into(),
+            loop_predictor_scale: data[loop_frame * BYTES_PER_FRAME] as u16,
+            loop_history: loop_history.into(),
+            padding: Default::default(),
+        };
+
+        let mut bytes = Vec::with_capacity(header.size() + data.len());
+        header.write_to(&mut bytes).unwrap();
+        bytes.extend(data);
+        Ok(bytes)
+    }
+
+    pub fn decode(bytes: &[u8]) -> Result<Self, String> {
+        let header_size = DspHeader::fixed_size().unwrap();
+        if bytes.len() < header_size {
+            return Err("The file is too small to be a .dsp".to_string());
+        }
+        if bytes[0x0E..0x10] != [0, 0] {
+            return Err("The .dsp isn't in the ADPCM format".to_string());
+        }
+        let header: DspHeader = Reader::new(bytes).read(());
+        let data = &bytes[header_size..];
+
+        let sample_count = header.sample_count as usize;
+        if data.len() < byte_count(sample_count) {
+            return Err(format!(
+                "The .dsp should hold {} samples but is truncated",
+                sample_count
+            ));
+        }
+
+        let mut coefs = [[0i16; 2]; 8];
+        for (i, coef) in header.coefs.iter().enumerate() {
+            coefs[i / 2][i % 2] = *coef;
+        }
+        let samples = decode(
+            data,
+            &coefs,
+            [header.initial_history[0], header.initial_history[1]],
+            sample_count,
+        );
+
+        let loop_range = (header.loop_flag != 0).then(|| {
+            address_sample(header.loop_start_address)..address_sample(header.loop_end_address) + 1
+        });
+
+        Ok(DspAudio {
+            samples,
+            sample_rate: header.sample_rate,
+            loop_range,
+        })
+    }
+}
+
+/* WAV */
+
+// Linear interpolation, which is enough for music and video soundtracks
+pub fn resample(samples: &[i16], from: u32, to: u32) -> Vec<i16> {
+    if from == to || samples.is_empty() {
+        return samples.to_vec();
+    }
+    let count = (samples.len() as u64 * to as u64 / from as u64) as usize;
+    (0..count)
+        .map(|i| {
+            let position = i as f64 * from as f64 / to as f64;
+            let index = position as usize;
+            let t = position - index as f64;
+            let a = samples[index] as f64;
+            let b = samples[(index + 1).min(samples.len() - 1)] as f64;
+            (a + (b - a) * t).round() as i16
+        })
+        .collect()
+}
+
+/// Reads every channel of a WAV file as 16-bit samples, along with its sample rate
+pub fn read_wav(path: &Path) -> Result<(Vec<Vec<i16>>, u32), String> {
+    let mut reader = hound::WavReader::open(path)
+        .map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+    let spec = reader.spec();
+    let read_error = |e: hound::Error| format!("Failed to read {}: {}", path.display(), e);
+
+    let interleaved: Vec<i16> = match spec.sample_format {
+        hound::SampleFormat::Int if spec.bits_per_sample <= 16 => {
+            let shift = 16 - spec.bits_per_sample;
+            reader
+                .samples::<i32>()
+                .map(|s| s.map(|s| (s << shift) as i16))
+                .collect::<Result<_, _>>()
+                .map_err(read_error)?
+        }
+        hound::SampleFormat::Int => {
+            let shift = spec.bits_per_sample - 16;
+            reader
+                .samples::<i32>()
+                .map(|s| s.map(|s| (s >> shift) as i16))
+                .collect::<Result<_, _>>()
+                .map_err(read_error)?
+        }
+        hound::SampleFormat::Float => reader
+            .samples::<f32>()
+            .map(|s| s.map(|s| (s.clamp(-1.0, 1.0) * i16::MAX as f32) as i16))
+            .collect::<Result<_, _>>()
+            .map_err(read_error)?,
+    };
+
+    let channel_count = spec.channels as usize;
+    let channels = (0..channel_count)
+        .map(|c| {
+            interleaved
+                .iter()
+                .skip(c)
+                .step_by(channel_count)
+                .copied()
+                .collect()
+        })
+        .collect();
+
+    Ok((channels, spec.sample_rate))
+}
+
+/// Writes 16-bit samples to a WAV file, the channels must have the same length
+pub fn write_wav(path: &Path, channels: &[Vec<i16>], sample_rate: u32) -> Result<(), String> {
+    let spec = hound::WavSpec {
+        channels: channels.len() as u16,
+        sample_rate,
+        bits_per_sample: 16,
+        sample_format: hound::SampleFormat::Int,
+    };
+    let write_error = |e: hound::Error| format!("Failed to write {}: {}", path.display(), e);
+
+    let mut writer = hound::WavWriter::create(path, spec).map_err(write_error)?;
+    for i in 0..channels[0].len() {
+        for channel in channels {
+            writer.write_sample(channel[i]).map_err(write_error)?;
+        }
+    }
+    writer.finalize().map_err(write_error)
+}
+
+/// Converts a mono or stereo WAV to the .dsp files of a looping music stream, one per channel
+pub fn encode_wav_stream(path: &Path) -> Result<Vec<Vec<u8>>, String> {
+    let (channels, sample_rate) = read_wav(path)?;
+    if channels.is_empty() || channels.len() > 2 {
+        return Err(format!(
+            "{} has {} channels, only mono and stereo are supported",
+            path.display(),
+            channels.len()
+        ));
+    }
+    channels
+        .iter()
+        .map(|samples| {
+            let samples = resample(samples, sample_rate, STREAM_SAMPLE_RATE);
+            DspAudio {
+                loop_range: Some(0..samples.len()),
+                samples,
+                sample_rate: STREAM_SAMPLE_RATE,
+            }
+            .encode()
+            .map_err(|e| format!("Failed to encode {}: {}", path.display(), e))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sine(count: usize, period: f64, amplitude: f64) -> Vec<i16> {
+        (0..count)
+            .map(|i| ((i as f64 * std::f64::consts::TAU / period).sin() * amplitude) as i16)
+            .collect()
+    }
+
+    // Signal to noise ratio of the decoded samples, in dB
+    fn snr(original: &[i16], decoded: &[i16]) -> f64 {
+        let signal: f64 = original.iter().map(|&s| (s as f64).powi(2)).sum();
+        let noise: f64 = original
+            .iter()
+            .zip(decoded)
+            .map(|(&a, &b)| (a as f64 - b as f64).powi(2))
+            .sum();
+        10.0 * (signal / noise.max(1.0)).log10()
+    }
+
+    #[test]
+    fn nibble_addresses_round_trip() {
+        for sample in 0..100 {
+            assert_eq!(address_sample(nibble_address(sample)), sample);
+        }
+        // Each frame starts with its header byte
+        assert_eq!(nibble_address(0), 2);
+        assert_eq!(nibble_address(14), 18);
+    }
+
+    #[test]
+    fn mono_round_trip() {
+        let samples = sine(1000, 50.0, 12000.0);
+        let bytes = DspAudio {
+            samples: samples.clone(),
+            sample_rate: 22050,
+            loop_range: None,
+        }
+        .encode()
+        .unwrap();
+
+        let decoded = DspAudio::decode(&bytes).unwrap();
+        assert_eq!(decoded.sample_rate, 22050);
+        assert_eq!(decoded.loop_range, None);
+        assert_eq!(decoded.samples.len(), samples.len());
+        assert!(snr(&samples, &decoded.samples) > 20.0);
+    }
+
+    #[test]
+    fn loop_start_header_matches_the_decoder_state() {
+        let samples = sine(1000, 37.0, 9000.0);
+        let loop_range = 100..900;
+        let bytes = DspAudio {
+            samples: samples.clone(),
+            sample_rate: STREAM_SAMPLE_RATE,
+            loop_range: Some(loop_range.clone()),
+        }
+        .encode()
+        .unwrap();
+
+        let header: DspHeader = Reader::new(&bytes[..]).read(());
+        let data = &bytes[DspHeader::fixed_size().unwrap()..];
+        assert_eq!(header.loop_flag, 1);
+        assert_eq!(address_sample(header.loop_start_address), loop_range.start);
+        assert_eq!(address_sample(header.loop_end_address), loop_range.end - 1);
+
+        // The predictor/scale of the frame holding the loop start and the two samples before it
+        let loop_frame = loop_range.start / SAMPLES_PER_FRAME;
+        assert_eq!(
+            header.loop_predictor_scale,
+            data[loop_frame * BYTES_PER_FRAME] as u16
+        );
+        assert_eq!(header.initial_predictor_scale, data[0] as u16);
+        let decoded = DspAudio::decode(&bytes).unwrap();
+        assert_eq!(
+            [header.loop_history[0], header.loop_history[1]],
+            [
+                decoded.samples[loop_range.start - 1],
+                decoded.samples[loop_range.start - 2]
+            ]
+        );
+        assert_eq!(decoded.loop_range, Some(loop_range));
+        assert!(snr(&samples, &decoded.samples) > 20.0);
+    }
+
+    #[test]
+    fn bad_loops_are_rejected() {
+        let audio = |loop_range| DspAudio {
+            samples: sine(100, 20.0, 1000.0),
+            sample_rate: STREAM_SAMPLE_RATE,
+            loop_range: Some(loop_range),
+        };
+        assert!(audio(50..50).encode().is_err());
+        assert!(audio(0..101).encode().is_err());
+        assert!(DspAudio {
+            samples: vec![],
+            sample_rate: STREAM_SAMPLE_RATE,
+            loop_range: None,
+        }
+        .encode()
+        .is_err());
+    }
+
+    #[test]
+    fn stereo_wav_stream_round_trip() {
+        let left = sine(2000, 64.0, 10000.0);
+        let right = sine(2000, 23.0, 6000.0);
+        let path = std::env::temp_dir().join(format!(
+            "randomprime_dsp_adpcm_test_{}.wav",
+            std::process::id()
+        ));
+        write_wav(&path, &[left.clone(), right.clone()], STREAM_SAMPLE_RATE).unwrap();
+        let dsps = encode_wav_stream(&path);
+        std::fs::remove_file(&path).unwrap();
+        let dsps = dsps.unwrap();
+
+        assert_eq!(dsps.len(), 2);
+        for (dsp, original) in dsps.iter().zip([&left, &right]) {
+            let decoded = DspAudio::decode(dsp).unwrap();
+            assert_eq!(decoded.sample_rate, STREAM_SAMPLE_RATE);
+            assert_eq!(decoded.loop_range, Some(0..original.len()));
+            assert!(snr(original, &decoded.samples) > 20.0);
+        }
+    }
+}
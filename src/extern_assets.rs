@@ -1,13 +1,27 @@
 use std::{
     collections::{HashMap, HashSet},
-    fs::{self, File},
+    fmt, fs,
     io::{self, Read},
+    ops::Range,
     path::{Path, PathBuf},
 };
 
+use flate2::read::GzDecoder;
 use reader_writer::FourCC;
 use serde::Deserialize;
 
+use crate::{custom_assets::custom_asset_ids, patch_config::Version};
+
+/// Newest asset package manifest layout this patcher understands
+pub const PACKAGE_SCHEMA_VERSION: u32 = 1;
+
+/// Name of the manifest at the root of an asset package
+pub const PACKAGE_MANIFEST: &str = "manifest.json";
+
+/// Ids handed out to the patcher's own custom assets, which extern assets may not use
+pub const RESERVED_ASSET_IDS: Range<u32> =
+    custom_asset_ids::PHAZON_SUIT_TXTR1.to_u32()..custom_asset_ids::EXTRA_IDS_END;
+
 /* Public Structs */
 #[derive(Debug, Clone)]
 pub struct ExternPickupModel {
@@ -24,6 +38,106 @@ pub struct ExternAsset {
     pub bytes: Vec<u8>,
 }
 
+#[derive(Debug)]
+pub enum ExternAssetsError {
+    Io(PathBuf, io::Error),
+    MalformedPackage(String),
+    MissingManifest(PathBuf),
+    InvalidManifest(serde_json::Error),
+    UnsupportedSchemaVersion(u32),
+    UnsupportedGameVersion(Version, Vec<String>),
+    InvalidFourCC(String),
+    DuplicateAssetId(u32),
+    ReservedAssetId(u32, FourCC),
+    GameAssetId(u32, FourCC),
+    MissingFile(u32, String),
+    BadMagic(u32, FourCC),
+    MissingDependency(u32, u32, FourCC),
+    MissingModelAsset(String, u32, FourCC),
+}
+
+impl fmt::Display for ExternAssetsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ExternAssetsError::Io(path, e) => {
+                write!(
+                    f,
+                    "Failed to read extern assets from '{}': {}",
+                    path.display(),
+                    e
+                )
+            }
+            ExternAssetsError::MalformedPackage(s) => {
+                write!(f, "Extern asset package is malformed: {}", s)
+            }
+            ExternAssetsError::MissingManifest(path) => write!(
+                f,
+                "'{}' has neither a {} nor a meta.json",
+                path.display(),
+                PACKAGE_MANIFEST
+            ),
+            ExternAssetsError::InvalidManifest(e) => {
+                write!(f, "Extern assets manifest parse failed: {}", e)
+            }
+            ExternAssetsError::UnsupportedSchemaVersion(version) => write!(
+                f,
+                "Extern asset package has schema version {}, only versions up to {} are supported",
+                version, PACKAGE_SCHEMA_VERSION
+            ),
+            ExternAssetsError::UnsupportedGameVersion(version, supported) => write!(
+                f,
+                "Extern asset package does not support game version {} (supports {})",
+                version,
+                supported.join(", ")
+            ),
+            ExternAssetsError::InvalidFourCC(s) => {
+                write!(f, "'{}' is not a valid asset type", s)
+            }
+            ExternAssetsError::DuplicateAssetId(id) => {
+                write!(f, "Extern asset 0x{:08X} is declared more than once", id)
+            }
+            ExternAssetsError::ReservedAssetId(id, fourcc) => write!(
+                f,
+                "Extern asset 0x{:08X}.{} uses an id reserved for the patcher's custom assets",
+                id, fourcc
+            ),
+            ExternAssetsError::GameAssetId(id, fourcc) => write!(
+                f,
+                "Extern asset 0x{:08X}.{} uses the id of an existing game asset",
+                id, fourcc
+            ),
+            ExternAssetsError::MissingFile(id, file) => write!(
+                f,
+                "Failed to find file '{}' for extern asset 0x{:08X}",
+                file, id
+            ),
+            ExternAssetsError::BadMagic(id, fourcc) => write!(
+                f,
+                "Extern asset 0x{:08X} does not look like a {} file",
+                id, fourcc
+            ),
+            ExternAssetsError::MissingDependency(id, dep, fourcc) => write!(
+                f,
+                "Extern asset 0x{:08X} depends on 0x{:08X}.{}, which is not in the package",
+                id, dep, fourcc
+            ),
+            ExternAssetsError::MissingModelAsset(item, id, fourcc) => write!(
+                f,
+                "Extern model '{}' uses 0x{:08X}.{}, which is not in the package",
+                item, id, fourcc
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ExternAssetsError {}
+
+impl From<ExternAssetsError> for String {
+    fn from(e: ExternAssetsError) -> String {
+        e.to_string()
+    }
+}
+
 /* Structs for modeling JSON format */
 
 #[derive(Deserialize, Debug, Default, Clone)]
@@ -54,57 +168,343 @@ struct MetadataJson {
     pub new_assets: Vec<ExternAssetJson>,
 }
 
-fn parse_dir(dir: &String) -> Result<Vec<PathBuf>, std::io::Error> {
-    let mut files = fs::read_dir(dir)?
-        .map(|res| res.map(|e| e.path()))
-        .collect::<Result<Vec<_>, io::Error>>()?;
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+struct ManifestJson {
+    pub schema_version: u32,
+    #[serde(default)]
+    pub game_versions: Vec<String>,
+    #[serde(default)]
+    pub items: HashMap<String, ExternPickupModelJson>,
+    pub assets: Vec<ManifestAssetJson>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+struct ManifestAssetJson {
+    pub id: u32,
+    #[serde(rename = "type")]
+    pub fourcc: String,
+    pub file: String,
+    #[serde(default)]
+    pub dependencies: Vec<ExternAssetDependencyJson>,
+}
+
+/* Loading */
+
+type Dependencies = Vec<(u32, FourCC)>;
+
+// Assets and models as read from a package or directory, before validation
+struct Package {
+    models: HashMap<String, ExternPickupModelJson>,
+    assets: Vec<(u32, FourCC, Dependencies)>,
+    files: HashMap<u32, ExternAsset>,
+}
+
+fn parse_fourcc(s: &str) -> Result<FourCC, ExternAssetsError> {
+    let bytes: [u8; 4] = s
+        .as_bytes()
+        .try_into()
+        .map_err(|_| ExternAssetsError::InvalidFourCC(s.to_string()))?;
+    Ok(FourCC::from_bytes(&bytes))
+}
+
+fn read_file(path: &Path) -> Result<Vec<u8>, ExternAssetsError> {
+    fs::read(path).map_err(|e| ExternAssetsError::Io(path.to_path_buf(), e))
+}
+
+fn parse_dir(dir: &Path) -> Result<Vec<PathBuf>, ExternAssetsError> {
+    let mut files = fs::read_dir(dir)
+        .and_then(|entries| {
+            entries
+                .map(|res| res.map(|e| e.path()))
+                .collect::<Result<Vec<_>, io::Error>>()
+        })
+        .map_err(|e| ExternAssetsError::Io(dir.to_path_buf(), e))?;
     files.sort();
     Ok(files)
 }
 
-impl ExternPickupModel {
-    #[allow(clippy::type_complexity)]
-    pub fn parse(
-        dir: &String,
-    ) -> Result<(HashMap<String, Self>, HashMap<u32, ExternAsset>), String> {
-        // Get file list in dir
-        let files = parse_dir(dir).map_err(|e| format!("Extern Assets dir parse failed: {}", e))?;
-
-        // Deserialize JSON
-        let _metadata = fs::read_to_string(Path::new(dir).join("meta.json"))
-            .unwrap_or_else(|_| panic!("Unable to read extern model metadata from '{}'", dir));
-        let metadata: MetadataJson = serde_json::from_str(&_metadata)
-            .map_err(|e| format!("Extern Assets metadata.json parse failed: {}", e))?;
-
-        // Parse model info
-        let mut models: HashMap<String, Self> = HashMap::new();
-        for (name, model) in metadata.items.iter() {
+fn octal_field(field: &[u8]) -> Result<usize, ExternAssetsError> {
+    let s = String::from_utf8_lossy(field);
+    let s = s.trim_matches(|c: char| c == '\0' || c == ' ');
+    if s.is_empty() {
+        return Ok(0);
+    }
+    usize::from_str_radix(s, 8)
+        .map_err(|_| ExternAssetsError::MalformedPackage(format!("bad tar header field '{}'", s)))
+}
+
+fn c_string(field: &[u8]) -> String {
+    let len = field.iter().position(|&b| b == 0).unwrap_or(field.len());
+    String::from_utf8_lossy(&field[..len]).into_owned()
+}
+
+// Reads the regular files of a (optionally gzipped) tar archive
+fn read_tar(mut data: Vec<u8>) -> Result<HashMap<String, Vec<u8>>, ExternAssetsError> {
+    if data.starts_with(&[0x1F, 0x8B]) {
+        let mut decompressed = vec![];
+        GzDecoder::new(&data[..])
+            .read_to_end(&mut decompressed)
+            .map_err(|e| ExternAssetsError::MalformedPackage(e.to_string()))?;
+        data = decompressed;
+    }
+
+    let mut files = HashMap::new();
+    let mut long_name = None;
+    let mut offset = 0;
+    while offset + 512 <= data.len() {
+        let header = &data[offset..offset + 512];
+        if header.iter().all(|&b| b == 0) {
+            break;
+        }
+        if &header[257..262] != b"ustar" {
+            return Err(ExternAssetsError::MalformedPackage(
+                "not a tar archive".to_string(),
+            ));
+        }
+
+        let size = octal_field(&header[124..136])?;
+        let start = offset + 512;
+        let end = start + size;
+        if end > data.len() {
+            return Err(ExternAssetsError::MalformedPackage(
+                "archive is truncated".to_string(),
+            ));
+        }
+        let contents = &data[start..end];
+        offset = start + (size + 511) / 512 * 512;
+
+        let name = long_name.take().unwrap_or_else(|| {
+            let prefix = c_string(&header[345..500]);
+            let name = c_string(&header[..100]);
+            if prefix.is_empty() {
+                name
+            } else {
+                format!("{}/{}", prefix, name)
+            }
+        });
+        match header[156] {
+            b'0' | b'\0' => {
+                let name = name.trim_start_matches("./").to_string();
+                files.insert(name, contents.to_vec());
+            }
+            // GNU long name for the next entry
+            b'L' => long_name = Some(c_string(contents)),
+            // Directories, links and pax metadata carry no asset data
+            _ => (),
+        }
+    }
+    if offset == 0 {
+        return Err(ExternAssetsError::MalformedPackage(
+            "not a tar archive".to_string(),
+        ));
+    }
+    Ok(files)
+}
+
+// Extern assets from before packages were versioned: a directory with a meta.json, where each
+// asset's file is whichever has its decimal id in the name and its type as the extension
+fn read_legacy_dir(dir: &Path) -> Result<Package, ExternAssetsError> {
+    let files = parse_dir(dir)?;
+
+    let metadata = read_file(&dir.join("meta.json"))?;
+    let metadata: MetadataJson =
+        serde_json::from_slice(&metadata).map_err(ExternAssetsError::InvalidManifest)?;
+
+    let mut dependencies: HashMap<u32, Dependencies> = HashMap::new();
+    for asset in metadata.new_assets.iter() {
+        let deps = asset
+            .dependencies
+            .iter()
+            .map(|dep| Ok((dep.id, parse_fourcc(&dep.fourcc)?)))
+            .collect::<Result<Vec<_>, _>>()?;
+        dependencies.entry(asset.new_id).or_default().extend(deps);
+    }
+
+    // Every asset a model can reach is expected to have a file
+    let mut ids_to_find: Vec<u32> = vec![];
+    for model in metadata.items.values() {
+        ids_to_find.push(model.ancs);
+        ids_to_find.push(model.cmdl);
+    }
+    let mut found: HashSet<u32> = HashSet::new();
+    let mut assets = vec![];
+    let mut package_files = HashMap::new();
+    while let Some(id) = ids_to_find.pop() {
+        if !found.insert(id) {
+            continue;
+        }
+
+        let filename = files
+            .iter()
+            .find(|file| file.to_string_lossy().contains(&format!("{}", id)))
+            .ok_or_else(|| ExternAssetsError::MissingFile(id, format!("*{}*", id)))?;
+
+        // Derive FourCC from file extension
+        let extension = filename
+            .extension()
+            .map(|ext| ext.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let fourcc = parse_fourcc(&extension)?;
+
+        let deps = dependencies.remove(&id).unwrap_or_default();
+        ids_to_find.extend(deps.iter().map(|&(dep, _)| dep));
+        assets.push((id, fourcc, deps));
+        package_files.insert(
+            id,
+            ExternAsset {
+                fourcc,
+                bytes: read_file(filename)?,
+            },
+        );
+    }
+
+    Ok(Package {
+        models: metadata.items,
+        assets,
+        files: package_files,
+    })
+}
+
+// A package is a tar archive, or an extracted one, with a manifest.json at its root
+fn read_package(
+    manifest: &[u8],
+    mut files: impl FnMut(&str) -> Option<Vec<u8>>,
+    version: Version,
+) -> Result<Package, ExternAssetsError> {
+    let manifest: ManifestJson =
+        serde_json::from_slice(manifest).map_err(ExternAssetsError::InvalidManifest)?;
+
+    if manifest.schema_version == 0 || manifest.schema_version > PACKAGE_SCHEMA_VERSION {
+        return Err(ExternAssetsError::UnsupportedSchemaVersion(
+            manifest.schema_version,
+        ));
+    }
+    if !manifest.game_versions.is_empty()
+        && !manifest
+            .game_versions
+            .iter()
+            .any(|v| *v == version.to_string())
+    {
+        return Err(ExternAssetsError::UnsupportedGameVersion(
+            version,
+            manifest.game_versions,
+        ));
+    }
+
+    let mut assets = vec![];
+    let mut package_files = HashMap::new();
+    for asset in manifest.assets.iter() {
+        let fourcc = parse_fourcc(&asset.fourcc)?;
+        let deps = asset
+            .dependencies
+            .iter()
+            .map(|dep| Ok((dep.id, parse_fourcc(&dep.fourcc)?)))
+            .collect::<Result<Vec<_>, _>>()?;
+        let bytes = files(&asset.file)
+            .ok_or_else(|| ExternAssetsError::MissingFile(asset.id, asset.file.clone()))?;
+        assets.push((asset.id, fourcc, deps));
+        if package_files
+            .insert(asset.id, ExternAsset { fourcc, bytes })
+            .is_some()
+        {
+            return Err(ExternAssetsError::DuplicateAssetId(asset.id));
+        }
+    }
+
+    Ok(Package {
+        models: manifest.items,
+        assets,
+        files: package_files,
+    })
+}
+
+// The leading bytes of the asset formats which have a fixed magic
+fn expected_magic(fourcc: FourCC) -> Option<&'static [u8]> {
+    Some(match fourcc.as_bytes() {
+        b"CMDL" => &[0xDE, 0xAD, 0xBA, 0xBE],
+        b"MREA" => &[0xDE, 0xAD, 0xBE, 0xEF],
+        b"MAPA" => &[0xDE, 0xAD, 0xD0, 0x0D],
+        b"MAPW" => &[0xDE, 0xAD, 0xF0, 0x0D],
+        b"MAPU" => &[0xAB, 0xCD, 0xEF, 0x01],
+        b"SAVW" => &[0xC0, 0x01, 0xD0, 0x0D],
+        b"STRG" => &[0x87, 0x65, 0x43, 0x21],
+        b"FONT" => b"FONT",
+        b"PART" => b"GPSM",
+        b"ELSC" => b"ELSM",
+        b"SWHC" => b"SWSH",
+        b"WPSC" => b"WPSM",
+        b"CRSC" => b"CRSM",
+        b"DPSC" => b"DPSM",
+        _ => return None,
+    })
+}
+
+impl Package {
+    fn validate(&self, is_game_asset: &dyn Fn(u32) -> bool) -> Result<(), ExternAssetsError> {
+        for (id, fourcc, deps) in self.assets.iter() {
+            if RESERVED_ASSET_IDS.contains(id) {
+                return Err(ExternAssetsError::ReservedAssetId(*id, *fourcc));
+            }
+            if is_game_asset(*id) {
+                return Err(ExternAssetsError::GameAssetId(*id, *fourcc));
+            }
+            if let Some(magic) = expected_magic(*fourcc) {
+                if !self.files[id].bytes.starts_with(magic) {
+                    return Err(ExternAssetsError::BadMagic(*id, *fourcc));
+                }
+            }
+            for &(dep, dep_fourcc) in deps {
+                if !self.contains(dep, dep_fourcc) {
+                    return Err(ExternAssetsError::MissingDependency(*id, dep, dep_fourcc));
+                }
+            }
+        }
+
+        for (name, model) in self.models.iter() {
+            for (id, fourcc) in [(model.ancs, b"ANCS"), (model.cmdl, b"CMDL")] {
+                let fourcc = FourCC::from_bytes(fourcc);
+                if !self.contains(id, fourcc) {
+                    return Err(ExternAssetsError::MissingModelAsset(
+                        name.clone(),
+                        id,
+                        fourcc,
+                    ));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn contains(&self, id: u32, fourcc: FourCC) -> bool {
+        self.files.get(&id).is_some_and(|f| f.fourcc == fourcc)
+    }
+
+    fn models(&self) -> HashMap<String, ExternPickupModel> {
+        let dependencies: HashMap<u32, &Dependencies> = self
+            .assets
+            .iter()
+            .map(|(id, _, deps)| (*id, deps))
+            .collect();
+
+        let mut models: HashMap<String, ExternPickupModel> = HashMap::new();
+        for (name, model) in self.models.iter() {
             // Collect all dependencies for this model
-            let mut dependencies: Vec<(u32, FourCC)> = Vec::new();
-            let mut deps: HashSet<u32> = HashSet::new();
-            dependencies.push((model.ancs, FourCC::from_bytes(b"ANCS")));
-            deps.insert(model.ancs);
-            dependencies.push((model.cmdl, FourCC::from_bytes(b"CMDL")));
-            deps.insert(model.cmdl);
-            let mut added = true;
-            while added {
-                added = false;
-                for asset in metadata.new_assets.iter() {
-                    if deps.contains(&asset.new_id) {
-                        for dep in &asset.dependencies {
-                            if deps.contains(&dep.id) {
-                                continue;
-                            }
-                            let fourcc = dep.fourcc.as_bytes();
-                            let fourcc: [u8; 4] = [fourcc[0], fourcc[1], fourcc[2], fourcc[3]];
-                            let fourcc = FourCC::from_bytes(&fourcc);
-
-                            dependencies.push((dep.id, fourcc));
-                            deps.insert(dep.id);
-                            added = true;
-                        }
+            let mut deps = vec![
+                (model.ancs, FourCC::from_bytes(b"ANCS")),
+                (model.cmdl, FourCC::from_bytes(b"CMDL")),
+            ];
+            let mut seen: HashSet<u32> = deps.iter().map(|&(id, _)| id).collect();
+            let mut i = 0;
+            while i < deps.len() {
+                for &(dep, fourcc) in dependencies[&deps[i].0].iter() {
+                    if seen.insert(dep) {
+                        deps.push((dep, fourcc));
                     }
                 }
+                i += 1;
             }
 
             // Add model to list of availible models
@@ -115,66 +515,50 @@ impl ExternPickupModel {
                     cmdl: model.cmdl,
                     scale: model.scale,
                     character: model.character,
-                    dependencies,
+                    dependencies: deps,
                 },
             );
         }
+        models
+    }
+}
 
-        // Asset ids required
-        let mut ids_to_find: HashSet<u32> = HashSet::new();
-        for (_, model) in models.iter() {
-            ids_to_find.insert(model.ancs);
-            ids_to_find.insert(model.cmdl);
-            for (dep, _) in model.dependencies.iter() {
-                ids_to_find.insert(*dep);
-            }
-        }
-
-        // Parse asset data
-        let mut assets: HashMap<u32, ExternAsset> = HashMap::new();
-        for id in ids_to_find {
-            // Find the file which corresponds to this id
-            let mut filename = None;
-            let mut found = false;
-            for file in &files {
-                if file
-                    .to_str()
-                    .unwrap()
-                    .to_string()
-                    .contains(&format!("{}", id))
-                {
-                    found = true;
-                    filename = Some(file);
-                    break;
-                }
-            }
-            if !found {
-                panic!("Failed to find file corresponding to asset id {}", id)
-            }
-            let filename = filename.unwrap();
-            // Derrive FourCC from file extension
-            // (I dislike Rust; This is just for parsing 4 letters)
-            let fourcc = filename.clone();
-            let fourcc = fourcc.to_str().unwrap();
-            let fourcc = fourcc.split('.');
-            let fourcc: Vec<&str> = fourcc.collect();
-            if fourcc.len() < 2 {
-                panic!("Extern asset, unexpected asset filename format");
+impl ExternPickupModel {
+    /// Loads extern assets from a package file, an extracted package, or a legacy meta.json
+    /// directory. `is_game_asset` reports ids already used by the game's own assets.
+    #[allow(clippy::type_complexity)]
+    pub fn parse(
+        path: &str,
+        version: Version,
+        is_game_asset: &dyn Fn(u32) -> bool,
+    ) -> Result<(HashMap<String, Self>, HashMap<u32, ExternAsset>), ExternAssetsError> {
+        let path = Path::new(path);
+        let package = if path.is_dir() {
+            let manifest = path.join(PACKAGE_MANIFEST);
+            if manifest.is_file() {
+                read_package(
+                    &read_file(&manifest)?,
+                    |file| fs::read(path.join(file)).ok(),
+                    version,
+                )?
+            } else if path.join("meta.json").is_file() {
+                read_legacy_dir(path)?
+            } else {
+                return Err(ExternAssetsError::MissingManifest(path.to_path_buf()));
             }
-            let fourcc = fourcc[fourcc.len() - 1];
-            let fourcc = fourcc.as_bytes();
-            let fourcc: [u8; 4] = [fourcc[0], fourcc[1], fourcc[2], fourcc[3]];
-            let fourcc = FourCC::from_bytes(&fourcc);
-
-            // Read file contents to RAM
-            let mut file = File::open(filename).expect("no file found");
-            let metadata = fs::metadata(filename).expect("unable to read metadata");
-            let mut bytes = vec![0; metadata.len() as usize];
-            file.read_exact(&mut bytes).expect("buffer overflow");
-
-            assets.insert(id, ExternAsset { fourcc, bytes });
-        }
+        } else {
+            let mut files = read_tar(read_file(path)?)?;
+            let manifest = files
+                .remove(PACKAGE_MANIFEST)
+                .ok_or_else(|| ExternAssetsError::MissingManifest(path.to_path_buf()))?;
+            read_package(
+                &manifest,
+                |file| files.get(file.trim_start_matches("./")).cloned(),
+                version,
+            )?
+        };
 
-        Ok((models, assets))
+        package.validate(is_game_asset)?;
+        Ok((package.models(), package.files))
     }
 }
@@ -0,0 +1,331 @@
+use ab_glyph::{Font, FontRef, GlyphId, PxScale, ScaleFont};
+use reader_writer::{Reader, Writable};
+use resource_info_table::{resource_info, ResourceInfo};
+use structs::{FontGlyph, FontKerning, TxtrFormat};
+
+use crate::patch_config::FontGlyphsConfig;
+
+// The game's fonts and their textures. Deface_Head14B is left out as it is only found in
+// GGuiSys.pak, which is not searched for game resources.
+pub const GAME_FONTS: &[(ResourceInfo, ResourceInfo)] = &[
+    (
+        resource_info!("Deface13B.FONT"),
+        resource_info!("Deface13B_tex.TXTR"),
+    ),
+    (
+        resource_info!("Deface14B_O.FONT"),
+        resource_info!("Deface14B_O_tex.TXTR"),
+    ),
+    (
+        resource_info!("Deface18B.FONT"),
+        resource_info!("Deface18B_tex.TXTR"),
+    ),
+    (
+        resource_info!("Deface18B#.FONT"),
+        resource_info!("Deface18B#_tex.TXTR"),
+    ),
+    (
+        resource_info!("Deface24B.FONT"),
+        resource_info!("Deface24B_tex.TXTR"),
+    ),
+];
+
+// Font texture modes, see `Font::txtr_fmt`
+const FONT_MODE_ONE_LAYER: u32 = 0;
+const FONT_MODE_ONE_LAYER_OUTLINE: u32 = 1;
+
+// The bits of a texel value for each layer's fill and outline
+const FILL_BIT: u8 = 0x1;
+const OUTLINE_BIT: u8 = 0x2;
+
+// Empty texels left between glyphs so they don't bleed into each other when filtered
+const GLYPH_SPACING: usize = 1;
+
+// GX limits textures to 1024 texels on each side
+const MAX_TEXTURE_HEIGHT: usize = 1024;
+
+// Raw access to the texel values of a font texture, which are the index of each layer's bits
+// rather than colors
+struct FontTexels<'a> {
+    data: &'a mut Vec<u8>,
+    width: usize,
+    bits: usize,
+}
+
+impl FontTexels<'_> {
+    fn block_dimensions(&self) -> (usize, usize) {
+        if self.bits == 4 {
+            (8, 8)
+        } else {
+            (8, 4)
+        }
+    }
+
+    fn offset(&self, x: usize, y: usize) -> (usize, usize) {
+        let (bw, bh) = self.block_dimensions();
+        let blocks_per_row = self.width / bw;
+        let block = (y / bh) * blocks_per_row + x / bw;
+        let texel = (y % bh) * bw + x % bw;
+        let bit_offset = (block * bw * bh + texel) * self.bits;
+        (bit_offset / 8, bit_offset % 8)
+    }
+
+    fn set(&mut self, x: usize, y: usize, value: u8) {
+        let (byte, bit) = self.offset(x, y);
+        if self.bits == 4 {
+            // The first texel of a byte is in its high nibble
+            let shift = 4 - bit;
+            self.data[byte] = (self.data[byte] & !(0xF << shift)) | ((value & 0xF) << shift);
+        } else {
+            self.data[byte] = value;
+        }
+    }
+
+    fn grow(&mut self, height: usize) {
+        self.data.resize(self.width * height * self.bits / 8, 0);
+    }
+}
+
+struct RasterizedGlyph {
+    chr: u16,
+    id: GlyphId,
+    width: usize,
+    height: usize,
+    texels: Vec<u8>,
+    left_padding: i32,
+    advance: i32,
+    baseline: i32,
+}
+
+fn rasterize_glyph(
+    ttf: &FontRef,
+    scale: PxScale,
+    c: char,
+    outline: bool,
+) -> Result<RasterizedGlyph, String> {
+    let chr: u16 = (c as u32)
+        .try_into()
+        .map_err(|_| format!("'{}' is outside of the Basic Multilingual Plane", c))?;
+    let id = ttf.glyph_id(c);
+    if id.0 == 0 {
+        return Err(format!("The font file has no glyph for '{}'", c));
+    }
+
+    // Outlines are drawn in a ring of texels around the fill
+    let margin = outline as usize;
+    let h_advance = ttf.as_scaled(scale).h_advance(id).round() as i32;
+
+    let Some(outlined) = ttf.outline_glyph(id.with_scale(scale)) else {
+        // Whitespace has nothing to draw, but still advances the print head
+        return Ok(RasterizedGlyph {
+            chr,
+            id,
+            width: 0,
+            height: 0,
+            texels: vec![],
+            left_padding: 0,
+            advance: h_advance,
+            baseline: 0,
+        });
+    };
+
+    let bounds = outlined.px_bounds();
+    let ink_width = bounds.width() as usize;
+    let ink_height = bounds.height() as usize;
+    let width = ink_width + margin * 2;
+    let height = ink_height + margin * 2;
+
+    let mut texels = vec![0u8; width * height];
+    outlined.draw(|x, y, coverage| {
+        if coverage >= 0.5 {
+            texels[(y as usize + margin) * width + x as usize + margin] |= FILL_BIT;
+        }
+    });
+
+    if outline {
+        let filled = texels.clone();
+        for y in 0..height {
+            for x in 0..width {
+                if filled[y * width + x] & FILL_BIT != 0 {
+                    continue;
+                }
+                let near_fill = (y.saturating_sub(1)..(y + 2).min(height)).any(|ny| {
+                    (x.saturating_sub(1)..(x + 2).min(width))
+                        .any(|nx| filled[ny * width + nx] & FILL_BIT != 0)
+                });
+                if near_fill {
+                    texels[y * width + x] |= OUTLINE_BIT;
+                }
+            }
+        }
+    }
+
+    let left_padding = bounds.min.x as i32 - margin as i32;
+    Ok(RasterizedGlyph {
+        chr,
+        id,
+        width,
+        height,
+        texels,
+        left_padding,
+        advance: h_advance - left_padding,
+        baseline: -(bounds.min.y as i32) + margin as i32,
+    })
+}
+
+/// Rasterizes the configured characters missing from a FONT into new rows at the bottom of its
+/// texture, returning the patched FONT and TXTR
+pub fn add_font_glyphs(
+    font_bytes: &[u8],
+    txtr_bytes: &[u8],
+    ttf: &FontRef,
+    config: &FontGlyphsConfig,
+) -> Result<(Vec<u8>, Vec<u8>), String> {
+    let mut font: structs::Font = Reader::new(font_bytes).read(());
+    let mut txtr: structs::Txtr = Reader::new(txtr_bytes).read(());
+
+    let outline = match font.txtr_fmt {
+        FONT_MODE_ONE_LAYER => false,
+        FONT_MODE_ONE_LAYER_OUTLINE => true,
+        mode => return Err(format!("Unsupported font texture mode {}", mode)),
+    };
+    let bits = match txtr.format {
+        TxtrFormat::I4 | TxtrFormat::C4(..) => 4,
+        TxtrFormat::I8 | TxtrFormat::C8(..) => 8,
+        _ => return Err("Unsupported font texture format".to_string()),
+    };
+
+    let scale = ttf
+        .pt_to_px_scale(font.font_size as f32 * config.scale.unwrap_or(1.0))
+        .ok_or("The font file has no units per em")?;
+
+    let mut glyphs = vec![];
+    for c in config.characters.chars() {
+        let rasterized = rasterize_glyph(ttf, scale, c, outline)?;
+        let exists = font.glyphs.iter().any(|g| g.utf16_char == rasterized.chr)
+            || glyphs
+                .iter()
+                .any(|g: &RasterizedGlyph| g.chr == rasterized.chr);
+        if !exists {
+            glyphs.push(rasterized);
+        }
+    }
+    if glyphs.is_empty() {
+        return Ok((font_bytes.to_vec(), txtr_bytes.to_vec()));
+    }
+
+    // Pack the new glyphs in rows below the existing texture
+    let width = txtr.width as usize;
+    let old_height = txtr.height as usize;
+    let (mut x, mut y, mut row_height) = (0, old_height + GLYPH_SPACING, 0);
+    let mut positions = vec![];
+    for glyph in &glyphs {
+        if glyph.width > width {
+            return Err(format!(
+                "The glyph for '{}' is wider than the font texture",
+                char::from_u32(glyph.chr as u32).unwrap()
+            ));
+        }
+        if x + glyph.width > width {
+            x = 0;
+            y += row_height + GLYPH_SPACING;
+            row_height = 0;
+        }
+        positions.push((x, y));
+        x += glyph.width + GLYPH_SPACING;
+        row_height = row_height.max(glyph.height);
+    }
+
+    let mut texels = FontTexels {
+        data: txtr.pixel_data.as_mut_vec()[0].as_mut_vec(),
+        width,
+        bits,
+    };
+    let (_, block_height) = texels.block_dimensions();
+    let height = (y + row_height).div_ceil(block_height) * block_height;
+    if height > MAX_TEXTURE_HEIGHT {
+        return Err(format!(
+            "The font texture would need to be {} texels high, more than the maximum of {}",
+            height, MAX_TEXTURE_HEIGHT
+        ));
+    }
+
+    texels.grow(height);
+    for (glyph, &(gx, gy)) in glyphs.iter().zip(&positions) {
+        for (i, &value) in glyph.texels.iter().enumerate() {
+            if value != 0 {
+                texels.set(gx + i % glyph.width, gy + i / glyph.width, value);
+            }
+        }
+    }
+
+    // Only the first mipmap is kept, the game draws fonts at their native size
+    txtr.pixel_data.as_mut_vec().truncate(1);
+    txtr.height = height as u16;
+
+    // The existing glyphs keep their texels, which are now a smaller part of the texture
+    let v_scale = old_height as f32 / height as f32;
+    for glyph in font.glyphs.as_mut_vec() {
+        glyph.top_uv_coordinate *= v_scale;
+        glyph.bottom_uv_coordinate *= v_scale;
+    }
+
+    let scaled_ttf = ttf.as_scaled(scale);
+    let all_chars: Vec<(u16, GlyphId)> = font
+        .glyphs
+        .iter()
+        .filter_map(|g| {
+            let c = char::from_u32(g.utf16_char as u32)?;
+            Some((g.utf16_char, ttf.glyph_id(c)))
+        })
+        .chain(glyphs.iter().map(|g| (g.chr, g.id)))
+        .collect();
+
+    for (glyph, &(gx, gy)) in glyphs.iter().zip(&positions) {
+        // Each glyph's kerning pairs are a contiguous run, searched from its start index while the
+        // first character matches. Pairs ending on a new character aren't added, as they would
+        // have to be inserted into the existing runs.
+        let kerning_start_index = font.kernings.len() as u32;
+        for &(other_chr, other_id) in &all_chars {
+            let kerning_adjust = scaled_ttf.kern(glyph.id, other_id).round() as i32;
+            if kerning_adjust != 0 {
+                font.kernings.as_mut_vec().push(FontKerning {
+                    char1: glyph.chr,
+                    char2: other_chr,
+                    kerning_adjust,
+                });
+            }
+        }
+        let kerning_start_index = if kerning_start_index == font.kernings.len() as u32 {
+            0
+        } else {
+            kerning_start_index
+        };
+
+        let new_glyph = FontGlyph {
+            utf16_char: glyph.chr,
+            left_uv_coordinate: gx as f32 / width as f32,
+            top_uv_coordinate: gy as f32 / height as f32,
+            right_uv_coordinate: (gx + glyph.width) as f32 / width as f32,
+            bottom_uv_coordinate: (gy + glyph.height) as f32 / height as f32,
+            // The game reads the padding and offsets as signed
+            left_padding: glyph.left_padding as u32,
+            print_head_advance: glyph.width as u32,
+            right_padding: (glyph.advance - glyph.width as i32) as u32,
+            width: glyph.width as u32,
+            height: glyph.height as u32,
+            vertical_offset: glyph.baseline as u32,
+            kerning_start_index,
+        };
+
+        let font_glyphs = font.glyphs.as_mut_vec();
+        let index = font_glyphs.partition_point(|g| g.utf16_char < glyph.chr);
+        font_glyphs.insert(index, new_glyph);
+    }
+
+    let mut new_font_bytes = vec![];
+    font.write_to(&mut new_font_bytes).unwrap();
+    let mut new_txtr_bytes = vec![];
+    txtr.write_to(&mut new_txtr_bytes).unwrap();
+    Ok((new_font_bytes, new_txtr_bytes))
+}
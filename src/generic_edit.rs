@@ -25,12 +25,14 @@ pub fn patch_edit_objects<'r>(
     let edit_objs = sorted_by_key(edit_objs);
 
     /* Add layers */
-    for (_, config) in edit_objs.iter() {
+    let mut requested_layers = HashMap::new();
+    for (obj_id, config) in edit_objs.iter() {
         if config.layer.is_none() {
             continue;
         }
 
-        let layer_id = config.layer.unwrap();
+        let layer_id = area.layer_index(config.layer.as_ref().unwrap())?;
+        requested_layers.insert(*obj_id, layer_id);
         if layer_id >= 63 {
             panic!(
                 "Layer #{} above maximum (63) in room 0x{:X}",
@@ -51,13 +53,12 @@ pub fn patch_edit_objects<'r>(
 
     /* Move Objects */
 
-    for (obj_id, config) in edit_objs.iter() {
-        if config.layer.is_none() {
-            continue;
-        }
-
+    for (obj_id, _) in edit_objs.iter() {
+        let layer_id = match requested_layers.get(obj_id) {
+            Some(layer_id) => *layer_id as usize,
+            None => continue,
+        };
         let obj_id = obj_id & 0x00FFFFFF;
-        let layer_id = config.layer.unwrap() as usize;
 
         // find existing object
         let old_layer_id = {
@@ -9,24 +9,35 @@ use reader_writer::{LCow, Reader};
 pub use structs;
 
 pub mod add_modify_obj_patches;
+pub mod ancs_edits;
 pub mod c_interface;
 pub mod ciso_writer;
+pub mod clone_objects;
 pub mod custom_assets;
 pub mod dol_codegen;
 pub mod dol_patcher;
 pub mod dol_patches;
 pub mod door_meta;
+pub mod dsp_adpcm;
 pub mod elevators;
 pub mod extern_assets;
+pub mod font_glyphs;
 pub mod gcz_writer;
 pub mod generic_edit;
+pub mod map_render;
 pub mod mlvl_wrapper;
 pub mod patch_config;
 pub mod patcher;
 pub mod patches;
+pub mod persistent_flags;
 pub mod pickup_meta;
+pub mod prefabs;
 pub mod room_lookup;
+pub mod script_layers;
 pub mod starting_items;
+pub mod strg_conversions;
+pub mod texture_swaps;
+pub mod thp_conversions;
 pub mod txtr_conversions;
 
 // Helper function to deterministically flatten a HashMap so it's items
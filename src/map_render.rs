@@ -0,0 +1,521 @@
+use std::{borrow::Cow, collections::HashMap, fmt::Write};
+
+use image::{Rgba, RgbaImage};
+use reader_writer::FourCC;
+use structs::{Mapa, MapaObjectType, MapaObjectVisibilityMode};
+
+use crate::{elevators::World, pickup_meta, GcDiscLookupExtensions};
+
+const BACKGROUND: [u8; 4] = [0x00, 0x08, 0x14, 0xFF];
+const SURFACE: [u8; 4] = [0x1D, 0x4F, 0x91, 0x99];
+const BORDER: [u8; 4] = [0x7F, 0xC8, 0xFF, 0xFF];
+
+// Icon size in world units
+const ICON_RADIUS: f32 = 1.5;
+
+/// A world's automap, flattened to a top-down view in world coordinates
+#[derive(Debug, Clone)]
+pub struct WorldMap {
+    pub world: World,
+    pub areas: Vec<AreaMap>,
+}
+
+#[derive(Debug, Clone)]
+pub struct AreaMap {
+    pub mrea: u32,
+    pub name: String,
+    pub triangles: Vec<[[f32; 2]; 3]>,
+    pub borders: Vec<Vec<[f32; 2]>>,
+    pub icons: Vec<MapIcon>,
+    // Average height of the area, higher areas are drawn over lower ones
+    pub height: f32,
+}
+
+#[derive(Debug, Clone)]
+pub struct MapIcon {
+    pub type_: u32,
+    pub editor_id: u32,
+    pub position: [f32; 2],
+    // Direction of the object's local X axis
+    pub facing: [f32; 2],
+}
+
+#[derive(Debug, Clone, Copy)]
+enum IconShape {
+    Square,
+    Diamond,
+    ArrowUp,
+    ArrowDown,
+}
+
+impl MapIcon {
+    fn style(&self) -> Option<(IconShape, [u8; 4])> {
+        const BLUE: [u8; 4] = [0x3F, 0x7F, 0xFF, 0xFF];
+        const GREY: [u8; 4] = [0xA0, 0xA0, 0xA0, 0xFF];
+        const WHITE: [u8; 4] = [0xFF, 0xFF, 0xFF, 0xFF];
+        const PURPLE: [u8; 4] = [0xB0, 0x60, 0xFF, 0xFF];
+        const RED: [u8; 4] = [0xFF, 0x40, 0x30, 0xFF];
+        const YELLOW: [u8; 4] = [0xFF, 0xD0, 0x20, 0xFF];
+        const GREEN: [u8; 4] = [0x40, 0xE0, 0x60, 0xFF];
+
+        use IconShape::*;
+        use MapaObjectType::*;
+        let door = |color| Some((Square, color));
+        match self.type_ {
+            t if t == DoorNormal as u32 || t == DoorBig as u32 || t == DoorBig2 as u32 => {
+                door(BLUE)
+            }
+            t if t == DoorShield as u32 => door(GREY),
+            t if t == DoorIce as u32 => door(WHITE),
+            t if t == DoorWave as u32 => door(PURPLE),
+            t if t == DoorPlasma as u32 => door(RED),
+            7 | 8 | 13 => door(WHITE),
+            9 | 10 | 14 => door(PURPLE),
+            11 | 12 | 15 => door(RED),
+            t if t == DownArrowYellow as u32 => Some((ArrowDown, YELLOW)),
+            t if t == UpArrowYellow as u32 => Some((ArrowUp, YELLOW)),
+            t if t == DownArrowGreen as u32 => Some((ArrowDown, GREEN)),
+            t if t == UpArrowGreen as u32 => Some((ArrowUp, GREEN)),
+            t if t == DownArrowRed as u32 => Some((ArrowDown, RED)),
+            t if t == UpArrowRed as u32 => Some((ArrowUp, RED)),
+            t if t == Elevator as u32 => Some((Diamond, GREEN)),
+            t if t == SaveStation as u32 => Some((Diamond, BLUE)),
+            t if t == MissileStation as u32 => Some((Diamond, RED)),
+            t if t == Pickup as u32 => Some((Diamond, YELLOW)),
+            _ => None,
+        }
+    }
+
+    // The icon's outline, doors are turned to match the door
+    fn outline(&self, shape: IconShape) -> Vec<[f32; 2]> {
+        let [x, y] = self.position;
+        let r = ICON_RADIUS;
+        match shape {
+            IconShape::Square => {
+                let [fx, fy] = self.facing;
+                let (ax, ay) = (fx * r, fy * r);
+                let (bx, by) = (-fy * r, fx * r);
+                vec![
+                    [x - ax - bx, y - ay - by],
+                    [x + ax - bx, y + ay - by],
+                    [x + ax + bx, y + ay + by],
+                    [x - ax + bx, y - ay + by],
+                ]
+            }
+            IconShape::Diamond => vec![[x, y - r], [x + r, y], [x, y + r], [x - r, y]],
+            IconShape::ArrowUp => vec![[x, y + r], [x + r, y - r], [x - r, y - r]],
+            IconShape::ArrowDown => vec![[x, y - r], [x - r, y + r], [x + r, y + r]],
+        }
+    }
+}
+
+fn transform_point(m: &[f32; 12], p: [f32; 3]) -> [f32; 3] {
+    [
+        m[0] * p[0] + m[1] * p[1] + m[2] * p[2] + m[3],
+        m[4] * p[0] + m[5] * p[1] + m[6] * p[2] + m[7],
+        m[8] * p[0] + m[9] * p[1] + m[10] * p[2] + m[11],
+    ]
+}
+
+// Both the GX primitive enum values and their indices show up as primitive types
+fn primitive_triangles(type_: u32, indices: &[u8]) -> Result<Vec<[u8; 3]>, String> {
+    Ok(match type_ {
+        2 | 0x90 => indices
+            .chunks_exact(3)
+            .map(|t| [t[0], t[1], t[2]])
+            .collect(),
+        3 | 0x98 => indices
+            .windows(3)
+            .enumerate()
+            .map(|(i, t)| {
+                if i % 2 == 0 {
+                    [t[0], t[1], t[2]]
+                } else {
+                    [t[1], t[0], t[2]]
+                }
+            })
+            .collect(),
+        4 | 0xA0 => indices
+            .windows(2)
+            .skip(1)
+            .map(|t| [indices[0], t[0], t[1]])
+            .collect(),
+        _ => Err(format!("Unsupported map primitive type 0x{:X}", type_))?,
+    })
+}
+
+impl AreaMap {
+    /// Flattens an area's map, placing it in the world with the area's MLVL transform
+    pub fn from_mapa(
+        mapa: &Mapa,
+        transform: &[f32; 12],
+        mrea: u32,
+        name: String,
+    ) -> Result<Self, String> {
+        let vertices: Vec<[f32; 3]> = mapa
+            .vertices
+            .iter()
+            .map(|v| transform_point(transform, [v[0], v[1], v[2]]))
+            .collect();
+        let vertex = |i: u8| -> Result<[f32; 2], String> {
+            let v = vertices
+                .get(i as usize)
+                .ok_or_else(|| format!("Map of room 0x{:08X} uses missing vertex {}", mrea, i))?;
+            Ok([v[0], v[1]])
+        };
+
+        let mut triangles = vec![];
+        let mut borders = vec![];
+        for surface in mapa.surfaces.iter() {
+            for primitive in surface.primitives.iter() {
+                let indices: Vec<u8> = primitive.indices.iter().collect();
+                for [a, b, c] in primitive_triangles(primitive.type_, &indices)? {
+                    triangles.push([vertex(a)?, vertex(b)?, vertex(c)?]);
+                }
+            }
+            for border in surface.borders.iter() {
+                borders.push(
+                    border
+                        .indices
+                        .iter()
+                        .map(vertex)
+                        .collect::<Result<Vec<_>, _>>()?,
+                );
+            }
+        }
+
+        let icons = mapa
+            .objects
+            .iter()
+            .filter(|obj| obj.visibility_mode != MapaObjectVisibilityMode::Never as u32)
+            .map(|obj| {
+                let m = &obj.transform_matrix;
+                let [x, y, _] = transform_point(transform, [m[3], m[7], m[11]]);
+                let [ox, oy, _] =
+                    transform_point(transform, [m[3] + m[0], m[7] + m[4], m[11] + m[8]]);
+                let (fx, fy) = (ox - x, oy - y);
+                let length = (fx * fx + fy * fy).sqrt();
+                let facing = if length > 0.001 {
+                    [fx / length, fy / length]
+                } else {
+                    [1.0, 0.0]
+                };
+                MapIcon {
+                    type_: obj.type_,
+                    editor_id: obj.editor_id,
+                    position: [x, y],
+                    facing,
+                }
+            })
+            .collect();
+
+        let height = if vertices.is_empty() {
+            transform[11]
+        } else {
+            vertices.iter().map(|v| v[2]).sum::<f32>() / vertices.len() as f32
+        };
+
+        Ok(AreaMap {
+            mrea,
+            name,
+            triangles,
+            borders,
+            icons,
+            height,
+        })
+    }
+}
+
+/// Reads the automap of every world from the (possibly patched) paks of a disc
+pub fn world_maps(gc_disc: &structs::GcDisc) -> Result<Vec<WorldMap>, String> {
+    let room_names: HashMap<u32, &str> = pickup_meta::ROOM_INFO
+        .iter()
+        .flat_map(|(_, rooms)| rooms.iter())
+        .map(|room| (room.room_id.to_u32(), room.name()))
+        .collect();
+
+    let mut maps = vec![];
+    for world in World::iter() {
+        let file_entry = match gc_disc.find_file(world.to_pak_str()) {
+            Some(file_entry) => file_entry,
+            None => continue,
+        };
+        let pak = match *file_entry.file().unwrap() {
+            structs::FstEntryFile::Pak(ref pak) => Cow::Borrowed(pak),
+            structs::FstEntryFile::Unknown(ref reader) => Cow::Owned(reader.clone().read(())),
+            _ => panic!(),
+        };
+
+        let resources: HashMap<(u32, FourCC), _> = pak
+            .resources
+            .iter()
+            .map(|res| ((res.file_id, res.fourcc()), res))
+            .collect();
+        let find = |id: u32, fourcc: &[u8; 4]| {
+            resources
+                .get(&(id, FourCC::from_bytes(fourcc)))
+                .filter(|res| !res.compressed)
+        };
+
+        // Worlds without a map, or which were gutted, are skipped
+        let mlvl = match find(world.mlvl(), b"MLVL") {
+            Some(res) => res.kind.as_mlvl().unwrap(),
+            None => continue,
+        };
+        let mapw = match find(mlvl.world_map_mapw, b"MAPW") {
+            Some(res) => res.kind.as_mapw().unwrap(),
+            None => continue,
+        };
+
+        let mut areas = vec![];
+        for (area, mapa_id) in mlvl.areas.iter().zip(mapw.area_maps.iter()) {
+            let mapa = match find(*mapa_id, b"MAPA") {
+                Some(res) => res.kind.as_mapa().unwrap(),
+                None => continue,
+            };
+            let mrea = area.mrea.to_u32();
+            let name = room_names
+                .get(&mrea)
+                .map(|name| name.to_string())
+                .unwrap_or_else(|| format!("0x{:08X}", mrea));
+            let transform: [f32; 12] = area.area_transform.into();
+            areas.push(AreaMap::from_mapa(&mapa, &transform, mrea, name)?);
+        }
+        areas.sort_by(|a, b| a.height.total_cmp(&b.height));
+
+        maps.push(WorldMap { world, areas });
+    }
+
+    Ok(maps)
+}
+
+fn svg_color(color: [u8; 4]) -> String {
+    format!("#{:02x}{:02x}{:02x}", color[0], color[1], color[2])
+}
+
+// The SVG's Y axis points down
+fn svg_point([x, y]: [f32; 2]) -> [f32; 2] {
+    // Adding zero turns -0.0 into 0.0
+    [x, -y + 0.0]
+}
+
+fn xml_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+impl WorldMap {
+    // (min x, min y, max x, max y) of everything drawn, with some margin
+    fn bounds(&self) -> [f32; 4] {
+        let mut bounds = [f32::MAX, f32::MAX, f32::MIN, f32::MIN];
+        let points = self.areas.iter().flat_map(|area| {
+            area.triangles
+                .iter()
+                .flatten()
+                .chain(area.borders.iter().flatten())
+                .chain(area.icons.iter().map(|icon| &icon.position))
+        });
+        for &[x, y] in points {
+            bounds = [
+                bounds[0].min(x),
+                bounds[1].min(y),
+                bounds[2].max(x),
+                bounds[3].max(y),
+            ];
+        }
+        if bounds[0] > bounds[2] {
+            return [0.0, 0.0, 1.0, 1.0];
+        }
+
+        let margin = ICON_RADIUS * 4.0;
+        [
+            bounds[0] - margin,
+            bounds[1] - margin,
+            bounds[2] + margin,
+            bounds[3] + margin,
+        ]
+    }
+
+    /// Draws the map as seen from above, with +Y up
+    pub fn to_svg(&self) -> String {
+        let [min_x, min_y, max_x, max_y] = self.bounds();
+        let mut svg = String::new();
+        writeln!(
+            svg,
+            r#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="{} {} {} {}">"#,
+            min_x,
+            -max_y,
+            max_x - min_x,
+            max_y - min_y
+        )
+        .unwrap();
+        writeln!(
+            svg,
+            "<title>{}</title>",
+            xml_escape(self.world.to_json_key())
+        )
+        .unwrap();
+        writeln!(
+            svg,
+            "<style>.surface{{fill:{};fill-opacity:{:.2};stroke:none}} \
+             .border{{fill:none;stroke:{};stroke-width:0.3;stroke-linejoin:round}}</style>",
+            svg_color(SURFACE),
+            SURFACE[3] as f32 / 255.0,
+            svg_color(BORDER),
+        )
+        .unwrap();
+        writeln!(
+            svg,
+            r#"<rect x="{}" y="{}" width="{}" height="{}" fill="{}"/>"#,
+            min_x,
+            -max_y,
+            max_x - min_x,
+            max_y - min_y,
+            svg_color(BACKGROUND)
+        )
+        .unwrap();
+
+        for area in &self.areas {
+            writeln!(svg, r#"<g id="room-{:08X}">"#, area.mrea).unwrap();
+            writeln!(svg, "<title>{}</title>", xml_escape(&area.name)).unwrap();
+
+            let mut d = String::new();
+            for triangle in &area.triangles {
+                let [a, b, c] = triangle.map(svg_point);
+                write!(
+                    d,
+                    "M{:.2} {:.2}L{:.2} {:.2}L{:.2} {:.2}Z",
+                    a[0], a[1], b[0], b[1], c[0], c[1]
+                )
+                .unwrap();
+            }
+            writeln!(svg, r#"<path class="surface" d="{}"/>"#, d).unwrap();
+
+            let mut d = String::new();
+            for border in area.borders.iter().filter(|b| !b.is_empty()) {
+                for (i, &point) in border.iter().enumerate() {
+                    let command = if i == 0 { 'M' } else { 'L' };
+                    let [x, y] = svg_point(point);
+                    write!(d, "{}{:.2} {:.2}", command, x, y).unwrap();
+                }
+            }
+            writeln!(svg, r#"<path class="border" d="{}"/>"#, d).unwrap();
+
+            for icon in &area.icons {
+                let (shape, color) = match icon.style() {
+                    Some(style) => style,
+                    None => continue,
+                };
+                let points: Vec<String> = icon
+                    .outline(shape)
+                    .into_iter()
+                    .map(|p| {
+                        let [x, y] = svg_point(p);
+                        format!("{:.2},{:.2}", x, y)
+                    })
+                    .collect();
+                writeln!(
+                    svg,
+                    r#"<polygon points="{}" fill="{}"><title>0x{:08X}</title></polygon>"#,
+                    points.join(" "),
+                    svg_color(color),
+                    icon.editor_id
+                )
+                .unwrap();
+            }
+
+            writeln!(svg, "</g>").unwrap();
+        }
+
+        writeln!(svg, "</svg>").unwrap();
+        svg
+    }
+
+    /// Rasterizes the map the same way as `to_svg`, with its longest side `size` pixels long
+    pub fn to_image(&self, size: u32) -> RgbaImage {
+        let [min_x, min_y, max_x, max_y] = self.bounds();
+        let scale = size as f32 / (max_x - min_x).max(max_y - min_y);
+        let width = (((max_x - min_x) * scale).ceil() as u32).max(1);
+        let height = (((max_y - min_y) * scale).ceil() as u32).max(1);
+        let to_pixel = |[x, y]: [f32; 2]| [(x - min_x) * scale, (max_y - y) * scale];
+
+        let mut image = RgbaImage::from_pixel(width, height, Rgba(BACKGROUND));
+        for area in &self.areas {
+            for triangle in &area.triangles {
+                fill_triangle(&mut image, triangle.map(to_pixel), SURFACE);
+            }
+            for border in &area.borders {
+                for segment in border.windows(2) {
+                    draw_line(
+                        &mut image,
+                        to_pixel(segment[0]),
+                        to_pixel(segment[1]),
+                        BORDER,
+                    );
+                }
+            }
+            for icon in &area.icons {
+                if let Some((shape, color)) = icon.style() {
+                    // Outlines are convex, so can be drawn as a fan
+                    let outline: Vec<_> = icon.outline(shape).into_iter().map(to_pixel).collect();
+                    for edge in outline[1..].windows(2) {
+                        fill_triangle(&mut image, [outline[0], edge[0], edge[1]], color);
+                    }
+                }
+            }
+        }
+        image
+    }
+}
+
+fn blend(image: &mut RgbaImage, x: i64, y: i64, color: [u8; 4]) {
+    if x < 0 || y < 0 || x >= image.width() as i64 || y >= image.height() as i64 {
+        return;
+    }
+    let pixel = image.get_pixel_mut(x as u32, y as u32);
+    let alpha = color[3] as u32;
+    for i in 0..3 {
+        pixel[i] = ((color[i] as u32 * alpha + pixel[i] as u32 * (255 - alpha)) / 255) as u8;
+    }
+}
+
+fn fill_triangle(image: &mut RgbaImage, [a, b, c]: [[f32; 2]; 3], color: [u8; 4]) {
+    let edge = |p: [f32; 2], q: [f32; 2], x: f32, y: f32| {
+        (q[0] - p[0]) * (y - p[1]) - (q[1] - p[1]) * (x - p[0])
+    };
+    let area = edge(a, b, c[0], c[1]);
+    if area.abs() < f32::EPSILON {
+        return;
+    }
+
+    let min_x = a[0].min(b[0]).min(c[0]).floor() as i64;
+    let max_x = a[0].max(b[0]).max(c[0]).ceil() as i64;
+    let min_y = a[1].min(b[1]).min(c[1]).floor() as i64;
+    let max_y = a[1].max(b[1]).max(c[1]).ceil() as i64;
+    for y in min_y..=max_y {
+        for x in min_x..=max_x {
+            let (px, py) = (x as f32 + 0.5, y as f32 + 0.5);
+            // Inside when on the same side of all three edges as the triangle's own winding
+            let w = [edge(a, b, px, py), edge(b, c, px, py), edge(c, a, px, py)];
+            if w.iter().all(|&w| w * area >= 0.0) {
+                blend(image, x, y, color);
+            }
+        }
+    }
+}
+
+fn draw_line(image: &mut RgbaImage, from: [f32; 2], to: [f32; 2], color: [u8; 4]) {
+    let steps = (to[0] - from[0]).abs().max((to[1] - from[1]).abs()).ceil() as i64;
+    let mut last = None;
+    for i in 0..=steps.max(1) {
+        let t = i as f32 / steps.max(1) as f32;
+        let x = (from[0] + (to[0] - from[0]) * t) as i64;
+        let y = (from[1] + (to[1] - from[1]) * t) as i64;
+        if last != Some((x, y)) {
+            blend(image, x, y, color);
+            last = Some((x, y));
+        }
+    }
+}
@@ -1,15 +1,34 @@
-use std::collections::{HashMap, HashSet};
+use std::{
+    borrow::Cow,
+    collections::{HashMap, HashSet},
+};
 
 use reader_writer::{CStr, CStrConversionExtension, FourCC, LazyArray};
 use structs::{
-    Area, AreaLayerFlags, Dependency, MemoryRelayConn, Mlvl, Mrea, Resource, ResourceListCursor,
-    SclyLayer, SclyObject,
+    Area, AreaLayerFlags, Dependency, MemoryRelayConn, Mlvl, Mrea, Resource, ResourceKind,
+    ResourceListCursor, SclyLayer, SclyObject,
+};
+
+use crate::{
+    patch_config::LayerId,
+    pickup_meta,
+    prefabs::{PREFAB_OBJECT_INDEX_END, PREFAB_OBJECT_INDEX_START},
+    GcDiscLookupExtensions, ResourceData,
 };
 
 pub struct MlvlEditor<'r> {
     pub mlvl: Mlvl<'r>,
 }
 
+// The indices given to prefab objects are reserved, so the two never hand out the same id
+fn skip_prefab_indices(index: u32) -> u32 {
+    if (PREFAB_OBJECT_INDEX_END..=PREFAB_OBJECT_INDEX_START).contains(&index) {
+        PREFAB_OBJECT_INDEX_END - 1
+    } else {
+        index
+    }
+}
+
 pub struct MlvlArea<'r, 'mlvl, 'cursor, 'list> {
     pub mrea_index: usize,
     pub mrea_cursor: &'cursor mut ResourceListCursor<'r, 'list>,
@@ -61,9 +80,7 @@ impl<'r> MlvlArea<'r, '_, '_, '_> {
     }
 
     pub fn get_layer_id_from_name(&mut self, layer_name: &str) -> usize {
-        let layer_name_nul = format!("{}\0", layer_name);
-        let c_layer_name = (layer_name_nul[..]).as_bytes().as_cstr();
-        let layer_id = self.layer_names.iter().position(|x| x.eq(&c_layer_name));
+        let layer_id = self.find_layer_by_name(layer_name);
 
         if layer_id.is_none() {
             panic!("Layer {} doesn't exist", layer_name);
@@ -72,6 +89,34 @@ impl<'r> MlvlArea<'r, '_, '_, '_> {
         layer_id.unwrap()
     }
 
+    pub fn find_layer_by_name(&self, layer_name: &str) -> Option<usize> {
+        let layer_name_nul = format!("{}\0", layer_name);
+        let c_layer_name = (layer_name_nul[..]).as_bytes().as_cstr();
+        self.layer_names.iter().position(|x| x.eq(&c_layer_name))
+    }
+
+    /// Returns the index of the layer given by `layer`. Indices past the last layer are returned as
+    /// is, because the patches placing objects create any missing layers.
+    pub fn layer_index(&self, layer: &LayerId) -> Result<u32, String> {
+        match layer {
+            LayerId::Index(index) => Ok(*index),
+            LayerId::Name(name) => self
+                .find_layer_by_name(name)
+                .map(|index| index as u32)
+                .ok_or_else(|| {
+                    format!(
+                        "Room 0x{:X} has no layer named '{}'",
+                        self.mlvl_area.mrea.to_u32(),
+                        name
+                    )
+                }),
+        }
+    }
+
+    pub fn resolve_layer(&self, layer: Option<&LayerId>) -> Result<Option<u32>, String> {
+        layer.map(|layer| self.layer_index(layer)).transpose()
+    }
+
     pub fn object_id_from_layer_name(&mut self, layer_name: &str, internal_idx: usize) -> u32 {
         let layer_id = self.get_layer_id_from_name(layer_name);
         self.object_id_from_layer_id(layer_id, internal_idx)
@@ -97,7 +142,7 @@ impl<'r> MlvlArea<'r, '_, '_, '_> {
 
     pub fn new_object_id_from_layer_id(&mut self, layer_id: usize) -> u32 {
         let mut new_obj_id: u32 = self.last_assigned_object_id;
-        new_obj_id -= 1; // subtract one to garuntee uniqueness
+        new_obj_id = skip_prefab_indices(new_obj_id - 1); // subtract one to garuntee uniqueness
 
         if new_obj_id < 0x7fff {
             panic!(
@@ -114,7 +159,7 @@ impl<'r> MlvlArea<'r, '_, '_, '_> {
 
         // search for a free slot in the existing objects
         while objects.any(|obj| obj.instance_id & 0xefff == new_obj_id) {
-            new_obj_id -= 1;
+            new_obj_id = skip_prefab_indices(new_obj_id - 1);
 
             if new_obj_id < 0x7fff {
                 panic!(
@@ -229,6 +274,88 @@ impl<'r> MlvlArea<'r, '_, '_, '_> {
         assert!(self.layer_flags.layer_count == self.mrea().scly_section().layers.len() as u32);
     }
 
+    /// Moves every object (and the dependencies loaded for them) from one layer to another
+    pub fn move_layer_objects(&mut self, from: usize, to: usize) {
+        let layers = self.mrea().scly_section_mut().layers.as_mut_vec();
+        let objects: Vec<SclyObject> = layers[from].objects.as_mut_vec().drain(..).collect();
+        layers[to].objects.as_mut_vec().extend(objects);
+
+        let deps = self.mlvl_area.dependencies.deps.as_mut_vec();
+        let moved: Vec<Dependency> = deps[from].as_mut_vec().drain(..).collect();
+        let target = deps[to].as_mut_vec();
+        for dep in moved {
+            if !target.contains(&dep) {
+                target.push(dep);
+            }
+        }
+    }
+
+    /// Removes a layer and its objects. Later layers each move down one index, and the layer bits
+    /// of their objects' instance ids, of the connections to those objects and of the world's
+    /// memory relay connections are renumbered to match. The layer's dependencies are kept on the
+    /// default layer, as objects elsewhere may rely on them.
+    pub fn delete_layer(&mut self, index: usize) {
+        assert!(index > 0 && index < self.layer_flags.layer_count as usize);
+
+        self.layer_names.remove(index);
+
+        let flags = self.layer_flags.flags;
+        let below = flags & ((1u64 << index) - 1);
+        let above = flags.checked_shr(index as u32 + 1).unwrap_or(0) << index;
+        self.layer_flags.flags = below | above;
+        self.layer_flags.layer_count -= 1;
+
+        {
+            let deps = self.mlvl_area.dependencies.deps.as_mut_vec();
+            let removed: Vec<Dependency> = deps.remove(index).as_mut_vec().drain(..).collect();
+            let default_layer = deps[0].as_mut_vec();
+            for dep in removed {
+                if !default_layer.contains(&dep) {
+                    default_layer.push(dep);
+                }
+            }
+        }
+
+        let layers = self.mrea().scly_section_mut().layers.as_mut_vec();
+        layers.remove(index);
+
+        // (id & 0x00FFFFFF) -> renumbered id
+        let mut renumbered = HashMap::new();
+        for (layer_index, layer) in layers.iter_mut().enumerate().skip(index) {
+            for obj in layer.objects.as_mut_vec().iter_mut() {
+                let id = (obj.instance_id & 0x03FFFFFF) | ((layer_index as u32) << 26);
+                renumbered.insert(obj.instance_id & 0x00FFFFFF, id);
+                obj.instance_id = id;
+            }
+        }
+        let renumber = |id: &mut u32| {
+            if let Some(new_id) = renumbered.get(&(*id & 0x00FFFFFF)) {
+                *id = *new_id;
+            }
+        };
+
+        for layer in layers.iter_mut() {
+            for obj in layer.objects.as_mut_vec().iter_mut() {
+                for conn in obj.connections.as_mut_vec().iter_mut() {
+                    renumber(&mut conn.target_object_id);
+                }
+            }
+        }
+
+        let area_bits = (self.mrea_index as u32) << 16;
+        for conn in self.memory_relay_conns.as_mut_vec().iter_mut() {
+            if conn.sender_id & 0x03FF0000 == area_bits {
+                renumber(&mut conn.sender_id);
+            }
+            if conn.target_id & 0x03FF0000 == area_bits {
+                renumber(&mut conn.target_id);
+            }
+        }
+
+        assert!(self.layer_names.len() as u32 == self.layer_flags.layer_count);
+        assert!(self.layer_flags.layer_count == self.mrea().scly_section().layers.len() as u32);
+    }
+
     pub fn add_dependencies<I>(
         &mut self,
         pickup_resources: &HashMap<(u32, FourCC), Resource<'r>>,
@@ -256,6 +383,38 @@ impl<'r> MlvlArea<'r, '_, '_, '_> {
         self.mrea_cursor.insert_after(iter);
     }
 
+    /// Like `add_dependencies`, but also adds everything `deps` in turn reference (see
+    /// `structs::dependency_closure`). Indirect dependencies are only added if they are present in
+    /// `pickup_resources`; the requested ones must always be.
+    pub fn add_dependencies_with_closure<I>(
+        &mut self,
+        pickup_resources: &HashMap<(u32, FourCC), Resource<'r>>,
+        layer_num: usize,
+        deps: I,
+    ) where
+        I: Iterator<Item = Dependency>,
+    {
+        let roots: Vec<Dependency> = deps.collect();
+        let closure = structs::dependency_closure(roots.iter().cloned(), |dep| {
+            pickup_resources
+                .get(&(dep.asset_id, dep.asset_type))
+                .and_then(resource_bytes)
+        });
+        let deps = closure.into_iter().filter(|dep| {
+            roots.contains(dep) || pickup_resources.contains_key(&(dep.asset_id, dep.asset_type))
+        });
+        self.add_dependencies(pickup_resources, layer_num, deps);
+    }
+
+    /// The dependency layer that lists `dep`, if the room lists it at all
+    pub fn dependency_layer(&self, dep: &Dependency) -> Option<usize> {
+        self.mlvl_area
+            .dependencies
+            .deps
+            .iter()
+            .position(|layer| layer.iter().any(|d| *d == *dep))
+    }
+
     pub fn remove_dependencies<I>(&mut self, deps: I)
     where
         I: IntoIterator<Item = Dependency>,
@@ -281,3 +440,75 @@ impl<'r> MlvlArea<'r, '_, '_, '_> {
         }
     }
 }
+
+pub fn world_mlvl<'r>(gc_disc: &structs::GcDisc<'r>, pak_name: &str) -> Result<Mlvl<'r>, String> {
+    let file_entry = gc_disc
+        .find_file(pak_name)
+        .ok_or_else(|| format!("Failed to find {}", pak_name))?;
+    let pak = match *file_entry.file().unwrap() {
+        structs::FstEntryFile::Pak(ref pak) => Cow::Borrowed(pak),
+        structs::FstEntryFile::Unknown(ref reader) => Cow::Owned(reader.clone().read(())),
+        _ => panic!(),
+    };
+    let mlvl = pak
+        .resources
+        .iter()
+        .find(|res| res.fourcc() == b"MLVL".into())
+        .unwrap()
+        .kind
+        .as_mlvl()
+        .unwrap()
+        .into_owned();
+
+    Ok(mlvl)
+}
+
+/// The rooms, as (pak name, MREA id), whose dependency lists hold an asset
+pub type RoomsListing = HashMap<(u32, FourCC), Vec<(&'static str, u32)>>;
+
+/// Finds the rooms that list each of `assets`
+pub fn rooms_listing(
+    gc_disc: &structs::GcDisc,
+    assets: &HashSet<(u32, FourCC)>,
+) -> Result<RoomsListing, String> {
+    let mut rooms = RoomsListing::new();
+    if assets.is_empty() {
+        return Ok(rooms);
+    }
+
+    for (pak_name, _) in pickup_meta::ROOM_INFO.iter() {
+        let mlvl = world_mlvl(gc_disc, pak_name)?;
+        for area in mlvl.areas.iter() {
+            let mut listed = HashSet::new();
+            for layer in area.dependencies.deps.iter() {
+                for dep in layer.iter() {
+                    let key = (dep.asset_id, dep.asset_type);
+                    if assets.contains(&key) {
+                        listed.insert(key);
+                    }
+                }
+            }
+            for key in listed {
+                rooms
+                    .entry(key)
+                    .or_default()
+                    .push((pak_name, area.mrea.to_u32()));
+            }
+        }
+    }
+
+    Ok(rooms)
+}
+
+// Resources that have already been parsed are written back out so their dependencies can be found
+fn resource_bytes<'a>(res: &'a Resource) -> Option<Cow<'a, [u8]>> {
+    match res.kind {
+        ResourceKind::Unknown(..) => Some(ResourceData::new(res).decompress()),
+        ResourceKind::External(..) => Some(ResourceData::new_external(res).decompress()),
+        ref kind => {
+            let mut bytes = Vec::with_capacity(kind.size());
+            kind.write_to(&mut bytes).ok()?;
+            Some(Cow::Owned(bytes))
+        }
+    }
+}
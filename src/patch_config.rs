@@ -1,5 +1,6 @@
 use std::{
-    collections::{HashMap, HashSet},
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    convert::TryFrom,
     fmt,
     fs::{self, File, OpenOptions},
     io::Read,
@@ -14,15 +15,18 @@ use serde::{
     de::{Error, Visitor},
     Deserialize, Deserializer, Serialize,
 };
-use structs::{res_id, MapaObjectVisibilityMode, ResId};
+use structs::{res_id, MapaObjectType, MapaObjectVisibilityMode, ResId};
 
 use crate::{
     custom_assets::custom_asset_ids,
     door_meta::DoorType,
     elevators::World,
     pickup_meta::{self, PickupType},
-    room_lookup::ROOM_BY_INTERNAL_ID,
+    prefabs::{instantiate_prefab, PREFAB_OBJECT_INDEX_END, PREFAB_OBJECT_INDEX_START},
+    room_lookup::{ROOM_BY_INTERNAL_ID, ROOM_BY_NAME},
+    sorted_by_key,
     starting_items::StartingItems,
+    txtr_conversions::{huerotate_matrix, RecolorParams},
 };
 
 /*** Parsed Config (fn patch_iso) ***/
@@ -33,6 +37,7 @@ pub enum RunMode {
     CreateIso,
     ExportLogbook,
     ExportAssets,
+    ExportStrings,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -80,14 +85,131 @@ pub enum Beam {
     Plasma,
 }
 
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct FontGlyphsConfig {
+    pub font_file: String,
+    pub characters: String,
+    pub scale: Option<f32>,
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug, Default)]
 #[serde(rename_all = "camelCase", deny_unknown_fields)]
 pub struct GameBanner {
-    pub game_name: Option<String>,
-    pub game_name_full: Option<String>,
-    pub developer: Option<String>,
-    pub developer_full: Option<String>,
-    pub description: Option<String>,
+    pub game_name: Option<Localized<String>>,
+    pub game_name_full: Option<Localized<String>>,
+    pub developer: Option<Localized<String>>,
+    pub developer_full: Option<Localized<String>>,
+    pub description: Option<Localized<String>>,
+
+    // Paths to PNGs shown by loaders (96x32), in the memory card menu (96x32) and as the save
+    // file's icon (32x32)
+    pub image: Option<String>,
+    pub save_banner: Option<String>,
+    pub save_icon: Option<String>,
+}
+
+/// Text given either once for every language, or per language keyed by the FourCC of the
+/// `StrgStringTable` it goes into. Languages missing from the map fall back to ENGL, then to the
+/// first language given.
+#[derive(Serialize, Deserialize, Clone, Debug, Hash, PartialEq, Eq)]
+#[serde(untagged, try_from = "LocalizedJson<T>")]
+pub enum Localized<T> {
+    Single(T),
+    PerLanguage(BTreeMap<String, T>),
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum LocalizedJson<T> {
+    Single(T),
+    PerLanguage(BTreeMap<String, T>),
+}
+
+impl<T> TryFrom<LocalizedJson<T>> for Localized<T> {
+    type Error = String;
+
+    fn try_from(json: LocalizedJson<T>) -> Result<Self, Self::Error> {
+        match json {
+            LocalizedJson::Single(value) => Ok(Localized::Single(value)),
+            LocalizedJson::PerLanguage(map) => {
+                if map.is_empty() {
+                    return Err("A language map must hold at least one language".to_string());
+                }
+                for lang in map.keys() {
+                    if !structs::SUPPORTED_LANGUAGES
+                        .iter()
+                        .any(|supported| supported[..] == *lang.as_bytes())
+                    {
+                        return Err(format!("Unsupported language '{}'", lang));
+                    }
+                }
+                Ok(Localized::PerLanguage(map))
+            }
+        }
+    }
+}
+
+impl<T: Default> Default for Localized<T> {
+    fn default() -> Self {
+        Localized::Single(T::default())
+    }
+}
+
+impl<T> From<T> for Localized<T> {
+    fn from(value: T) -> Self {
+        Localized::Single(value)
+    }
+}
+
+impl<T> Localized<T> {
+    pub fn get(&self, lang: FourCC) -> &T {
+        match self {
+            Localized::Single(value) => value,
+            Localized::PerLanguage(map) => {
+                let lang = String::from_utf8_lossy(lang.as_bytes());
+                map.get(lang.as_ref())
+                    .or_else(|| map.get("ENGL"))
+                    .or_else(|| map.values().next())
+                    .unwrap()
+            }
+        }
+    }
+
+    /// Combines two localized values language by language, each falling back as `get` does.
+    pub fn zip<U, V>(&self, other: &Localized<U>, f: impl Fn(&T, &U) -> V) -> Localized<V> {
+        let mut langs = BTreeSet::new();
+        match (self, other) {
+            (Localized::Single(a), Localized::Single(b)) => return Localized::Single(f(a, b)),
+            (Localized::PerLanguage(a), Localized::Single(_)) => langs.extend(a.keys()),
+            (Localized::Single(_), Localized::PerLanguage(b)) => langs.extend(b.keys()),
+            (Localized::PerLanguage(a), Localized::PerLanguage(b)) => {
+                langs.extend(a.keys());
+                langs.extend(b.keys());
+            }
+        }
+
+        Localized::PerLanguage(
+            langs
+                .into_iter()
+                .map(|lang| {
+                    let fourcc = FourCC::from_bytes(lang.as_bytes().try_into().unwrap());
+                    (lang.clone(), f(self.get(fourcc), other.get(fourcc)))
+                })
+                .collect(),
+        )
+    }
+
+    pub fn map<U>(&self, f: impl Fn(&T) -> U) -> Localized<U> {
+        match self {
+            Localized::Single(value) => Localized::Single(f(value)),
+            Localized::PerLanguage(map) => Localized::PerLanguage(
+                map.iter()
+                    .map(|(lang, value)| (lang.clone(), f(value)))
+                    .collect(),
+            ),
+        }
+    }
 }
 
 #[derive(Deserialize, Debug, Default, Clone)]
@@ -99,8 +221,8 @@ pub struct PickupConfig {
     pub curr_increase: Option<i32>,
     pub max_increase: Option<i32>,
     pub model: Option<String>,
-    pub scan_text: Option<String>,
-    pub hudmemo_text: Option<String>,
+    pub scan_text: Option<Localized<String>>,
+    pub hudmemo_text: Option<Localized<String>>,
     pub respawn: Option<bool>,
     pub position: Option<[f32; 3]>,
     pub modal_hudmemo: Option<bool>,
@@ -130,24 +252,24 @@ impl PickupConfig {
 pub struct ScanConfig {
     pub id: Option<u32>,
     pub actor_id: Option<u32>,
-    pub layer: Option<u32>,
+    pub layer: Option<LayerId>,
     pub position: [f32; 3],
     pub combat_visible: Option<bool>,
     pub rotation: Option<f32>,
     pub is_red: Option<bool>,
     pub logbook_category: Option<u32>,
-    pub logbook_title: Option<String>,
-    pub text: String,
+    pub logbook_title: Option<Localized<String>>,
+    pub text: Localized<String>,
 }
 
-#[derive(Deserialize, Debug, Default, Clone)]
+#[derive(Deserialize, Debug, Default, Clone, PartialEq)]
 #[serde(rename_all = "camelCase", deny_unknown_fields)]
 pub struct DoorDestination {
     pub room_name: String,
     pub dock_num: u32,
 }
 
-#[derive(Deserialize, Debug, Default, Clone)]
+#[derive(Deserialize, Debug, Default, Clone, PartialEq)]
 #[serde(rename_all = "camelCase", deny_unknown_fields)]
 pub struct DoorConfig {
     #[serde(alias = "type")]
@@ -164,6 +286,83 @@ pub struct SuitColors {
     pub gravity_deg: Option<i16>,
     pub phazon_deg: Option<i16>,
     pub gunship_deg: Option<i16>,
+    pub power: Option<SuitRecolor>,
+    pub varia: Option<SuitRecolor>,
+    pub gravity: Option<SuitRecolor>,
+    pub phazon: Option<SuitRecolor>,
+    pub gunship: Option<SuitRecolor>,
+    pub gun: Option<SuitRecolor>,
+}
+
+impl SuitColors {
+    pub fn power_recolor(&self) -> SuitRecolor {
+        SuitRecolor::resolve(&self.power, self.power_deg)
+    }
+
+    pub fn varia_recolor(&self) -> SuitRecolor {
+        SuitRecolor::resolve(&self.varia, self.varia_deg)
+    }
+
+    pub fn gravity_recolor(&self) -> SuitRecolor {
+        SuitRecolor::resolve(&self.gravity, self.gravity_deg)
+    }
+
+    pub fn phazon_recolor(&self) -> SuitRecolor {
+        SuitRecolor::resolve(&self.phazon, self.phazon_deg)
+    }
+
+    // If unspecified, default gunship color to power suit color
+    pub fn gunship_recolor(&self) -> SuitRecolor {
+        if self.gunship.is_none() && self.gunship_deg.is_none() {
+            return self.power_recolor();
+        }
+        SuitRecolor::resolve(&self.gunship, self.gunship_deg)
+    }
+
+    // The arm cannon is shared by every suit, so it doesn't follow any of them
+    pub fn gun_recolor(&self) -> SuitRecolor {
+        SuitRecolor::resolve(&self.gun, None)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct SuitRecolor {
+    pub hue_deg: Option<i16>,
+    pub primary: Option<[u8; 3]>,
+    pub secondary: Option<[u8; 3]>,
+    pub glow: Option<[u8; 3]>,
+    pub saturation: Option<f32>,
+    pub brightness: Option<f32>,
+    // TXTR ID -> PNG weighing the primary, secondary and glow regions in its red, green and blue
+    #[serde(default)]
+    pub masks: BTreeMap<u32, String>,
+}
+
+impl SuitRecolor {
+    // The legacy `*Deg` option is used when the recolor has no hue of its own
+    fn resolve(recolor: &Option<SuitRecolor>, deg: Option<i16>) -> Self {
+        let mut recolor = recolor.clone().unwrap_or_default();
+        recolor.hue_deg = recolor
+            .hue_deg
+            .or(deg)
+            .map(|deg| deg.rem_euclid(360))
+            .filter(|deg| *deg != 0);
+        recolor
+    }
+
+    pub fn is_identity(&self) -> bool {
+        self.params() == RecolorParams::default()
+    }
+
+    pub fn params(&self) -> RecolorParams {
+        RecolorParams {
+            hue_matrix: self.hue_deg.map(|deg| huerotate_matrix(deg as f32)),
+            regions: [self.primary, self.secondary, self.glow],
+            saturation: self.saturation.unwrap_or(1.0),
+            brightness: self.brightness.unwrap_or(1.0),
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Default, Clone)]
@@ -188,7 +387,7 @@ pub struct DefaultGameOptions {
 #[serde(rename_all = "camelCase", deny_unknown_fields)]
 pub struct WaterConfig {
     pub id: Option<u32>,
-    pub layer: Option<u32>,
+    pub layer: Option<LayerId>,
     pub position: [f32; 3],
     pub scale: [f32; 3],
     pub force: Option<[f32; 3]>,
@@ -230,7 +429,7 @@ pub enum PlatformType {
 #[serde(rename_all = "camelCase", deny_unknown_fields)]
 pub struct PlatformConfig {
     pub id: Option<u32>,
-    pub layer: Option<u32>,
+    pub layer: Option<LayerId>,
     pub name: Option<String>,
     pub active: Option<bool>,
     pub position: [f32; 3],
@@ -300,12 +499,20 @@ impl GenericTexture {
     }
 }
 
+// What to replace a texture with in `textureSwaps`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum TextureSwap {
+    Txtr(u32),   // ID of a texture in the game
+    Png(String), // Path to a PNG, converted to a texture when patching
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(rename_all = "camelCase", deny_unknown_fields)]
 pub struct BlockConfig {
     pub id: Option<u32>,
     pub active: Option<bool>,
-    pub layer: Option<u32>,
+    pub layer: Option<LayerId>,
     pub position: [f32; 3],
     pub scale: Option<[f32; 3]>,
     pub texture: Option<GenericTexture>,
@@ -325,7 +532,7 @@ pub struct EscapeSequenceConfig {
 #[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(rename_all = "camelCase", deny_unknown_fields)]
 pub struct CameraHintConfig {
-    pub layer: Option<u32>,
+    pub layer: Option<LayerId>,
     pub trigger_id: Option<u32>,
     pub trigger_pos: [f32; 3],
     pub trigger_scale: [f32; 3],
@@ -353,7 +560,7 @@ pub struct CameraHintConfig {
 #[serde(rename_all = "camelCase", deny_unknown_fields)]
 pub struct LockOnPoint {
     pub id1: Option<u32>,
-    pub layer: Option<u32>,
+    pub layer: Option<LayerId>,
     pub active1: Option<bool>,
     pub id2: Option<u32>,
     pub active2: Option<bool>,
@@ -397,7 +604,7 @@ pub enum DamageType {
 #[serde(rename_all = "camelCase", deny_unknown_fields)]
 pub struct TriggerConfig {
     pub id: Option<u32>,
-    pub layer: Option<u32>,
+    pub layer: Option<LayerId>,
     pub name: Option<String>,
     pub active: Option<bool>,
     pub position: Option<[f32; 3]>,
@@ -492,7 +699,7 @@ pub enum SpecialFunctionType {
 #[serde(rename_all = "camelCase", deny_unknown_fields)]
 pub struct SpecialFunctionConfig {
     pub id: Option<u32>,
-    pub layer: Option<u32>,
+    pub layer: Option<LayerId>,
 
     pub name: Option<String>,
     pub position: Option<[f32; 3]>,
@@ -507,7 +714,7 @@ pub struct SpecialFunctionConfig {
     pub value_param3: Option<f32>,
 
     pub layer_change_room_id: Option<u32>,
-    pub layer_change_layer_id: Option<u32>,
+    pub layer_change_layer_id: Option<LayerId>,
     pub item_id: Option<String>,
 
     pub active: Option<bool>,
@@ -523,7 +730,7 @@ pub struct SpecialFunctionConfig {
 #[serde(rename_all = "camelCase", deny_unknown_fields)]
 pub struct ActorRotateConfig {
     pub id: Option<u32>,
-    pub layer: Option<u32>,
+    pub layer: Option<LayerId>,
     pub name: Option<String>,
     #[serde(alias = "rotationOffset")]
     pub rotation: Option<[f32; 3]>,
@@ -537,7 +744,7 @@ pub struct ActorRotateConfig {
 #[serde(rename_all = "camelCase", deny_unknown_fields)]
 pub struct StreamedAudioConfig {
     pub id: Option<u32>,
-    pub layer: Option<u32>,
+    pub layer: Option<LayerId>,
     pub name: Option<String>,
     pub active: Option<bool>,
     pub audio_file_name: String,
@@ -580,7 +787,7 @@ pub struct ScannableParametersConfig {
 #[derive(Serialize, Deserialize, Debug, Clone, Default)]
 #[serde(rename_all = "camelCase", deny_unknown_fields)]
 pub struct EditObjConfig {
-    pub layer: Option<u32>,
+    pub layer: Option<LayerId>,
     pub active: Option<bool>,
     pub position: Option<[f32; 3]>,
     pub rotation: Option<[f32; 3]>,
@@ -609,11 +816,11 @@ pub struct EditObjConfig {
 // OrthoRevExp = 14,
 // OrthoRevExp2 = 15,
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 #[serde(rename_all = "camelCase", deny_unknown_fields)]
 pub struct FogConfig {
     pub id: Option<u32>,
-    pub layer: Option<u32>,
+    pub layer: Option<LayerId>,
     pub name: Option<String>,
     pub active: Option<bool>,
     pub mode: Option<u32>,
@@ -633,7 +840,7 @@ pub struct BombSlotConfig {
     pub ball_trigger_id: Option<u32>,
     pub activate_slot_id: Option<u32>,
     pub deactivate_slot_id: Option<u32>,
-    pub layer: Option<u32>,
+    pub layer: Option<LayerId>,
     pub active: Option<bool>,
     pub position: [f32; 3],
     pub rotation: [f32; 3],
@@ -644,7 +851,7 @@ pub struct BombSlotConfig {
 #[serde(rename_all = "camelCase", deny_unknown_fields)]
 pub struct PlayerActorConfig {
     pub id: Option<u32>,
-    pub layer: Option<u32>,
+    pub layer: Option<LayerId>,
     pub active: Option<bool>,
     pub position: Option<[f32; 3]>,
     pub rotation: Option<[f32; 3]>,
@@ -663,10 +870,10 @@ pub struct RepositionConfig {
 #[serde(rename_all = "camelCase", deny_unknown_fields)]
 pub struct HudmemoConfig {
     pub id: u32,
-    pub layer: Option<u32>,
+    pub layer: Option<LayerId>,
     pub name: Option<String>,
     pub active: Option<bool>,
-    pub text: Option<String>,
+    pub text: Option<Localized<String>>,
     pub message_time: Option<f32>,
     pub modal: Option<bool>,
 }
@@ -675,7 +882,7 @@ pub struct HudmemoConfig {
 #[serde(rename_all = "camelCase", deny_unknown_fields)]
 pub struct WaypointConfig {
     pub id: u32,
-    pub layer: Option<u32>,
+    pub layer: Option<LayerId>,
     pub name: Option<String>,
     pub position: Option<[f32; 3]>,
     pub rotation: Option<[f32; 3]>,
@@ -695,7 +902,7 @@ pub struct WaypointConfig {
 #[serde(rename_all = "camelCase", deny_unknown_fields)]
 pub struct CounterConfig {
     pub id: u32,
-    pub layer: Option<u32>,
+    pub layer: Option<LayerId>,
     pub name: Option<String>,
     pub active: Option<bool>,
     pub start_value: Option<u32>,
@@ -707,7 +914,7 @@ pub struct CounterConfig {
 #[serde(rename_all = "camelCase", deny_unknown_fields)]
 pub struct SwitchConfig {
     pub id: u32,
-    pub layer: Option<u32>,
+    pub layer: Option<LayerId>,
     pub name: Option<String>,
     pub active: Option<bool>,
     pub open: Option<bool>,
@@ -718,7 +925,7 @@ pub struct SwitchConfig {
 #[serde(rename_all = "camelCase", deny_unknown_fields)]
 pub struct PlayerHintConfig {
     pub id: u32,
-    pub layer: Option<u32>,
+    pub layer: Option<LayerId>,
     pub name: Option<String>,
     pub active: Option<bool>,
     pub priority: Option<u32>,
@@ -826,7 +1033,7 @@ pub enum ControllerActionType {
 #[serde(rename_all = "camelCase", deny_unknown_fields)]
 pub struct ControllerActionConfig {
     pub id: u32,
-    pub layer: Option<u32>,
+    pub layer: Option<LayerId>,
     pub name: Option<String>,
     pub active: Option<bool>,
 
@@ -839,7 +1046,7 @@ pub struct ControllerActionConfig {
 #[serde(rename_all = "camelCase", deny_unknown_fields)]
 pub struct CameraConfig {
     pub id: u32,
-    pub layer: Option<u32>,
+    pub layer: Option<LayerId>,
     pub name: Option<String>,
     pub position: Option<[f32; 3]>,
     pub rotation: Option<[f32; 3]>,
@@ -861,7 +1068,7 @@ pub struct CameraConfig {
 #[serde(rename_all = "camelCase", deny_unknown_fields)]
 pub struct CameraWaypointConfig {
     pub id: u32,
-    pub layer: Option<u32>,
+    pub layer: Option<LayerId>,
     pub name: Option<String>,
     pub position: Option<[f32; 3]>,
     pub rotation: Option<[f32; 3]>,
@@ -903,7 +1110,7 @@ pub enum FilterShape {
 #[serde(rename_all = "camelCase", deny_unknown_fields)]
 pub struct CameraFilterKeyframeConfig {
     pub id: u32,
-    pub layer: Option<u32>,
+    pub layer: Option<LayerId>,
     pub name: Option<String>,
     pub active: Option<bool>,
     pub filter_type: Option<FilterType>,
@@ -934,7 +1141,7 @@ pub enum EBallCameraBehaviour {
 #[serde(rename_all = "camelCase", deny_unknown_fields)]
 pub struct NewCameraHintConfig {
     pub id: u32,
-    pub layer: Option<u32>,
+    pub layer: Option<LayerId>,
     pub name: Option<String>,
     pub position: Option<[f32; 3]>,
     pub rotation: Option<[f32; 3]>,
@@ -995,7 +1202,7 @@ pub struct NewCameraHintConfig {
 #[serde(rename_all = "camelCase", deny_unknown_fields)]
 pub struct CameraHintTriggerConfig {
     pub id: Option<u32>,
-    pub layer: Option<u32>,
+    pub layer: Option<LayerId>,
     pub name: Option<String>,
     pub active: Option<bool>,
     pub position: Option<[f32; 3]>,
@@ -1009,7 +1216,7 @@ pub struct CameraHintTriggerConfig {
 #[serde(rename_all = "camelCase", deny_unknown_fields)]
 pub struct BallTriggerConfig {
     pub id: Option<u32>,
-    pub layer: Option<u32>,
+    pub layer: Option<LayerId>,
     pub name: Option<String>,
     pub position: Option<[f32; 3]>,
     pub scale: Option<[f32; 3]>,
@@ -1034,7 +1241,7 @@ pub enum InitialSplinePosition {
 #[serde(rename_all = "camelCase", deny_unknown_fields)]
 pub struct PathCameraConfig {
     pub id: Option<u32>,
-    pub layer: Option<u32>,
+    pub layer: Option<LayerId>,
     pub name: Option<String>,
     pub position: Option<[f32; 3]>,
     pub rotation: Option<[f32; 3]>,
@@ -1157,11 +1364,37 @@ pub struct ConnectionConfig {
     pub message: ConnectionMsg,
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct FlagTriggerConfig {
+    pub sender_id: u32,
+    pub state: ConnectionState,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct FlagHookConfig {
+    pub target_id: u32,
+    pub message: ConnectionMsg,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct PersistentFlagConfig {
+    pub name: String,
+    pub id: Option<u32>,
+    pub layer: Option<LayerId>,
+    pub set: Option<bool>,
+    pub set_by: Option<Vec<FlagTriggerConfig>>,
+    pub clear_by: Option<Vec<FlagTriggerConfig>>,
+    pub on_set: Option<Vec<FlagHookConfig>>,
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(rename_all = "camelCase", deny_unknown_fields)]
 pub struct RelayConfig {
     pub id: u32,
-    pub layer: Option<u32>,
+    pub layer: Option<LayerId>,
     pub name: Option<String>,
     pub active: Option<bool>,
 }
@@ -1170,7 +1403,7 @@ pub struct RelayConfig {
 #[serde(rename_all = "camelCase", deny_unknown_fields)]
 pub struct TimerConfig {
     pub id: u32,
-    pub layer: Option<u32>,
+    pub layer: Option<LayerId>,
     pub name: Option<String>,
     pub active: Option<bool>,
     pub time: Option<f32>,
@@ -1182,7 +1415,7 @@ pub struct TimerConfig {
 #[serde(rename_all = "camelCase", deny_unknown_fields)]
 pub struct ActorKeyframeConfig {
     pub id: u32,
-    pub layer: Option<u32>,
+    pub layer: Option<LayerId>,
     pub name: Option<String>,
     pub active: Option<bool>,
     pub animation_index: Option<u32>,
@@ -1196,7 +1429,7 @@ pub struct ActorKeyframeConfig {
 #[serde(rename_all = "camelCase", deny_unknown_fields)]
 pub struct SpawnPointConfig {
     pub id: u32,
-    pub layer: Option<u32>,
+    pub layer: Option<LayerId>,
     pub name: Option<String>,
     pub active: Option<bool>,
     pub position: [f32; 3],
@@ -1210,13 +1443,92 @@ pub struct SpawnPointConfig {
 #[serde(rename_all = "camelCase", deny_unknown_fields)]
 pub struct WorldLightFaderConfig {
     pub id: u32,
-    pub layer: Option<u32>,
+    pub layer: Option<LayerId>,
     pub name: Option<String>,
     pub active: Option<bool>,
     pub faded_light_level: Option<f32>,
     pub fade_speed: Option<f32>,
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct CloneObjectConfig {
+    pub source_room: String, // Ex: "Chozo Ruins:Main Plaza"
+    pub instance_id: u32,
+    pub id: Option<u32>,
+    pub layer: Option<LayerId>,
+    pub active: Option<bool>,
+    pub position: Option<[f32; 3]>,
+    pub rotation: Option<[f32; 3]>,
+    pub scale: Option<[f32; 3]>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct MapObjectConfig {
+    #[serde(alias = "type")]
+    pub type_: MapaObjectType,
+    pub position: [f32; 3],
+    pub rotation: Option<[f32; 3]>,
+    pub visibility_mode: Option<MapaObjectVisibilityMode>,
+    pub editor_id: Option<u32>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct MapEditsConfig {
+    pub add_objects: Option<Vec<MapObjectConfig>>,
+    pub remove_objects: Option<Vec<u32>>, // editor ids of the objects to remove
+    pub hide_surfaces: Option<Vec<u32>>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, Eq, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub enum LightType {
+    LocalAmbient = 0,
+    Directional = 1,
+    Custom = 2,
+    Spot = 3,
+}
+
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, Eq, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub enum LightFalloff {
+    Constant = 0,
+    Linear = 1,
+    Quadratic = 2,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct LightConfig {
+    pub layer: Option<u32>, // 0 or 1
+    pub index: Option<u32>, // which light to edit, unused when adding
+    #[serde(alias = "type")]
+    pub light_type: Option<LightType>,
+    pub color: Option<[f32; 3]>,
+    pub position: Option<[f32; 3]>,
+    pub direction: Option<[f32; 3]>,
+    pub brightness: Option<f32>,
+    pub spot_cutoff: Option<f32>,
+    pub falloff: Option<LightFalloff>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct LightIdConfig {
+    pub layer: Option<u32>,
+    pub index: u32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct LightsConfig {
+    pub add: Option<Vec<LightConfig>>,
+    pub edit: Option<Vec<LightConfig>>,
+    pub remove: Option<Vec<LightIdConfig>>,
+}
+
 #[derive(Deserialize, Debug, Default, Clone)]
 #[serde(rename_all = "camelCase", deny_unknown_fields)]
 pub struct RoomConfig {
@@ -1244,8 +1556,12 @@ pub struct RoomConfig {
     pub escape_sequences: Option<Vec<EscapeSequenceConfig>>,
     pub repositions: Option<Vec<RepositionConfig>>,
     pub hudmemos: Option<Vec<HudmemoConfig>>,
-    pub layers: Option<HashMap<u32, bool>>,
-    pub layer_objs: Option<HashMap<u32, u32>>,
+    pub add_layers: Option<Vec<AddLayerConfig>>,
+    pub rename_layers: Option<HashMap<LayerId, String>>,
+    pub move_layers: Option<HashMap<LayerId, LayerId>>, // every object on key's layer is moved to value's layer
+    pub delete_layers: Option<Vec<LayerId>>,
+    pub layers: Option<HashMap<LayerId, bool>>,
+    pub layer_objs: Option<HashMap<u32, LayerId>>,
     pub delete_ids: Option<Vec<u32>>,
     pub audio_override: Option<HashMap<String, String>>, // key=instance_id, value=/audio/min_phazonL.dsp|/audio/min_phazonR.dsp
     pub add_connections: Option<Vec<ConnectionConfig>>,
@@ -1275,11 +1591,98 @@ pub struct RoomConfig {
     pub new_camera_hints: Option<Vec<NewCameraHintConfig>>,
     pub camera_hint_triggers: Option<Vec<CameraHintTriggerConfig>>,
     pub set_memory_relays: Option<Vec<u32>>,
+    pub persistent_flags: Option<Vec<PersistentFlagConfig>>,
     pub ball_triggers: Option<Vec<BallTriggerConfig>>,
     pub path_cameras: Option<Vec<PathCameraConfig>>,
+    pub use_prefab: Option<Vec<PrefabInstanceConfig>>,
+    pub clone_objects: Option<Vec<CloneObjectConfig>>,
+    pub map_edits: Option<MapEditsConfig>,
+    pub lights: Option<LightsConfig>,
+    // only applied to the room's geometry
+    pub texture_swaps: Option<HashMap<u32, TextureSwap>>,
+    // ids handed out to this room's usePrefab objects
+    #[serde(skip)]
+    pub prefab_object_ids: Vec<u32>,
     // Don't forget to update merge_json when adding here
 }
 
+impl RoomConfig {
+    // Every object id this config names, whether it adds the object or edits an existing one
+    pub fn object_ids(&self) -> impl Iterator<Item = u32> + '_ {
+        macro_rules! ids {
+            ($label:ident) => {
+                self.$label.iter().flatten().filter_map(|obj| obj.id)
+            };
+            ($label:ident, required) => {
+                self.$label.iter().flatten().map(|obj| obj.id)
+            };
+        }
+
+        let optional = ids!(liquids)
+            .chain(ids!(pickups))
+            .chain(ids!(extra_scans))
+            .chain(ids!(platforms))
+            .chain(ids!(blocks))
+            .chain(ids!(triggers))
+            .chain(ids!(special_functions))
+            .chain(ids!(actor_rotates))
+            .chain(ids!(streamed_audios))
+            .chain(ids!(distance_fogs))
+            .chain(ids!(player_actors))
+            .chain(ids!(camera_hint_triggers))
+            .chain(ids!(persistent_flags))
+            .chain(ids!(ball_triggers))
+            .chain(ids!(path_cameras))
+            .chain(ids!(clone_objects));
+        let required = ids!(hudmemos, required)
+            .chain(ids!(relays, required))
+            .chain(ids!(timers, required))
+            .chain(ids!(actor_keyframes, required))
+            .chain(ids!(spawn_points, required))
+            .chain(ids!(waypoints, required))
+            .chain(ids!(counters, required))
+            .chain(ids!(switches, required))
+            .chain(ids!(player_hints, required))
+            .chain(ids!(controller_actions, required))
+            .chain(ids!(world_light_faders, required))
+            .chain(ids!(cameras, required))
+            .chain(ids!(camera_waypoints, required))
+            .chain(ids!(camera_filter_keyframes, required))
+            .chain(ids!(new_camera_hints, required));
+
+        optional
+            .chain(required)
+            .chain(self.cutscene_skip_fns.iter().flatten().copied())
+            .chain(self.edit_objs.iter().flat_map(|objs| objs.keys().copied()))
+            .chain(self.layer_objs.iter().flat_map(|objs| objs.keys().copied()))
+            .chain(self.prefab_object_ids.iter().copied())
+    }
+}
+
+#[derive(Deserialize, Debug, Default, Clone)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct AddLayerConfig {
+    pub name: String,
+    pub active: Option<bool>,
+}
+
+#[derive(Deserialize, Debug, Default, Clone, PartialEq)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct PrefabConfig {
+    #[serde(default)]
+    pub parameters: HashMap<String, serde_json::Value>, // null = must be provided by usePrefab
+    pub objects: serde_json::Value, // room config with "@local" ids and "$parameter" values
+}
+
+#[derive(Deserialize, Debug, Default, Clone)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct PrefabInstanceConfig {
+    pub prefab: String,
+    pub position: Option<[f32; 3]>,
+    #[serde(default)]
+    pub parameters: HashMap<String, serde_json::Value>,
+}
+
 #[derive(Deserialize, Debug, Default, Clone)]
 #[serde(rename_all = "camelCase", deny_unknown_fields)]
 pub struct LevelConfig {
@@ -1288,6 +1691,83 @@ pub struct LevelConfig {
 
     #[serde(default)]
     pub rooms: HashMap<String, RoomConfig>,
+
+    #[serde(default)]
+    pub texture_swaps: HashMap<u32, TextureSwap>,
+}
+
+// Text panes get their text from a STRG at runtime, so only how it's drawn can be changed here
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct FrmeWidgetEdit {
+    pub position: Option<[f32; 3]>,
+    pub offset: Option<[f32; 3]>,
+    pub scale: Option<[f32; 3]>,
+    pub color: Option<[f32; 4]>,
+    pub visible: Option<bool>,
+    pub font: Option<u32>,
+    pub fill_color: Option<[f32; 4]>,
+    pub outline_color: Option<[f32; 4]>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, Eq, PartialEq, Hash)]
+#[serde(deny_unknown_fields)]
+pub enum AnimEventKind {
+    Sound,
+    Effect,
+    User,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct AnimEventConfig {
+    pub kind: AnimEventKind,
+    pub index: Option<u32>, // which event of this kind to edit, unused when adding
+    pub time: Option<f32>,  // seconds from the start of the animation
+    pub name: Option<String>,
+    pub character_index: Option<i32>,
+    pub weight: Option<f32>,
+
+    // Sound
+    pub sound_id: Option<u32>,
+    pub reference_amplitude: Option<f32>,
+    pub reference_distance: Option<f32>,
+
+    // Effect
+    pub effect_id: Option<u32>,
+    pub effect_type: Option<String>, // PART, ELSC or SWHC
+    pub bone_name: Option<String>,
+    pub scale: Option<f32>,
+    pub parent_mode: Option<u32>,
+    pub frame_count: Option<u32>,
+
+    // User
+    pub user_event_type: Option<u32>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct AnimEventIdConfig {
+    pub kind: AnimEventKind,
+    pub index: u32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct AnimEventsConfig {
+    pub add: Option<Vec<AnimEventConfig>>,
+    pub edit: Option<Vec<AnimEventConfig>>,
+    pub remove: Option<Vec<AnimEventIdConfig>>,
+    pub mute_sounds: Option<bool>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct AncsEditConfig {
+    #[serde(default)]
+    pub particle_swaps: HashMap<u32, u32>,
+    #[serde(default)]
+    pub animations: HashMap<String, AnimEventsConfig>, // <animation name or "*">: <edits>
 }
 
 #[derive(Serialize, Deserialize, Debug, Default, Clone)]
@@ -1489,6 +1969,73 @@ impl<'de> Deserialize<'de> for SuitDamageReduction {
     }
 }
 
+/// A script layer, given either by its index or by its name
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum LayerId {
+    Index(u32),
+    Name(String),
+}
+
+impl fmt::Display for LayerId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        match self {
+            LayerId::Index(index) => write!(f, "#{}", index),
+            LayerId::Name(name) => write!(f, "'{}'", name),
+        }
+    }
+}
+
+impl Serialize for LayerId {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        match self {
+            LayerId::Index(index) => serializer.serialize_u32(*index),
+            LayerId::Name(name) => serializer.serialize_str(name),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for LayerId {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct LayerIdVisitor;
+
+        impl Visitor<'_> for LayerIdVisitor {
+            type Value = LayerId;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                write!(formatter, "a layer index or layer name")
+            }
+
+            fn visit_u64<E>(self, v: u64) -> Result<Self::Value, E>
+            where
+                E: Error,
+            {
+                u32::try_from(v)
+                    .map(LayerId::Index)
+                    .map_err(|_| E::custom(format!("Layer index {} is out of range", v)))
+            }
+
+            // Object keys are always strings, so "3" is also an index
+            fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
+            where
+                E: Error,
+            {
+                match v.parse::<u32>() {
+                    Ok(index) => Ok(LayerId::Index(index)),
+                    Err(_) => Ok(LayerId::Name(v.to_string())),
+                }
+            }
+        }
+
+        deserializer.deserialize_any(LayerIdVisitor)
+    }
+}
+
 impl fmt::Display for Version {
     fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
         match self {
@@ -1510,7 +2057,10 @@ pub struct PatchConfig {
     pub run_mode: RunMode,
     pub logbook_filename: Option<String>,
     pub export_asset_dir: Option<String>,
+    pub export_strings_filename: Option<String>,
+    pub import_strings_filename: Option<String>,
     pub extern_assets_dir: Option<String>,
+    pub map_render_dir: Option<String>,
     pub seed: u64,
     pub uuid: [u8; 16],
 
@@ -1550,11 +2100,14 @@ pub struct PatchConfig {
 
     #[serde(skip_serializing)] // stop racers from peeking at locations
     pub level_data: HashMap<String, LevelConfig>,
+    pub texture_swaps: HashMap<u32, TextureSwap>,
 
-    pub strg: HashMap<String, Vec<String>>, // "<decimal asset ID>": <non-null terminated table of strings>
+    pub strg: HashMap<String, Localized<Vec<String>>>, // "<decimal asset ID>": <non-null terminated table of strings>
+    pub frme_edits: HashMap<String, HashMap<String, FrmeWidgetEdit>>, // "<decimal asset ID>": <widget name>: <edit>
+    pub ancs_edits: HashMap<String, AncsEditConfig>, // "<decimal asset ID>": <edits>
 
     pub starting_room: String,
-    pub starting_memo: Option<String>,
+    pub starting_memo: Option<Localized<String>>,
     pub spring_ball: bool,
     pub spring_ball_item: PickupType,
     pub warp_to_start: bool,
@@ -1608,15 +2161,16 @@ pub struct PatchConfig {
     pub quickpatch: bool,
 
     pub game_banner: GameBanner,
+    pub font_glyphs: Option<FontGlyphsConfig>,
     pub comment: String,
-    pub main_menu_message: String,
+    pub main_menu_message: Localized<String>,
     pub save_name: Option<String>,
 
     #[serde(skip_serializing)] // skipped for competitive integrity reasons
-    pub credits_string: Option<String>,
+    pub credits_string: Option<Localized<String>>,
 
-    pub results_string: Option<String>,
-    pub artifact_hints: Option<HashMap<String, String>>, // e.g. "Strength":"This item can be found in Ruined Fountain"
+    pub results_string: Option<Localized<String>>,
+    pub artifact_hints: Option<HashMap<String, Localized<String>>>, // e.g. "Strength":"This item can be found in Ruined Fountain"
     pub required_artifact_count: Option<u32>,
     pub artifact_temple_layer_overrides: Option<HashMap<String, bool>>,
     pub no_doors: bool,
@@ -1665,7 +2219,7 @@ struct Preferences {
 #[serde(rename_all = "camelCase", deny_unknown_fields)]
 struct GameConfig {
     starting_room: Option<String>,
-    starting_memo: Option<String>,
+    starting_memo: Option<Localized<String>>,
     spring_ball: Option<bool>,
     spring_ball_item: Option<String>,
     warp_to_start: Option<bool>,
@@ -1718,13 +2272,14 @@ struct GameConfig {
     hall_of_the_elders_bomb_slot_covers: Option<HallOfTheEldersBombSlotCoversConfig>,
 
     game_banner: Option<GameBanner>,
+    font_glyphs: Option<FontGlyphsConfig>,
     comment: Option<String>,
-    main_menu_message: Option<String>,
+    main_menu_message: Option<Localized<String>>,
     save_name: Option<String>,
 
-    credits_string: Option<String>,
-    results_string: Option<String>,
-    artifact_hints: Option<HashMap<String, String>>, // e.g. "Strength":"This item can be found in Ruined Fountain"
+    credits_string: Option<Localized<String>>,
+    results_string: Option<Localized<String>>,
+    artifact_hints: Option<HashMap<String, Localized<String>>>, // e.g. "Strength":"This item can be found in Ruined Fountain"
     artifact_temple_layer_overrides: Option<HashMap<String, bool>>,
     required_artifact_count: Option<u32>,
     no_doors: Option<bool>, // Remove every door from the game
@@ -1734,6 +2289,7 @@ struct GameConfig {
     legacy_block_size: Option<bool>,
     patch_wallcrawling: Option<bool>,
     blast_shield_lockon: Option<bool>,
+    texture_swaps: Option<HashMap<u32, TextureSwap>>,
 }
 
 #[derive(Deserialize, Debug, Default, Clone)]
@@ -1745,10 +2301,13 @@ struct PatchConfigPrivate {
     run_mode: Option<String>,
     logbook_filename: Option<String>,
     export_asset_dir: Option<String>,
+    export_strings_filename: Option<String>,
+    import_strings_filename: Option<String>,
     input_iso: Option<String>,
     output_iso: Option<String>,
     force_vanilla_layout: Option<bool>,
     extern_assets_dir: Option<String>,
+    map_render_dir: Option<String>,
     seed: Option<u64>,
     uuid: Option<[u8; 16]>,
 
@@ -1765,7 +2324,16 @@ struct PatchConfigPrivate {
     level_data: HashMap<String, LevelConfig>,
 
     #[serde(default)]
-    strg: HashMap<String, Vec<String>>, // "<decimal asset ID>": <non-null terminated table of strings>
+    strg: HashMap<String, Localized<Vec<String>>>, // "<decimal asset ID>": <non-null terminated table of strings>
+
+    #[serde(default)]
+    frme_edits: HashMap<String, HashMap<String, FrmeWidgetEdit>>, // "<decimal asset ID>": <widget name>: <edit>
+
+    #[serde(default)]
+    ancs_edits: HashMap<String, AncsEditConfig>, // "<decimal asset ID>": <edits>
+
+    #[serde(default)]
+    prefabs: HashMap<String, PrefabConfig>,
 }
 
 /*** Parse Patcher Input ***/
@@ -1789,17 +2357,81 @@ macro_rules! extend_option_vec {
     };
 }
 
+macro_rules! merge_option_map {
+    ($label:ident, $self:expr, $other:expr, $what:expr, $world_key:expr, $room_name:expr) => {
+        if let Some(other_map) = &$other.$label {
+            let self_map = $self.$label.get_or_insert_with(HashMap::new);
+            for (layer, other_value) in other_map {
+                match self_map.get(layer) {
+                    Some(self_value) if self_value != other_value => {
+                        return Err(format!(
+                            "Conflicting {} {} in {} - {}",
+                            $what, layer, $world_key, $room_name
+                        ))
+                    }
+                    Some(_) => {}
+                    None => {
+                        self_map.insert(layer.clone(), other_value.clone());
+                    }
+                }
+            }
+        }
+    };
+}
+
+fn merge_texture_swaps(
+    dest: &mut HashMap<u32, TextureSwap>,
+    src: &HashMap<u32, TextureSwap>,
+    location: &str,
+) -> Result<(), String> {
+    for (txtr, swap) in src {
+        match dest.get(txtr) {
+            Some(existing) if existing != swap => {
+                return Err(format!(
+                    "Conflicting texture swaps for 0x{:X} in {}",
+                    txtr, location
+                ))
+            }
+            Some(_) => {}
+            None => {
+                dest.insert(*txtr, swap.clone());
+            }
+        }
+    }
+
+    Ok(())
+}
+
+macro_rules! merge_room_value {
+    ($label:ident, $self:expr, $other:expr, $world_key:expr, $room_name:expr) => {
+        if let Some(other_value) = &$other.$label {
+            match &$self.$label {
+                Some(self_value) if self_value != other_value => {
+                    return Err(format!(
+                        "Conflicting {} in {} - {}",
+                        stringify!($label),
+                        $world_key,
+                        $room_name
+                    ))
+                }
+                Some(_) => {}
+                None => $self.$label = Some(other_value.clone()),
+            }
+        }
+    };
+}
+
 macro_rules! merge_optional {
     ($label:ident, $self:expr, $other:expr, $room_name:expr) => {
-        if let Some(other_value) = $other.$label {
-            match $self.$label {
+        if let Some(other_value) = &$other.$label {
+            match &$self.$label {
                 Some(self_value) => {
                     if self_value != other_value {
-                        panic!("Conflict in {}'s editObjs", $room_name);
+                        return Err(format!("Conflict in {}'s editObjs", $room_name));
                     }
                 }
                 None => {
-                    $self.$label = Some(other_value);
+                    $self.$label = Some(other_value.to_owned());
                 }
             }
         }
@@ -1826,6 +2458,9 @@ impl PatchConfig {
             .arg(Arg::with_name("extern assets dir")
                 .long("extern-assets-dir")
                 .takes_value(true))
+            .arg(Arg::with_name("map render dir")
+                .long("map-render-dir")
+                .takes_value(true))
             .arg(Arg::with_name("profile json path")
                 .long("profile")
                 .help("Path to JSON file with patch configuration (cli config takes priority). See documentation for details.")
@@ -1996,6 +2631,9 @@ impl PatchConfig {
         if let Some(extern_assets_dir) = matches.value_of("extern assets dir") {
             patch_config.extern_assets_dir = Some(extern_assets_dir.to_string());
         }
+        if let Some(map_render_dir) = matches.value_of("map render dir") {
+            patch_config.map_render_dir = Some(map_render_dir.to_string());
+        }
         if let Some(map_default_state) = matches.value_of("map default state") {
             patch_config.preferences.map_default_state = Some(map_default_state.to_string());
         }
@@ -2061,9 +2699,7 @@ impl PatchConfig {
 fn merge_json(config: &mut PatchConfigPrivate, text: &'static str) -> Result<(), String> {
     let data = serde_json::from_str(text);
     let data: PatchConfigPrivate = data.map_err(|e| format!("JSON parse failed: {}", e))?;
-    config.merge(data);
-
-    Ok(())
+    config.merge(data)
 }
 
 impl PatchConfigPrivate {
@@ -2093,7 +2729,10 @@ impl PatchConfigPrivate {
                         continue; // not a layer change special function
                     }
 
-                    let layer = special_function.layer_change_layer_id.unwrap();
+                    let layer = match special_function.layer_change_layer_id.as_ref().unwrap() {
+                        LayerId::Index(layer) => *layer,
+                        LayerId::Name(_) => continue, // named layers can't collide
+                    };
                     let internal_id = special_function.layer_change_room_id.unwrap();
 
                     let room_lookup = ROOM_BY_INTERNAL_ID[&internal_id];
@@ -2116,7 +2755,20 @@ impl PatchConfigPrivate {
     }
 
     /* Extends the "stuff" added/edited in each room */
-    pub fn merge(&mut self, other: Self) {
+    pub fn merge(&mut self, other: Self) -> Result<(), String> {
+        for (name, other_prefab) in &other.prefabs {
+            match self.prefabs.get(name) {
+                Some(self_prefab) => {
+                    if self_prefab != other_prefab {
+                        return Err(format!("Conflicting definitions for prefab '{}'", name));
+                    }
+                }
+                None => {
+                    self.prefabs.insert(name.to_string(), other_prefab.clone());
+                }
+            }
+        }
+
         /* First check if there for any conflicts when adding new layers */
         let self_layers = self.layers();
         let other_layers = other.layers();
@@ -2133,7 +2785,7 @@ impl PatchConfigPrivate {
                     .intersection(&other_room_layers)
                     .cloned()
                     .collect();
-                panic!("Room 0x{:X} contains conflicting usage of new layers. The following layer IDs must not be used to resolve this conflict: {:?}", mrea_id, subset);
+                return Err(format!("Room 0x{:X} contains conflicting usage of new layers. The following layer IDs must not be used to resolve this conflict: {:?}", mrea_id, subset));
             }
         }
 
@@ -2150,6 +2802,12 @@ impl PatchConfigPrivate {
                     .insert(world_key.to_string(), LevelConfig::default());
             }
 
+            merge_texture_swaps(
+                &mut self.level_data.get_mut(world_key).unwrap().texture_swaps,
+                &other.level_data.get(world_key).unwrap().texture_swaps,
+                world_key,
+            )?;
+
             let self_rooms = &mut self.level_data.get_mut(world_key).unwrap().rooms;
             let other_rooms = &other.level_data.get(world_key).unwrap().rooms;
 
@@ -2172,6 +2830,7 @@ impl PatchConfigPrivate {
                 extend_option_vec!(hudmemos, self_room_config, other_room_config);
                 extend_option_vec!(delete_ids, self_room_config, other_room_config);
                 extend_option_vec!(set_memory_relays, self_room_config, other_room_config);
+                extend_option_vec!(persistent_flags, self_room_config, other_room_config);
                 extend_option_vec!(add_connections, self_room_config, other_room_config);
                 extend_option_vec!(remove_connections, self_room_config, other_room_config);
                 extend_option_vec!(relays, self_room_config, other_room_config);
@@ -2199,6 +2858,175 @@ impl PatchConfigPrivate {
                 extend_option_vec!(camera_hint_triggers, self_room_config, other_room_config);
                 extend_option_vec!(ball_triggers, self_room_config, other_room_config);
                 extend_option_vec!(path_cameras, self_room_config, other_room_config);
+                extend_option_vec!(use_prefab, self_room_config, other_room_config);
+                extend_option_vec!(clone_objects, self_room_config, other_room_config);
+                extend_option_vec!(add_layers, self_room_config, other_room_config);
+                extend_option_vec!(delete_layers, self_room_config, other_room_config);
+
+                if let Some(other_map_edits) = &other_room_config.map_edits {
+                    let self_map_edits = self_room_config
+                        .map_edits
+                        .get_or_insert_with(MapEditsConfig::default);
+                    extend_option_vec!(add_objects, self_map_edits, other_map_edits);
+                    extend_option_vec!(remove_objects, self_map_edits, other_map_edits);
+                    extend_option_vec!(hide_surfaces, self_map_edits, other_map_edits);
+                }
+
+                if let Some(other_lights) = &other_room_config.lights {
+                    let self_lights = self_room_config
+                        .lights
+                        .get_or_insert_with(LightsConfig::default);
+                    extend_option_vec!(add, self_lights, other_lights);
+                    extend_option_vec!(edit, self_lights, other_lights);
+                    extend_option_vec!(remove, self_lights, other_lights);
+                }
+
+                if let Some(other_swaps) = &other_room_config.texture_swaps {
+                    merge_texture_swaps(
+                        self_room_config
+                            .texture_swaps
+                            .get_or_insert_with(HashMap::new),
+                        other_swaps,
+                        &format!("{} - {}", world_key, room_name),
+                    )?;
+                }
+
+                merge_option_map!(
+                    rename_layers,
+                    self_room_config,
+                    other_room_config,
+                    "names for Layer",
+                    world_key,
+                    room_name
+                );
+                merge_option_map!(
+                    move_layers,
+                    self_room_config,
+                    other_room_config,
+                    "destinations for Layer",
+                    world_key,
+                    room_name
+                );
+                merge_option_map!(
+                    doors,
+                    self_room_config,
+                    other_room_config,
+                    "door configs for dock",
+                    world_key,
+                    room_name
+                );
+                merge_option_map!(
+                    layer_objs,
+                    self_room_config,
+                    other_room_config,
+                    "layers for object",
+                    world_key,
+                    room_name
+                );
+                merge_option_map!(
+                    audio_override,
+                    self_room_config,
+                    other_room_config,
+                    "audio overrides for object",
+                    world_key,
+                    room_name
+                );
+
+                merge_room_value!(
+                    superheated,
+                    self_room_config,
+                    other_room_config,
+                    world_key,
+                    room_name
+                );
+                merge_room_value!(
+                    remove_water,
+                    self_room_config,
+                    other_room_config,
+                    world_key,
+                    room_name
+                );
+                merge_room_value!(
+                    submerge,
+                    self_room_config,
+                    other_room_config,
+                    world_key,
+                    room_name
+                );
+                merge_room_value!(
+                    map_default_state,
+                    self_room_config,
+                    other_room_config,
+                    world_key,
+                    room_name
+                );
+                merge_room_value!(
+                    spawn_position_override,
+                    self_room_config,
+                    other_room_config,
+                    world_key,
+                    room_name
+                );
+                merge_room_value!(
+                    bounding_box_offset,
+                    self_room_config,
+                    other_room_config,
+                    world_key,
+                    room_name
+                );
+                merge_room_value!(
+                    bounding_box_scale,
+                    self_room_config,
+                    other_room_config,
+                    world_key,
+                    room_name
+                );
+                merge_room_value!(
+                    fog,
+                    self_room_config,
+                    other_room_config,
+                    world_key,
+                    room_name
+                );
+                merge_room_value!(
+                    ambient_lighting_scale,
+                    self_room_config,
+                    other_room_config,
+                    world_key,
+                    room_name
+                );
+                merge_room_value!(
+                    enviornmental_effect,
+                    self_room_config,
+                    other_room_config,
+                    world_key,
+                    room_name
+                );
+                merge_room_value!(
+                    initial_enviornmental_effect,
+                    self_room_config,
+                    other_room_config,
+                    world_key,
+                    room_name
+                );
+                merge_room_value!(
+                    initial_thermal_heat_level,
+                    self_room_config,
+                    other_room_config,
+                    world_key,
+                    room_name
+                );
+                merge_room_value!(
+                    xray_fog_distance,
+                    self_room_config,
+                    other_room_config,
+                    world_key,
+                    room_name
+                );
+
+                self_room_config
+                    .prefab_object_ids
+                    .extend(other_room_config.prefab_object_ids.iter().copied());
 
                 if let Some(other_layers) = &other_room_config.layers {
                     if self_room_config.layers.is_none() {
@@ -2210,14 +3038,14 @@ impl PatchConfigPrivate {
                         match self_layers.get_mut(layer) {
                             Some(self_state) => {
                                 if self_state != other_state {
-                                    panic!(
+                                    return Err(format!(
                                         "Conflicting enable/disable state for Layer {} in {} - {}",
                                         layer, world_key, room_name
-                                    );
+                                    ));
                                 }
                             }
                             None => {
-                                self_layers.insert(*layer, *other_state);
+                                self_layers.insert(layer.clone(), *other_state);
                             }
                         }
                     }
@@ -2257,7 +3085,10 @@ impl PatchConfigPrivate {
                                             if DoorType::from_string(other_vuln.to_string())
                                                 != DoorType::from_string(self_vuln.to_string())
                                             {
-                                                panic!("Conflict in {}'s editObjs", room_name);
+                                                return Err(format!(
+                                                    "Conflict in {}'s editObjs",
+                                                    room_name
+                                                ));
                                             }
                                         }
                                         None => {
@@ -2278,10 +3109,10 @@ impl PatchConfigPrivate {
                                                         ) != DoorType::from_string(
                                                             self_vuln.to_string(),
                                                         ) {
-                                                            panic!(
+                                                            return Err(format!(
                                                                 "Conflict in {}'s editObjs",
                                                                 room_name
-                                                            );
+                                                            ));
                                                         }
                                                     }
                                                     None => {
@@ -2301,7 +3132,10 @@ impl PatchConfigPrivate {
                                     match &self_config.scannable_parameters {
                                         Some(self_scan) => {
                                             if self_scan != other_scan {
-                                                panic!("Conflict in {}'s editObjs", room_name);
+                                                return Err(format!(
+                                                    "Conflict in {}'s editObjs",
+                                                    room_name
+                                                ));
                                             }
                                         }
                                         None => {
@@ -2318,10 +3152,10 @@ impl PatchConfigPrivate {
                                                 match self_healths.get_mut(idx) {
                                                     Some(self_health) => {
                                                         if self_health != other_health {
-                                                            panic!(
+                                                            return Err(format!(
                                                                 "Conflict in {}'s editObjs",
                                                                 room_name
-                                                            );
+                                                            ));
                                                         }
                                                     }
                                                     None => {
@@ -2345,6 +3179,8 @@ impl PatchConfigPrivate {
                 }
             }
         }
+
+        Ok(())
     }
 
     fn validate_level_data(&self) -> Result<(), String> {
@@ -2464,9 +3300,84 @@ impl PatchConfigPrivate {
             merge_json(&mut result, QOL_TUTORIAL)?;
         }
 
+        result.expand_prefabs()?;
+
         result.parse_inner(version)
     }
 
+    // replaces each room's usePrefab entries with the objects they describe
+    fn expand_prefabs(&mut self) -> Result<(), String> {
+        let mut instances = Vec::new();
+        for (world_key, level) in sorted_by_key(self.level_data.iter_mut()) {
+            for (room_name, room_config) in sorted_by_key(level.rooms.iter_mut()) {
+                if let Some(use_prefab) = room_config.use_prefab.take() {
+                    instances.push((world_key.clone(), room_name.clone(), use_prefab));
+                }
+            }
+        }
+
+        for (world_key, room_name, use_prefab) in instances {
+            let area_index = ROOM_BY_NAME
+                .get(&(world_key.clone(), room_name.clone()))
+                .ok_or_else(|| {
+                    format!(
+                        "'{}' in levelData is not a room in {}",
+                        room_name, world_key
+                    )
+                })?
+                ._area_index;
+
+            let mut next_index = PREFAB_OBJECT_INDEX_START;
+            for instance in use_prefab {
+                let prefab = self.prefabs.get(&instance.prefab).ok_or_else(|| {
+                    format!(
+                        "Unknown prefab '{}' used in {} - {}",
+                        instance.prefab, world_key, room_name
+                    )
+                })?;
+
+                // Skip over the indices of objects the room's config already names
+                let taken: HashSet<u32> = self.level_data[&world_key]
+                    .rooms
+                    .get(&room_name)
+                    .map(|room| room.object_ids().map(|id| id & 0xFFFF).collect())
+                    .unwrap_or_default();
+
+                let mut assigned = vec![];
+                let mut room_config =
+                    instantiate_prefab(&instance.prefab, prefab, &instance, || {
+                        while taken.contains(&next_index) {
+                            next_index -= 1;
+                        }
+                        if next_index < PREFAB_OBJECT_INDEX_END {
+                            return Err(format!(
+                                "Ran out of prefab object ids in {} - {}",
+                                world_key, room_name
+                            ));
+                        }
+                        let id = (area_index << 16) | next_index;
+                        next_index -= 1;
+                        assigned.push(id);
+                        Ok(id)
+                    })?;
+                room_config.prefab_object_ids = assigned;
+
+                let mut level = LevelConfig::default();
+                level.rooms.insert(room_name.clone(), room_config);
+                let mut fragment = PatchConfigPrivate::default();
+                fragment.level_data.insert(world_key.clone(), level);
+                self.merge(fragment).map_err(|e| {
+                    format!(
+                        "Failed to instantiate prefab '{}' in {} - {}: {}",
+                        instance.prefab, world_key, room_name, e
+                    )
+                })?;
+            }
+        }
+
+        Ok(())
+    }
+
     fn parse_inner(&self, version: Version) -> Result<PatchConfig, String> {
         self.validate_level_data()?;
 
@@ -2476,6 +3387,7 @@ impl PatchConfigPrivate {
                     "create_iso" => RunMode::CreateIso,
                     "export_logbook" => RunMode::ExportLogbook,
                     "export_assets" => RunMode::ExportAssets,
+                    "export_strings" => RunMode::ExportStrings,
                     _ => panic!("Unsupported run mode: {}", self.run_mode.as_ref().unwrap()),
                 }
             } else {
@@ -2746,12 +3658,12 @@ impl PatchConfigPrivate {
             let message = self.game_config.main_menu_message.as_ref();
 
             match message {
-                Some(message) => message.to_string(),
+                Some(message) => message.clone(),
                 None => {
                     if force_vanilla_layout {
-                        "".to_string()
+                        "".to_string().into()
                     } else {
-                        "randomprime".to_string()
+                        "randomprime".to_string().into()
                     }
                 }
             }
@@ -2761,10 +3673,10 @@ impl PatchConfigPrivate {
             let message = self.game_config.credits_string.as_ref();
 
             match message {
-                Some(message) => Some(message.to_string()),
+                Some(message) => Some(message.clone()),
                 None => {
                     if force_vanilla_layout {
-                        Some("".to_string())
+                        Some("".to_string().into())
                     } else {
                         None
                     }
@@ -2776,10 +3688,10 @@ impl PatchConfigPrivate {
             let message = self.game_config.results_string.as_ref();
 
             match message {
-                Some(message) => Some(message.to_string()),
+                Some(message) => Some(message.clone()),
                 None => {
                     if force_vanilla_layout {
-                        Some("".to_string())
+                        Some("".to_string().into())
                     } else {
                         None
                     }
@@ -2831,6 +3743,8 @@ impl PatchConfigPrivate {
             run_mode,
             logbook_filename: self.logbook_filename.clone(),
             export_asset_dir: self.export_asset_dir.clone(),
+            export_strings_filename: self.export_strings_filename.clone(),
+            import_strings_filename: self.import_strings_filename.clone(),
             version,
             input_iso,
             iso_format,
@@ -2840,9 +3754,13 @@ impl PatchConfigPrivate {
             seed: self.seed.unwrap_or(123),
             uuid: self.uuid.unwrap_or([0u8; 16]),
             extern_assets_dir: self.extern_assets_dir.clone(),
+            map_render_dir: self.map_render_dir.clone(),
 
             level_data: self.level_data.clone(),
+            texture_swaps: self.game_config.texture_swaps.clone().unwrap_or_default(),
             strg: self.strg.clone(),
+            frme_edits: self.frme_edits.clone(),
+            ancs_edits: self.ancs_edits.clone(),
 
             qol_game_breaking,
             qol_cosmetic,
@@ -2976,6 +3894,7 @@ impl PatchConfigPrivate {
             missile_costs,
 
             game_banner: self.game_config.game_banner.clone().unwrap_or_default(),
+            font_glyphs: self.game_config.font_glyphs.clone(),
             comment: self.game_config.comment.clone().unwrap_or_default(),
             main_menu_message,
             save_name,
@@ -3021,3 +3940,65 @@ fn read_file(
     partition.open_file(node)?.read_to_end(&mut buf)?;
     Ok(buf)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::txtr_conversions::recolor_color;
+
+    #[test]
+    fn scan_text_and_title_are_combined_per_language() {
+        let scan: ScanConfig = serde_json::from_str(
+            r#"{
+                "position": [0, 0, 0],
+                "text": {"ENGL": "Hello", "FREN": "Bonjour"},
+                "logbookTitle": "Title",
+                "logbookCategory": 1
+            }"#,
+        )
+        .unwrap();
+
+        let strings = scan
+            .text
+            .zip(scan.logbook_title.as_ref().unwrap(), |text, title| {
+                vec![text.clone(), title.clone()]
+            });
+        assert_eq!(strings.get(b"FREN".into()), &["Bonjour", "Title"]);
+        assert_eq!(strings.get(b"GERM".into()), &["Hello", "Title"]);
+
+        let single = Localized::from("a").zip(&Localized::from("b"), |a, b| format!("{a}{b}"));
+        assert_eq!(single, Localized::Single("ab".to_string()));
+    }
+
+    #[test]
+    fn unsupported_languages_are_rejected() {
+        assert!(
+            serde_json::from_str::<HudmemoConfig>(r#"{"id": 1, "text": {"KLNG": "Qapla'"}}"#)
+                .is_err()
+        );
+        assert!(serde_json::from_str::<HudmemoConfig>(r#"{"id": 1, "text": {}}"#).is_err());
+    }
+
+    #[test]
+    fn the_gun_is_only_recolored_by_its_own_spec() {
+        let colors: SuitColors =
+            serde_json::from_str(r#"{"powerDeg": 90, "power": {"primary": [0, 0, 255]}}"#).unwrap();
+        assert!(colors.gun_recolor().is_identity());
+
+        let colors: SuitColors = serde_json::from_str(
+            r#"{"powerDeg": 90, "gun": {"primary": [0, 0, 255], "brightness": 0.5}}"#,
+        )
+        .unwrap();
+        let params = colors.gun_recolor().params();
+        assert_eq!(
+            recolor_color(&params, [200, 0, 0], [1.0, 0.0, 0.0]),
+            [0, 0, 100]
+        );
+        // Without a target of its own, the glow region is left alone
+        assert_eq!(
+            recolor_color(&params, [200, 0, 0], [0.0, 0.0, 1.0]),
+            [100, 0, 0]
+        );
+        assert_eq!(colors.gun_recolor().hue_deg, None);
+    }
+}
@@ -26,39 +26,48 @@ use structs::{
 
 use crate::{
     add_modify_obj_patches::*,
+    ancs_edits,
     ciso_writer::CisoWriter,
+    clone_objects::collect_cloned_objects,
     custom_assets::{
         collect_game_resources, custom_asset_filename, custom_asset_ids, PickupHashKey,
     },
     dol_patches,
     door_meta::{BlastShieldType, DoorType},
+    dsp_adpcm,
     elevators::{is_elevator, Elevator, SpawnRoom, SpawnRoomData, World},
     extern_assets::ExternPickupModel,
+    font_glyphs,
     gcz_writer::GczWriter,
     generic_edit::patch_edit_objects,
-    mlvl_wrapper,
+    map_render, mlvl_wrapper,
     patch_config::{
         ArtifactHintBehavior, BlockConfig, BombSlotCover, ConnectionConfig, ConnectionMsg,
         ConnectionState, CtwkConfig, CutsceneMode, DifficultyBehavior, DoorConfig, DoorOpenMode,
-        FogConfig, GameBanner, GenericTexture, HallOfTheEldersBombSlotCoversConfig, IsoFormat,
-        LevelConfig, PatchConfig, PickupConfig, PlatformConfig, PlatformType, RoomConfig, RunMode,
-        SpecialFunctionType, TimerConfig, Version,
+        FogConfig, FontGlyphsConfig, FrmeWidgetEdit, GameBanner, GenericTexture,
+        HallOfTheEldersBombSlotCoversConfig, IsoFormat, LayerId, LevelConfig, LightConfig,
+        LightFalloff, LightsConfig, Localized, MapEditsConfig, PatchConfig, PickupConfig,
+        PlatformConfig, PlatformType, RoomConfig, RunMode, SpecialFunctionType, SuitRecolor,
+        TimerConfig, Version,
     },
     patcher::{PatcherState, PrimePatcher},
+    persistent_flags,
     pickup_meta::{
         self, pickup_model_for_pickup, pickup_type_for_pickup, DoorLocation, ObjectsToRemove,
         PickupModel, PickupType, ScriptObjectLocation,
     },
     room_lookup::ROOM_BY_NAME,
-    sorted_by_key,
+    script_layers, sorted_by_key,
     starting_items::StartingItems,
+    strg_conversions::{self, StrgExport, StrgTranslations},
     structs::LightLayer,
+    texture_swaps,
     txtr_conversions::{
-        cmpr_compress, cmpr_decompress, huerotate_in_place, huerotate_matrix, whiten_in_place,
-        FUSION_GRAVITY_SUIT_TEXTURES, FUSION_PHAZON_BALL_TEXTURES, FUSION_PHAZON_SUIT_TEXTURES,
-        FUSION_POWER_SUIT_TEXTURES, FUSION_VARIA_SUIT_TEXTURES, GRAVITY_SUIT_TEXTURES,
-        PHAZON_SPIDER_BALL_TEXTURES, PHAZON_SUIT_TEXTURES, POWER_SUIT_TEXTURES, SHIP_TEXTURES,
-        VARIA_SUIT_TEXTURES,
+        cmpr_compress, cmpr_decompress, encode_rgb5a3_texels, recolor_in_place, whiten_in_place,
+        RecolorMask, SuitTexture, FUSION_GRAVITY_SUIT_TEXTURES, FUSION_PHAZON_BALL_TEXTURES,
+        FUSION_PHAZON_SUIT_TEXTURES, FUSION_POWER_SUIT_TEXTURES, FUSION_VARIA_SUIT_TEXTURES,
+        GRAVITY_SUIT_TEXTURES, GUN_BEAM_ANCS, PHAZON_SPIDER_BALL_TEXTURES, PHAZON_SUIT_TEXTURES,
+        POWER_SUIT_TEXTURES, RECOLOR_PRIMARY, SHIP_TEXTURES, VARIA_SUIT_TEXTURES,
     },
     GcDiscLookupExtensions,
 };
@@ -146,8 +155,8 @@ fn post_pickup_relay_template<'r>(
 fn build_artifact_temple_totem_scan_strings<R>(
     level_data: &HashMap<String, LevelConfig>,
     rng: &mut R,
-    artifact_hints: Option<HashMap<String, String>>,
-) -> [String; 12]
+    artifact_hints: Option<HashMap<String, Localized<String>>>,
+) -> [Localized<String>; 12]
 where
     R: Rng,
 {
@@ -260,6 +269,7 @@ where
         }
     }
 
+    let mut scan_text = scan_text.map(Localized::from);
     if artifact_hints.is_some() {
         for (artifact_name, hint) in artifact_hints.unwrap() {
             let words: Vec<&str> = artifact_name.split(' ').collect();
@@ -280,7 +290,7 @@ where
                 _ => panic!("Error - Unknown artifact - '{}'", artifact_name),
             };
 
-            scan_text[idx] = format!("{}\0", hint.to_owned());
+            scan_text[idx] = hint.map(|hint| format!("{}\0", hint));
         }
     }
 
@@ -289,17 +299,17 @@ where
 
 fn patch_artifact_totem_scan_strg(
     res: &mut structs::Resource,
-    text: &str,
+    text: &Localized<String>,
     version: Version,
 ) -> Result<(), String> {
-    let mut string = text.to_string();
-    if version == Version::NtscJ {
-        string = format!("&line-extra-space=4;&font=C29C51F1;{}", string);
-    }
     let strg = res.kind.as_strg_mut().unwrap();
     for st in strg.string_tables.as_mut_vec().iter_mut() {
+        let mut string = text.get(st.lang).clone();
+        if version == Version::NtscJ {
+            string = format!("&line-extra-space=4;&font=C29C51F1;{}", string);
+        }
         let strings = st.strings.as_mut_vec();
-        *strings.last_mut().unwrap() = string.to_string().into();
+        *strings.last_mut().unwrap() = string.into();
     }
     Ok(())
 }
@@ -311,6 +321,29 @@ fn patch_save_banner_txtr(res: &mut structs::Resource) -> Result<(), String> {
     Ok(())
 }
 
+// The memory card banner and icon are TXTRs whose texels the game copies into the save file,
+// it picks the card's banner/icon format from the TXTR's
+fn patch_save_image_txtr(
+    res: &mut structs::Resource,
+    field: &str,
+    path: &str,
+    width: u16,
+    height: u16,
+) -> Result<(), String> {
+    let texels = read_banner_png(field, path, width as u32, height as u32)?;
+    let txtr = structs::Txtr {
+        format: structs::TxtrFormat::Rgb5A3,
+        width,
+        height,
+        pixel_data: vec![texels.into()].into(),
+    };
+    let mut bytes = vec![];
+    txtr.write_to(&mut bytes).unwrap();
+    res.compressed = false;
+    res.kind = structs::ResourceKind::External(bytes, b"TXTR".into());
+    Ok(())
+}
+
 fn patch_tournament_winners<'r>(
     _ps: &mut PatcherState,
     area: &mut mlvl_wrapper::MlvlArea<'r, '_, '_, '_>,
@@ -591,7 +624,110 @@ fn patch_map_door_icon(
                 door_id, mrea_id
             )
         });
-    door_icon.type_ = map_object_type;
+    door_icon.set_door_type(map_object_type);
+
+    Ok(())
+}
+
+fn patch_map_edits(
+    res: &mut structs::Resource,
+    map_edits: &MapEditsConfig,
+    mrea_id: u32,
+) -> Result<(), String> {
+    let mapa = res.kind.as_mapa_mut().unwrap();
+
+    for &editor_id in map_edits.remove_objects.iter().flatten() {
+        if mapa.remove_objects(|obj| obj.editor_id == editor_id) == 0 {
+            return Err(format!(
+                "Failed to find map object 0x{:X} in room 0x{:X}",
+                editor_id, mrea_id
+            ));
+        }
+    }
+
+    for object in map_edits.add_objects.iter().flatten() {
+        mapa.add_object(
+            object.type_ as u32,
+            object
+                .visibility_mode
+                .unwrap_or(MapaObjectVisibilityMode::MapStationOrVisit),
+            object.editor_id.unwrap_or(0xFFFFFFFF),
+            transform_matrix(object.position, object.rotation.unwrap_or([0.0, 0.0, 0.0])),
+        );
+    }
+
+    for &index in map_edits.hide_surfaces.iter().flatten() {
+        mapa.hide_surface(index as usize)
+            .map_err(|e| format!("{} (room 0x{:X})", e, mrea_id))?;
+    }
+
+    Ok(())
+}
+
+// Warn about config positions that are outside the room or end up inside walls. These only show up
+// in-game otherwise.
+fn validate_room_positions(
+    _ps: &mut PatcherState,
+    area: &mut mlvl_wrapper::MlvlArea<'_, '_, '_, '_>,
+    room: &RoomConfig,
+    room_name: &str,
+) -> Result<(), String> {
+    // (what, position, whether the player stands there)
+    let mut positions: Vec<(String, [f32; 3], bool)> = Vec::new();
+    for (i, pickup) in room.pickups.iter().flatten().enumerate() {
+        if let Some(position) = pickup.position {
+            positions.push((format!("pickup #{}", i), position, false));
+        }
+    }
+    for (i, platform) in room.platforms.iter().flatten().enumerate() {
+        positions.push((format!("platform #{}", i), platform.position, false));
+    }
+    for spawn_point in room.spawn_points.iter().flatten() {
+        positions.push((
+            format!("spawn point 0x{:X}", spawn_point.id),
+            spawn_point.position,
+            true,
+        ));
+    }
+    if let Some(position) = room.spawn_position_override {
+        positions.push(("spawn position override".to_string(), position, true));
+    }
+    if positions.is_empty() {
+        return Ok(());
+    }
+
+    let mesh = match area.mrea().collision_section().mesh() {
+        Ok(mesh) => mesh,
+        Err(e) => {
+            println!(
+                "Warning, couldn't check positions in {}, failed to read its collision: {}",
+                room_name, e
+            );
+            return Ok(());
+        }
+    };
+    for (what, position, standing) in positions {
+        // Check the player's body rather than the point at their feet, which touches the floor
+        let probe = if standing {
+            [position[0], position[1], position[2] + 1.0]
+        } else {
+            position
+        };
+
+        let problem = if !mesh.contains(probe) {
+            "is outside the room's bounds"
+        } else if mesh.is_inside_geometry(probe) {
+            "is inside the room's geometry"
+        } else if standing && mesh.floor_below(probe).is_none() {
+            "has no floor below it"
+        } else {
+            continue;
+        };
+        println!(
+            "Warning, {} in {} at {:?} {}",
+            what, room_name, position, problem
+        );
+    }
 
     Ok(())
 }
@@ -3946,9 +4082,9 @@ fn patch_add_poi<'r>(
     strg_id: ResId<res_id::STRG>,
     position: [f32; 3],
     id: Option<u32>,
-    layer: Option<u32>,
+    layer: Option<&LayerId>,
 ) -> Result<(), String> {
-    let layer = layer.unwrap_or(0) as usize;
+    let layer = area.resolve_layer(layer)?.unwrap_or(0) as usize;
 
     let instance_id = match id {
         Some(id) => id,
@@ -3993,10 +4129,10 @@ fn patch_add_scan_actor<'r>(
     game_resources: &HashMap<(u32, FourCC), structs::Resource<'r>>,
     position: [f32; 3],
     rotation: f32,
-    layer: Option<u32>,
+    layer: Option<&LayerId>,
     actor_id: Option<u32>,
 ) -> Result<(), String> {
-    let layer = layer.unwrap_or(0) as usize;
+    let layer = area.resolve_layer(layer)?.unwrap_or(0) as usize;
     let instance_id = actor_id.unwrap_or(area.new_object_id_from_layer_id(layer));
     let scly = area.mrea().scly_section_mut();
     scly.layers.as_mut_vec()[layer]
@@ -4289,6 +4425,88 @@ fn append_suit_texture_copy(
     Ok(())
 }
 
+// The CMPR textures of the beams' arm cannon models, leaving out those the suits already recolor
+fn collect_gun_textures(gc_disc: &structs::GcDisc) -> Result<Vec<SuitTexture>, String> {
+    const SAMUS_GUN_PAKS: &[&[u8]] = &[b"SamusGun.pak"];
+
+    let file_entry = gc_disc
+        .find_file("SamusGun.pak")
+        .ok_or("Failed to find SamusGun.pak")?;
+    let pak = match *file_entry.file().unwrap() {
+        structs::FstEntryFile::Pak(ref pak) => Cow::Borrowed(pak),
+        structs::FstEntryFile::Unknown(ref reader) => Cow::Owned(reader.clone().read(())),
+        _ => panic!(),
+    };
+    let resource_bytes = |dep: &Dependency| {
+        pak.resources
+            .iter()
+            .find(|res| res.file_id == dep.asset_id && res.fourcc() == dep.asset_type)
+            .map(|res| crate::ResourceData::new(&res).decompress().into_owned())
+            .ok_or_else(|| {
+                format!(
+                    "Failed to find 0x{:08X}.{} in SamusGun.pak",
+                    dep.asset_id, dep.asset_type
+                )
+            })
+    };
+
+    let suit_ids: HashSet<u32> = [
+        POWER_SUIT_TEXTURES,
+        VARIA_SUIT_TEXTURES,
+        GRAVITY_SUIT_TEXTURES,
+        PHAZON_SUIT_TEXTURES,
+        FUSION_POWER_SUIT_TEXTURES,
+        FUSION_VARIA_SUIT_TEXTURES,
+        FUSION_GRAVITY_SUIT_TEXTURES,
+        FUSION_PHAZON_SUIT_TEXTURES,
+    ]
+    .iter()
+    .flat_map(|textures| textures.iter())
+    .map(|texture| texture.info.res_id)
+    .collect();
+
+    let mut textures: Vec<SuitTexture> = vec![];
+    for ancs in GUN_BEAM_ANCS {
+        let ancs_dep = Dependency {
+            asset_id: ancs.res_id,
+            asset_type: ancs.fourcc,
+        };
+        let ancs_deps = structs::direct_dependencies(ancs.fourcc, &resource_bytes(&ancs_dep)?);
+        for cmdl in ancs_deps
+            .iter()
+            .filter(|dep| dep.asset_type == b"CMDL".into())
+        {
+            let cmdl_deps = structs::direct_dependencies(cmdl.asset_type, &resource_bytes(cmdl)?);
+            for txtr in cmdl_deps {
+                if suit_ids.contains(&txtr.asset_id)
+                    || textures.iter().any(|t| t.info.res_id == txtr.asset_id)
+                {
+                    continue;
+                }
+                let txtr_bytes = resource_bytes(&txtr)?;
+                let format = Reader::new(&txtr_bytes[..])
+                    .read::<structs::Txtr>(())
+                    .format;
+                if !matches!(format, structs::TxtrFormat::Cmpr) {
+                    continue;
+                }
+                textures.push(SuitTexture {
+                    info: ResourceInfo {
+                        long_name: "",
+                        short_name: None,
+                        res_id: txtr.asset_id,
+                        fourcc: txtr.asset_type,
+                        paks: SAMUS_GUN_PAKS,
+                    },
+                    region: RECOLOR_PRIMARY,
+                });
+            }
+        }
+    }
+
+    Ok(textures)
+}
+
 fn repoint_cmdl_texture(
     res: &mut structs::Resource,
     original_id: u32,
@@ -6401,6 +6619,114 @@ fn patch_visible_aether_boundaries<'r>(
     Ok(())
 }
 
+fn light_layer<'a>(
+    lights: &'a mut structs::Lights<'_>,
+    layer: Option<u32>,
+    mrea_id: u32,
+) -> Result<&'a mut Vec<LightLayer>, String> {
+    match layer.unwrap_or(0) {
+        0 => Ok(lights.light_layers.as_mut_vec()),
+        1 => Ok(lights.light_layers_b.as_mut_vec()),
+        layer => Err(format!(
+            "Invalid light layer {} in room 0x{:X}, must be 0 or 1",
+            layer, mrea_id
+        )),
+    }
+}
+
+fn update_light(light: &mut LightLayer, config: &LightConfig) {
+    if let Some(light_type) = config.light_type {
+        light.light_type = light_type as u32;
+    }
+    if let Some(color) = config.color {
+        light.color = color.into();
+    }
+    if let Some(position) = config.position {
+        light.position = position.into();
+    }
+    if let Some(direction) = config.direction {
+        light.direction = direction.into();
+    }
+    if let Some(brightness) = config.brightness {
+        light.brightness = brightness;
+    }
+    if let Some(spot_cutoff) = config.spot_cutoff {
+        light.spot_cutoff = spot_cutoff;
+    }
+    if let Some(falloff) = config.falloff {
+        light.falloff_type = falloff as u32;
+    }
+}
+
+// Indices in `edit` and `remove` refer to the room's lights before any of them are removed
+fn patch_lights(
+    _ps: &mut PatcherState,
+    area: &mut mlvl_wrapper::MlvlArea<'_, '_, '_, '_>,
+    config: &LightsConfig,
+    mrea_id: u32,
+) -> Result<(), String> {
+    edit_lights(area.mrea().lights_section_mut(), config, mrea_id)
+}
+
+fn edit_lights(
+    lights: &mut structs::Lights,
+    config: &LightsConfig,
+    mrea_id: u32,
+) -> Result<(), String> {
+    for light_config in config.edit.iter().flatten() {
+        let index = light_config
+            .index
+            .ok_or_else(|| format!("Light edit in room 0x{:X} is missing an index", mrea_id))?;
+        let light = light_layer(lights, light_config.layer, mrea_id)?
+            .get_mut(index as usize)
+            .ok_or_else(|| format!("Room 0x{:X} has no light {}", mrea_id, index))?;
+        update_light(light, light_config);
+    }
+
+    let mut remove: Vec<(u32, u32)> = config
+        .remove
+        .iter()
+        .flatten()
+        .map(|light| (light.layer.unwrap_or(0), light.index))
+        .collect();
+    remove.sort_unstable();
+    remove.dedup();
+    for &(layer, index) in remove.iter().rev() {
+        let layer = light_layer(lights, Some(layer), mrea_id)?;
+        if index as usize >= layer.len() {
+            return Err(format!("Room 0x{:X} has no light {}", mrea_id, index));
+        }
+        layer.remove(index as usize);
+    }
+
+    for light_config in config.add.iter().flatten() {
+        if light_config.light_type.is_none() {
+            return Err(format!(
+                "Light added to room 0x{:X} is missing a type",
+                mrea_id
+            ));
+        }
+
+        let mut light = LightLayer {
+            light_type: 0,
+            color: [1.0, 1.0, 1.0].into(),
+            position: [0.0, 0.0, 0.0].into(),
+            direction: [0.0, -1.0, 0.0].into(),
+            brightness: 1.0,
+            spot_cutoff: 0.0,
+            unknown0: 0.0,
+            unknown1: 0,
+            unknown2: 0.0,
+            falloff_type: LightFalloff::Constant as u32,
+            unknown3: 0.0,
+        };
+        update_light(&mut light, light_config);
+        light_layer(lights, light_config.layer, mrea_id)?.push(light);
+    }
+
+    Ok(())
+}
+
 fn patch_ambient_lighting(
     _ps: &mut PatcherState,
     area: &mut mlvl_wrapper::MlvlArea<'_, '_, '_, '_>,
@@ -8002,12 +8328,71 @@ fn patch_remove_ids(
     Ok(())
 }
 
+fn patch_edit_layers(
+    _ps: &mut PatcherState,
+    area: &mut mlvl_wrapper::MlvlArea<'_, '_, '_, '_>,
+    room_name: &str,
+    room: &RoomConfig,
+) -> Result<(), String> {
+    // Catch anything the patches below would choke on with the same errors as config parsing
+    let layer_names = |area: &mlvl_wrapper::MlvlArea<'_, '_, '_, '_>| -> Vec<String> {
+        area.layer_names
+            .iter()
+            .map(|name| name.to_str().unwrap().to_string())
+            .collect()
+    };
+    script_layers::edited_layer_names(layer_names(area), room_name, room)?;
+
+    for add_layer in room.add_layers.iter().flatten() {
+        area.add_layer(string_to_cstr(add_layer.name.clone()));
+        if !add_layer.active.unwrap_or(true) {
+            let index = area.layer_flags.layer_count - 1;
+            area.layer_flags.flags &= !(1 << index);
+        }
+    }
+
+    let names = layer_names(area);
+    for (layer, name) in room.rename_layers.iter().flatten() {
+        let index = script_layers::find_layer(&names, layer).unwrap();
+        area.layer_names[index] = string_to_cstr(name.clone());
+    }
+
+    let names = layer_names(area);
+    for (from, to) in sorted_by_key(room.move_layers.clone().unwrap_or_default()) {
+        let find = |layer: &LayerId| {
+            script_layers::find_layer(&names, layer).ok_or_else(|| {
+                format!(
+                    "Cannot move objects to or from missing layer {} in {}",
+                    layer, room_name
+                )
+            })
+        };
+        let (from, to) = (find(&from)?, find(&to)?);
+        if from != to {
+            area.move_layer_objects(from, to);
+        }
+    }
+
+    for index in script_layers::deleted_layer_indices(&names, room_name, room)?
+        .into_iter()
+        .rev()
+    {
+        area.delete_layer(index);
+    }
+
+    Ok(())
+}
+
 fn patch_set_layers(
     _ps: &mut PatcherState,
     area: &mut mlvl_wrapper::MlvlArea<'_, '_, '_, '_>,
-    layers: HashMap<u32, bool>,
+    layers: HashMap<LayerId, bool>,
 ) -> Result<(), String> {
     let mrea_id = area.mlvl_area.mrea.to_u32();
+    let layers = layers
+        .iter()
+        .map(|(layer, enabled)| Ok((area.layer_index(layer)?, *enabled)))
+        .collect::<Result<HashMap<u32, bool>, String>>()?;
 
     // add more layers if needed
     let max = {
@@ -8046,9 +8431,13 @@ fn patch_set_layers(
 fn patch_move_objects(
     _ps: &mut PatcherState,
     area: &mut mlvl_wrapper::MlvlArea<'_, '_, '_, '_>,
-    layer_objs: HashMap<u32, u32>,
+    layer_objs: HashMap<u32, LayerId>,
 ) -> Result<(), String> {
     let mrea_id = area.mlvl_area.mrea.to_u32();
+    let layer_objs = layer_objs
+        .iter()
+        .map(|(obj_id, layer)| Ok((*obj_id, area.layer_index(layer)?)))
+        .collect::<Result<HashMap<u32, u32>, String>>()?;
     let layer_objs = sorted_by_key(layer_objs);
 
     // Add layers
@@ -10353,7 +10742,11 @@ fn front_end_languages(version: Version) -> Languages {
     }
 }
 
-fn patch_main_strg(res: &mut structs::Resource, version: Version, msg: &str) -> Result<(), String> {
+fn patch_main_strg(
+    res: &mut structs::Resource,
+    version: Version,
+    msg: &Localized<String>,
+) -> Result<(), String> {
     let strg = res.kind.as_strg_mut().unwrap();
     check_english_string(strg, EXTRAS_MENU_INDEX, "Metroid Fusion Connection Bonuses")?;
 
@@ -10384,7 +10777,10 @@ fn patch_main_strg(res: &mut structs::Resource, version: Version, msg: &str) ->
         return Err("Found no empty-slot world name in STRG_Main".to_string());
     }
 
-    strg.add_strings(&[format!("{}\0", msg)], front_end_languages(version));
+    strg.add_localized_string(
+        |lang| format!("{}\0", msg.get(lang)),
+        front_end_languages(version),
+    );
 
     Ok(())
 }
@@ -10398,6 +10794,129 @@ fn patch_no_hud(res: &mut structs::Resource) -> Result<(), String> {
     Ok(())
 }
 
+fn patch_frme_edits(
+    res: &mut structs::Resource,
+    edits: &HashMap<String, FrmeWidgetEdit>,
+) -> Result<(), String> {
+    let frme_id = res.file_id;
+    let frme = res.kind.as_frme_mut().unwrap();
+
+    for (name, edit) in edits {
+        let widget = frme
+            .widgets
+            .as_mut_vec()
+            .iter_mut()
+            .find(|w| w.name.to_bytes() == name.as_bytes())
+            .ok_or_else(|| format!("FRME 0x{:08X} has no widget named '{}'", frme_id, name))?;
+
+        if let Some(position) = edit.position {
+            widget.origin = position.into();
+        }
+        if let Some(offset) = edit.offset {
+            for (origin, offset) in widget.origin.iter_mut().zip(offset) {
+                *origin += offset;
+            }
+        }
+        if let Some(scale) = edit.scale {
+            // Scale each local axis, which are the columns of the row-major basis
+            for (i, value) in widget.basis.iter_mut().enumerate() {
+                *value *= scale[i % 3];
+            }
+        }
+        if let Some(color) = edit.color {
+            widget.color = color.into();
+        }
+        if let Some(visible) = edit.visible {
+            widget.default_visible = visible as u8;
+        }
+
+        if edit.font.is_none() && edit.fill_color.is_none() && edit.outline_color.is_none() {
+            continue;
+        }
+        let structs::FrmeWidgetKind::TextPane(textpane) = &mut widget.kind else {
+            return Err(format!(
+                "Widget '{}' of FRME 0x{:08X} is not a text pane",
+                name, frme_id
+            ));
+        };
+        if let Some(font) = edit.font {
+            textpane.font = ResId::new(font);
+        }
+        if let Some(fill_color) = edit.fill_color {
+            textpane.fill_color = fill_color.into();
+        }
+        if let Some(outline_color) = edit.outline_color {
+            textpane.outline_color = outline_color.into();
+        }
+    }
+
+    Ok(())
+}
+
+// The FONTs the edits switch the frame's text panes to, each with its texture
+fn frme_edit_fonts(
+    frme_id: u32,
+    edits: &HashMap<String, FrmeWidgetEdit>,
+) -> Result<Vec<(structs::Dependency, structs::Dependency)>, String> {
+    let mut fonts = vec![];
+    for font in edits.values().filter_map(|edit| edit.font) {
+        let (font, txtr) = font_glyphs::GAME_FONTS
+            .iter()
+            .find(|(info, _)| info.res_id == font)
+            .ok_or_else(|| {
+                format!(
+                    "frmeEdits: 0x{:08X} used by FRME 0x{:08X} is not a font in the game",
+                    font, frme_id
+                )
+            })?;
+        let dep = |info: &ResourceInfo| structs::Dependency {
+            asset_id: info.res_id,
+            asset_type: info.fourcc,
+        };
+        let pair = (dep(font), dep(txtr));
+        if !fonts.contains(&pair) {
+            fonts.push(pair);
+        }
+    }
+    Ok(fonts)
+}
+
+fn add_frme_fonts<'r>(
+    file: &mut structs::FstEntryFile<'r>,
+    frme_id: u32,
+    fonts: &[(structs::Dependency, structs::Dependency)],
+    resources: &HashMap<(u32, FourCC), structs::Resource<'r>>,
+) -> Result<(), String> {
+    let pak = match file {
+        structs::FstEntryFile::Pak(pak) => pak,
+        _ => return Ok(()),
+    };
+    let has = |dep: &structs::Dependency| {
+        pak.resources
+            .iter()
+            .any(|res| res.file_id == dep.asset_id && res.fourcc() == dep.asset_type)
+    };
+    if !pak
+        .resources
+        .iter()
+        .any(|res| res.file_id == frme_id && res.fourcc() == b"FRME".into())
+    {
+        return Ok(());
+    }
+    let missing: Vec<_> = fonts
+        .iter()
+        .flat_map(|(font, txtr)| [font, txtr])
+        .filter(|dep| !has(dep))
+        .map(|dep| resources[&(dep.asset_id, dep.asset_type)].clone())
+        .collect();
+
+    // append at the end of the pak
+    let mut cursor = pak.resources.cursor();
+    while cursor.cursor_advancer().peek().is_some() {}
+    cursor.insert_after(missing.into_iter());
+    Ok(())
+}
+
 fn patch_main_menu(res: &mut structs::Resource) -> Result<(), String> {
     let frme = res.kind.as_frme_mut().unwrap();
 
@@ -10501,19 +11020,15 @@ fn patch_credits(
         );
     }
 
-    if config.credits_string.is_some() {
-        output = format!("{}{}", output, config.credits_string.as_ref().unwrap());
+    let credits_string = if let Some(credits_string) = config.credits_string.as_ref() {
+        credits_string.clone()
     } else {
-        output = format!(
-            "{}{}",
-            output,
-            concat!(
-                "&push;&font=C29C51F1;&main-color=#89D6FF;",
-                "Major Item Locations",
-                "&pop;",
-            )
-            .to_owned()
-        );
+        let mut output = concat!(
+            "&push;&font=C29C51F1;&main-color=#89D6FF;",
+            "Major Item Locations",
+            "&pop;",
+        )
+        .to_owned();
 
         use std::fmt::Write;
         const PICKUPS_TO_PRINT: &[PickupType] = &[
@@ -10568,12 +11083,19 @@ fn patch_credits(
             let pickup_name = pickup_type.name();
             write!(output, "\n\n{}: {}", pickup_name, room_name).unwrap();
         }
-    }
-    output = format!("{}{}", output, "\n\n\n\n\n\n\n\n\n\n\n\n\n\n\n\n\0");
-    res.kind
-        .as_strg_mut()
-        .unwrap()
-        .add_strings(&[output.to_string()], Languages::All);
+        output.into()
+    };
+    res.kind.as_strg_mut().unwrap().add_localized_string(
+        |lang| {
+            format!(
+                "{}{}{}",
+                output,
+                credits_string.get(lang),
+                "\n\n\n\n\n\n\n\n\n\n\n\n\n\n\n\n\0"
+            )
+        },
+        Languages::All,
+    );
 
     /* We are who we choose to be */
     /* https://mobile.twitter.com/ZoidCTF/status/1542699504041750528 */
@@ -10591,18 +11113,19 @@ fn patch_credits(
 
 fn patch_completion_screen(
     res: &mut structs::Resource,
-    mut results_string: String,
+    results_string: &Localized<String>,
     version: Version,
 ) -> Result<(), String> {
-    if version == Version::NtscJ {
-        results_string = format!("&line-extra-space=4;&font=C29C51F1;{}", results_string);
-    }
-    results_string += "\nPercentage Complete\0";
-
     let strg = res.kind.as_strg_mut().unwrap();
     for st in strg.string_tables.as_mut_vec().iter_mut() {
+        let mut results_string = results_string.get(st.lang).clone();
+        if version == Version::NtscJ {
+            results_string = format!("&line-extra-space=4;&font=C29C51F1;{}", results_string);
+        }
+        results_string += "\nPercentage Complete\0";
+
         let strings = st.strings.as_mut_vec();
-        strings[1] = results_string.to_owned().into();
+        strings[1] = results_string.into();
     }
     Ok(())
 }
@@ -10698,17 +11221,24 @@ fn patch_pause_screen_gravity_suit_pane(res: &mut structs::Resource) -> Result<(
     Ok(())
 }
 
+// Replaces the strings of every table `replacement_strings` gives strings for. The tables must
+// still hold the same number of strings afterwards.
 fn patch_arbitrary_strg(
     res: &mut structs::Resource,
-    replacement_strings: Vec<String>,
+    replacement_strings: impl Fn(FourCC) -> Option<Vec<String>>,
 ) -> Result<(), String> {
+    let strg_id = res.file_id;
     let strg = res.kind.as_strg_mut().unwrap();
 
     for st in strg.string_tables.as_mut_vec().iter_mut() {
+        let Some(replacement_strings) = replacement_strings(st.lang) else {
+            continue;
+        };
+
         let strings = st.strings.as_mut_vec();
         strings.clear();
 
-        for mut replacement_string in replacement_strings.clone() {
+        for mut replacement_string in replacement_strings {
             if !replacement_string.ends_with('\0') {
                 replacement_string += "\0";
             }
@@ -10716,21 +11246,134 @@ fn patch_arbitrary_strg(
         }
     }
 
+    let mut string_counts = strg.string_tables.iter().map(|st| st.strings.len());
+    let string_count = string_counts.next().unwrap_or(0);
+    if string_counts.any(|count| count != string_count) {
+        return Err(format!(
+            "The string tables of STRG 0x{:08X} hold differing numbers of strings",
+            strg_id
+        ));
+    }
+
     Ok(())
 }
 
-fn patch_starting_pickups<'r>(
-    _ps: &mut PatcherState,
-    area: &mut mlvl_wrapper::MlvlArea<'r, '_, '_, '_>,
-    starting_items: &StartingItems,
-    show_starting_memo: bool,
-    game_resources: &HashMap<(u32, FourCC), structs::Resource<'r>>,
-    skip_id: u32,
+// Imported strings are only applied to the paks they were exported from, so each of those has to
+// hold the STRG. Custom STRGs aren't in any pak yet and are translated directly.
+fn check_translated_strg_paks(
+    gc_disc: &structs::GcDisc,
+    translations: &StrgTranslations,
+    custom_strgs: &HashSet<u32>,
 ) -> Result<(), String> {
-    let area_internal_id = area.mlvl_area.internal_id;
+    let mut pak_strgs: HashMap<&str, HashSet<u32>> = HashMap::new();
+    for (id, translation) in translations {
+        if custom_strgs.contains(id) {
+            continue;
+        }
+        if translation.paks.is_empty() {
+            return Err(format!(
+                "Imported strings for STRG 0x{:08X} don't list the paks it is in",
+                id
+            ));
+        }
 
-    let mut starting_memo_layer_idx = 0;
-    let mut timer_starting_items_popup_id = 0;
+        for pak_name in &translation.paks {
+            if !pak_strgs.contains_key(pak_name.as_str()) {
+                let file_entry = gc_disc.find_file(pak_name).ok_or_else(|| {
+                    format!(
+                        "Imported strings for STRG 0x{:08X} list {}, which is not on the disc",
+                        id, pak_name
+                    )
+                })?;
+                let pak = match *file_entry.file().unwrap() {
+                    structs::FstEntryFile::Pak(ref pak) => Cow::Borrowed(pak),
+                    structs::FstEntryFile::Unknown(ref reader) => {
+                        Cow::Owned(reader.clone().read(()))
+                    }
+                    _ => return Err(format!("{} is not a pak", pak_name)),
+                };
+                let strgs = pak
+                    .resources
+                    .iter()
+                    .filter(|res| res.fourcc() == b"STRG".into())
+                    .map(|res| res.file_id)
+                    .collect();
+                pak_strgs.insert(pak_name, strgs);
+            }
+
+            if !pak_strgs[pak_name.as_str()].contains(id) {
+                return Err(format!(
+                    "Imported strings for STRG 0x{:08X} list {}, which has no such STRG",
+                    id, pak_name
+                ));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+// Overlays translated strings onto the STRG's existing tables. Strings the game sets in the JPN
+// font must keep its prefix.
+fn patch_translated_strg(
+    res: &mut structs::Resource,
+    translations: &HashMap<FourCC, HashMap<usize, String>>,
+) -> Result<(), String> {
+    let strg_id = res.file_id;
+    let strg = res.kind.as_strg_mut().unwrap();
+
+    let mut tables = HashMap::new();
+    for (lang, translated_strings) in translations {
+        let lang_name = String::from_utf8_lossy(lang.as_bytes());
+        let table = strg
+            .string_tables
+            .iter()
+            .find(|table| table.lang == *lang)
+            .ok_or_else(|| format!("STRG 0x{:08X} has no {} table", strg_id, lang_name))?;
+        let mut strings: Vec<String> = table
+            .strings
+            .iter()
+            .map(|s| s.into_owned().into_string())
+            .collect();
+
+        for (index, translated_string) in translated_strings {
+            let string = strings.get_mut(*index).ok_or_else(|| {
+                format!(
+                    "STRG 0x{:08X} {} table has no string {}",
+                    strg_id, lang_name, index
+                )
+            })?;
+            if string.starts_with(structs::JPN_FONT_PREFIX)
+                && !translated_string.starts_with(structs::JPN_FONT_PREFIX)
+            {
+                return Err(format!(
+                    "String {} of STRG 0x{:08X} {} table must start with the JPN font prefix \"{}\"",
+                    index,
+                    strg_id,
+                    lang_name,
+                    structs::JPN_FONT_PREFIX
+                ));
+            }
+            *string = translated_string.clone();
+        }
+        tables.insert(*lang, strings);
+    }
+
+    patch_arbitrary_strg(res, |lang| tables.get(&lang).cloned())
+}
+
+fn patch_starting_pickups<'r>(
+    _ps: &mut PatcherState,
+    area: &mut mlvl_wrapper::MlvlArea<'r, '_, '_, '_>,
+    starting_items: &StartingItems,
+    show_starting_memo: bool,
+    game_resources: &HashMap<(u32, FourCC), structs::Resource<'r>>,
+    skip_id: u32,
+) -> Result<(), String> {
+    let area_internal_id = area.mlvl_area.internal_id;
+
+    let mut starting_memo_layer_idx = 0;
+    let mut timer_starting_items_popup_id = 0;
     let mut hud_memo_starting_items_popup_id = 0;
     let mut special_function_id = 0;
 
@@ -13443,14 +14086,43 @@ fn patch_garbeetle_scale(
     Ok(())
 }
 
+// Reads a PNG of exactly the given size as RGB5A3 texels
+fn read_banner_png(field: &str, path: &str, width: u32, height: u32) -> Result<Vec<u8>, String> {
+    let image = image::open(path)
+        .map_err(|e| format!("Failed to read {} image {}: {}", field, path, e))?
+        .into_rgba8();
+    if image.dimensions() != (width, height) {
+        Err(format!(
+            "The {} image must be {}x{}, but {} is {}x{}",
+            field,
+            width,
+            height,
+            path,
+            image.width(),
+            image.height()
+        ))?
+    }
+    Ok(encode_rgb5a3_texels(
+        image.as_raw(),
+        width as usize,
+        height as usize,
+    ))
+}
+
 fn patch_bnr(file: &mut structs::FstEntryFile, banner: &GameBanner) -> Result<(), String> {
     let bnr = match file {
         structs::FstEntryFile::Bnr(bnr) => bnr,
         _ => panic!(),
     };
 
-    bnr.pixels
-        .clone_from_slice(include_bytes!("../extra_assets/banner_image.bin"));
+    match &banner.image {
+        Some(path) => bnr
+            .pixels
+            .clone_from_slice(&read_banner_png("banner", path, 96, 32)?),
+        None => bnr
+            .pixels
+            .clone_from_slice(include_bytes!("../extra_assets/banner_image.bin")),
+    }
 
     fn write_encoded_str(field: &str, s: &Option<String>, slice: &mut [u8]) -> Result<(), String> {
         if let Some(s) = s {
@@ -13471,31 +14143,41 @@ fn patch_bnr(file: &mut structs::FstEntryFile, banner: &GameBanner) -> Result<()
         Ok(())
     }
 
-    write_encoded_str(
-        "game_name",
-        &banner.game_name,
-        &mut bnr.english_fields.game_name,
-    )?;
-    write_encoded_str(
-        "developer",
-        &banner.developer,
-        &mut bnr.english_fields.developer,
-    )?;
-    write_encoded_str(
-        "game_name_full",
-        &banner.game_name_full,
-        &mut bnr.english_fields.game_name_full,
-    )?;
-    write_encoded_str(
-        "developer_full",
-        &banner.developer_full,
-        &mut bnr.english_fields.developer_full,
-    )?;
-    write_encoded_str(
-        "description",
-        &banner.description,
-        &mut bnr.english_fields.description,
-    )?;
+    fn write_metadata(
+        banner: &GameBanner,
+        lang: FourCC,
+        fields: &mut structs::BnrMetadata,
+    ) -> Result<(), String> {
+        let get = |s: &Option<Localized<String>>| s.as_ref().map(|s| s.get(lang).clone());
+        write_encoded_str("game_name", &get(&banner.game_name), &mut fields.game_name)?;
+        write_encoded_str("developer", &get(&banner.developer), &mut fields.developer)?;
+        write_encoded_str(
+            "game_name_full",
+            &get(&banner.game_name_full),
+            &mut fields.game_name_full,
+        )?;
+        write_encoded_str(
+            "developer_full",
+            &get(&banner.developer_full),
+            &mut fields.developer_full,
+        )?;
+        write_encoded_str(
+            "description",
+            &get(&banner.description),
+            &mut fields.description,
+        )?;
+        Ok(())
+    }
+
+    write_metadata(banner, b"ENGL".into(), &mut bnr.english_fields)?;
+
+    // PAL discs have a BNR2, with the metadata of the other languages in this order
+    const OTHER_LANGUAGES: [&[u8; 4]; 5] = [b"GERM", b"FREN", b"SPAN", b"ITAL", b"DUTC"];
+    if let Some(other_lang_fields) = &mut bnr.other_lang_fields {
+        for (lang, fields) in OTHER_LANGUAGES.iter().zip(other_lang_fields.iter_mut()) {
+            write_metadata(banner, (*lang).into(), fields)?;
+        }
+    }
 
     Ok(())
 }
@@ -14814,12 +15496,39 @@ fn patch_arboretum_sandstone(patcher: &mut PrimePatcher<'_, '_>) {
     });
 }
 
+// Converts a WAV to the .dsp files of a stream once, no matter how many streamers play it, and
+// returns the file name the streamers play it by
+fn wav_stream_file_name(
+    file_name: &str,
+    wav_streams: &mut HashMap<String, String>,
+    wav_stream_files: &mut Vec<(String, Vec<u8>)>,
+) -> Result<String, String> {
+    if let Some(stream_name) = wav_streams.get(file_name) {
+        return Ok(stream_name.clone());
+    }
+
+    let dsps = dsp_adpcm::encode_wav_stream(Path::new(file_name))?;
+    let name = format!("randomprime_music_{}", wav_streams.len());
+    let suffixes: &[&str] = if dsps.len() == 1 { &[""] } else { &["L", "R"] };
+    let mut stream_names = vec![];
+    for (dsp, suffix) in dsps.into_iter().zip(suffixes) {
+        wav_stream_files.push((format!("Audio/{}{}.dsp", name, suffix), dsp));
+        stream_names.push(format!("/audio/{}{}.dsp", name, suffix));
+    }
+    let stream_name = stream_names.join("|");
+    wav_streams.insert(file_name.to_string(), stream_name.clone());
+    Ok(stream_name)
+}
+
 pub fn patch_iso<T>(config: PatchConfig, mut pn: T) -> Result<(), String>
 where
     T: structs::ProgressNotifier,
 {
     let start_time = Instant::now();
     let mut audio_override_patches: Vec<AudioOverridePatch> = Vec::new();
+    // WAVs are converted once each, no matter how many streamers play them
+    let mut wav_streams: HashMap<String, String> = HashMap::new();
+    let mut wav_stream_files: Vec<(String, Vec<u8>)> = Vec::new();
     for (pak_name, rooms) in pickup_meta::ROOM_INFO.iter() {
         let world = World::from_pak(pak_name).unwrap();
         for room_info in rooms.iter() {
@@ -14834,6 +15543,13 @@ where
             }
 
             let room = room.unwrap();
+            for streamed_audio in room.streamed_audios.iter().flatten() {
+                let file_name = &streamed_audio.audio_file_name;
+                if file_name.to_lowercase().ends_with(".wav") {
+                    wav_stream_file_name(file_name, &mut wav_streams, &mut wav_stream_files)?;
+                }
+            }
+
             if room.audio_override.is_none() {
                 continue;
             }
@@ -14845,6 +15561,12 @@ where
                     Err(_e) => panic!("{} is not a valid number", id_str),
                 };
 
+                let file_name = if file_name.to_lowercase().ends_with(".wav") {
+                    &wav_stream_file_name(file_name, &mut wav_streams, &mut wav_stream_files)?
+                } else {
+                    file_name
+                };
+
                 let file_name = format!("{}\0", file_name.clone());
                 let file_name = file_name.as_bytes();
                 let file_name: Vec<u8> = file_name.to_vec();
@@ -14879,12 +15601,23 @@ where
     } else if config.run_mode == RunMode::ExportAssets {
         export_assets(&mut gc_disc, &config)?;
         return Ok(());
+    } else if config.run_mode == RunMode::ExportStrings {
+        export_strings(&mut gc_disc, &config)?;
+        return Ok(());
     }
 
-    build_and_run_patches(&mut gc_disc, &config, audio_override_patches)?;
+    for (path, dsp) in wav_stream_files {
+        gc_disc.add_file(&path, structs::FstEntryFile::ExternalFile(Box::new(dsp)))?;
+    }
+
+    build_and_run_patches(&mut gc_disc, &config, audio_override_patches, &wav_streams)?;
 
     println!("Created patches in {:?}", start_time.elapsed());
 
+    if let Some(map_render_dir) = &config.map_render_dir {
+        render_world_maps(&gc_disc, Path::new(map_render_dir))?;
+    }
+
     {
         let json_value = serde_json::to_value(&config)
             .map_err(|e| format!("Failed to serialize patch config: {}", e))?;
@@ -14945,6 +15678,22 @@ where
     Ok(())
 }
 
+fn render_world_maps(gc_disc: &structs::GcDisc, dir: &Path) -> Result<(), String> {
+    fs::create_dir_all(dir).map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+    for world_map in map_render::world_maps(gc_disc)? {
+        let path = dir.join(format!("{}.svg", world_map.world.to_json_key()));
+        fs::write(&path, world_map.to_svg())
+            .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+
+        let path = path.with_extension("png");
+        world_map
+            .to_image(2048)
+            .save(&path)
+            .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+    }
+    Ok(())
+}
+
 fn export_logbook(gc_disc: &mut structs::GcDisc, config: &PatchConfig) -> Result<(), String> {
     let filenames = [
         "AudioGrp.pak",
@@ -15030,6 +15779,89 @@ fn export_logbook(gc_disc: &mut structs::GcDisc, config: &PatchConfig) -> Result
     Ok(())
 }
 
+#[allow(clippy::type_complexity)]
+fn build_font_patches(
+    game_resources: &HashMap<(u32, FourCC), structs::Resource>,
+    config: &FontGlyphsConfig,
+) -> Result<Vec<(ResourceInfo, Vec<u8>, ResourceInfo, Vec<u8>)>, String> {
+    let ttf_bytes = fs::read(&config.font_file)
+        .map_err(|e| format!("Failed to read {}: {}", config.font_file, e))?;
+    let ttf = ab_glyph::FontRef::try_from_slice(&ttf_bytes)
+        .map_err(|e| format!("Failed to parse {}: {}", config.font_file, e))?;
+
+    let mut font_patches = vec![];
+    for &(font, txtr) in font_glyphs::GAME_FONTS {
+        let font_res = &game_resources[&font.into()];
+        let txtr_res = &game_resources[&txtr.into()];
+        let (font_bytes, txtr_bytes) = font_glyphs::add_font_glyphs(
+            &crate::ResourceData::new(font_res).decompress(),
+            &crate::ResourceData::new(txtr_res).decompress(),
+            &ttf,
+            config,
+        )
+        .map_err(|e| format!("Failed to add glyphs to {}: {}", font.long_name, e))?;
+        font_patches.push((font, font_bytes, txtr, txtr_bytes));
+    }
+
+    Ok(font_patches)
+}
+
+fn export_strings(gc_disc: &mut structs::GcDisc, config: &PatchConfig) -> Result<(), String> {
+    let mut strgs = Vec::<StrgExport>::new();
+    let mut strg_indices = HashMap::<u32, usize>::new();
+
+    let mut pak_names: Vec<String> = gc_disc
+        .file_system_root
+        .dir_entries()
+        .unwrap()
+        .iter()
+        .map(|entry| entry.name().to_str().unwrap().to_string())
+        .filter(|name| name.to_lowercase().ends_with(".pak"))
+        .collect();
+    pak_names.sort();
+
+    for pak_name in &pak_names {
+        let file_entry = gc_disc.find_file(pak_name).unwrap();
+        let pak = match *file_entry.file().unwrap() {
+            structs::FstEntryFile::Pak(ref pak) => pak.clone(),
+            structs::FstEntryFile::Unknown(ref reader) => reader.clone().read(()),
+            _ => panic!(),
+        };
+
+        for res in pak.resources.iter() {
+            if res.fourcc() != b"STRG".into() {
+                continue;
+            }
+
+            // The same STRG is often duplicated across paks
+            if let Some(&idx) = strg_indices.get(&res.file_id) {
+                strgs[idx].paks.push(pak_name.clone());
+                continue;
+            }
+
+            let mut res = res.into_owned();
+            let strg = res.kind.as_strg_mut().unwrap();
+            strg_indices.insert(res.file_id, strgs.len());
+            strgs.push(StrgExport::new(res.file_id, pak_name, strg));
+        }
+    }
+
+    let (_, _, _, _, _, _, _, _, _, custom_assets) = collect_game_resources(gc_disc, None, config)?;
+    for res in custom_assets {
+        if res.fourcc() != b"STRG".into() || strg_indices.contains_key(&res.file_id) {
+            continue;
+        }
+        let strg = res.kind.as_strg().unwrap();
+        strgs.push(StrgExport::new(res.file_id, "custom", &strg));
+    }
+
+    let filename = config
+        .export_strings_filename
+        .as_deref()
+        .unwrap_or("strings.csv");
+    strg_conversions::write_strings_file(Path::new(filename), &strgs)
+}
+
 fn export_asset(asset_dir: &str, filename: String, bytes: Vec<u8>) -> Result<(), String> {
     let mut file = File::create(format!("{}/{}", asset_dir, filename))
         .map_err(|e| format!("Failed to create asset file: {}", e))?;
@@ -15074,6 +15906,7 @@ fn build_and_run_patches<'r>(
     gc_disc: &mut structs::GcDisc<'r>,
     config: &PatchConfig,
     audio_override_patches: &'r Vec<AudioOverridePatch>,
+    wav_streams: &HashMap<String, String>,
 ) -> Result<(), String> {
     let morph_ball_size = config.ctwk_config.morph_ball_size.unwrap_or(1.0);
     let player_size = config.ctwk_config.player_size.unwrap_or(1.0);
@@ -15090,13 +15923,7 @@ fn build_and_run_patches<'r>(
             let world = World::from_pak(pak_name).unwrap();
 
             if !level_data.contains_key(world.to_json_key()) {
-                level_data.insert(
-                    world.to_json_key().to_string(),
-                    LevelConfig {
-                        transports: HashMap::new(),
-                        rooms: HashMap::new(),
-                    },
-                );
+                level_data.insert(world.to_json_key().to_string(), LevelConfig::default());
             }
 
             let level = level_data.get_mut(world.to_json_key()).unwrap();
@@ -15250,16 +16077,10 @@ fn build_and_run_patches<'r>(
 
     let show_starting_memo = config.starting_memo.is_some();
 
-    let starting_memo = {
-        if config.starting_memo.is_some() {
-            Some(config.starting_memo.as_ref().unwrap().as_str())
-        } else {
-            None
-        }
-    };
+    let starting_memo = config.starting_memo.as_ref();
 
     let (
-        game_resources,
+        mut game_resources,
         pickup_hudmemos,
         pickup_scans,
         extra_scans,
@@ -15268,9 +16089,53 @@ fn build_and_run_patches<'r>(
         local_savw_scans_to_add,
         savw_scan_logbook_category,
         extern_models,
-        _,
+        custom_assets,
     ) = collect_game_resources(gc_disc, starting_memo, config)?;
 
+    let cloned_objects = collect_cloned_objects(gc_disc, &level_data, &mut game_resources)?;
+    ancs_edits::collect_ancs_edit_resources(gc_disc, config, &mut game_resources)?;
+
+    let strg_translations = match config.import_strings_filename.as_ref() {
+        Some(filename) => strg_conversions::read_strings_file(Path::new(filename))?,
+        None => HashMap::new(),
+    };
+    let custom_strgs: HashSet<u32> = custom_assets
+        .iter()
+        .filter(|res| res.fourcc() == b"STRG".into())
+        .map(|res| res.file_id)
+        .collect();
+    check_translated_strg_paks(gc_disc, &strg_translations, &custom_strgs)?;
+    for (id, translation) in strg_translations.iter() {
+        if custom_strgs.contains(id) {
+            let res = game_resources
+                .get_mut(&(*id, FourCC::from_bytes(b"STRG")))
+                .unwrap();
+            patch_translated_strg(res, &translation.strings)?;
+        }
+    }
+    let cloned_objects = &cloned_objects;
+    let layer_indices = script_layers::collect_layer_indices(gc_disc, &level_data)?;
+    let deleted_layers = script_layers::collect_deleted_layers(gc_disc, &level_data)?;
+    let frme_rooms = {
+        let frmes = config
+            .frme_edits
+            .keys()
+            .filter_map(|frme| frme.parse::<u32>().ok())
+            .map(|id| (id, FourCC::from_bytes(b"FRME")))
+            .collect();
+        mlvl_wrapper::rooms_listing(gc_disc, &frmes)?
+    };
+    let frme_rooms = &frme_rooms;
+    let ancs_rooms = ancs_edits::collect_ancs_rooms(gc_disc, config)?;
+    let gun_textures = match config.suit_colors.as_ref() {
+        Some(suit_colors) if !suit_colors.gun_recolor().is_identity() => {
+            collect_gun_textures(gc_disc)?
+        }
+        _ => vec![],
+    };
+    let persistent_flags = persistent_flags::collect_persistent_flags(&level_data, &layer_indices)?;
+    let persistent_flags = &persistent_flags;
+
     let extern_models = &extern_models;
     let game_resources = &game_resources;
     let pickup_hudmemos = &pickup_hudmemos;
@@ -15279,6 +16144,11 @@ fn build_and_run_patches<'r>(
     let edit_obj_scans = &edit_obj_scans;
     let strgs = config.strg.clone();
     let strgs = &strgs;
+    let strg_translations = &strg_translations;
+    let font_patches = match config.font_glyphs.as_ref() {
+        Some(font_glyphs) => build_font_patches(game_resources, font_glyphs)?,
+        None => vec![],
+    };
 
     let savw_scans_to_add = &savw_scans_to_add;
     let local_savw_scans_to_add = &local_savw_scans_to_add;
@@ -15405,6 +16275,79 @@ fn build_and_run_patches<'r>(
 
     let mut patcher = PrimePatcher::new();
 
+    // Layers are added, renamed and deleted before any other room patches so that every later
+    // reference to a layer (by index or by name) sees the final layout
+    for (world_key, level) in sorted_by_key(&level_data) {
+        let pak_name = World::from_json_key(world_key).to_pak_str();
+        for (room_name, room) in sorted_by_key(&level.rooms) {
+            if room.add_layers.is_none()
+                && room.rename_layers.is_none()
+                && room.move_layers.is_none()
+                && room.delete_layers.is_none()
+            {
+                continue;
+            }
+
+            let mrea_id = ROOM_BY_NAME
+                .get(&(world_key.clone(), room_name.clone()))
+                .ok_or_else(|| {
+                    format!(
+                        "'{}' in levelData is not a room in {}",
+                        room_name, world_key
+                    )
+                })?
+                .mrea_id;
+            patcher.add_scly_patch((pak_name.as_bytes(), mrea_id), move |ps, area| {
+                patch_edit_layers(ps, area, room_name, room)
+            });
+        }
+    }
+
+    // Deleting a layer renumbers the ones after it, so everything in the world that toggles one of
+    // those layers by index has to follow. This is done before any objects are added to the world,
+    // as the config's own references already use the new indices.
+    for (pak_name, deleted_layers) in sorted_by_key(&deleted_layers) {
+        let rooms = pickup_meta::ROOM_INFO
+            .iter()
+            .find(|(name, _)| name == pak_name)
+            .map(|(_, rooms)| *rooms)
+            .unwrap_or_default();
+        for room_info in rooms {
+            patcher.add_scly_patch(
+                (pak_name.as_bytes(), room_info.room_id.to_u32()),
+                move |_ps, area| script_layers::patch_remap_layer_controllers(area, deleted_layers),
+            );
+        }
+        patcher.add_resource_patch(
+            World::from_pak(pak_name).unwrap().savw().into(),
+            move |res| script_layers::patch_remap_savw_layers(res, deleted_layers),
+        );
+    }
+
+    // Prefab ids are picked without seeing the room, so make sure none of them are taken before
+    // anything is added to it
+    for (world_key, level) in sorted_by_key(&level_data) {
+        let pak_name = World::from_json_key(world_key).to_pak_str();
+        for (room_name, room) in sorted_by_key(&level.rooms) {
+            if room.prefab_object_ids.is_empty() {
+                continue;
+            }
+
+            let mrea_id = ROOM_BY_NAME[&(world_key.clone(), room_name.clone())].mrea_id;
+            patcher.add_scly_patch((pak_name.as_bytes(), mrea_id), move |_ps, area| match room
+                .prefab_object_ids
+                .iter()
+                .find(|id| id_in_use(area, **id))
+            {
+                Some(id) => Err(format!(
+                    "Prefab object id 0x{:X} is already used by an object in {} - {}",
+                    id, world_key, room_name
+                )),
+                None => Ok(()),
+            });
+        }
+    }
+
     // Add the freeze effect assets required by CPlayer::Freeze()
     patcher.add_file_patch(b"GGuiSys.pak", |file| {
         add_player_freeze_assets(file, game_resources)
@@ -15634,7 +16577,7 @@ fn build_and_run_patches<'r>(
                         BlockConfig {
                             active: Some(true),
                             id: None,
-                            layer: Some(1),
+                            layer: Some(LayerId::Index(1)),
                             position: [42.9551, -287.1726, -240.7044],
                             scale: Some([50.0, 50.0, 1.0]),
                             texture: None,
@@ -15772,6 +16715,33 @@ fn build_and_run_patches<'r>(
                 move |res| set_room_map_default_state(res, map_default_state),
             );
 
+            if let Some(map_edits) = level_data
+                .get(world.to_json_key())
+                .and_then(|level| level.rooms.get(room_info.name().trim()))
+                .and_then(|room| room.map_edits.as_ref())
+            {
+                patcher.add_resource_patch(
+                    (
+                        &[pak_name.as_bytes()],
+                        room_info.mapa_id.to_u32(),
+                        FourCC::from_bytes(b"MAPA"),
+                    ),
+                    move |res| patch_map_edits(res, map_edits, room_info.room_id.to_u32()),
+                );
+            }
+
+            if let Some(room) = level_data
+                .get(world.to_json_key())
+                .and_then(|level| level.rooms.get(room_info.name().trim()))
+            {
+                patcher.add_scly_patch(
+                    (pak_name.as_bytes(), room_info.room_id.to_u32()),
+                    move |ps, area| {
+                        validate_room_positions(ps, area, room, room_info.name().trim())
+                    },
+                );
+            }
+
             // Get list of patches specified for this room
             let (pickups, scans, doors, hudmemos) = {
                 let mut _pickups = Vec::new();
@@ -15875,6 +16845,26 @@ fn build_and_run_patches<'r>(
                             }
                         }
 
+                        if let Some(clone_objects) = room.clone_objects.as_ref() {
+                            for config in clone_objects {
+                                let source = SpawnRoomData::from_str(&config.source_room);
+                                let cloned_object = &cloned_objects
+                                    [&(source.mrea, config.instance_id & 0x00FFFFFF)];
+                                patcher.add_scly_patch(
+                                    (pak_name.as_bytes(), room_info.room_id.to_u32()),
+                                    move |ps, area| {
+                                        patch_clone_object(
+                                            ps,
+                                            area,
+                                            game_resources,
+                                            cloned_object,
+                                            config.clone(),
+                                        )
+                                    },
+                                );
+                            }
+                        }
+
                         if room.spawn_points.is_some() {
                             for spawn_point_config in room.spawn_points.as_ref().unwrap() {
                                 patcher.add_scly_patch(
@@ -15899,6 +16889,17 @@ fn build_and_run_patches<'r>(
 
                         if room.special_functions.is_some() {
                             for special_fn_config in room.special_functions.as_ref().unwrap() {
+                                let mut special_fn_config = special_fn_config.clone();
+                                if let (Some(room_id), Some(LayerId::Name(name))) = (
+                                    special_fn_config.layer_change_room_id,
+                                    special_fn_config.layer_change_layer_id.as_ref(),
+                                ) {
+                                    if let Some(index) = layer_indices.get(&(room_id, name.clone()))
+                                    {
+                                        special_fn_config.layer_change_layer_id =
+                                            Some(LayerId::Index(*index));
+                                    }
+                                }
                                 patcher.add_scly_patch(
                                     (pak_name.as_bytes(), room_info.room_id.to_u32()),
                                     move |ps, area| {
@@ -16096,6 +17097,12 @@ fn build_and_run_patches<'r>(
 
                         if room.streamed_audios.is_some() {
                             for config in room.streamed_audios.as_ref().unwrap() {
+                                // WAVs were converted to streams added to the disc
+                                let mut config = config.clone();
+                                if let Some(stream_name) = wav_streams.get(&config.audio_file_name)
+                                {
+                                    config.audio_file_name = stream_name.clone();
+                                }
                                 patcher.add_scly_patch(
                                     (pak_name.as_bytes(), room_info.room_id.to_u32()),
                                     move |ps, area| {
@@ -16215,7 +17222,7 @@ fn build_and_run_patches<'r>(
                                             camera_hint.camera_pos,
                                             camera_hint.camera_rot,
                                             camera_hint.behavior,
-                                            camera_hint.layer.unwrap_or(0),
+                                            camera_hint.layer.as_ref(),
                                             camera_hint.camera_id,
                                             camera_hint.trigger_id,
                                         )
@@ -16319,6 +17326,15 @@ fn build_and_run_patches<'r>(
                             );
                         }
 
+                        if let Some(lights) = room.lights.as_ref() {
+                            patcher.add_scly_patch(
+                                (pak_name.as_bytes(), room_info.room_id.to_u32()),
+                                move |_ps, area| {
+                                    patch_lights(_ps, area, lights, room_info.room_id.to_u32())
+                                },
+                            );
+                        }
+
                         let (remove, submerge) = {
                             let remove = room.remove_water.unwrap_or(false);
                             let submerge = room.submerge.unwrap_or(false);
@@ -16562,6 +17578,7 @@ fn build_and_run_patches<'r>(
                 };
 
                 let (scan_id, strg_id) = extra_scans.get(&key).unwrap();
+                let scan_actor_layer = scan.layer.clone();
 
                 patcher.add_scly_patch(
                     (pak_name.as_bytes(), room_info.room_id.to_u32()),
@@ -16574,7 +17591,7 @@ fn build_and_run_patches<'r>(
                             *strg_id,
                             scan.position,
                             scan.id,
-                            scan.layer,
+                            scan.layer.as_ref(),
                         )
                     },
                 );
@@ -16589,7 +17606,7 @@ fn build_and_run_patches<'r>(
                                 game_resources,
                                 scan.position,
                                 scan.rotation.unwrap_or(0.0),
-                                scan.layer,
+                                scan_actor_layer.as_ref(),
                                 scan.actor_id,
                             )
                         },
@@ -16935,6 +17952,8 @@ fn build_and_run_patches<'r>(
         }
     }
 
+    texture_swaps::add_texture_swap_patches(&mut patcher, config, &level_data, game_resources);
+
     let (skip_frigate, skip_ending_cinematic) = make_elevators_patch(
         &mut patcher,
         &level_data,
@@ -17054,6 +18073,18 @@ fn build_and_run_patches<'r>(
             });
         }
     }
+    for (font, font_bytes, txtr, txtr_bytes) in font_patches {
+        patcher.add_resource_patch(font.into(), move |res| {
+            res.kind = structs::ResourceKind::External(font_bytes.clone(), b"FONT".into());
+            res.compressed = false;
+            Ok(())
+        });
+        patcher.add_resource_patch(txtr.into(), move |res| {
+            res.kind = structs::ResourceKind::External(txtr_bytes.clone(), b"TXTR".into());
+            res.compressed = false;
+            Ok(())
+        });
+    }
     patcher.add_resource_patch(
         resource_info!("STRG_Main.STRG").into(), // 0x0552a456
         |res| patch_main_strg(res, config.version, &config.main_menu_message),
@@ -17076,9 +18107,9 @@ fn build_and_run_patches<'r>(
         }
     }
 
-    if config.results_string.is_some() {
+    if let Some(results_string) = config.results_string.as_ref() {
         patcher.add_resource_patch(resource_info!("STRG_CompletionScreen.STRG").into(), |res| {
-            patch_completion_screen(res, config.results_string.clone().unwrap(), config.version)
+            patch_completion_screen(res, results_string, config.version)
         });
     }
 
@@ -17090,10 +18121,27 @@ fn build_and_run_patches<'r>(
         patch_required_artifact_count(&mut patcher, config.required_artifact_count.unwrap());
     }
 
-    patcher.add_resource_patch(
-        resource_info!("TXTR_SaveBanner.TXTR").into(),
-        patch_save_banner_txtr,
-    );
+    match &config.game_banner.save_banner {
+        Some(path) => patcher
+            .add_resource_patch(resource_info!("TXTR_SaveBanner.TXTR").into(), move |res| {
+                patch_save_image_txtr(res, "save banner", path, 96, 32)
+            }),
+        None => patcher.add_resource_patch(
+            resource_info!("TXTR_SaveBanner.TXTR").into(),
+            patch_save_banner_txtr,
+        ),
+    }
+    if let Some(path) = &config.game_banner.save_icon {
+        // The save icon is animated between these two frames, both are replaced with the image
+        for res_info in [
+            resource_info!("TXTR_SaveIcon0.TXTR"),
+            resource_info!("TXTR_SaveIcon1.TXTR"),
+        ] {
+            patcher.add_resource_patch(res_info.into(), move |res| {
+                patch_save_image_txtr(res, "save icon", path, 32, 32)
+            });
+        }
+    }
 
     if config.patch_power_conduits {
         patch_power_conduits(&mut patcher);
@@ -17206,6 +18254,20 @@ fn build_and_run_patches<'r>(
         );
     }
 
+    for world in World::iter() {
+        let flags: Vec<_> = persistent_flags
+            .iter()
+            .filter(|flag| flag.world == world)
+            .cloned()
+            .collect();
+        if flags.is_empty() {
+            continue;
+        }
+        patcher.add_resource_patch(world.savw().into(), move |res| {
+            persistent_flags::patch_add_flags_to_savw(res, &flags)
+        });
+    }
+
     patcher.add_resource_patch(
         resource_info!("!TalonOverworld_Master.SAVW").into(),
         move |res| {
@@ -17883,11 +18945,83 @@ fn build_and_run_patches<'r>(
         for pak in paks.iter() {
             patcher.add_resource_patch(
                 (&[pak.as_bytes()], id, FourCC::from_bytes(b"STRG")),
-                move |res| patch_arbitrary_strg(res, replacement_strings.clone()),
+                move |res| {
+                    patch_arbitrary_strg(res, |lang| Some(replacement_strings.get(lang).clone()))
+                },
+            );
+        }
+    }
+
+    for (id, translation) in strg_translations {
+        if custom_strgs.contains(id) {
+            continue;
+        }
+        for pak in translation.paks.iter() {
+            patcher.add_resource_patch(
+                (&[pak.as_bytes()], *id, FourCC::from_bytes(b"STRG")),
+                move |res| patch_translated_strg(res, &translation.strings),
+            );
+        }
+    }
+
+    // Edit FRME widgets
+    for (frme, edits) in &config.frme_edits {
+        let id = match frme.parse::<u32>() {
+            Ok(n) => n,
+            Err(_e) => panic!("{} is not a valid FRME identifier", frme),
+        };
+
+        for pak in paks.iter() {
+            patcher.add_resource_patch(
+                (&[pak.as_bytes()], id, FourCC::from_bytes(b"FRME")),
+                move |res| patch_frme_edits(res, edits),
             );
         }
+
+        // A font the frame didn't use before has to be loaded along with it
+        let fonts = frme_edit_fonts(id, edits)?;
+        if fonts.is_empty() {
+            continue;
+        }
+        let frme_dep = structs::Dependency {
+            asset_id: id,
+            asset_type: FourCC::from_bytes(b"FRME"),
+        };
+        for (pak_name, room_id) in frme_rooms
+            .get(&(id, frme_dep.asset_type))
+            .into_iter()
+            .flatten()
+        {
+            let frme_dep = frme_dep.clone();
+            let fonts = fonts.clone();
+            patcher.add_scly_patch((pak_name.as_bytes(), *room_id), move |_ps, area| {
+                if let Some(layer_num) = area.dependency_layer(&frme_dep) {
+                    area.add_dependencies(
+                        game_resources,
+                        layer_num,
+                        fonts
+                            .iter()
+                            .flat_map(|(font, txtr)| [txtr.clone(), font.clone()]),
+                    );
+                }
+                Ok(())
+            });
+        }
+        // Outside of the world paks nothing is loaded per room, the font just has to be in the pak
+        for pak in paks
+            .iter()
+            .filter(|pak| !pickup_meta::ROOM_INFO.iter().any(|(name, _)| name == *pak))
+        {
+            let fonts = fonts.clone();
+            patcher.add_file_patch(pak.as_bytes(), move |file| {
+                add_frme_fonts(file, id, &fonts, game_resources)
+            });
+        }
     }
 
+    // Edit character animation events and particles
+    ancs_edits::add_ancs_edit_patches(&mut patcher, config, game_resources, &ancs_rooms)?;
+
     // Change the missile refill text if it also refills ammo
     if config.missile_station_pb_refill {
         let id: u32 = 2871382149;
@@ -17895,7 +19029,9 @@ fn build_and_run_patches<'r>(
         for pak in paks.iter() {
             patcher.add_resource_patch(
                 (&[pak.as_bytes()], id, FourCC::from_bytes(b"STRG")),
-                move |res| patch_arbitrary_strg(res, missile_station_refill_strings.clone()),
+                move |res| {
+                    patch_arbitrary_strg(res, |_| Some(missile_station_refill_strings.clone()))
+                },
             );
         }
     }
@@ -17920,6 +19056,12 @@ fn build_and_run_patches<'r>(
     }
 
     for (room, room_config) in other_patches {
+        if room_config.persistent_flags.is_some() {
+            patcher.add_scly_patch(*room, move |ps, area| {
+                persistent_flags::patch_add_persistent_flags(ps, area, persistent_flags)
+            });
+        }
+
         if let Some(connections) = room_config.add_connections.as_ref() {
             patcher.add_scly_patch(*room, move |ps, area| {
                 patch_add_connections(ps, area, connections)
@@ -17965,27 +19107,26 @@ fn build_and_run_patches<'r>(
     }
 
     if let Some(suit_colors) = config.suit_colors.as_ref() {
-        // If unspecified, default gunship color to power suit color
-        let gunship_deg = if let Some(gunship_deg) = suit_colors.gunship_deg {
-            Some(gunship_deg)
-        } else {
-            suit_colors.power_deg
-        };
-
-        let mut suit_lists: Vec<(Vec<ResourceInfo>, Option<i16>)> = [
-            (POWER_SUIT_TEXTURES, suit_colors.power_deg),
-            (FUSION_POWER_SUIT_TEXTURES, suit_colors.power_deg),
-            (VARIA_SUIT_TEXTURES, suit_colors.varia_deg),
-            (FUSION_VARIA_SUIT_TEXTURES, suit_colors.varia_deg),
-            (GRAVITY_SUIT_TEXTURES, suit_colors.gravity_deg),
-            (FUSION_GRAVITY_SUIT_TEXTURES, suit_colors.gravity_deg),
-            (PHAZON_SUIT_TEXTURES, suit_colors.phazon_deg),
-            (FUSION_PHAZON_SUIT_TEXTURES, suit_colors.phazon_deg),
-            (SHIP_TEXTURES, gunship_deg),
+        let power = suit_colors.power_recolor();
+        let varia = suit_colors.varia_recolor();
+        let gravity = suit_colors.gravity_recolor();
+        let phazon = suit_colors.phazon_recolor();
+        // The beams' arm cannon models are shared by every suit, so they have a recolor of their own
+        let mut suit_lists: Vec<(Vec<SuitTexture>, SuitRecolor)> = [
+            (POWER_SUIT_TEXTURES, power.clone()),
+            (FUSION_POWER_SUIT_TEXTURES, power),
+            (VARIA_SUIT_TEXTURES, varia.clone()),
+            (FUSION_VARIA_SUIT_TEXTURES, varia),
+            (GRAVITY_SUIT_TEXTURES, gravity.clone()),
+            (FUSION_GRAVITY_SUIT_TEXTURES, gravity),
+            (PHAZON_SUIT_TEXTURES, phazon.clone()),
+            (FUSION_PHAZON_SUIT_TEXTURES, phazon),
+            (SHIP_TEXTURES, suit_colors.gunship_recolor()),
         ]
         .into_iter()
-        .map(|(textures, angle)| (textures.to_vec(), angle))
+        .map(|(textures, recolor)| (textures.to_vec(), recolor))
         .collect();
+        suit_lists.push((gun_textures, suit_colors.gun_recolor()));
 
         if config.rainbow_phazon_ball {
             let ball_ids: HashSet<u32> = PHAZON_SPIDER_BALL_TEXTURES
@@ -17993,36 +19134,42 @@ fn build_and_run_patches<'r>(
                 .map(|t| t.res_id)
                 .collect();
             for (textures, _) in suit_lists.iter_mut() {
-                textures.retain(|t| !ball_ids.contains(&t.res_id));
+                textures.retain(|t| !ball_ids.contains(&t.info.res_id));
             }
         }
 
         // Give the second suit sharing a texture its own recolorable copy
-        let normalize = |deg: Option<i16>| deg.unwrap_or(0).rem_euclid(360);
         for shared in SHARED_SUIT_TEXTURES {
-            let owner_angle = suit_lists
+            let owner_recolor = suit_lists
                 .iter()
-                .find(|(textures, _)| textures.iter().any(|t| t.res_id == shared.original.res_id))
-                .map(|(_, angle)| normalize(*angle))
-                .unwrap_or(0);
+                .find(|(textures, _)| {
+                    textures
+                        .iter()
+                        .any(|t| t.info.res_id == shared.original.res_id)
+                })
+                .map(|(_, recolor)| recolor.clone())
+                .unwrap_or_default();
 
             let mut owner_pending = true;
             let mut duplicated = false;
-            for (textures, angle) in suit_lists.iter_mut() {
-                if !textures.iter().any(|t| t.res_id == shared.original.res_id) {
+            for (textures, recolor) in suit_lists.iter_mut() {
+                if !textures
+                    .iter()
+                    .any(|t| t.info.res_id == shared.original.res_id)
+                {
                     continue;
                 }
                 if owner_pending {
                     owner_pending = false;
                     continue;
                 }
-                if normalize(*angle) == owner_angle {
+                if *recolor == owner_recolor {
                     continue;
                 }
                 for t in textures.iter_mut() {
-                    if t.res_id == shared.original.res_id {
-                        t.res_id = shared.copy.to_u32();
-                        t.paks = shared.original.paks;
+                    if t.info.res_id == shared.original.res_id {
+                        t.info.res_id = shared.copy.to_u32();
+                        t.info.paks = shared.original.paks;
                     }
                 }
                 duplicated = true;
@@ -18061,19 +19208,61 @@ fn build_and_run_patches<'r>(
                 }
             }
         }
-        for (suit_textures, angle) in &suit_lists {
-            let angle = match *angle {
-                Some(angle) => angle % 360,
-                None => continue,
-            };
-            if angle == 0 {
+        for (suit_textures, recolor) in &suit_lists {
+            if recolor.is_identity() {
                 continue;
             }
-            let angle = angle as f32;
 
-            let cache_subdir = format!("{}/{}", config.cache_dir, angle);
+            // Masks are keyed by the original texture, even for textures given their own copy
+            let mask_id = |res_id: u32| {
+                SHARED_SUIT_TEXTURES
+                    .iter()
+                    .find(|shared| shared.copy.to_u32() == res_id)
+                    .map(|shared| shared.original.res_id)
+                    .unwrap_or(res_id)
+            };
+            let mut masks = HashMap::new();
+            for (txtr_id, path) in &recolor.masks {
+                if !suit_textures
+                    .iter()
+                    .any(|t| mask_id(t.info.res_id) == *txtr_id)
+                {
+                    return Err(format!(
+                        "suitColors: 0x{:08X} is not one of the suit's textures",
+                        txtr_id
+                    ));
+                }
+                let png = fs::read(path)
+                    .map_err(|e| format!("Failed to read suit color mask {}: {}", path, e))?;
+                let mask = RecolorMask::from_png(&png)
+                    .map_err(|e| format!("Suit color mask {}: {}", path, e))?;
+                masks.insert(*txtr_id, (mask, calculate_hash(&png)));
+            }
+
+            // A plain hue rotation keeps using the cache of the `*Deg` options
+            let cache_name = match recolor {
+                SuitRecolor {
+                    hue_deg: Some(angle),
+                    primary: None,
+                    secondary: None,
+                    glow: None,
+                    saturation: None,
+                    brightness: None,
+                    ..
+                } => format!("{}", angle),
+                _ => format!(
+                    "recolor_{:016x}",
+                    calculate_hash(&(
+                        recolor.hue_deg,
+                        [recolor.primary, recolor.secondary, recolor.glow],
+                        recolor.saturation.map(f32::to_bits),
+                        recolor.brightness.map(f32::to_bits),
+                    ))
+                ),
+            };
+            let cache_subdir = format!("{}/{}", config.cache_dir, cache_name);
             if !Path::new(&cache_subdir).is_dir() {
-                match fs::create_dir(cache_subdir) {
+                match fs::create_dir(&cache_subdir) {
                     Ok(()) => {}
                     Err(error) => {
                         if !complained {
@@ -18087,25 +19276,35 @@ fn build_and_run_patches<'r>(
                 }
             }
 
-            let matrix = huerotate_matrix(angle);
+            let params = recolor.params();
             for texture in suit_textures {
-                if !rotated_ids.insert(texture.res_id) {
+                if !rotated_ids.insert(texture.info.res_id) {
                     continue;
                 }
-                patcher.add_resource_patch((*texture).into(), move |res| {
+                let mask = masks.get(&mask_id(texture.info.res_id)).cloned();
+                let default_region = texture.region;
+                let cache_subdir = cache_subdir.clone();
+                let params = params.clone();
+                patcher.add_resource_patch(texture.info.into(), move |res| {
                     patch_txtr_mipmaps(res, |mipmap, w, h| {
-                        let filename = format!(
-                            "{}/{}/{}",
-                            config.cache_dir,
-                            angle,
-                            calculate_hash(&*mipmap)
-                        );
+                        let hash = match &mask {
+                            Some((_, mask_hash)) => calculate_hash(&(&*mipmap, mask_hash)),
+                            None => calculate_hash(&*mipmap),
+                        };
+                        let filename = format!("{}/{}", cache_subdir, hash);
                         if let Ok(cached) = fs::read(&filename) {
                             *mipmap = cached;
                             return Ok(());
                         }
                         recolor_mipmap(mipmap, w, h, |pixels| {
-                            huerotate_in_place(pixels, w, h, matrix)
+                            recolor_in_place(
+                                pixels,
+                                w,
+                                h,
+                                &params,
+                                default_region,
+                                mask.as_ref().map(|(mask, _)| mask),
+                            )
                         });
                         if let Err(error) = fs::write(&filename, &*mipmap) {
                             if !complained {
@@ -18691,3 +19890,88 @@ fn patch_elite_research_door_lock_0_02(
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::patch_config::LightType;
+
+    fn light(brightness: f32) -> LightLayer {
+        LightLayer {
+            light_type: 1,
+            color: [1.0, 1.0, 1.0].into(),
+            position: [0.0, 0.0, 0.0].into(),
+            direction: [0.0, -1.0, 0.0].into(),
+            brightness,
+            spot_cutoff: 0.0,
+            unknown0: 0.0,
+            unknown1: 0,
+            unknown2: 0.0,
+            falloff_type: 0,
+            unknown3: 0.0,
+        }
+    }
+
+    fn lights(layer: &[f32], layer_b: &[f32]) -> structs::Lights<'static> {
+        structs::Lights {
+            light_layers: layer.iter().map(|&b| light(b)).collect::<Vec<_>>().into(),
+            light_layers_b: layer_b.iter().map(|&b| light(b)).collect::<Vec<_>>().into(),
+        }
+    }
+
+    fn brightnesses(layer: &reader_writer::LazyArray<LightLayer>) -> Vec<f32> {
+        layer.iter().map(|light| light.brightness).collect()
+    }
+
+    fn lights_config(json: &str) -> LightsConfig {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn lights_are_edited_then_removed_then_added() {
+        let mut lights = lights(&[0.0, 1.0, 2.0, 3.0], &[10.0]);
+        let config = lights_config(
+            r#"{
+                "edit": [
+                    {"index": 3, "brightness": 30.0},
+                    {"index": 0, "brightness": 5.0},
+                    {"layer": 1, "index": 0, "brightness": 15.0}
+                ],
+                "remove": [{"index": 1}, {"index": 0}, {"layer": 0, "index": 1}],
+                "add": [
+                    {"type": "Spot", "brightness": 40.0},
+                    {"layer": 1, "type": "LocalAmbient", "brightness": 50.0}
+                ]
+            }"#,
+        );
+        edit_lights(&mut lights, &config, 0).unwrap();
+
+        // The edits and removals both index the lights the room started with, and the removed
+        // light 0 takes its edit with it
+        assert_eq!(brightnesses(&lights.light_layers), [2.0, 30.0, 40.0]);
+        assert_eq!(brightnesses(&lights.light_layers_b), [15.0, 50.0]);
+
+        let added = lights.light_layers.iter().nth(2).unwrap();
+        assert_eq!(added.light_type, LightType::Spot as u32);
+        assert_eq!(&added.direction[..], [0.0, -1.0, 0.0]);
+        assert_eq!(added.falloff_type, LightFalloff::Constant as u32);
+    }
+
+    #[test]
+    fn bad_light_edits_are_rejected() {
+        for json in [
+            r#"{"edit": [{"brightness": 1.0}]}"#,
+            r#"{"edit": [{"index": 2}]}"#,
+            r#"{"remove": [{"index": 2}]}"#,
+            r#"{"remove": [{"layer": 2, "index": 0}]}"#,
+            r#"{"add": [{"brightness": 1.0}]}"#,
+        ] {
+            let mut lights = lights(&[0.0, 1.0], &[]);
+            assert!(
+                edit_lights(&mut lights, &lights_config(json), 0).is_err(),
+                "{}",
+                json
+            );
+        }
+    }
+}
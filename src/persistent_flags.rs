@@ -0,0 +1,316 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+    elevators::World,
+    mlvl_wrapper::MlvlArea,
+    patch_config::{LayerId, LevelConfig, PersistentFlagConfig},
+    patcher::PatcherState,
+    patches::string_to_cstr,
+    room_lookup::ROOM_BY_NAME,
+};
+
+/// Object index of the memory relay backing a room's first flag without an explicit id. New objects
+/// are allocated downwards from 0xEFFF, but ids picked by the config can still land in this range,
+/// so each index is checked against the room's other ids before it's handed out.
+const FIRST_FLAG_INDEX: u32 = 0xF000;
+
+/// A flag from a room's `persistentFlags`. The id of its memory relay is decided before patching
+/// so that it can be written to the world's SAVW, which may come before the room in the pak.
+#[derive(Clone, Debug)]
+pub struct PersistentFlag<'a> {
+    pub world: World,
+    pub mrea_id: u32,
+    pub area_index: u32,
+    pub layer: u32,
+    pub instance_id: u32,
+    pub config: &'a PersistentFlagConfig,
+}
+
+pub fn collect_persistent_flags<'a>(
+    level_data: &'a HashMap<String, LevelConfig>,
+    layer_indices: &HashMap<(u32, String), u32>,
+) -> Result<Vec<PersistentFlag<'a>>, String> {
+    let mut flags = vec![];
+    for (world_key, level) in level_data.iter() {
+        for (room_name, room) in level.rooms.iter() {
+            let room_flags = match room.persistent_flags.as_ref() {
+                Some(room_flags) if !room_flags.is_empty() => room_flags,
+                _ => continue,
+            };
+            let room_lookup = ROOM_BY_NAME
+                .get(&(world_key.clone(), room_name.clone()))
+                .ok_or_else(|| {
+                    format!(
+                        "'{}' in levelData is not a room in {}",
+                        room_name, world_key
+                    )
+                })?;
+            let area_index = room_lookup._area_index;
+
+            // Ids the config gives to objects in this room
+            let taken: HashSet<u32> = room.object_ids().map(|id| id & 0x00FFFFFF).collect();
+
+            let mut names: Vec<&str> = vec![];
+            let mut ids: Vec<u32> = vec![];
+            let mut next_index = FIRST_FLAG_INDEX;
+            for config in room_flags {
+                if names.contains(&config.name.as_str()) {
+                    return Err(format!(
+                        "Persistent flag '{}' is declared twice in {}",
+                        config.name, room_name
+                    ));
+                }
+                names.push(&config.name);
+
+                let layer = match config.layer.as_ref() {
+                    Some(LayerId::Index(index)) => Some(*index),
+                    Some(LayerId::Name(name)) => Some(
+                        *layer_indices
+                            .get(&(room_lookup.internal_id, name.clone()))
+                            .unwrap(),
+                    ),
+                    None => None,
+                };
+
+                let instance_id = match config.id {
+                    Some(id) => {
+                        if (id >> 16) & 0x3FF != area_index {
+                            return Err(format!(
+                                "Persistent flag '{}' has id 0x{:X}, which doesn't belong to {}",
+                                config.name, id, room_name
+                            ));
+                        }
+                        if layer.is_some_and(|layer| layer != (id >> 26) & 0x1F) {
+                            return Err(format!(
+                                "Persistent flag '{}' has id 0x{:X}, which doesn't belong to layer {}",
+                                config.name, id, config.layer.as_ref().unwrap()
+                            ));
+                        }
+                        id
+                    }
+                    None => {
+                        let layer = layer.unwrap_or(0);
+                        if layer > 0x1F {
+                            return Err(format!(
+                                "Persistent flag '{}' in {} must be on one of the first 32 layers",
+                                config.name, room_name
+                            ));
+                        }
+                        let id = (layer << 26) | (area_index << 16) | next_index;
+                        if taken.contains(&(id & 0x00FFFFFF)) {
+                            return Err(format!(
+                                "Persistent flag '{}' in {} would be given the id 0x{:X}, which is already used by another object",
+                                config.name, room_name, id
+                            ));
+                        }
+                        next_index += 1;
+                        id
+                    }
+                };
+
+                if ids.contains(&(instance_id & 0x00FFFFFF)) {
+                    return Err(format!(
+                        "Persistent flags in {} share the id 0x{:X}",
+                        room_name, instance_id
+                    ));
+                }
+                ids.push(instance_id & 0x00FFFFFF);
+
+                flags.push(PersistentFlag {
+                    world: World::from_json_key(world_key),
+                    mrea_id: room_lookup.mrea_id,
+                    area_index,
+                    layer: (instance_id >> 26) & 0x1F,
+                    instance_id,
+                    config,
+                });
+            }
+        }
+    }
+
+    Ok(flags)
+}
+
+fn add_flag_connection(
+    area: &mut MlvlArea,
+    sender_id: u32,
+    connection: structs::Connection,
+) -> Result<(), String> {
+    let mrea_id = area.mlvl_area.mrea.to_u32();
+    let sender = area
+        .mrea()
+        .scly_section_mut()
+        .layers
+        .as_mut_vec()
+        .iter_mut()
+        .flat_map(|layer| layer.objects.as_mut_vec().iter_mut())
+        .find(|obj| obj.instance_id & 0x00FFFFFF == sender_id & 0x00FFFFFF)
+        .ok_or_else(|| {
+            format!(
+                "Could not find object 0x{:X} when setting a persistent flag in room 0x{:X}",
+                sender_id, mrea_id
+            )
+        })?;
+    sender.connections.as_mut_vec().push(connection);
+
+    Ok(())
+}
+
+pub fn patch_add_persistent_flags(
+    _ps: &mut PatcherState,
+    area: &mut MlvlArea,
+    flags: &[PersistentFlag],
+) -> Result<(), String> {
+    let mrea_id = area.mlvl_area.mrea.to_u32();
+    for flag in flags.iter().filter(|flag| flag.mrea_id == mrea_id) {
+        let config = flag.config;
+        if flag.layer >= area.layer_flags.layer_count {
+            return Err(format!(
+                "Persistent flag '{}' is on layer {}, but room 0x{:X} only has {} layers",
+                config.name, flag.layer, mrea_id, area.layer_flags.layer_count
+            ));
+        }
+
+        let id_taken = area
+            .mrea()
+            .scly_section_mut()
+            .layers
+            .as_mut_vec()
+            .iter_mut()
+            .flat_map(|layer| layer.objects.as_mut_vec().iter())
+            .any(|obj| obj.instance_id & 0x00FFFFFF == flag.instance_id & 0x00FFFFFF);
+        if id_taken {
+            return Err(format!(
+                "Cannot add persistent flag '{}' to room 0x{:X}, id 0x{:X} is already in use",
+                config.name, mrea_id, flag.instance_id
+            ));
+        }
+
+        for trigger in config.set_by.iter().flatten() {
+            add_flag_connection(
+                area,
+                trigger.sender_id,
+                structs::Connection {
+                    state: structs::ConnectionState(trigger.state as u32),
+                    message: structs::ConnectionMsg::ACTIVATE,
+                    target_object_id: flag.instance_id,
+                },
+            )?;
+        }
+        for trigger in config.clear_by.iter().flatten() {
+            add_flag_connection(
+                area,
+                trigger.sender_id,
+                structs::Connection {
+                    state: structs::ConnectionState(trigger.state as u32),
+                    message: structs::ConnectionMsg::DEACTIVATE,
+                    target_object_id: flag.instance_id,
+                },
+            )?;
+        }
+
+        // The relay's connections are also registered in the MLVL, which resends them whenever
+        // the room is loaded while the flag is set
+        let connections: Vec<structs::Connection> = config
+            .on_set
+            .iter()
+            .flatten()
+            .map(|hook| structs::Connection {
+                state: structs::ConnectionState::ACTIVE,
+                message: structs::ConnectionMsg(hook.message as u32),
+                target_object_id: hook.target_id,
+            })
+            .collect();
+        area.add_memory_relay(structs::SclyObject {
+            instance_id: flag.instance_id,
+            property_data: structs::MemoryRelay {
+                name: string_to_cstr(format!("flag {}", config.name)),
+                skip_send_active: 0,
+                active: config.set.unwrap_or(false) as u8,
+            }
+            .into(),
+            connections: connections.into(),
+        });
+    }
+
+    Ok(())
+}
+
+/// Lists the flags' memory relays in the SAVW so their state is kept in save files. Flags on a
+/// layer other than the default one also need that layer's state saved.
+pub fn patch_add_flags_to_savw(
+    res: &mut structs::Resource,
+    flags: &[PersistentFlag],
+) -> Result<(), String> {
+    let savw = res.kind.as_savw_mut().unwrap();
+    for flag in flags {
+        let relays = savw.memory_relay_array.as_mut_vec();
+        if !relays.contains(&flag.instance_id) {
+            relays.push(flag.instance_id);
+        }
+
+        if flag.layer != 0 {
+            let layer_toggles = savw.layer_toggle_array.as_mut_vec();
+            let saved = layer_toggles.iter().any(|toggle| {
+                toggle.area_id == flag.area_index && toggle.layer_index == flag.layer
+            });
+            if !saved {
+                layer_toggles.push(structs::LayerToggle {
+                    area_id: flag.area_index,
+                    layer_index: flag.layer,
+                });
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn level_data(rooms: serde_json::Value) -> HashMap<String, LevelConfig> {
+        let level = serde_json::from_value(json!({ "rooms": rooms })).unwrap();
+        HashMap::from([("Tallon Overworld".to_string(), level)])
+    }
+
+    fn area_index() -> u32 {
+        ROOM_BY_NAME[&("Tallon Overworld".to_string(), "Landing Site".to_string())]._area_index
+    }
+
+    #[test]
+    fn flags_are_numbered_from_the_first_flag_index() {
+        let level_data = level_data(json!({
+            "Landing Site": {
+                "persistentFlags": [{ "name": "a" }, { "name": "b", "layer": 1 }],
+            },
+        }));
+        let flags = collect_persistent_flags(&level_data, &HashMap::new()).unwrap();
+
+        let mut ids: Vec<u32> = flags.iter().map(|flag| flag.instance_id).collect();
+        ids.sort();
+        let base = area_index() << 16;
+        assert_eq!(
+            ids,
+            [
+                base | FIRST_FLAG_INDEX,
+                (1 << 26) | base | (FIRST_FLAG_INDEX + 1)
+            ]
+        );
+    }
+
+    #[test]
+    fn flags_dont_reuse_ids_given_by_the_config() {
+        let level_data = level_data(json!({
+            "Landing Site": {
+                "persistentFlags": [{ "name": "a" }],
+                "relays": [{ "id": (area_index() << 16) | FIRST_FLAG_INDEX }],
+            },
+        }));
+        let err = collect_persistent_flags(&level_data, &HashMap::new()).unwrap_err();
+        assert!(err.contains("already used"), "{}", err);
+    }
+}
@@ -0,0 +1,319 @@
+use std::collections::HashMap;
+
+use serde_json::{Map, Value};
+
+use crate::patch_config::{PrefabConfig, PrefabInstanceConfig, RoomConfig};
+
+/// Object indices handed out to prefab objects count down from here. The retail game never uses
+/// indices this high and the patcher's own allocator (`new_object_id_from_layer_id`) skips them.
+pub const PREFAB_OBJECT_INDEX_START: u32 = 0xDFFF;
+pub const PREFAB_OBJECT_INDEX_END: u32 = 0xC000;
+
+struct PrefabExpander<'a, F: FnMut() -> Result<u32, String>> {
+    prefab_name: &'a str,
+    parameters: HashMap<&'a str, &'a Value>,
+    local_ids: HashMap<String, u32>,
+    offset: [f32; 3],
+    fresh_id: F,
+}
+
+impl<F: FnMut() -> Result<u32, String>> PrefabExpander<'_, F> {
+    fn local_id(&mut self, name: &str) -> Result<u32, String> {
+        if let Some(id) = self.local_ids.get(name) {
+            return Ok(*id);
+        }
+
+        let id = (self.fresh_id)()?;
+        self.local_ids.insert(name.to_string(), id);
+        Ok(id)
+    }
+
+    fn parameter(&self, name: &str) -> Result<&Value, String> {
+        self.parameters.get(name).copied().ok_or_else(|| {
+            format!(
+                "Prefab '{}' references undeclared parameter '{}'",
+                self.prefab_name, name
+            )
+        })
+    }
+
+    // Replaces every "${name}" occurrence inside of a longer string
+    fn interpolate(&self, text: &str) -> Result<String, String> {
+        let mut result = String::new();
+        let mut rest = text;
+        while let Some(start) = rest.find("${") {
+            let end = rest[start..].find('}').ok_or_else(|| {
+                format!(
+                    "Unterminated parameter reference in prefab '{}': '{}'",
+                    self.prefab_name, text
+                )
+            })?;
+            result.push_str(&rest[..start]);
+            match self.parameter(&rest[start + 2..start + end])? {
+                Value::String(s) => result.push_str(s),
+                value => result.push_str(&value.to_string()),
+            }
+            rest = &rest[start + end + 1..];
+        }
+        result.push_str(rest);
+
+        Ok(result)
+    }
+
+    fn expand_string(&mut self, text: &str) -> Result<Value, String> {
+        if let Some(name) = text.strip_prefix('@') {
+            return Ok(Value::from(self.local_id(name)?));
+        }
+
+        if let Some(name) = text.strip_prefix('$') {
+            if !name.starts_with('{') {
+                return Ok(self.parameter(name)?.clone());
+            }
+        }
+
+        Ok(Value::String(self.interpolate(text)?))
+    }
+
+    fn expand_key(&mut self, key: &str) -> Result<String, String> {
+        match self.expand_string(key)? {
+            Value::String(s) => Ok(s),
+            value => Ok(value.to_string()),
+        }
+    }
+
+    fn expand(&mut self, value: &Value) -> Result<Value, String> {
+        let result = match value {
+            Value::String(text) => self.expand_string(text)?,
+            Value::Array(values) => Value::Array(
+                values
+                    .iter()
+                    .map(|v| self.expand(v))
+                    .collect::<Result<_, _>>()?,
+            ),
+            Value::Object(fields) => {
+                let mut result = Map::new();
+                for (key, value) in fields {
+                    let mut value = self.expand(value)?;
+                    if is_position_key(key) {
+                        self.apply_offset(&mut value);
+                    }
+                    result.insert(self.expand_key(key)?, value);
+                }
+                Value::Object(result)
+            }
+            _ => value.clone(),
+        };
+
+        Ok(result)
+    }
+
+    fn apply_offset(&self, value: &mut Value) {
+        let values = match value.as_array_mut() {
+            Some(values) if values.len() == 3 => values,
+            _ => return,
+        };
+
+        for (value, offset) in values.iter_mut().zip(self.offset) {
+            if let Some(coord) = value.as_f64() {
+                *value = Value::from(coord + offset as f64);
+            }
+        }
+    }
+}
+
+// e.g. "position", "triggerPos", "destinationPosition"
+fn is_position_key(key: &str) -> bool {
+    key == "position" || key.ends_with("Position") || key.ends_with("Pos")
+}
+
+/// Builds the room config described by `instance`. Local ids (`"@name"`) are assigned using
+/// `fresh_id`, parameters (`"$name"` or `"${name}"`) are substituted and the instance's position is
+/// added to every position in the prefab.
+pub fn instantiate_prefab<F>(
+    prefab_name: &str,
+    prefab: &PrefabConfig,
+    instance: &PrefabInstanceConfig,
+    fresh_id: F,
+) -> Result<RoomConfig, String>
+where
+    F: FnMut() -> Result<u32, String>,
+{
+    if let Some(name) = instance
+        .parameters
+        .keys()
+        .find(|name| !prefab.parameters.contains_key(*name))
+    {
+        return Err(format!(
+            "Prefab '{}' has no parameter named '{}'",
+            prefab_name, name
+        ));
+    }
+
+    let mut parameters = HashMap::new();
+    for (name, default) in prefab.parameters.iter() {
+        let value = instance.parameters.get(name).unwrap_or(default);
+        if value.is_null() {
+            return Err(format!(
+                "Prefab '{}' requires a value for parameter '{}'",
+                prefab_name, name
+            ));
+        }
+        parameters.insert(name.as_str(), value);
+    }
+
+    let mut expander = PrefabExpander {
+        prefab_name,
+        parameters,
+        local_ids: HashMap::new(),
+        offset: instance.position.unwrap_or([0.0, 0.0, 0.0]),
+        fresh_id,
+    };
+
+    let objects = expander.expand(&prefab.objects)?;
+    let room_config: RoomConfig = serde_json::from_value(objects)
+        .map_err(|e| format!("Failed to instantiate prefab '{}': {}", prefab_name, e))?;
+
+    if room_config.use_prefab.is_some() {
+        return Err(format!(
+            "Prefab '{}' cannot instantiate other prefabs",
+            prefab_name
+        ));
+    }
+
+    Ok(room_config)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn prefab(parameters: Value, objects: Value) -> PrefabConfig {
+        serde_json::from_value(json!({ "parameters": parameters, "objects": objects })).unwrap()
+    }
+
+    fn instance(position: Option<[f32; 3]>, parameters: Value) -> PrefabInstanceConfig {
+        serde_json::from_value(json!({
+            "prefab": "test",
+            "position": position,
+            "parameters": parameters,
+        }))
+        .unwrap()
+    }
+
+    fn counter(start: u32) -> impl FnMut() -> Result<u32, String> {
+        let mut next = start;
+        move || {
+            next -= 1;
+            Ok(next + 1)
+        }
+    }
+
+    #[test]
+    fn local_ids_are_remapped_consistently() {
+        let prefab = prefab(
+            json!({}),
+            json!({
+                "timers": [{ "id": "@timer" }],
+                "relays": [{ "id": "@relay" }],
+                "addConnections": [{
+                    "senderId": "@timer",
+                    "targetId": "@relay",
+                    "state": "ZERO",
+                    "message": "SET_TO_ZERO",
+                }],
+            }),
+        );
+
+        let room =
+            instantiate_prefab("test", &prefab, &instance(None, json!({})), counter(10)).unwrap();
+
+        let timer = room.timers.as_ref().unwrap()[0].id;
+        let relay = room.relays.as_ref().unwrap()[0].id;
+        assert_eq!((timer, relay), (10, 9));
+        let connection = &room.add_connections.as_ref().unwrap()[0];
+        assert_eq!((connection.sender_id, connection.target_id), (timer, relay));
+    }
+
+    #[test]
+    fn parameters_are_substituted() {
+        let prefab = prefab(
+            json!({ "time": 2.5, "label": null }),
+            json!({
+                "timers": [{
+                    "id": 1,
+                    "time": "$time",
+                    "name": "${label} timer (${time}s)",
+                }],
+            }),
+        );
+
+        let room = instantiate_prefab(
+            "test",
+            &prefab,
+            &instance(None, json!({ "label": "door" })),
+            counter(10),
+        )
+        .unwrap();
+
+        let timer = &room.timers.as_ref().unwrap()[0];
+        assert_eq!(timer.time, Some(2.5));
+        assert_eq!(timer.name.as_deref(), Some("door timer (2.5s)"));
+    }
+
+    #[test]
+    fn missing_and_extra_parameters_are_rejected() {
+        let prefab = prefab(
+            json!({ "time": null }),
+            json!({ "timers": [{ "id": 1, "time": "$time" }] }),
+        );
+
+        let missing = instantiate_prefab("test", &prefab, &instance(None, json!({})), counter(10));
+        assert!(missing
+            .unwrap_err()
+            .contains("requires a value for parameter 'time'"));
+
+        let extra = instantiate_prefab(
+            "test",
+            &prefab,
+            &instance(None, json!({ "time": 1.0, "speed": 2.0 })),
+            counter(10),
+        );
+        assert!(extra
+            .unwrap_err()
+            .contains("has no parameter named 'speed'"));
+
+        let undeclared = self::prefab(
+            json!({}),
+            json!({ "timers": [{ "id": 1, "time": "$time" }] }),
+        );
+        let undeclared =
+            instantiate_prefab("test", &undeclared, &instance(None, json!({})), counter(10));
+        assert!(undeclared
+            .unwrap_err()
+            .contains("undeclared parameter 'time'"));
+    }
+
+    #[test]
+    fn positions_are_offset() {
+        let prefab = prefab(
+            json!({}),
+            json!({
+                "waypoints": [{ "id": 1, "position": [1.0, 2.0, 3.0], "rotation": [0.0, 0.0, 90.0] }],
+            }),
+        );
+
+        let room = instantiate_prefab(
+            "test",
+            &prefab,
+            &instance(Some([10.0, -20.0, 0.5]), json!({})),
+            counter(10),
+        )
+        .unwrap();
+
+        let waypoint = &room.waypoints.as_ref().unwrap()[0];
+        assert_eq!(waypoint.position, Some([11.0, -18.0, 3.5]));
+        assert_eq!(waypoint.rotation, Some([0.0, 0.0, 90.0]));
+    }
+}
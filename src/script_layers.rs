@@ -0,0 +1,441 @@
+use std::collections::HashMap;
+
+use structs::{Mlvl, Savw};
+
+use crate::{
+    elevators::World,
+    mlvl_wrapper::{world_mlvl, MlvlArea},
+    patch_config::{LayerId, LevelConfig, RoomConfig},
+    room_lookup::{ROOM_BY_INTERNAL_ID, ROOM_BY_NAME},
+};
+
+pub fn find_layer(names: &[String], layer: &LayerId) -> Option<usize> {
+    match layer {
+        LayerId::Index(index) if (*index as usize) < names.len() => Some(*index as usize),
+        LayerId::Index(_) => None,
+        LayerId::Name(name) => names.iter().position(|n| n == name),
+    }
+}
+
+/// Applies a room's `addLayers` and `renameLayers` to its layer names, which are the names
+/// `deleteLayers` refers to.
+fn added_and_renamed_layer_names(
+    mut names: Vec<String>,
+    room_name: &str,
+    room: &RoomConfig,
+) -> Result<Vec<String>, String> {
+    for add_layer in room.add_layers.iter().flatten() {
+        if names.contains(&add_layer.name) {
+            return Err(format!(
+                "Layer '{}' already exists in {}",
+                add_layer.name, room_name
+            ));
+        }
+        names.push(add_layer.name.clone());
+    }
+
+    let original_names = names.clone();
+    for (layer, name) in room.rename_layers.iter().flatten() {
+        let index = find_layer(&original_names, layer)
+            .ok_or_else(|| format!("Cannot rename missing layer {} in {}", layer, room_name))?;
+        names[index] = name.clone();
+    }
+
+    Ok(names)
+}
+
+/// The indices of the layers `deleteLayers` removes, in ascending order. `names` are the room's
+/// layer names after its added and renamed layers.
+pub fn deleted_layer_indices(
+    names: &[String],
+    room_name: &str,
+    room: &RoomConfig,
+) -> Result<Vec<usize>, String> {
+    let mut deleted = vec![];
+    for layer in room.delete_layers.iter().flatten() {
+        let index = find_layer(names, layer)
+            .ok_or_else(|| format!("Cannot delete missing layer {} in {}", layer, room_name))?;
+        if index == 0 {
+            return Err(format!("Cannot delete the default layer of {}", room_name));
+        }
+        deleted.push(index);
+    }
+    deleted.sort_unstable();
+    deleted.dedup();
+
+    Ok(deleted)
+}
+
+/// Applies a room's `addLayers`, `renameLayers` and `deleteLayers` to its layer names, in the same
+/// order the patcher does.
+pub fn edited_layer_names(
+    names: Vec<String>,
+    room_name: &str,
+    room: &RoomConfig,
+) -> Result<Vec<String>, String> {
+    let mut names = added_and_renamed_layer_names(names, room_name, room)?;
+    for index in deleted_layer_indices(&names, room_name, room)?
+        .into_iter()
+        .rev()
+    {
+        names.remove(index);
+    }
+
+    Ok(names)
+}
+
+fn vanilla_layer_names(mlvl: &Mlvl, area_index: usize) -> Vec<String> {
+    mlvl.area_layer_names
+        .names_for_area(area_index)
+        .unwrap()
+        .iter()
+        .map(|name| name.to_str().unwrap().to_string())
+        .collect()
+}
+
+/// The layers `deleteLayers` removes from one room
+pub struct DeletedLayers {
+    pub internal_id: u32,
+    pub area_index: u32,
+    pub layers: Vec<u32>, // ascending, numbered as they are before any is deleted
+}
+
+impl DeletedLayers {
+    /// The index a layer ends up at once the deleted layers are gone, or None if it's deleted
+    pub fn remap(&self, layer: u32) -> Option<u32> {
+        if self.layers.contains(&layer) {
+            return None;
+        }
+        Some(
+            layer
+                - self
+                    .layers
+                    .iter()
+                    .filter(|deleted| **deleted < layer)
+                    .count() as u32,
+        )
+    }
+
+    /// Renumbers the layer bits of an instance id from this room, or None if its layer is deleted
+    pub fn remap_instance_id(&self, id: u32) -> Option<u32> {
+        if (id >> 16) & 0x3FF != self.area_index {
+            return Some(id);
+        }
+        self.remap(id >> 26)
+            .map(|layer| (id & 0x03FFFFFF) | (layer << 26))
+    }
+}
+
+/// Finds the layers every room's `deleteLayers` removes, grouped by the pak of the room's world.
+/// Deleting a layer moves every later layer down one index, so references to those layers from
+/// anywhere in the world have to be renumbered.
+pub fn collect_deleted_layers(
+    gc_disc: &structs::GcDisc,
+    level_data: &HashMap<String, LevelConfig>,
+) -> Result<HashMap<&'static str, Vec<DeletedLayers>>, String> {
+    let mut deleted_layers: HashMap<&'static str, Vec<DeletedLayers>> = HashMap::new();
+    for (world_key, level) in level_data.iter() {
+        let rooms: Vec<_> = level
+            .rooms
+            .iter()
+            .filter(|(_, room)| room.delete_layers.is_some())
+            .collect();
+        if rooms.is_empty() {
+            continue;
+        }
+
+        let pak_name = World::from_json_key(world_key).to_pak_str();
+        let mlvl = world_mlvl(gc_disc, pak_name)?;
+        for (room_name, room) in rooms {
+            let room_lookup = ROOM_BY_NAME
+                .get(&(world_key.clone(), room_name.clone()))
+                .ok_or_else(|| {
+                    format!(
+                        "'{}' in levelData is not a room in {}",
+                        room_name, world_key
+                    )
+                })?;
+            let area_index = mlvl
+                .areas
+                .iter()
+                .position(|area| area.mrea == room_lookup.mrea_id)
+                .ok_or_else(|| {
+                    format!("Room 0x{:X} is not in {}", room_lookup.mrea_id, pak_name)
+                })?;
+
+            let names = added_and_renamed_layer_names(
+                vanilla_layer_names(&mlvl, area_index),
+                room_name,
+                room,
+            )?;
+            let layers = deleted_layer_indices(&names, room_name, room)?;
+            deleted_layers
+                .entry(pak_name)
+                .or_default()
+                .push(DeletedLayers {
+                    internal_id: room_lookup.internal_id,
+                    area_index: area_index as u32,
+                    layers: layers.into_iter().map(|index| index as u32).collect(),
+                });
+        }
+    }
+
+    Ok(deleted_layers)
+}
+
+// ScriptLayerController
+const LAYER_CONTROLLER_TYPE: u32 = 16;
+
+/// Points the ScriptLayerControllers in `area` that toggle a layer of a room in `deleted_layers`
+/// at the layer's new index. Controllers for a deleted layer have nothing left to toggle, so they
+/// are removed.
+pub fn patch_remap_layer_controllers(
+    area: &mut MlvlArea,
+    deleted_layers: &[DeletedLayers],
+) -> Result<(), String> {
+    let scly = area.mrea().scly_section_mut();
+    for layer in scly.layers.as_mut_vec().iter_mut() {
+        layer.objects.as_mut_vec().retain_mut(|obj| {
+            let special_function = match obj.property_data.as_special_function_mut() {
+                Some(special_function) if special_function.type_ == LAYER_CONTROLLER_TYPE => {
+                    special_function
+                }
+                _ => return true,
+            };
+            let deleted = match deleted_layers
+                .iter()
+                .find(|deleted| deleted.internal_id == special_function.layer_change_room_id)
+            {
+                Some(deleted) => deleted,
+                None => return true,
+            };
+            match deleted.remap(special_function.layer_change_layer_id) {
+                Some(layer) => {
+                    special_function.layer_change_layer_id = layer;
+                    true
+                }
+                None => false,
+            }
+        });
+    }
+
+    Ok(())
+}
+
+/// Renumbers the layers the SAVW saves the state of, along with the memory relays on them
+pub fn patch_remap_savw_layers(
+    res: &mut structs::Resource,
+    deleted_layers: &[DeletedLayers],
+) -> Result<(), String> {
+    remap_savw_layers(res.kind.as_savw_mut().unwrap(), deleted_layers);
+    Ok(())
+}
+
+fn remap_savw_layers(savw: &mut Savw, deleted_layers: &[DeletedLayers]) {
+    let layer_toggles = savw.layer_toggle_array.as_mut_vec();
+    layer_toggles.retain_mut(|toggle| {
+        let deleted = match deleted_layers
+            .iter()
+            .find(|deleted| deleted.area_index == toggle.area_id)
+        {
+            Some(deleted) => deleted,
+            None => return true,
+        };
+        match deleted.remap(toggle.layer_index) {
+            Some(layer) => {
+                toggle.layer_index = layer;
+                true
+            }
+            None => false,
+        }
+    });
+
+    let relays = savw.memory_relay_array.as_mut_vec();
+    relays.retain_mut(|id| {
+        for deleted in deleted_layers {
+            match deleted.remap_instance_id(*id) {
+                Some(new_id) => *id = new_id,
+                None => return false,
+            }
+        }
+        true
+    });
+}
+
+/// Finds the layer index of every layer referred to by name in another room's `layerChangeLayerId`
+/// or in a room's `persistentFlags`. The names are those the layers will have after the target
+/// room's own layer edits are applied.
+pub fn collect_layer_indices(
+    gc_disc: &structs::GcDisc,
+    level_data: &HashMap<String, LevelConfig>,
+) -> Result<HashMap<(u32, String), u32>, String> {
+    // Rooms which need their layer names looked up
+    let mut requests: HashMap<u32, Vec<String>> = HashMap::new();
+    for (world_key, level) in level_data.iter() {
+        for (room_name, room) in level.rooms.iter() {
+            let own_internal_id = ROOM_BY_NAME
+                .get(&(world_key.clone(), room_name.clone()))
+                .map(|room| room.internal_id);
+            for special_function in room.special_functions.iter().flatten() {
+                let (room_id, name) = match (
+                    special_function.layer_change_room_id,
+                    special_function.layer_change_layer_id.as_ref(),
+                ) {
+                    (Some(room_id), Some(LayerId::Name(name))) => (room_id, name),
+                    _ => continue,
+                };
+                if Some(room_id) != own_internal_id {
+                    requests.entry(room_id).or_default().push(name.clone());
+                }
+            }
+
+            // Flags have their ids decided before patching, which includes the layer
+            if let Some(internal_id) = own_internal_id {
+                for flag in room.persistent_flags.iter().flatten() {
+                    if let Some(LayerId::Name(name)) = flag.layer.as_ref() {
+                        requests.entry(internal_id).or_default().push(name.clone());
+                    }
+                }
+            }
+        }
+    }
+
+    let mut indices = HashMap::new();
+    for (internal_id, layer_names) in requests {
+        let room_lookup = ROOM_BY_INTERNAL_ID.get(&internal_id).ok_or_else(|| {
+            format!(
+                "layerChangeRoomId 0x{:X} is not the id of any room",
+                internal_id
+            )
+        })?;
+        let pak_name = World::from_json_key(room_lookup.region_name).to_pak_str();
+
+        let mlvl = world_mlvl(gc_disc, pak_name)?;
+        let area_index = mlvl
+            .areas
+            .iter()
+            .position(|area| area.internal_id == internal_id)
+            .ok_or_else(|| format!("Room 0x{:X} is not in {}", internal_id, pak_name))?;
+
+        let vanilla_names = vanilla_layer_names(&mlvl, area_index);
+        let names = match level_data
+            .get(room_lookup.region_name)
+            .and_then(|level| level.rooms.get(room_lookup.room_name))
+        {
+            Some(room) => edited_layer_names(vanilla_names, room_lookup.room_name, room)?,
+            None => vanilla_names,
+        };
+
+        for name in layer_names {
+            let index = find_layer(&names, &LayerId::Name(name.clone())).ok_or_else(|| {
+                format!("{} has no layer named '{}'", room_lookup.room_name, name)
+            })?;
+            indices.insert((internal_id, name), index as u32);
+        }
+    }
+
+    Ok(indices)
+}
+
+#[cfg(test)]
+mod tests {
+    use reader_writer::{Reader, Writable};
+    use serde_json::json;
+
+    use super::*;
+
+    fn names(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn deleting_a_middle_layer_renumbers_the_later_ones() {
+        let vanilla = names(&["Default", "1st Pass", "2nd Pass", "3rd Pass"]);
+        let room: RoomConfig = serde_json::from_value(json!({
+            "addLayers": [{ "name": "Extra" }],
+            "deleteLayers": ["1st Pass"],
+        }))
+        .unwrap();
+
+        let before = added_and_renamed_layer_names(vanilla.clone(), "Room", &room).unwrap();
+        let after = edited_layer_names(vanilla, "Room", &room).unwrap();
+        assert_eq!(after, names(&["Default", "2nd Pass", "3rd Pass", "Extra"]));
+
+        let deleted = DeletedLayers {
+            internal_id: 0x1234,
+            area_index: 5,
+            layers: deleted_layer_indices(&before, "Room", &room)
+                .unwrap()
+                .into_iter()
+                .map(|index| index as u32)
+                .collect(),
+        };
+        assert_eq!(deleted.layers, vec![1]);
+
+        // Every layer that's kept is still found under its name at its new index
+        for (old_index, name) in before.iter().enumerate() {
+            let new_index = deleted.remap(old_index as u32);
+            let expected = find_layer(&after, &LayerId::Name(name.clone())).map(|i| i as u32);
+            assert_eq!(new_index, expected, "{}", name);
+        }
+
+        let id = |layer: u32, area: u32| (layer << 26) | (area << 16) | 0x0012;
+        assert_eq!(deleted.remap_instance_id(id(0, 5)), Some(id(0, 5)));
+        assert_eq!(deleted.remap_instance_id(id(1, 5)), None);
+        assert_eq!(deleted.remap_instance_id(id(3, 5)), Some(id(2, 5)));
+        assert_eq!(deleted.remap_instance_id(id(3, 6)), Some(id(3, 6)));
+    }
+
+    #[test]
+    fn savw_layer_toggles_and_relays_follow_deleted_layers() {
+        let mut bytes = vec![];
+        for word in [
+            0xC001D00Du32, // magic
+            3,             // version
+            8,             // area count
+            0,             // cinematic skips
+            3,             // memory relays
+            (1 << 26) | (5 << 16) | 1,
+            (2 << 26) | (5 << 16) | 2,
+            (2 << 26) | (6 << 16) | 3,
+            3, // layer toggles
+            5,
+            1,
+            5,
+            2,
+            6,
+            2,
+            0, // doors
+            0, // scans
+        ] {
+            bytes.extend(word.to_be_bytes());
+        }
+        bytes.resize(96, 0);
+
+        let mut savw: Savw = Reader::new(&bytes[..]).read(());
+        let deleted = DeletedLayers {
+            internal_id: 0x1234,
+            area_index: 5,
+            layers: vec![1],
+        };
+        remap_savw_layers(&mut savw, &[deleted]);
+
+        let relays: Vec<u32> = savw.memory_relay_array.iter().map(|id| *id).collect();
+        assert_eq!(
+            relays,
+            vec![(1 << 26) | (5 << 16) | 2, (2 << 26) | (6 << 16) | 3]
+        );
+        let toggles: Vec<(u32, u32)> = savw
+            .layer_toggle_array
+            .iter()
+            .map(|toggle| (toggle.area_id, toggle.layer_index))
+            .collect();
+        assert_eq!(toggles, vec![(5, 1), (6, 2)]);
+
+        let mut written = vec![];
+        savw.write_to(&mut written).unwrap();
+        let reread: Savw = Reader::new(&written[..]).read(());
+        assert_eq!(reread.memory_relay_array.len(), 2);
+        assert_eq!(reread.layer_toggle_array.len(), 2);
+    }
+}
@@ -0,0 +1,529 @@
+use std::{collections::HashMap, fmt::Write, path::Path};
+
+use reader_writer::FourCC;
+
+// Every string table of a STRG, with the null terminators removed
+#[derive(Debug, Clone)]
+pub struct StrgExport {
+    pub id: u32,
+    pub paks: Vec<String>,
+    pub tables: Vec<(FourCC, Vec<String>)>,
+}
+
+impl StrgExport {
+    pub fn new(id: u32, pak: &str, strg: &structs::Strg) -> Self {
+        let tables = strg
+            .string_tables
+            .iter()
+            .map(|table| {
+                let strings = table
+                    .strings
+                    .iter()
+                    .map(|s| {
+                        s.into_owned()
+                            .into_string()
+                            .trim_end_matches('\0')
+                            .to_string()
+                    })
+                    .collect();
+                (table.lang, strings)
+            })
+            .collect();
+
+        StrgExport {
+            id,
+            paks: vec![pak.to_string()],
+            tables,
+        }
+    }
+
+    fn string_count(&self) -> usize {
+        self.tables.first().map_or(0, |(_, strings)| strings.len())
+    }
+}
+
+// The strings translated in one STRG and the paks it was exported from
+#[derive(Debug, Clone, Default)]
+pub struct StrgTranslation {
+    pub paks: Vec<String>,
+    // language -> string index -> translated string
+    pub strings: HashMap<FourCC, HashMap<usize, String>>,
+}
+
+impl StrgTranslation {
+    fn add_paks<'a>(&mut self, paks: impl IntoIterator<Item = &'a str>) {
+        for pak in paks {
+            if !self.paks.iter().any(|p| p == pak) {
+                self.paks.push(pak.to_string());
+            }
+        }
+    }
+}
+
+// STRG id -> its translation
+pub type StrgTranslations = HashMap<u32, StrgTranslation>;
+
+fn lang_str(lang: FourCC) -> String {
+    String::from_utf8_lossy(lang.as_bytes()).into_owned()
+}
+
+fn parse_lang(lang: &str) -> Result<FourCC, String> {
+    let bytes: [u8; 4] = lang
+        .as_bytes()
+        .try_into()
+        .map_err(|_| format!("'{}' is not a language FourCC", lang))?;
+    Ok(FourCC::from_bytes(&bytes))
+}
+
+fn parse_id(id: &str) -> Result<u32, String> {
+    let id = id.trim();
+    match id.strip_prefix("0x") {
+        Some(hex) => u32::from_str_radix(hex, 16),
+        None => id.parse(),
+    }
+    .map_err(|_| format!("'{}' is not a valid STRG identifier", id))
+}
+
+/* PO */
+
+// Each string of each language is its own message, identified by its context "<id>:<index>:<lang>".
+// Messages left with an empty msgstr keep the game's text on import.
+
+fn po_escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '"' => escaped.push_str("\\\""),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn po_unescape(s: &str) -> Result<String, String> {
+    let mut unescaped = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('\\') => unescaped.push('\\'),
+            Some('"') => unescaped.push('"'),
+            Some('n') => unescaped.push('\n'),
+            Some('r') => unescaped.push('\r'),
+            Some('t') => unescaped.push('\t'),
+            c => Err(format!(
+                "Unsupported escape sequence '\\{}'",
+                c.unwrap_or(' ')
+            ))?,
+        }
+    }
+    Ok(unescaped)
+}
+
+pub fn write_po(strgs: &[StrgExport]) -> String {
+    let mut po = String::new();
+    po.push_str("msgid \"\"\nmsgstr \"\"\n\"Content-Type: text/plain; charset=UTF-8\\n\"\n");
+
+    for strg in strgs {
+        for (lang, strings) in &strg.tables {
+            for (index, string) in strings.iter().enumerate() {
+                writeln!(po).unwrap();
+                writeln!(po, "#: {}", strg.paks.join(" ")).unwrap();
+                writeln!(
+                    po,
+                    "msgctxt \"0x{:08X}:{}:{}\"",
+                    strg.id,
+                    index,
+                    lang_str(*lang)
+                )
+                .unwrap();
+                writeln!(po, "msgid \"{}\"", po_escape(string)).unwrap();
+                writeln!(po, "msgstr \"\"").unwrap();
+            }
+        }
+    }
+
+    po
+}
+
+pub fn read_po(po: &str) -> Result<StrgTranslations, String> {
+    #[derive(PartialEq)]
+    enum Field {
+        None,
+        Ctxt,
+        Id,
+        Str,
+    }
+
+    let mut translations = StrgTranslations::new();
+    let mut entries = vec![];
+    let (mut ctxt, mut msgstr) = (None::<String>, String::new());
+    // The paks listed by the "#:" reference comment of the current message
+    let mut paks = String::new();
+    let mut field = Field::None;
+
+    for (line_idx, line) in po.lines().chain(std::iter::once("")).enumerate() {
+        let line = line.trim();
+        let (keyword, rest) = match line.split_once(' ') {
+            Some((keyword, rest)) if !line.starts_with('"') => (keyword, rest.trim()),
+            _ => ("", line),
+        };
+
+        if line.is_empty()
+            || line.starts_with('#')
+            || keyword == "msgctxt"
+            || (keyword == "msgid" && field == Field::Str)
+        {
+            if field == Field::Str {
+                entries.push((
+                    ctxt.take(),
+                    std::mem::take(&mut paks),
+                    std::mem::take(&mut msgstr),
+                ));
+            }
+            field = Field::None;
+        }
+        if let Some(references) = line.strip_prefix("#:") {
+            paks.push_str(references);
+            paks.push(' ');
+        }
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let quoted = rest
+            .strip_prefix('"')
+            .and_then(|s| s.strip_suffix('"'))
+            .ok_or_else(|| format!("Line {}: expected a quoted string", line_idx + 1))?;
+        let text = po_unescape(quoted).map_err(|e| format!("Line {}: {}", line_idx + 1, e))?;
+
+        match keyword {
+            "msgctxt" => {
+                ctxt = Some(text);
+                field = Field::Ctxt;
+            }
+            "msgid" => field = Field::Id,
+            "msgstr" => {
+                msgstr = text;
+                field = Field::Str;
+            }
+            "" => match field {
+                Field::Ctxt => ctxt.as_mut().unwrap().push_str(&text),
+                Field::Str => msgstr.push_str(&text),
+                Field::Id => {}
+                Field::None => Err(format!("Line {}: unexpected string", line_idx + 1))?,
+            },
+            _ => Err(format!(
+                "Line {}: unsupported keyword '{}'",
+                line_idx + 1,
+                keyword
+            ))?,
+        }
+    }
+
+    for (ctxt, paks, msgstr) in entries {
+        // The header has no context
+        let Some(ctxt) = ctxt else { continue };
+        if msgstr.is_empty() {
+            continue;
+        }
+
+        let mut parts = ctxt.split(':');
+        let (id, index, lang) = match (parts.next(), parts.next(), parts.next(), parts.next()) {
+            (Some(id), Some(index), Some(lang), None) => (id, index, lang),
+            _ => Err(format!("'{}' is not a STRG message context", ctxt))?,
+        };
+        let index = index
+            .parse::<usize>()
+            .map_err(|_| format!("'{}' has an invalid string index", ctxt))?;
+
+        let translation = translations.entry(parse_id(id)?).or_default();
+        translation.add_paks(paks.split_whitespace());
+        translation
+            .strings
+            .entry(parse_lang(lang)?)
+            .or_default()
+            .insert(index, msgstr);
+    }
+
+    Ok(translations)
+}
+
+/* CSV */
+
+// One row per string index of each STRG, with a column for every language found. Cells left
+// empty keep the game's text on import.
+
+fn csv_escape(s: &str) -> String {
+    if s.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+fn csv_records(csv: &str) -> Result<Vec<Vec<String>>, String> {
+    let mut records = vec![];
+    let mut record = vec![];
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = csv.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match (quoted, c) {
+            (true, '"') if chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            (true, '"') => quoted = false,
+            (true, c) => field.push(c),
+            (false, '"') if field.is_empty() => quoted = true,
+            (false, ',') => record.push(std::mem::take(&mut field)),
+            (false, '\r') if chars.peek() == Some(&'\n') => {}
+            (false, '\n') => {
+                record.push(std::mem::take(&mut field));
+                records.push(std::mem::take(&mut record));
+            }
+            (false, c) => field.push(c),
+        }
+    }
+    if quoted {
+        Err("Unterminated quoted field".to_string())?;
+    }
+    if !field.is_empty() || !record.is_empty() {
+        record.push(field);
+        records.push(record);
+    }
+
+    Ok(records)
+}
+
+pub fn write_csv(strgs: &[StrgExport]) -> String {
+    let mut langs: Vec<FourCC> = vec![];
+    for (lang, _) in strgs.iter().flat_map(|strg| strg.tables.iter()) {
+        if !langs.contains(lang) {
+            langs.push(*lang);
+        }
+    }
+
+    let mut csv = String::from("id,paks,index");
+    for lang in &langs {
+        write!(csv, ",{}", lang_str(*lang)).unwrap();
+    }
+    csv.push('\n');
+
+    for strg in strgs {
+        for index in 0..strg.string_count() {
+            write!(csv, "0x{:08X},{},{}", strg.id, strg.paks.join(" "), index).unwrap();
+            for lang in &langs {
+                let string = strg
+                    .tables
+                    .iter()
+                    .find(|(l, _)| l == lang)
+                    .map_or("", |(_, strings)| &strings[index]);
+                write!(csv, ",{}", csv_escape(string)).unwrap();
+            }
+            csv.push('\n');
+        }
+    }
+
+    csv
+}
+
+pub fn read_csv(csv: &str) -> Result<StrgTranslations, String> {
+    let mut records = csv_records(csv)?.into_iter();
+    let header = records.next().ok_or("The CSV file is empty")?;
+    if header.len() < 3 || header[0] != "id" || header[2] != "index" {
+        Err("The CSV header must start with the id, paks and index columns")?;
+    }
+    let langs = header[3..]
+        .iter()
+        .map(|lang| parse_lang(lang))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut translations = StrgTranslations::new();
+    for (row_idx, record) in records.enumerate() {
+        if record.len() != header.len() {
+            Err(format!(
+                "Row {} has {} columns, expected {}",
+                row_idx + 2,
+                record.len(),
+                header.len()
+            ))?;
+        }
+        let id = parse_id(&record[0])?;
+        let index = record[2]
+            .trim()
+            .parse::<usize>()
+            .map_err(|_| format!("Row {} has an invalid string index", row_idx + 2))?;
+
+        for (lang, string) in langs.iter().zip(&record[3..]) {
+            if string.is_empty() {
+                continue;
+            }
+            let translation = translations.entry(id).or_default();
+            translation.add_paks(record[1].split_whitespace());
+            translation
+                .strings
+                .entry(*lang)
+                .or_default()
+                .insert(index, string.clone());
+        }
+    }
+
+    Ok(translations)
+}
+
+/* Files */
+
+fn is_po(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("po") || ext.eq_ignore_ascii_case("pot"))
+}
+
+// The format is picked from the extension, .po and .pot for PO and CSV otherwise
+pub fn write_strings_file(path: &Path, strgs: &[StrgExport]) -> Result<(), String> {
+    let contents = if is_po(path) {
+        write_po(strgs)
+    } else {
+        write_csv(strgs)
+    };
+    std::fs::write(path, contents).map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+}
+
+pub fn read_strings_file(path: &Path) -> Result<StrgTranslations, String> {
+    let contents = std::fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    let contents = contents.trim_start_matches('\u{feff}');
+    if is_po(path) {
+        read_po(contents)
+    } else {
+        read_csv(contents)
+    }
+    .map_err(|e| format!("Failed to parse {}: {}", path.display(), e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn export(strings: &[&str]) -> StrgExport {
+        StrgExport {
+            id: 0x1234ABCD,
+            paks: vec!["Metroid2.pak".to_string(), "Metroid4.pak".to_string()],
+            tables: vec![
+                (
+                    FourCC::from_bytes(b"ENGL"),
+                    strings.iter().map(|s| s.to_string()).collect(),
+                ),
+                (
+                    FourCC::from_bytes(b"JAPN"),
+                    strings.iter().map(|s| format!("{} (JP)", s)).collect(),
+                ),
+            ],
+        }
+    }
+
+    fn translation<'a>(
+        translations: &'a StrgTranslations,
+        lang: &[u8; 4],
+        index: usize,
+    ) -> Option<&'a str> {
+        translations[&0x1234ABCD].strings[&FourCC::from_bytes(lang)]
+            .get(&index)
+            .map(|s| s.as_str())
+    }
+
+    #[test]
+    fn po_escapes_round_trip() {
+        let text = "say \"hi\"\\\r\n\ttab";
+        assert_eq!(po_unescape(&po_escape(text)).unwrap(), text);
+        assert!(!po_escape(text).contains(['\r', '\n', '\t']));
+        assert!(po_unescape("\\x").is_err());
+    }
+
+    #[test]
+    fn po_reads_escaped_and_multi_line_messages() {
+        let po = concat!(
+            "msgid \"\"\n",
+            "msgstr \"\"\n",
+            "\"Content-Type: text/plain; charset=UTF-8\\n\"\n",
+            "\n",
+            "#: Metroid2.pak\n",
+            "msgctxt \"0x1234ABCD:0:ENGL\"\n",
+            "msgid \"Original\"\n",
+            "msgstr \"A \\\"quoted\\\" word\"\n",
+            "\n",
+            "msgctxt \"0x1234ABCD:1:ENGL\"\n",
+            "msgid \"Original\"\n",
+            "msgstr \"\"\n",
+            "\"first line\\r\\n\"\n",
+            "\"second line\"\n",
+            "\n",
+            "msgctxt \"0x1234ABCD:2:ENGL\"\n",
+            "msgid \"Kept as is\"\n",
+            "msgstr \"\"\n",
+        );
+
+        let translations = read_po(po).unwrap();
+        assert_eq!(
+            translation(&translations, b"ENGL", 0),
+            Some("A \"quoted\" word")
+        );
+        assert_eq!(
+            translation(&translations, b"ENGL", 1),
+            Some("first line\r\nsecond line")
+        );
+        assert_eq!(translation(&translations, b"ENGL", 2), None);
+        assert_eq!(translations[&0x1234ABCD].paks, ["Metroid2.pak"]);
+    }
+
+    #[test]
+    fn po_export_lists_every_string() {
+        let po = write_po(&[export(&["one", "line\r\nbreak"])]);
+        assert!(po.contains("msgctxt \"0x1234ABCD:1:JAPN\"\nmsgid \"line\\r\\nbreak (JP)\""));
+        assert!(po.contains("#: Metroid2.pak Metroid4.pak"));
+
+        // Nothing is translated until a msgstr is filled in
+        assert!(read_po(&po).unwrap().is_empty());
+    }
+
+    #[test]
+    fn csv_round_trips_commas_quotes_and_newlines() {
+        let strings = [
+            "plain",
+            "with, comma",
+            "with \"quotes\"",
+            "two\nlines",
+            "crlf\r\nlines",
+        ];
+        let csv = write_csv(&[export(&strings)]);
+        let translations = read_csv(&csv).unwrap();
+
+        for (index, string) in strings.iter().enumerate() {
+            assert_eq!(translation(&translations, b"ENGL", index), Some(*string));
+            assert_eq!(
+                translation(&translations, b"JAPN", index),
+                Some(format!("{} (JP)", string).as_str())
+            );
+        }
+        assert_eq!(
+            translations[&0x1234ABCD].paks,
+            ["Metroid2.pak", "Metroid4.pak"]
+        );
+    }
+
+    #[test]
+    fn csv_rejects_malformed_rows() {
+        assert!(read_csv("id,paks,index,ENGL\n0x1,a.pak,0,\"open\n").is_err());
+        assert!(read_csv("id,paks,index,ENGL\n0x1,a.pak,0\n").is_err());
+        assert!(read_csv("name,paks,index\n").is_err());
+    }
+}
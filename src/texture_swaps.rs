@@ -0,0 +1,226 @@
+use std::{collections::HashMap, fs};
+
+use reader_writer::{FourCC, Reader, Writable};
+use structs::{res_id, Dependency, ResId, Resource, ResourceKind};
+
+use crate::{
+    custom_assets::{build_resource, custom_asset_ids},
+    elevators::World,
+    mlvl_wrapper,
+    patch_config::{LevelConfig, PatchConfig, TextureSwap},
+    patcher::{PatcherState, PrimePatcher},
+    pickup_meta,
+    txtr_conversions::{png_to_cmpr_txtr, CmprQuality},
+};
+
+// Swaps are resolved to "replace this TXTR with that TXTR"
+type Swaps = HashMap<u32, u32>;
+
+/// Every PNG used in `textureSwaps`. Their TXTRs take the first custom asset IDs after
+/// `EXTRA_IDS_START`, in this order.
+pub fn png_paths(config: &PatchConfig) -> Vec<&str> {
+    let mut paths: Vec<&str> = all_swaps(config)
+        .filter_map(|swap| match swap {
+            TextureSwap::Png(path) => Some(path.as_str()),
+            TextureSwap::Txtr(_) => None,
+        })
+        .collect();
+    paths.sort_unstable();
+    paths.dedup();
+    paths
+}
+
+fn png_txtr_id(index: usize) -> ResId<res_id::TXTR> {
+    ResId::new(custom_asset_ids::EXTRA_IDS_START.to_u32() + index as u32)
+}
+
+pub fn png_txtrs<'r>(config: &PatchConfig) -> Result<Vec<Resource<'r>>, String> {
+    png_paths(config)
+        .into_iter()
+        .enumerate()
+        .map(|(i, path)| {
+            let png = fs::read(path)
+                .map_err(|e| format!("Failed to read texture swap {}: {}", path, e))?;
+            let txtr = png_to_cmpr_txtr(&png, CmprQuality::Normal)
+                .map_err(|e| format!("Texture swap {}: {}", path, e))?;
+            let mut bytes = vec![];
+            txtr.write_to(&mut bytes).unwrap();
+            bytes.extend(reader_writer::pad_bytes(32, bytes.len()).iter());
+            Ok(build_resource(
+                png_txtr_id(i),
+                ResourceKind::External(bytes, b"TXTR".into()),
+            ))
+        })
+        .collect()
+}
+
+/// The game's TXTRs used as replacements, which have to be collected from the paks
+pub fn game_txtrs(config: &PatchConfig) -> Vec<(u32, FourCC)> {
+    all_swaps(config)
+        .filter_map(|swap| match swap {
+            TextureSwap::Txtr(id) => Some((*id, FourCC::from_bytes(b"TXTR"))),
+            TextureSwap::Png(_) => None,
+        })
+        .collect()
+}
+
+fn all_swaps(config: &PatchConfig) -> impl Iterator<Item = &TextureSwap> {
+    let level_swaps = config.level_data.values().flat_map(|level| {
+        level.texture_swaps.values().chain(
+            level
+                .rooms
+                .values()
+                .flat_map(|room| room.texture_swaps.iter().flat_map(|swaps| swaps.values())),
+        )
+    });
+    config.texture_swaps.values().chain(level_swaps)
+}
+
+fn resolve(pngs: &[&str], swaps: &HashMap<u32, TextureSwap>) -> Swaps {
+    swaps
+        .iter()
+        .map(|(&original, swap)| {
+            let replacement = match swap {
+                TextureSwap::Txtr(id) => *id,
+                TextureSwap::Png(path) => {
+                    let index = pngs.iter().position(|p| p == path).unwrap();
+                    png_txtr_id(index).to_u32()
+                }
+            };
+            (original, replacement)
+        })
+        .collect()
+}
+
+/// Global and per world swaps apply to every model and room in the world, per room swaps only
+/// to that room's geometry
+pub fn add_texture_swap_patches<'r, 's>(
+    patcher: &mut PrimePatcher<'r, 's>,
+    config: &PatchConfig,
+    level_data: &HashMap<String, LevelConfig>,
+    game_resources: &'s HashMap<(u32, FourCC), Resource<'r>>,
+) {
+    let pngs = png_paths(config);
+    let global_swaps = resolve(&pngs, &config.texture_swaps);
+
+    for (pak_name, rooms) in pickup_meta::ROOM_INFO.iter() {
+        let world = World::from_pak(pak_name).unwrap();
+        let level = level_data.get(world.to_json_key());
+
+        let mut world_swaps = global_swaps.clone();
+        if let Some(level) = level {
+            world_swaps.extend(resolve(&pngs, &level.texture_swaps));
+        }
+
+        if !world_swaps.is_empty() {
+            let swaps = world_swaps.clone();
+            patcher.add_file_patch(pak_name.as_bytes(), move |file| {
+                patch_pak_cmdls(file, &swaps)
+            });
+        }
+
+        for room_info in rooms.iter() {
+            let mut room_swaps = world_swaps.clone();
+            if let Some(swaps) = level
+                .and_then(|level| level.rooms.get(room_info.name().trim()))
+                .and_then(|room| room.texture_swaps.as_ref())
+            {
+                room_swaps.extend(resolve(&pngs, swaps));
+            }
+            if room_swaps.is_empty() {
+                continue;
+            }
+
+            patcher.add_scly_patch(
+                (pak_name.as_bytes(), room_info.room_id.to_u32()),
+                move |ps, area| patch_area_textures(ps, area, game_resources, &room_swaps),
+            );
+        }
+    }
+}
+
+fn patch_pak_cmdls(file: &mut structs::FstEntryFile, swaps: &Swaps) -> Result<(), String> {
+    let pak = match file {
+        structs::FstEntryFile::Pak(pak) => pak,
+        _ => return Ok(()),
+    };
+
+    let mut cursor = pak.resources.cursor();
+    while cursor.peek().is_some() {
+        let mut cursor = cursor.cursor_advancer();
+        if cursor.peek().unwrap().fourcc() != b"CMDL".into() {
+            continue;
+        }
+        swap_cmdl_textures(cursor.value().unwrap(), swaps);
+    }
+
+    Ok(())
+}
+
+fn swap_cmdl_textures(res: &mut Resource, swaps: &Swaps) {
+    let res_data = match &res.kind {
+        ResourceKind::Unknown(_, _) => crate::ResourceData::new(res),
+        ResourceKind::External(_, _) => crate::ResourceData::new_external(res),
+        _ => return,
+    };
+    let cmdl_bytes = res_data.decompress().into_owned();
+    let mut cmdl = Reader::new(&cmdl_bytes[..]).read::<structs::Cmdl>(());
+
+    let mut changed = false;
+    for material_set in cmdl.material_sets.as_mut_vec() {
+        for texture_id in material_set.texture_ids.as_mut_vec() {
+            if let Some(&replacement) = swaps.get(&texture_id.to_u32()) {
+                *texture_id = ResId::new(replacement);
+                changed = true;
+            }
+        }
+    }
+    if !changed {
+        return;
+    }
+
+    let mut new_bytes = vec![];
+    cmdl.write_to(&mut new_bytes).unwrap();
+    res.kind = ResourceKind::External(new_bytes, b"CMDL".into());
+    res.compressed = false;
+}
+
+fn patch_area_textures<'r>(
+    _ps: &mut PatcherState,
+    area: &mut mlvl_wrapper::MlvlArea<'r, '_, '_, '_>,
+    game_resources: &HashMap<(u32, FourCC), Resource<'r>>,
+    swaps: &Swaps,
+) -> Result<(), String> {
+    let material_set = area.mrea().material_set_section_mut();
+    for texture_id in material_set.texture_ids.as_mut_vec() {
+        if let Some(&replacement) = swaps.get(&texture_id.to_u32()) {
+            *texture_id = ResId::new(replacement);
+        }
+    }
+
+    // The area's dependencies list every texture its geometry and models use, so wherever a
+    // swapped texture was needed its replacement now is
+    let txtr = FourCC::from_bytes(b"TXTR");
+    let mut new_deps: Vec<(usize, Dependency)> = vec![];
+    for (layer_num, layer) in area.mlvl_area.dependencies.deps.iter().enumerate() {
+        for dep in layer.iter() {
+            if dep.asset_type != txtr {
+                continue;
+            }
+            if let Some(&replacement) = swaps.get(&dep.asset_id) {
+                new_deps.push((
+                    layer_num,
+                    Dependency {
+                        asset_id: replacement,
+                        asset_type: txtr,
+                    },
+                ));
+            }
+        }
+    }
+    for (layer_num, dep) in new_deps {
+        area.add_dependencies(game_resources, layer_num, std::iter::once(dep));
+    }
+
+    Ok(())
+}
@@ -0,0 +1,618 @@
+use std::path::Path;
+
+use reader_writer::{Readable, Reader, Writable};
+
+use crate::dsp_adpcm::{self, DspEncoder};
+
+// THP videos always play at 29.97 frames per second, see `structs::Thp`
+pub const THP_FPS: f64 = 30000.0 / 1001.0;
+
+// The movie player streams audio at 32kHz
+pub const THP_AUDIO_FREQUENCY: u32 = 32000;
+
+// Frames are made of 16x16 MCUs, with the chroma subsampled 2x2
+pub const THP_MACROBLOCK_SIZE: u32 = 16;
+
+const MAX_COMPONENTS: usize = 16;
+const COMPONENT_VIDEO: u8 = 0;
+const COMPONENT_AUDIO: u8 = 1;
+const COMPONENT_NONE: u8 = 0xFF;
+
+// Size of the header of each frame's audio, which always has room for two channels
+const AUDIO_HEADER_SIZE: usize = 8 + 2 * 32 + 2 * 4;
+
+/* JPEG */
+
+// THP frames are baseline JPEGs with the same layout as the ones made by Nintendo's encoder:
+// a single DQT and DHT segment with both tables, 4:2:0 YCbCr, no restart markers and no 0xFF
+// byte stuffing in the entropy-coded data.
+
+const ZIGZAG: [usize; 64] = [
+    0, 1, 8, 16, 9, 2, 3, 10, 17, 24, 32, 25, 18, 11, 4, 5, 12, 19, 26, 33, 40, 48, 41, 34, 27, 20,
+    13, 6, 7, 14, 21, 28, 35, 42, 49, 56, 57, 50, 43, 36, 29, 22, 15, 23, 30, 37, 44, 51, 58, 59,
+    52, 45, 38, 31, 39, 46, 53, 60, 61, 54, 47, 55, 62, 63,
+];
+
+// The example tables of Annex K of the JPEG specification, in natural order
+const LUMA_QUANT: [u16; 64] = [
+    16, 11, 10, 16, 24, 40, 51, 61, 12, 12, 14, 19, 26, 58, 60, 55, 14, 13, 16, 24, 40, 57, 69, 56,
+    14, 17, 22, 29, 51, 87, 80, 62, 18, 22, 37, 56, 68, 109, 103, 77, 24, 35, 55, 64, 81, 104, 113,
+    92, 49, 64, 78, 87, 103, 121, 120, 101, 72, 92, 95, 98, 112, 100, 103, 99,
+];
+const CHROMA_QUANT: [u16; 64] = [
+    17, 18, 24, 47, 99, 99, 99, 99, 18, 21, 26, 66, 99, 99, 99, 99, 24, 26, 56, 99, 99, 99, 99, 99,
+    47, 66, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99,
+    99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99,
+];
+
+const DC_LUMA_BITS: [u8; 16] = [0, 1, 5, 1, 1, 1, 1, 1, 1, 0, 0, 0, 0, 0, 0, 0];
+const DC_CHROMA_BITS: [u8; 16] = [0, 3, 1, 1, 1, 1, 1, 1, 1, 1, 1, 0, 0, 0, 0, 0];
+const DC_VALUES: [u8; 12] = [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11];
+
+const AC_LUMA_BITS: [u8; 16] = [0, 2, 1, 3, 3, 2, 4, 3, 5, 5, 4, 4, 0, 0, 1, 0x7d];
+const AC_LUMA_VALUES: [u8; 162] = [
+    0x01, 0x02, 0x03, 0x00, 0x04, 0x11, 0x05, 0x12, 0x21, 0x31, 0x41, 0x06, 0x13, 0x51, 0x61, 0x07,
+    0x22, 0x71, 0x14, 0x32, 0x81, 0x91, 0xa1, 0x08, 0x23, 0x42, 0xb1, 0xc1, 0x15, 0x52, 0xd1, 0xf0,
+    0x24, 0x33, 0x62, 0x72, 0x82, 0x09, 0x0a, 0x16, 0x17, 0x18, 0x19, 0x1a, 0x25, 0x26, 0x27, 0x28,
+    0x29, 0x2a, 0x34, 0x35, 0x36, 0x37, 0x38, 0x39, 0x3a, 0x43, 0x44, 0x45, 0x46, 0x47, 0x48, 0x49,
+    0x4a, 0x53, 0x54, 0x55, 0x56, 0x57, 0x58, 0x59, 0x5a, 0x63, 0x64, 0x65, 0x66, 0x67, 0x68, 0x69,
+    0x6a, 0x73, 0x74, 0x75, 0x76, 0x77, 0x78, 0x79, 0x7a, 0x83, 0x84, 0x85, 0x86, 0x87, 0x88, 0x89,
+    0x8a, 0x92, 0x93, 0x94, 0x95, 0x96, 0x97, 0x98, 0x99, 0x9a, 0xa2, 0xa3, 0xa4, 0xa5, 0xa6, 0xa7,
+    0xa8, 0xa9, 0xaa, 0xb2, 0xb3, 0xb4, 0xb5, 0xb6, 0xb7, 0xb8, 0xb9, 0xba, 0xc2, 0xc3, 0xc4, 0xc5,
+    0xc6, 0xc7, 0xc8, 0xc9, 0xca, 0xd2, 0xd3, 0xd4, 0xd5, 0xd6, 0xd7, 0xd8, 0xd9, 0xda, 0xe1, 0xe2,
+    0xe3, 0xe4, 0xe5, 0xe6, 0xe7, 0xe8, 0xe9, 0xea, 0xf1, 0xf2, 0xf3, 0xf4, 0xf5, 0xf6, 0xf7, 0xf8,
+    0xf9, 0xfa,
+];
+
+const AC_CHROMA_BITS: [u8; 16] = [0, 2, 1, 2, 4, 4, 3, 4, 7, 5, 4, 4, 0, 1, 2, 0x77];
+const AC_CHROMA_VALUES: [u8; 162] = [
+    0x00, 0x01, 0x02, 0x03, 0x11, 0x04, 0x05, 0x21, 0x31, 0x06, 0x12, 0x41, 0x51, 0x07, 0x61, 0x71,
+    0x13, 0x22, 0x32, 0x81, 0x08, 0x14, 0x42, 0x91, 0xa1, 0xb1, 0xc1, 0x09, 0x23, 0x33, 0x52, 0xf0,
+    0x15, 0x62, 0x72, 0xd1, 0x0a, 0x16, 0x24, 0x34, 0xe1, 0x25, 0xf1, 0x17, 0x18, 0x19, 0x1a, 0x26,
+    0x27, 0x28, 0x29, 0x2a, 0x35, 0x36, 0x37, 0x38, 0x39, 0x3a, 0x43, 0x44, 0x45, 0x46, 0x47, 0x48,
+    0x49, 0x4a, 0x53, 0x54, 0x55, 0x56, 0x57, 0x58, 0x59, 0x5a, 0x63, 0x64, 0x65, 0x66, 0x67, 0x68,
+    0x69, 0x6a, 0x73, 0x74, 0x75, 0x76, 0x77, 0x78, 0x79, 0x7a, 0x82, 0x83, 0x84, 0x85, 0x86, 0x87,
+    0x88, 0x89, 0x8a, 0x92, 0x93, 0x94, 0x95, 0x96, 0x97, 0x98, 0x99, 0x9a, 0xa2, 0xa3, 0xa4, 0xa5,
+    0xa6, 0xa7, 0xa8, 0xa9, 0xaa, 0xb2, 0xb3, 0xb4, 0xb5, 0xb6, 0xb7, 0xb8, 0xb9, 0xba, 0xc2, 0xc3,
+    0xc4, 0xc5, 0xc6, 0xc7, 0xc8, 0xc9, 0xca, 0xd2, 0xd3, 0xd4, 0xd5, 0xd6, 0xd7, 0xd8, 0xd9, 0xda,
+    0xe2, 0xe3, 0xe4, 0xe5, 0xe6, 0xe7, 0xe8, 0xe9, 0xea, 0xf2, 0xf3, 0xf4, 0xf5, 0xf6, 0xf7, 0xf8,
+    0xf9, 0xfa,
+];
+
+// Code and length of each symbol
+struct HuffmanTable {
+    codes: [(u16, u8); 256],
+}
+
+impl HuffmanTable {
+    fn new(bits: &[u8; 16], values: &[u8]) -> Self {
+        let mut codes = [(0, 0); 256];
+        let mut code = 0u16;
+        let mut values = values.iter();
+        for (i, &count) in bits.iter().enumerate() {
+            for _ in 0..count {
+                codes[*values.next().unwrap() as usize] = (code, i as u8 + 1);
+                code += 1;
+            }
+            code <<= 1;
+        }
+        HuffmanTable { codes }
+    }
+}
+
+struct BitWriter {
+    data: Vec<u8>,
+    bits: u32,
+    bit_count: u32,
+}
+
+impl BitWriter {
+    fn write(&mut self, value: u16, len: u8) {
+        self.bits = (self.bits << len) | (value as u32 & ((1 << len) - 1));
+        self.bit_count += len as u32;
+        while self.bit_count >= 8 {
+            self.bit_count -= 8;
+            self.data.push((self.bits >> self.bit_count) as u8);
+        }
+    }
+
+    // The last byte is padded with 1s
+    fn finish(mut self) -> Vec<u8> {
+        if self.bit_count > 0 {
+            let pad = 8 - self.bit_count as u8;
+            self.write((1 << pad) - 1, pad);
+        }
+        self.data
+    }
+}
+
+// Quantization tables scaled the same way as the IJG library, from 1 (worst) to 100 (best)
+fn scaled_quant_table(table: &[u16; 64], quality: u8) -> [u16; 64] {
+    let quality = quality.clamp(1, 100) as u32;
+    let scale = if quality < 50 {
+        5000 / quality
+    } else {
+        200 - quality * 2
+    };
+    table.map(|q| ((q as u32 * scale + 50) / 100).clamp(1, 255) as u16)
+}
+
+fn fdct(block: &[f32; 64]) -> [f32; 64] {
+    let mut cos = [[0.0f32; 8]; 8];
+    for (x, row) in cos.iter_mut().enumerate() {
+        for (u, value) in row.iter_mut().enumerate() {
+            *value = (((2 * x + 1) * u) as f32 * std::f32::consts::PI / 16.0).cos();
+        }
+    }
+    let c = |u: usize| {
+        if u == 0 {
+            std::f32::consts::FRAC_1_SQRT_2
+        } else {
+            1.0
+        }
+    };
+
+    let mut rows = [0.0f32; 64];
+    for y in 0..8 {
+        for u in 0..8 {
+            let sum: f32 = (0..8).map(|x| block[y * 8 + x] * cos[x][u]).sum();
+            rows[y * 8 + u] = sum * c(u) / 2.0;
+        }
+    }
+
+    let mut out = [0.0f32; 64];
+    for u in 0..8 {
+        for v in 0..8 {
+            let sum: f32 = (0..8).map(|y| rows[y * 8 + u] * cos[y][v]).sum();
+            out[v * 8 + u] = sum * c(v) / 2.0;
+        }
+    }
+    out
+}
+
+fn magnitude(value: i32) -> (u16, u8) {
+    let len = (32 - value.unsigned_abs().leading_zeros()) as u8;
+    let bits = if value < 0 {
+        (value - 1) as u16
+    } else {
+        value as u16
+    };
+    (bits, len)
+}
+
+fn encode_block(
+    writer: &mut BitWriter,
+    block: &[f32; 64],
+    quant: &[u16; 64],
+    dc: &HuffmanTable,
+    ac: &HuffmanTable,
+    prev_dc: &mut i32,
+) {
+    let coefficients = fdct(block);
+    let mut quantized = [0i32; 64];
+    for (i, &natural) in ZIGZAG.iter().enumerate() {
+        quantized[i] = (coefficients[natural] / quant[natural] as f32).round() as i32;
+    }
+
+    let diff = quantized[0] - *prev_dc;
+    *prev_dc = quantized[0];
+    let (bits, len) = magnitude(diff);
+    let (code, code_len) = dc.codes[len as usize];
+    writer.write(code, code_len);
+    writer.write(bits, len);
+
+    let mut zeros = 0;
+    for &value in &quantized[1..] {
+        if value == 0 {
+            zeros += 1;
+            continue;
+        }
+        while zeros >= 16 {
+            let (code, code_len) = ac.codes[0xF0];
+            writer.write(code, code_len);
+            zeros -= 16;
+        }
+        let (bits, len) = magnitude(value);
+        let (code, code_len) = ac.codes[(zeros << 4) | len as usize];
+        writer.write(code, code_len);
+        writer.write(bits, len);
+        zeros = 0;
+    }
+    if zeros > 0 {
+        let (code, code_len) = ac.codes[0x00];
+        writer.write(code, code_len);
+    }
+}
+
+fn write_segment(out: &mut Vec<u8>, marker: u8, data: &[u8]) {
+    out.extend([0xFF, marker]);
+    out.extend((data.len() as u16 + 2).to_be_bytes());
+    out.extend(data);
+}
+
+/// Encodes an RGB image as a THP frame, the dimensions must be multiples of 16
+pub fn encode_thp_jpeg(rgb: &[u8], width: usize, height: usize, quality: u8) -> Vec<u8> {
+    assert!(width % THP_MACROBLOCK_SIZE as usize == 0);
+    assert!(height % THP_MACROBLOCK_SIZE as usize == 0);
+
+    let luma_quant = scaled_quant_table(&LUMA_QUANT, quality);
+    let chroma_quant = scaled_quant_table(&CHROMA_QUANT, quality);
+
+    let mut out = vec![0xFF, 0xD8];
+
+    let mut dqt = vec![];
+    for (id, table) in [&luma_quant, &chroma_quant].into_iter().enumerate() {
+        dqt.push(id as u8);
+        dqt.extend(ZIGZAG.iter().map(|&natural| table[natural] as u8));
+    }
+    write_segment(&mut out, 0xDB, &dqt);
+
+    let mut dht = vec![];
+    for (class_id, bits, values) in [
+        (0x00, &DC_LUMA_BITS, &DC_VALUES[..]),
+        (0x10, &AC_LUMA_BITS, &AC_LUMA_VALUES[..]),
+        (0x01, &DC_CHROMA_BITS, &DC_VALUES[..]),
+        (0x11, &AC_CHROMA_BITS, &AC_CHROMA_VALUES[..]),
+    ] {
+        dht.push(class_id);
+        dht.extend(bits);
+        dht.extend(values);
+    }
+    write_segment(&mut out, 0xC4, &dht);
+
+    let mut sof = vec![8];
+    sof.extend((height as u16).to_be_bytes());
+    sof.extend((width as u16).to_be_bytes());
+    sof.extend([3, 1, 0x22, 0, 2, 0x11, 1, 3, 0x11, 1]);
+    write_segment(&mut out, 0xC0, &sof);
+
+    write_segment(&mut out, 0xDA, &[3, 1, 0x00, 2, 0x11, 3, 0x11, 0, 63, 0]);
+
+    // Convert to level shifted YCbCr
+    let pixel_count = width * height;
+    let mut y_plane = vec![0.0f32; pixel_count];
+    let mut cb_plane = vec![0.0f32; pixel_count];
+    let mut cr_plane = vec![0.0f32; pixel_count];
+    for i in 0..pixel_count {
+        let (r, g, b) = (
+            rgb[i * 3] as f32,
+            rgb[i * 3 + 1] as f32,
+            rgb[i * 3 + 2] as f32,
+        );
+        y_plane[i] = 0.299 * r + 0.587 * g + 0.114 * b - 128.0;
+        cb_plane[i] = -0.168736 * r - 0.331264 * g + 0.5 * b;
+        cr_plane[i] = 0.5 * r - 0.418688 * g - 0.081312 * b;
+    }
+
+    let dc_luma = HuffmanTable::new(&DC_LUMA_BITS, &DC_VALUES);
+    let ac_luma = HuffmanTable::new(&AC_LUMA_BITS, &AC_LUMA_VALUES);
+    let dc_chroma = HuffmanTable::new(&DC_CHROMA_BITS, &DC_VALUES);
+    let ac_chroma = HuffmanTable::new(&AC_CHROMA_BITS, &AC_CHROMA_VALUES);
+
+    let mut writer = BitWriter {
+        data: vec![],
+        bits: 0,
+        bit_count: 0,
+    };
+    let mut prev_dc = [0i32; 3];
+    let mut block = [0.0f32; 64];
+    for mcu_y in (0..height).step_by(16) {
+        for mcu_x in (0..width).step_by(16) {
+            for (block_x, block_y) in [(0, 0), (8, 0), (0, 8), (8, 8)] {
+                for y in 0..8 {
+                    for x in 0..8 {
+                        let src = (mcu_y + block_y + y) * width + mcu_x + block_x + x;
+                        block[y * 8 + x] = y_plane[src];
+                    }
+                }
+                encode_block(
+                    &mut writer,
+                    &block,
+                    &luma_quant,
+                    &dc_luma,
+                    &ac_luma,
+                    &mut prev_dc[0],
+                );
+            }
+
+            for (plane, prev_dc) in [&cb_plane, &cr_plane].into_iter().zip(&mut prev_dc[1..]) {
+                for y in 0..8 {
+                    for x in 0..8 {
+                        let src = (mcu_y + y * 2) * width + mcu_x + x * 2;
+                        block[y * 8 + x] = (plane[src]
+                            + plane[src + 1]
+                            + plane[src + width]
+                            + plane[src + width + 1])
+                            / 4.0;
+                    }
+                }
+                encode_block(
+                    &mut writer,
+                    &block,
+                    &chroma_quant,
+                    &dc_chroma,
+                    &ac_chroma,
+                    prev_dc,
+                );
+            }
+        }
+    }
+    out.extend(writer.finish());
+
+    out.extend([0xFF, 0xD9]);
+    out.resize(out.len().next_multiple_of(4), 0);
+    out
+}
+
+/* THP */
+
+pub struct ThpAudio {
+    // Left and right channels, mono audio has the same samples in both
+    pub channels: [Vec<i16>; 2],
+    pub frequency: u32,
+}
+
+impl ThpAudio {
+    /// Reads a mono or stereo WAV, resampled to the frequency the movie player expects
+    pub fn from_wav(path: &Path) -> Result<Self, String> {
+        let (channels, frequency) = dsp_adpcm::read_wav(path)?;
+        let channels = match <[Vec<i16>; 2]>::try_from(channels) {
+            Ok(channels) => channels,
+            Err(channels) if channels.len() == 1 => [channels[0].clone(), channels[0].clone()],
+            Err(channels) => {
+                return Err(format!(
+                    "{} has {} channels, only mono and stereo are supported",
+                    path.display(),
+                    channels.len()
+                ))
+            }
+        };
+
+        Ok(ThpAudio {
+            channels: channels
+                .map(|samples| dsp_adpcm::resample(&samples, frequency, THP_AUDIO_FREQUENCY)),
+            frequency: THP_AUDIO_FREQUENCY,
+        })
+    }
+}
+
+// Samples of audio played alongside the given frame, so that rounding doesn't accumulate
+fn frame_sample_range(frame: usize, frequency: u32) -> std::ops::Range<usize> {
+    let start = (frame as f64 * frequency as f64 / THP_FPS) as usize;
+    let end = ((frame + 1) as f64 * frequency as f64 / THP_FPS) as usize;
+    start..end
+}
+
+fn encode_audio_frame(
+    channels: &[Vec<i16>; 2],
+    encoders: &mut [DspEncoder; 2],
+    range: std::ops::Range<usize>,
+) -> Vec<u8> {
+    let sample_count = range.len();
+    let channel_size = dsp_adpcm::byte_count(sample_count);
+
+    let mut data = Vec::with_capacity(AUDIO_HEADER_SIZE + channel_size * 2);
+    data.extend((channel_size as u32).to_be_bytes());
+    data.extend((sample_count as u32).to_be_bytes());
+    for encoder in encoders.iter() {
+        for coef in encoder.coefs.iter().flatten() {
+            data.extend(coef.to_be_bytes());
+        }
+    }
+    for encoder in encoders.iter() {
+        data.extend(encoder.history[0].to_be_bytes());
+        data.extend(encoder.history[1].to_be_bytes());
+    }
+
+    for (samples, encoder) in channels.iter().zip(encoders.iter_mut()) {
+        // Audio shorter than the video is padded with silence
+        let mut frame_samples = vec![0i16; sample_count];
+        if range.start < samples.len() {
+            let available = &samples[range.start..range.end.min(samples.len())];
+            frame_samples[..available.len()].copy_from_slice(available);
+        }
+        data.extend(encoder.encode(&frame_samples));
+    }
+
+    data
+}
+
+/// Builds a THP from RGB frames with the given dimensions and optional audio
+pub fn encode_thp(
+    frames: &[Vec<u8>],
+    width: u32,
+    height: u32,
+    audio: Option<&ThpAudio>,
+    quality: u8,
+) -> Result<Vec<u8>, String> {
+    if frames.is_empty() {
+        return Err("A THP needs at least one frame".to_string());
+    }
+    if width % THP_MACROBLOCK_SIZE != 0 || height % THP_MACROBLOCK_SIZE != 0 {
+        return Err(format!(
+            "The frame dimensions ({}, {}) must be multiples of {}",
+            width, height, THP_MACROBLOCK_SIZE
+        ));
+    }
+
+    let mut encoders = audio.map(|audio| {
+        audio
+            .channels
+            .each_ref()
+            .map(|samples| DspEncoder::new(dsp_adpcm::correlate_coefs(samples)))
+    });
+
+    // Each frame's video and audio, which the frames of the THP borrow
+    let mut frame_data = vec![];
+    let mut max_audio_samples = 0;
+    for (i, rgb) in frames.iter().enumerate() {
+        if rgb.len() != width as usize * height as usize * 3 {
+            return Err(format!("Frame {} doesn't have the expected dimensions", i));
+        }
+        let video = encode_thp_jpeg(rgb, width as usize, height as usize, quality);
+        let audio_data = audio.zip(encoders.as_mut()).map(|(audio, encoders)| {
+            let range = frame_sample_range(i, audio.frequency);
+            max_audio_samples = max_audio_samples.max(range.len() as u32);
+            encode_audio_frame(&audio.channels, encoders, range)
+        });
+        frame_data.push((video, audio_data));
+    }
+
+    let mut component_types = [COMPONENT_NONE; MAX_COMPONENTS];
+    component_types[0] = COMPONENT_VIDEO;
+    let mut components = vec![structs::ThpComponent {
+        video_info: Some(structs::ThpVideoInfo { width, height }),
+        audio_info: None,
+    }];
+    if let Some(audio) = audio {
+        component_types[1] = COMPONENT_AUDIO;
+        components.push(structs::ThpComponent {
+            video_info: None,
+            audio_info: Some(structs::ThpAudioInfo {
+                channels_count: 2,
+                frequency: audio.frequency,
+                samples_count: frame_sample_range(frames.len() - 1, audio.frequency).end as u32,
+            }),
+        });
+    }
+
+    let frames: Vec<_> = frame_data
+        .iter()
+        .map(|(video, audio_data)| structs::ThpFrameData {
+            frame_size_next: 0,
+            frame_size_prev: 0,
+            video_size: video.len() as u32,
+            audio_size: audio_data.as_ref().map(|data| data.len() as u32),
+            video_data: Reader::new(video).read((video.len(), ())),
+            audio_data: audio_data
+                .as_ref()
+                .map(|data| Reader::new(data).read((data.len(), ()))),
+        })
+        .collect();
+    let mut thp = structs::Thp {
+        max_buffer_size: 0,
+        max_audio_samples,
+        components: structs::ThpComponents {
+            component_count: components.len() as u32,
+            component_types: Reader::new(&component_types).read((MAX_COMPONENTS, ())),
+            components: components.into(),
+        },
+        frames: frames.into(),
+    };
+
+    // The first and last frames are each other's neighbours, so the video can loop
+    thp.update_sibling_frame_sizes();
+    thp.max_buffer_size = thp.frames.iter().map(|frame| frame.size()).max().unwrap() as u32;
+
+    let mut bytes = Vec::with_capacity(thp.size());
+    thp.write_to(&mut bytes).unwrap();
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gradient_frame(width: u32, height: u32, shift: u8) -> Vec<u8> {
+        (0..width * height)
+            .flat_map(|i| {
+                let (x, y) = ((i % width) as u8, (i / width) as u8);
+                [x.wrapping_mul(8), y.wrapping_mul(8), shift]
+            })
+            .collect()
+    }
+
+    fn tone(samples: usize, period: usize) -> Vec<i16> {
+        (0..samples)
+            .map(|i| ((i % period) as i32 * 2000 - 10000) as i16)
+            .collect()
+    }
+
+    #[test]
+    fn jpeg_frames_have_the_thp_layout() {
+        let jpeg = encode_thp_jpeg(&gradient_frame(32, 16, 0), 32, 16, 90);
+
+        assert_eq!(jpeg[..4], [0xFF, 0xD8, 0xFF, 0xDB]);
+        assert_eq!(jpeg.len() % 4, 0);
+        let end = jpeg.iter().rposition(|&b| b != 0).unwrap();
+        assert_eq!(jpeg[end - 1..=end], [0xFF, 0xD9]);
+
+        let sof = jpeg.windows(2).position(|w| w == [0xFF, 0xC0]).unwrap();
+        assert_eq!(jpeg[sof + 5..sof + 9], [0, 16, 0, 32]);
+        assert!(jpeg.windows(2).any(|w| w == [0xFF, 0xDA]));
+    }
+
+    #[test]
+    fn encoded_thp_parses_back() {
+        let frames: Vec<Vec<u8>> = (0..3).map(|i| gradient_frame(32, 32, i * 100)).collect();
+        let audio = ThpAudio {
+            channels: [tone(3000, 40), tone(3000, 64)],
+            frequency: THP_AUDIO_FREQUENCY,
+        };
+        let bytes = encode_thp(&frames, 32, 32, Some(&audio), 80).unwrap();
+
+        let thp: structs::Thp = Reader::new(&bytes[..]).read(());
+        assert_eq!(thp.components.component_count, 2);
+        let components: Vec<_> = thp.components.components.iter().collect();
+        let video_info = components[0].video_info.as_ref().unwrap();
+        assert_eq!((video_info.width, video_info.height), (32, 32));
+        let audio_info = components[1].audio_info.as_ref().unwrap();
+        assert_eq!(audio_info.channels_count, 2);
+        assert_eq!(audio_info.frequency, THP_AUDIO_FREQUENCY);
+        assert_eq!(
+            audio_info.samples_count as usize,
+            frame_sample_range(2, THP_AUDIO_FREQUENCY).end
+        );
+
+        let parsed: Vec<_> = thp.frames.iter().collect();
+        assert_eq!(parsed.len(), 3);
+        let sizes: Vec<u32> = parsed.iter().map(|frame| frame.size() as u32).collect();
+        for (i, frame) in parsed.iter().enumerate() {
+            assert_eq!(frame.frame_size_next, sizes[(i + 1) % 3]);
+            assert_eq!(frame.frame_size_prev, sizes[(i + 2) % 3]);
+
+            let video: Vec<u8> = frame.video_data.iter().collect();
+            assert_eq!(video, encode_thp_jpeg(&frames[i], 32, 32, 80));
+
+            // Sample count of the frame's audio, after the size of each channel
+            let audio: Vec<u8> = frame.audio_data.as_ref().unwrap().iter().collect();
+            let samples = u32::from_be_bytes(audio[4..8].try_into().unwrap());
+            assert_eq!(
+                samples as usize,
+                frame_sample_range(i, THP_AUDIO_FREQUENCY).len()
+            );
+        }
+        assert_eq!(thp.max_buffer_size, *sizes.iter().max().unwrap());
+        assert_eq!(
+            thp.max_audio_samples as usize,
+            (0..3)
+                .map(|i| frame_sample_range(i, THP_AUDIO_FREQUENCY).len())
+                .max()
+                .unwrap()
+        );
+
+        let mut rewritten = vec![];
+        thp.write_to(&mut rewritten).unwrap();
+        assert_eq!(rewritten, bytes);
+    }
+
+    #[test]
+    fn video_only_thp_has_a_single_component() {
+        let bytes = encode_thp(&[gradient_frame(16, 16, 0)], 16, 16, None, 50).unwrap();
+
+        let thp: structs::Thp = Reader::new(&bytes[..]).read(());
+        assert_eq!(thp.components.component_count, 1);
+        assert_eq!(thp.max_audio_samples, 0);
+        let frame = thp.frames.iter().next().unwrap();
+        assert!(frame.audio_data.is_none());
+        assert_eq!(frame.frame_size_next, frame.size() as u32);
+        assert_eq!(frame.frame_size_prev, frame.size() as u32);
+    }
+
+    #[test]
+    fn bad_frames_are_rejected() {
+        assert!(encode_thp(&[], 16, 16, None, 50).is_err());
+        assert!(encode_thp(&[gradient_frame(24, 16, 0)], 24, 16, None, 50).is_err());
+        assert!(encode_thp(&[gradient_frame(16, 16, 0)], 32, 16, None, 50).is_err());
+    }
+}
@@ -1,6 +1,8 @@
-use std::convert::TryInto;
+use std::{collections::HashMap, convert::TryInto, iter};
 
+use image::{imageops::FilterType, ColorType, DynamicImage, ImageBuffer, ImageFormat};
 use resource_info_table::{resource_info, ResourceInfo};
+use structs::{Txtr, TxtrFormat, TxtrPaletteFormat};
 use texpresso::Format;
 
 // 0 - Power
@@ -8,124 +10,145 @@ use texpresso::Format;
 // 2 - Varia
 // 3 - Phazon
 
-pub const POWER_SUIT_TEXTURES: &[ResourceInfo] = &[
+/// A suit texture and the region it's recolored as when it has no mask
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SuitTexture {
+    pub info: ResourceInfo,
+    pub region: usize,
+}
+
+const fn primary(info: ResourceInfo) -> SuitTexture {
+    SuitTexture {
+        info,
+        region: RECOLOR_PRIMARY,
+    }
+}
+
+const fn glow(info: ResourceInfo) -> SuitTexture {
+    SuitTexture {
+        info,
+        region: RECOLOR_GLOW,
+    }
+}
+
+pub const POWER_SUIT_TEXTURES: &[SuitTexture] = &[
     // High res Characters/Samus/cooked/powersuit_high_rez_bound.CMDL
-    resource_info!("power_head_chest.TXTR"),
-    resource_info!("power_head_chest_incan.TXTR"),
-    resource_info!("power_arms.TXTR"),
-    resource_info!("power_arms_incan.TXTR"),
-    resource_info!("power_torso.TXTR"),
-    resource_info!("power_legs.TXTR"),
+    primary(resource_info!("power_head_chest.TXTR")),
+    glow(resource_info!("power_head_chest_incan.TXTR")),
+    primary(resource_info!("power_arms.TXTR")),
+    glow(resource_info!("power_arms_incan.TXTR")),
+    primary(resource_info!("power_torso.TXTR")),
+    primary(resource_info!("power_legs.TXTR")),
     // Low poly TestAnim/PowerSuit.CMDL
-    resource_info!("5C02CF66.TXTR"),
-    resource_info!("6FC1D36D.TXTR"),
+    glow(resource_info!("5C02CF66.TXTR")),
+    primary(resource_info!("6FC1D36D.TXTR")),
     // Left arm SamusGun/LeftArm.CMDL
-    resource_info!("B2F8703C.TXTR"),
-    resource_info!("1AE46C50.TXTR"),
+    primary(resource_info!("B2F8703C.TXTR")),
+    glow(resource_info!("1AE46C50.TXTR")),
     // Morph ball - TestAnim/SamusBallNew.CMDL MS 0
-    resource_info!("C01FFF01.TXTR"),
+    primary(resource_info!("C01FFF01.TXTR")),
     // MB Low poly - TestAnim/SamusBallLowPolyCMDL.CMDL MS 0
     // resource_info!("C01FFF01.TXTR"),
 
     // No spider ball... yet
 ];
 
-pub const VARIA_SUIT_TEXTURES: &[ResourceInfo] = &[
+pub const VARIA_SUIT_TEXTURES: &[SuitTexture] = &[
     // High res Characters/Samus/cooked/variasuit_high_rez_bound.CMDL
-    resource_info!("gravity_head_chest.TXTR"),
-    resource_info!("power_head_chest_incan.TXTR"),
-    resource_info!("gravity_torso_ball.TXTR"),
-    resource_info!("gravity_legs.TXTR"),
-    resource_info!("gravity_legs_incan.TXTR"),
-    resource_info!("gravity_arms.TXTR"),
-    resource_info!("gravity_arms_incan.TXTR"),
+    primary(resource_info!("gravity_head_chest.TXTR")),
+    glow(resource_info!("power_head_chest_incan.TXTR")),
+    primary(resource_info!("gravity_torso_ball.TXTR")),
+    primary(resource_info!("gravity_legs.TXTR")),
+    glow(resource_info!("gravity_legs_incan.TXTR")),
+    primary(resource_info!("gravity_arms.TXTR")),
+    glow(resource_info!("gravity_arms_incan.TXTR")),
     // Low res? TestAnim/VariaSuit.CMDL
-    resource_info!("D2149656.TXTR"),
-    resource_info!("C06147B3.TXTR"),
+    primary(resource_info!("D2149656.TXTR")),
+    primary(resource_info!("C06147B3.TXTR")),
     // Left hand - SamusGun/Varia.CMDL
-    resource_info!("309AA3D4.TXTR"),
-    resource_info!("5D380050.TXTR"),
+    primary(resource_info!("309AA3D4.TXTR")),
+    primary(resource_info!("5D380050.TXTR")),
     // Morph ball - TestAnim/SamusBallNew.CMDL MS 1
-    resource_info!("49A1D81D.TXTR"),
+    primary(resource_info!("49A1D81D.TXTR")),
     // MB Low poly - TestAnim/SamusBallLowPolyCMDL.CMDL MS 1
     // resource_info!("49A1D81D.TXTR"),
 
     // Spider ball - Uncategorized/spiderball_gravity.CMDL MS 1
-    resource_info!("2EE6F56F.TXTR"),
-    resource_info!("AD3748D3.TXTR"),
+    primary(resource_info!("2EE6F56F.TXTR")),
+    primary(resource_info!("AD3748D3.TXTR")),
     // Spider ball glass - TestAnim/SamusSpiderBallGlassCMDL.CMDL - MS 1
-    resource_info!("9024CB39.TXTR"),
-    resource_info!("7A755049.TXTR"),
+    primary(resource_info!("9024CB39.TXTR")),
+    primary(resource_info!("7A755049.TXTR")),
     // SB Low poly - TestAnim/SamusSpiderBallLowPolyCMDL.CMDL MS 1
-    resource_info!("07675658.TXTR"),
+    primary(resource_info!("07675658.TXTR")),
 ];
 
-pub const GRAVITY_SUIT_TEXTURES: &[ResourceInfo] = &[
+pub const GRAVITY_SUIT_TEXTURES: &[SuitTexture] = &[
     // High res Characters/Samus/cooked/gravitysuit_high_rez_bound.CMDL
-    resource_info!("f_varia_legs.TXTR"),
-    resource_info!("f_varia_legs_incan.TXTR"),
-    resource_info!("spider_glass.TXTR"),
-    resource_info!("f_varia_torso.TXTR"),
-    resource_info!("f_varia_head_chest.TXTR"),
-    resource_info!("f_varia_head_incan.TXTR"),
-    resource_info!("f_varia_arms.TXTR"),
-    resource_info!("f_varia_arms_incan.TXTR"),
-    resource_info!("f_varia_reflected.TXTR"),
-    resource_info!("power_reflected_l_blue.TXTR"),
+    primary(resource_info!("f_varia_legs.TXTR")),
+    glow(resource_info!("f_varia_legs_incan.TXTR")),
+    primary(resource_info!("spider_glass.TXTR")),
+    primary(resource_info!("f_varia_torso.TXTR")),
+    primary(resource_info!("f_varia_head_chest.TXTR")),
+    glow(resource_info!("f_varia_head_incan.TXTR")),
+    primary(resource_info!("f_varia_arms.TXTR")),
+    glow(resource_info!("f_varia_arms_incan.TXTR")),
+    primary(resource_info!("f_varia_reflected.TXTR")),
+    primary(resource_info!("power_reflected_l_blue.TXTR")),
     // Low poly TestAnim/GravitySuit.CMDL
-    resource_info!("349AD971.TXTR"),
-    resource_info!("A082D0BF.TXTR"),
-    resource_info!("648AF351.TXTR"),
-    resource_info!("5B05039F.TXTR"),
-    resource_info!("1C38E5E2.TXTR"),
+    primary(resource_info!("349AD971.TXTR")),
+    primary(resource_info!("A082D0BF.TXTR")),
+    primary(resource_info!("648AF351.TXTR")),
+    primary(resource_info!("5B05039F.TXTR")),
+    primary(resource_info!("1C38E5E2.TXTR")),
     // Left arm SamusGun/Gravity.CMDL
-    resource_info!("985C0EAA.TXTR"),
-    resource_info!("60EA8AC4.TXTR"),
+    primary(resource_info!("985C0EAA.TXTR")),
+    primary(resource_info!("60EA8AC4.TXTR")),
     // Doesn't have a morph-ball model
 
     // Spider ball - TestAnim/Node1_1.CMDL MS 0
-    resource_info!("50A70472.TXTR"),
-    resource_info!("1AEC5A79.TXTR"),
+    primary(resource_info!("50A70472.TXTR")),
+    primary(resource_info!("1AEC5A79.TXTR")),
     // Spider ball glass - TestAnim/SamusSpiderBallGlassCMDL.CMDL - MS 0
-    resource_info!("27FFD993.TXTR"),
+    primary(resource_info!("27FFD993.TXTR")),
     // SB Low poly - TestAnim/SamusSpiderBallLowPolyCMDL.CMDL MS 0
-    resource_info!("BA7DF5D6.TXTR"),
+    primary(resource_info!("BA7DF5D6.TXTR")),
 ];
 
-pub const PHAZON_SUIT_TEXTURES: &[ResourceInfo] = &[
+pub const PHAZON_SUIT_TEXTURES: &[SuitTexture] = &[
     // high res Characters/Samus/cooked/phazon_suit_high_rez_bound.CMDL
-    resource_info!("phason_arm_black.TXTR"),
-    resource_info!("phason_arm_incandescence.TXTR"),
-    resource_info!("phason_head_black.TXTR"),
-    resource_info!("phason_head_incandescence.TXTR"),
-    resource_info!("phason_legs_black.TXTR"),
-    resource_info!("phason_legs_incandescence.TXTR"),
-    resource_info!("phason_torso_black.TXTR"),
-    resource_info!("phason_torso_specialincandescence.TXTR"),
-    resource_info!("phasonred_rampincandes.TXTR"),
-    resource_info!("Characters/common_textures/glow10.TXTR"),
+    primary(resource_info!("phason_arm_black.TXTR")),
+    glow(resource_info!("phason_arm_incandescence.TXTR")),
+    primary(resource_info!("phason_head_black.TXTR")),
+    glow(resource_info!("phason_head_incandescence.TXTR")),
+    primary(resource_info!("phason_legs_black.TXTR")),
+    glow(resource_info!("phason_legs_incandescence.TXTR")),
+    primary(resource_info!("phason_torso_black.TXTR")),
+    glow(resource_info!("phason_torso_specialincandescence.TXTR")),
+    glow(resource_info!("phasonred_rampincandes.TXTR")),
+    glow(resource_info!("Characters/common_textures/glow10.TXTR")),
     // Low poly TestAnim/PhazonSuit.CMDL
-    resource_info!("08FA7447.TXTR"),
-    resource_info!("EC4184DF.TXTR"),
+    primary(resource_info!("08FA7447.TXTR")),
+    primary(resource_info!("EC4184DF.TXTR")),
     // Left hand SamusGun/Phazon.CMDL
-    resource_info!("C94DD270.TXTR"),
-    resource_info!("1A9153A8.TXTR"),
+    primary(resource_info!("C94DD270.TXTR")),
+    primary(resource_info!("1A9153A8.TXTR")),
     // resource_info!("1A9153A8.TXTR"),
 
     // Spider ball TestAnim/Node1_0.CMDL
-    resource_info!("8B105F2E.TXTR"),
-    resource_info!("2F1AC0DD.TXTR"),
+    primary(resource_info!("8B105F2E.TXTR")),
+    primary(resource_info!("2F1AC0DD.TXTR")),
     // resource_info!("Uncategorized/8B105F2E.TXTR"),
-    resource_info!("8BF681E5.TXTR"),
-    resource_info!("51F20A44.TXTR"),
+    primary(resource_info!("8BF681E5.TXTR")),
+    primary(resource_info!("51F20A44.TXTR")),
     //
     // Glass ball TestAnim/SamusPhazonBallGlassCMDL.CMDL
-    resource_info!("D3889172.TXTR"),
-    resource_info!("0B3DBDB4.TXTR"),
+    primary(resource_info!("D3889172.TXTR")),
+    primary(resource_info!("0B3DBDB4.TXTR")),
     // resource_info!("Uncategorized/596C7FFF.TXTR"),
 
     // Spider ball Low poly - TestAnim/SamusSpiderBallLowPolyCMDL.CMDL MS 2
-    resource_info!("06CE2C16.TXTR"),
+    primary(resource_info!("06CE2C16.TXTR")),
 ];
 
 // Full-detail phazon spider ball only: body TestAnim/Node1_0.CMDL (SamusPhazonBallANCS) and glass
@@ -146,73 +169,83 @@ pub const FUSION_PHAZON_BALL_TEXTURES: &[ResourceInfo] = &[
 ];
 
 // Fusion morph ball ANCS TestAnim/Fusion_Ball.ANCS
-pub const FUSION_POWER_SUIT_TEXTURES: &[ResourceInfo] = &[
+pub const FUSION_POWER_SUIT_TEXTURES: &[SuitTexture] = &[
     // High res Characters/Samus/cooked/fusion_suit_high_rez_bound.CMDL
     // Left arm SamusGun/Fusion.CMDL (torso + arms_legs)
-    resource_info!("fusion_head_chest.TXTR"),
-    resource_info!("fusion_torso.TXTR"),
-    resource_info!("fusion_arms_legs.TXTR"),
+    primary(resource_info!("fusion_head_chest.TXTR")),
+    primary(resource_info!("fusion_torso.TXTR")),
+    primary(resource_info!("fusion_arms_legs.TXTR")),
     // Low poly morph/unmorph transition TestAnim/BallTransitions FusionSuit.CMDL
-    resource_info!("100032C8.TXTR"),
-    resource_info!("67C7C1EB.TXTR"),
-    resource_info!("848EC21E.TXTR"),
+    primary(resource_info!("100032C8.TXTR")),
+    primary(resource_info!("67C7C1EB.TXTR")),
+    primary(resource_info!("848EC21E.TXTR")),
     // Morph ball shell - SamusFusionBallANCS MS0 / SamusBallFusionLowPolyCMDL MS0
-    resource_info!("917987E9.TXTR"),
-    resource_info!("EC97CF37.TXTR"),
+    primary(resource_info!("917987E9.TXTR")),
+    primary(resource_info!("EC97CF37.TXTR")),
 ];
 
-pub const FUSION_VARIA_SUIT_TEXTURES: &[ResourceInfo] = &[
+pub const FUSION_VARIA_SUIT_TEXTURES: &[SuitTexture] = &[
     // High res Characters/Samus/cooked/fusion_varia_high_rez_bound.CMDL
     // Left arm SamusGun/FusionV.CMDL (torso + arms_legs)
-    resource_info!("fusion_varia_head_chest.TXTR"),
-    resource_info!("fusion_varia_torso.TXTR"),
-    resource_info!("fusion_varia_arms_legs.TXTR"),
+    primary(resource_info!("fusion_varia_head_chest.TXTR")),
+    primary(resource_info!("fusion_varia_torso.TXTR")),
+    primary(resource_info!("fusion_varia_arms_legs.TXTR")),
     // Low poly morph/unmorph transition TestAnim/BallTransitions Fusion_Varia.CMDL
-    resource_info!("986278F4.TXTR"),
-    resource_info!("B809E769.TXTR"),
-    resource_info!("DE5D5172.TXTR"),
+    primary(resource_info!("986278F4.TXTR")),
+    primary(resource_info!("B809E769.TXTR")),
+    primary(resource_info!("DE5D5172.TXTR")),
     // Morph ball shell - SamusFusionBallANCS MS1 / SamusBallFusionLowPolyCMDL MS1
-    resource_info!("424EC5F7.TXTR"),
-    resource_info!("A092956F.TXTR"),
+    primary(resource_info!("424EC5F7.TXTR")),
+    primary(resource_info!("A092956F.TXTR")),
 ];
 
-pub const FUSION_GRAVITY_SUIT_TEXTURES: &[ResourceInfo] = &[
+pub const FUSION_GRAVITY_SUIT_TEXTURES: &[SuitTexture] = &[
     // High res Characters/Samus/cooked/fusion_gravity_high_rez_bound.CMDL
     // Left arm SamusGun/FusionG.CMDL (torso + arms_legs)
-    resource_info!("fusion_gravity_head_chest.TXTR"),
-    resource_info!("fusion_gravity_torso.TXTR"),
-    resource_info!("fusion_gravity_arms_legs.TXTR"),
+    primary(resource_info!("fusion_gravity_head_chest.TXTR")),
+    primary(resource_info!("fusion_gravity_torso.TXTR")),
+    primary(resource_info!("fusion_gravity_arms_legs.TXTR")),
     // Low poly morph/unmorph transition TestAnim/BallTransitions Fusion_Gravity.CMDL
-    resource_info!("06AE8EEA.TXTR"),
-    resource_info!("AA522481.TXTR"),
-    resource_info!("F1E13711.TXTR"),
+    primary(resource_info!("06AE8EEA.TXTR")),
+    primary(resource_info!("AA522481.TXTR")),
+    primary(resource_info!("F1E13711.TXTR")),
     // Morph ball shell - SamusFusionBallANCS MS2 / SamusBallFusionLowPolyCMDL MS2
-    resource_info!("62D5C2D0.TXTR"),
-    resource_info!("31E36774.TXTR"),
+    primary(resource_info!("62D5C2D0.TXTR")),
+    primary(resource_info!("31E36774.TXTR")),
 ];
 
-pub const FUSION_PHAZON_SUIT_TEXTURES: &[ResourceInfo] = &[
+pub const FUSION_PHAZON_SUIT_TEXTURES: &[SuitTexture] = &[
     // High res Characters/Samus/cooked/fusion_phazon_high_rez_bound.CMDL
     // Left arm SamusGun/FusionP.CMDL (torso + arms_legs)
-    resource_info!("fusion_phazon_head_chest.TXTR"),
-    resource_info!("fusion_phazon_torso.TXTR"),
-    resource_info!("fusion_phazon_arms_legs.TXTR"),
+    primary(resource_info!("fusion_phazon_head_chest.TXTR")),
+    primary(resource_info!("fusion_phazon_torso.TXTR")),
+    primary(resource_info!("fusion_phazon_arms_legs.TXTR")),
     // Low poly morph/unmorph transition TestAnim/BallTransitions Fusion_Phazon.CMDL
-    resource_info!("3A3B3B2E.TXTR"),
-    resource_info!("6BE50A0D.TXTR"),
-    resource_info!("D09DA9D4.TXTR"),
+    primary(resource_info!("3A3B3B2E.TXTR")),
+    primary(resource_info!("6BE50A0D.TXTR")),
+    primary(resource_info!("D09DA9D4.TXTR")),
     // Morph ball shell - SamusFusionBallANCS MS3 / SamusBallFusionLowPolyCMDL MS3
-    resource_info!("B1986E4B.TXTR"),
-    resource_info!("D1CD17F3.TXTR"),
+    primary(resource_info!("B1986E4B.TXTR")),
+    primary(resource_info!("D1CD17F3.TXTR")),
+];
+
+pub const SHIP_TEXTURES: &[SuitTexture] = &[
+    primary(resource_info!("ship_hatch.TXTR")),
+    primary(resource_info!("ship_top_front.TXTR")),
+    glow(resource_info!("ship_top_front_incan.TXTR")),
+    primary(resource_info!("ship_top_rear.TXTR")),
+    primary(resource_info!("ship_bottom.TXTR")),
+    glow(resource_info!("ship_bottom_incan.TXTR")),
 ];
 
-pub const SHIP_TEXTURES: &[ResourceInfo] = &[
-    resource_info!("ship_hatch.TXTR"),
-    resource_info!("ship_top_front.TXTR"),
-    resource_info!("ship_top_front_incan.TXTR"),
-    resource_info!("ship_top_rear.TXTR"),
-    resource_info!("ship_bottom.TXTR"),
-    resource_info!("ship_bottom_incan.TXTR"),
+// The arm cannon of each beam. Their textures are unnamed, so they're found through the models
+// when patching.
+pub const GUN_BEAM_ANCS: &[ResourceInfo] = &[
+    resource_info!("SamusGun/Power.ANCS"),
+    resource_info!("SamusGun/Ice.ANCS"),
+    resource_info!("SamusGun/Wave.ANCS"),
+    resource_info!("SamusGun/Plasma.ANCS"),
+    resource_info!("SamusGun/Phazon.ANCS"),
 ];
 
 struct CmprPixelIter {
@@ -340,12 +373,172 @@ pub fn huerotate_in_place(image: &mut [u8], width: usize, height: usize, matrix:
     }
 }
 
+pub const RECOLOR_PRIMARY: usize = 0;
+pub const RECOLOR_SECONDARY: usize = 1;
+pub const RECOLOR_GLOW: usize = 2;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecolorParams {
+    pub hue_matrix: Option<[f32; 9]>,
+    // Target color of the primary, secondary and glow regions
+    pub regions: [Option<[u8; 3]>; 3],
+    pub saturation: f32,
+    pub brightness: f32,
+}
+
+impl Default for RecolorParams {
+    fn default() -> Self {
+        RecolorParams {
+            hue_matrix: None,
+            regions: [None; 3],
+            saturation: 1.0,
+            brightness: 1.0,
+        }
+    }
+}
+
+/// An RGBA image stretched over the recolored texture. Its red, green and blue channels weigh how
+/// much each pixel belongs to the primary, secondary and glow regions.
+#[derive(Debug, Clone)]
+pub struct RecolorMask {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<u8>,
+}
+
+impl RecolorMask {
+    pub fn from_png(png: &[u8]) -> Result<Self, String> {
+        let image = image::load_from_memory(png)
+            .map_err(|e| format!("Failed to decode PNG: {}", e))?
+            .into_rgba8();
+        Ok(RecolorMask {
+            width: image.width() as usize,
+            height: image.height() as usize,
+            pixels: image.into_raw(),
+        })
+    }
+
+    fn weights(&self, x: usize, y: usize, width: usize, height: usize) -> [f32; 3] {
+        let mask_x = x * self.width / width;
+        let mask_y = y * self.height / height;
+        let start = (mask_y * self.width + mask_x) * 4;
+        [
+            self.pixels[start] as f32 / 255.0,
+            self.pixels[start + 1] as f32 / 255.0,
+            self.pixels[start + 2] as f32 / 255.0,
+        ]
+    }
+}
+
+// Hue, saturation and value, all between 0 and 1
+fn rgb_to_hsv(rgb: [f32; 3]) -> [f32; 3] {
+    let [r, g, b] = rgb;
+    let max = r.max(g).max(b);
+    let delta = max - r.min(g).min(b);
+    if delta <= 0.0 {
+        return [0.0, 0.0, max];
+    }
+
+    let hue = if max == r {
+        ((g - b) / delta).rem_euclid(6.0)
+    } else if max == g {
+        (b - r) / delta + 2.0
+    } else {
+        (r - g) / delta + 4.0
+    };
+    [hue / 6.0, delta / max, max]
+}
+
+fn hsv_to_rgb(hsv: [f32; 3]) -> [f32; 3] {
+    let [h, s, v] = hsv;
+    let h = h.rem_euclid(1.0) * 6.0;
+    let c = v * s;
+    let x = c * (1.0 - (h % 2.0 - 1.0).abs());
+    let m = v - c;
+    let (r, g, b) = match h as u32 {
+        0 => (c, x, 0.0),
+        1 => (x, c, 0.0),
+        2 => (0.0, c, x),
+        3 => (0.0, x, c),
+        4 => (x, 0.0, c),
+        _ => (c, 0.0, x),
+    };
+    [r + m, g + m, b + m]
+}
+
+/// Recolors a single color. Each region takes the hue of its target color, while keeping the
+/// shading and how colorful the original color was.
+pub fn recolor_color(params: &RecolorParams, rgb: [u8; 3], weights: [f32; 3]) -> [u8; 3] {
+    let rgb = match params.hue_matrix {
+        Some(matrix) => huerotate_color(matrix, rgb[0], rgb[1], rgb[2]),
+        None => rgb,
+    };
+    let mut rgb = rgb.map(|c| c as f32 / 255.0);
+
+    for (target, weight) in params.regions.iter().zip(weights) {
+        let target = match target {
+            Some(target) if weight > 0.0 => rgb_to_hsv(target.map(|c| c as f32 / 255.0)),
+            _ => continue,
+        };
+        let [_, s, v] = rgb_to_hsv(rgb);
+        let tinted = hsv_to_rgb([target[0], target[1] * s, target[2] * v]);
+        for (c, t) in rgb.iter_mut().zip(tinted) {
+            *c += (t - *c) * weight.min(1.0);
+        }
+    }
+
+    if params.saturation != 1.0 || params.brightness != 1.0 {
+        let [h, s, v] = rgb_to_hsv(rgb);
+        rgb = hsv_to_rgb([
+            h,
+            (s * params.saturation).clamp(0.0, 1.0),
+            (v * params.brightness).clamp(0.0, 1.0),
+        ]);
+    }
+
+    rgb.map(|c| (c * 255.0).round().clamp(0.0, 255.0) as u8)
+}
+
+/// Without a mask, the whole texture belongs to `default_region`
+pub fn recolor_in_place(
+    image: &mut [u8],
+    width: usize,
+    height: usize,
+    params: &RecolorParams,
+    default_region: usize,
+    mask: Option<&RecolorMask>,
+) {
+    let mut default_weights = [0.0; 3];
+    default_weights[default_region] = 1.0;
+
+    for y in 0..height {
+        for x in 0..width {
+            let start = (y * width + x) * 4;
+            let pixel = &mut image[start..start + 4];
+            let weights = match mask {
+                Some(mask) => mask.weights(x, y, width, height),
+                None => default_weights,
+            };
+            let new_rgb = recolor_color(params, [pixel[0], pixel[1], pixel[2]], weights);
+            pixel[..3].copy_from_slice(&new_rgb);
+        }
+    }
+}
+
 pub fn compress_dxt1gcn_block(rgba: [[u8; 4]; 16], block: &mut [u8]) {
+    compress_dxt1gcn_block_with_quality(rgba, block, CmprQuality::Best)
+}
+
+pub fn compress_dxt1gcn_block_with_quality(
+    rgba: [[u8; 4]; 16],
+    block: &mut [u8],
+    quality: CmprQuality,
+) {
     Format::Bc1.compress_block_masked(
         rgba,
         0xFFFF,
         texpresso::Params {
-            algorithm: texpresso::Algorithm::IterativeClusterFit,
+            algorithm: quality.algorithm(),
             ..Default::default()
         },
         block,
@@ -375,3 +568,860 @@ fn reverse_byte(byte: u8) -> u8 {
         | ((byte & 0b00110000) >> 2)
         | ((byte & 0b11000000) >> 6)
 }
+
+// XXX The following conversion functions are borrowed from URDE https://github.com/AxioDL/urde/blob/master/DataSpec/DNACommon/TXTR.cpp
+fn convert3to8(v: u8) -> u8 {
+    (v << 5) | (v << 2) | (v >> 1)
+}
+
+fn convert4to8(v: u8) -> u8 {
+    (v << 4) | v
+}
+
+fn convert5to8(v: u8) -> u8 {
+    (v << 3) | (v >> 2)
+}
+
+fn convert6to8(v: u8) -> u8 {
+    (v << 2) | (v >> 4)
+}
+
+fn encode_rgb5a3(pixel: [u8; 4]) -> [u8; 2] {
+    let v = if pixel[3] == 0xff {
+        0x8000
+            | ((pixel[0] as u16 >> 3) << 10)
+            | ((pixel[1] as u16 >> 3) << 5)
+            | (pixel[2] as u16 >> 3)
+    } else {
+        ((pixel[0] as u16 >> 4) << 8)
+            | ((pixel[1] as u16 >> 4) << 4)
+            | (pixel[2] as u16 >> 4)
+            | ((pixel[3] as u16 >> 5) << 12)
+    };
+    v.to_be_bytes()
+}
+
+fn encode_rgb565(pixel: [u8; 3]) -> [u8; 2] {
+    let v = ((pixel[0] as u16 >> 3) << 11) | ((pixel[1] as u16 >> 2) << 5) | (pixel[2] as u16 >> 3);
+    v.to_be_bytes()
+}
+
+fn decode_rgb5a3(texel: [u8; 2]) -> [u8; 4] {
+    let v = u16::from_be_bytes(texel);
+    if v & 0x8000 != 0 {
+        [
+            convert5to8(((v >> 10) & 0x1f) as u8),
+            convert5to8(((v >> 5) & 0x1f) as u8),
+            convert5to8((v & 0x1f) as u8),
+            0xff,
+        ]
+    } else {
+        [
+            convert4to8(((v >> 8) & 0xf) as u8),
+            convert4to8(((v >> 4) & 0xf) as u8),
+            convert4to8((v & 0xf) as u8),
+            convert3to8(((v >> 12) & 0x7) as u8),
+        ]
+    }
+}
+
+fn decode_rgb565(texel: [u8; 2]) -> [u8; 3] {
+    let v = u16::from_be_bytes(texel);
+    [
+        convert5to8(((v >> 11) & 0x1f) as u8),
+        convert6to8(((v >> 5) & 0x3f) as u8),
+        convert5to8((v & 0x1f) as u8),
+    ]
+}
+
+fn decode_ia8(texel: [u8; 2]) -> [u8; 2] {
+    // GX stores the alpha first
+    [texel[1], texel[0]]
+}
+
+fn encode_ia8(pixel: [u8; 2]) -> [u8; 2] {
+    [pixel[1], pixel[0]]
+}
+
+/// How hard to search for the best colors of each CMPR block. Slower settings give
+/// noticeably better gradients.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CmprQuality {
+    Fast,
+    Normal,
+    Best,
+}
+
+impl CmprQuality {
+    fn algorithm(&self) -> texpresso::Algorithm {
+        match self {
+            CmprQuality::Fast => texpresso::Algorithm::RangeFit,
+            CmprQuality::Normal => texpresso::Algorithm::ClusterFit,
+            CmprQuality::Best => texpresso::Algorithm::IterativeClusterFit,
+        }
+    }
+}
+
+#[allow(clippy::result_unit_err)]
+pub trait TxtrFormatExt {
+    fn color_type(&self) -> ColorType;
+    fn bytes_per_block(&self) -> usize;
+    fn block_dimensions(&self) -> (usize, usize);
+    fn flipped(&self) -> bool;
+    fn compute_palette(&mut self, pixels: &[u8]) -> Result<(), ()>;
+    fn decode_block(&self, block: &[u8], pixels: &mut [u8]);
+    fn encode_block(&self, block: &mut [u8], pixels: &[u8], quality: CmprQuality);
+    fn from_str(s: &str) -> Result<TxtrFormat, ()>;
+}
+
+impl TxtrFormatExt for TxtrFormat {
+    fn color_type(&self) -> ColorType {
+        match self {
+            TxtrFormat::I4 => ColorType::L8,
+            TxtrFormat::I8 => ColorType::L8,
+            TxtrFormat::Ia4 => ColorType::La8,
+            TxtrFormat::Ia8 => ColorType::La8,
+            TxtrFormat::C4(fmt, _) | TxtrFormat::C8(fmt, _) | TxtrFormat::C14x2(fmt, _) => {
+                fmt.color_type()
+            }
+            TxtrFormat::Rgb565 => ColorType::Rgb8,
+            TxtrFormat::Rgb5A3 => ColorType::Rgba8,
+            TxtrFormat::Rgba8 => ColorType::Rgba8,
+            TxtrFormat::Cmpr => ColorType::Rgba8,
+        }
+    }
+
+    fn bytes_per_block(&self) -> usize {
+        match self {
+            TxtrFormat::I4 => 32,
+            TxtrFormat::I8 => 32,
+            TxtrFormat::Ia4 => 32,
+            TxtrFormat::Ia8 => 32,
+            TxtrFormat::C4(_, _) => 32,
+            TxtrFormat::C8(_, _) => 32,
+            TxtrFormat::C14x2(_, _) => 32,
+            TxtrFormat::Rgb565 => 32,
+            TxtrFormat::Rgb5A3 => 32,
+            TxtrFormat::Rgba8 => 64,
+            TxtrFormat::Cmpr => 32,
+        }
+    }
+
+    fn block_dimensions(&self) -> (usize, usize) {
+        match self {
+            TxtrFormat::I4 => (8, 8),
+            TxtrFormat::I8 => (8, 4),
+            TxtrFormat::Ia4 => (8, 4),
+            TxtrFormat::Ia8 => (4, 4),
+            TxtrFormat::C4(_, _) => (8, 8),
+            TxtrFormat::C8(_, _) => (8, 4),
+            TxtrFormat::C14x2(_, _) => (4, 4),
+            TxtrFormat::Rgb565 => (4, 4),
+            TxtrFormat::Rgb5A3 => (4, 4),
+            TxtrFormat::Rgba8 => (4, 4),
+            TxtrFormat::Cmpr => (8, 8),
+        }
+    }
+
+    fn flipped(&self) -> bool {
+        !matches!(
+            self,
+            TxtrFormat::C4(_, _) | TxtrFormat::C8(_, _) | TxtrFormat::C14x2(_, _)
+        )
+    }
+
+    fn compute_palette(&mut self, pixels: &[u8]) -> Result<(), ()> {
+        let (fmt, buf) = match self {
+            TxtrFormat::C4(fmt, buf) => (fmt, &mut buf[..]),
+            TxtrFormat::C8(fmt, buf) => (fmt, &mut buf[..]),
+            TxtrFormat::C14x2(fmt, buf) => (fmt, &mut buf[..]),
+            _ => return Ok(()),
+        };
+        let mut palette_values = HashMap::with_capacity(buf.len() / 2);
+        for chunk in pixels.chunks(fmt.color_type().channel_count() as usize) {
+            let encoded = fmt.encode_color(chunk);
+            let pv_len = palette_values.len();
+            let idx = *palette_values.entry(encoded).or_insert(pv_len);
+            if idx * 2 + 2 > buf.len() {
+                Err(())?;
+            }
+            buf[idx * 2..idx * 2 + 2].copy_from_slice(&encoded[..]);
+        }
+        Ok(())
+    }
+
+    fn decode_block(&self, block: &[u8], pixels: &mut [u8]) {
+        assert_eq!(block.len(), self.bytes_per_block());
+        let channel_count = self.color_type().channel_count() as usize;
+        assert_eq!(
+            pixels.len(),
+            channel_count * self.block_dimensions().0 * self.block_dimensions().1,
+        );
+        match self {
+            TxtrFormat::I4 => {
+                for (texel_byte, pixel_bytes) in block.iter().zip(pixels.chunks_mut(2)) {
+                    pixel_bytes[0] = convert4to8(texel_byte >> 4);
+                    pixel_bytes[1] = convert4to8(texel_byte & 0xf);
+                }
+            }
+            TxtrFormat::I8 => pixels.copy_from_slice(block),
+            TxtrFormat::Ia4 => {
+                // The alpha is in the high nibble
+                for (texel_byte, pixel_bytes) in block.iter().zip(pixels.chunks_mut(2)) {
+                    pixel_bytes[0] = convert4to8(texel_byte & 0xf);
+                    pixel_bytes[1] = convert4to8(texel_byte >> 4);
+                }
+            }
+            TxtrFormat::Ia8 => {
+                for (texel, pixel) in block.chunks(2).zip(pixels.chunks_mut(2)) {
+                    pixel.copy_from_slice(&decode_ia8(texel.try_into().unwrap())[..]);
+                }
+            }
+            TxtrFormat::C4(fmt, palette) => {
+                let iter = block
+                    .iter()
+                    .flat_map(|texel| iter::once(texel >> 4).chain(iter::once(texel & 0xf)))
+                    .map(|nibble| nibble as usize)
+                    .zip(pixels.chunks_mut(channel_count));
+                for (texel_nibble, pixel_bytes) in iter {
+                    fmt.decode_color(
+                        &palette[texel_nibble * 2..texel_nibble * 2 + 2],
+                        pixel_bytes,
+                    );
+                }
+            }
+            TxtrFormat::C8(fmt, palette) => {
+                let iter = block
+                    .iter()
+                    .map(|byte| *byte as usize)
+                    .zip(pixels.chunks_mut(channel_count));
+                for (texel_byte, pixel_bytes) in iter {
+                    fmt.decode_color(&palette[texel_byte * 2..texel_byte * 2 + 2], pixel_bytes);
+                }
+            }
+            TxtrFormat::C14x2(fmt, palette) => {
+                let iter = block
+                    .chunks(2)
+                    .map(|texel| (u16::from_be_bytes([texel[0], texel[1]]) & 0x3fff) as usize)
+                    .zip(pixels.chunks_mut(channel_count));
+                for (index, pixel_bytes) in iter {
+                    fmt.decode_color(&palette[index * 2..index * 2 + 2], pixel_bytes);
+                }
+            }
+            TxtrFormat::Rgb565 => {
+                for (texel, pixel) in block.chunks(2).zip(pixels.chunks_mut(3)) {
+                    pixel.copy_from_slice(&decode_rgb565(texel.try_into().unwrap())[..]);
+                }
+            }
+            TxtrFormat::Rgb5A3 => {
+                for (texel, pixel) in block.chunks(2).zip(pixels.chunks_mut(4)) {
+                    pixel.copy_from_slice(&decode_rgb5a3(texel.try_into().unwrap())[..]);
+                }
+            }
+            TxtrFormat::Rgba8 => {
+                // The block holds the alpha and red of each pixel, then the green and blue
+                let (ar, gb) = block.split_at(32);
+                for (i, pixel) in pixels.chunks_mut(4).enumerate() {
+                    pixel.copy_from_slice(&[ar[i * 2 + 1], gb[i * 2], gb[i * 2 + 1], ar[i * 2]]);
+                }
+            }
+            TxtrFormat::Cmpr => {
+                for i in 0..4 {
+                    let decoded_dxt1_block = decompress_dxt1gcn_block(&block[i * 8..(i + 1) * 8]);
+                    let outer_x = i % 2 * 4;
+                    let outer_y = i / 2 * 4;
+                    for (k, decoded_pixel) in decoded_dxt1_block.iter().enumerate() {
+                        let inner_x = k % 4;
+                        let inner_y = k / 4;
+                        let start = (outer_y + inner_y) * 32 + (outer_x + inner_x) * 4;
+                        pixels[start..start + 4].copy_from_slice(&decoded_pixel[..]);
+                    }
+                }
+            }
+        }
+    }
+
+    fn encode_block(&self, block: &mut [u8], pixels: &[u8], quality: CmprQuality) {
+        assert_eq!(block.len(), self.bytes_per_block());
+        let channel_count = self.color_type().channel_count() as usize;
+        assert_eq!(
+            pixels.len(),
+            channel_count * self.block_dimensions().0 * self.block_dimensions().1,
+        );
+        match self {
+            TxtrFormat::I4 => {
+                for (block_byte, pixel_bytes) in block.iter_mut().zip(pixels.chunks(2)) {
+                    *block_byte = (pixel_bytes[1] >> 4) | ((pixel_bytes[0] >> 4) << 4);
+                }
+            }
+            TxtrFormat::I8 => block.copy_from_slice(pixels),
+            TxtrFormat::Ia4 => {
+                for (block_byte, pixel_bytes) in block.iter_mut().zip(pixels.chunks(2)) {
+                    *block_byte = (pixel_bytes[0] >> 4) | ((pixel_bytes[1] >> 4) << 4);
+                }
+            }
+            TxtrFormat::Ia8 => {
+                for (texel, pixel) in block.chunks_mut(2).zip(pixels.chunks(2)) {
+                    texel.copy_from_slice(&encode_ia8(pixel.try_into().unwrap())[..]);
+                }
+            }
+            TxtrFormat::C4(fmt, palette) => {
+                let map = fmt.palette_indices(&palette[..]);
+                for (texel, pixels) in block.iter_mut().zip(pixels.chunks(channel_count * 2)) {
+                    let mut nibbles = [0; 2];
+                    for (nibble, pixel) in nibbles.iter_mut().zip(pixels.chunks(channel_count)) {
+                        *nibble = map[&fmt.encode_color(pixel)] as u8;
+                    }
+                    *texel = nibbles[1] | (nibbles[0] << 4);
+                }
+            }
+            TxtrFormat::C8(fmt, palette) => {
+                let map = fmt.palette_indices(&palette[..]);
+                for (texel, pixel) in block.iter_mut().zip(pixels.chunks(channel_count)) {
+                    *texel = map[&fmt.encode_color(pixel)] as u8;
+                }
+            }
+            TxtrFormat::C14x2(fmt, palette) => {
+                let map = fmt.palette_indices(&palette[..]);
+                for (texel, pixel) in block.chunks_mut(2).zip(pixels.chunks(channel_count)) {
+                    let index = map[&fmt.encode_color(pixel)] as u16;
+                    texel.copy_from_slice(&index.to_be_bytes());
+                }
+            }
+            TxtrFormat::Rgb565 => {
+                for (texel, pixel) in block.chunks_mut(2).zip(pixels.chunks(3)) {
+                    texel.copy_from_slice(&encode_rgb565(pixel.try_into().unwrap())[..]);
+                }
+            }
+            TxtrFormat::Rgb5A3 => {
+                for (texel, pixel) in block.chunks_mut(2).zip(pixels.chunks(4)) {
+                    texel.copy_from_slice(&encode_rgb5a3(pixel.try_into().unwrap())[..]);
+                }
+            }
+            TxtrFormat::Rgba8 => {
+                let (ar, gb) = block.split_at_mut(32);
+                for (i, pixel) in pixels.chunks(4).enumerate() {
+                    ar[i * 2..i * 2 + 2].copy_from_slice(&[pixel[3], pixel[0]]);
+                    gb[i * 2..i * 2 + 2].copy_from_slice(&[pixel[1], pixel[2]]);
+                }
+            }
+            TxtrFormat::Cmpr => {
+                let mut sub_block_pixels = [[0u8; 4]; 16];
+                for (i, sub_block) in block.chunks_mut(8).enumerate() {
+                    let outer_x = i % 2 * 4;
+                    let outer_y = i / 2 * 4;
+                    for (k, sub_block_pixel) in sub_block_pixels.iter_mut().enumerate() {
+                        let inner_x = k % 4;
+                        let inner_y = k / 4;
+                        let start = (outer_y + inner_y) * 32 + (outer_x + inner_x) * 4;
+                        sub_block_pixel[..].copy_from_slice(&pixels[start..start + 4]);
+                    }
+
+                    compress_dxt1gcn_block_with_quality(sub_block_pixels, sub_block, quality);
+                }
+            }
+        }
+    }
+
+    fn from_str(s: &str) -> Result<TxtrFormat, ()> {
+        let s = s.to_ascii_lowercase();
+        if let Some(palette) = s.strip_suffix(')') {
+            let (format, palette) = palette.split_once('(').ok_or(())?;
+            let palette = match palette {
+                "ia8" => TxtrPaletteFormat::Ia8,
+                "rgb565" => TxtrPaletteFormat::Rgb565,
+                "rgb5a3" => TxtrPaletteFormat::Rgb5A3,
+                _ => return Err(()),
+            };
+            return match format {
+                "c4" => Ok(TxtrFormat::C4(palette, Default::default())),
+                "c8" => Ok(TxtrFormat::C8(palette, Default::default())),
+                "c14x2" => Ok(TxtrFormat::C14x2(palette, Default::default())),
+                _ => Err(()),
+            };
+        }
+        match s.as_str() {
+            "i4" => Ok(TxtrFormat::I4),
+            "i8" => Ok(TxtrFormat::I8),
+            "ia4" => Ok(TxtrFormat::Ia4),
+            "ia8" => Ok(TxtrFormat::Ia8),
+            "rgb565" => Ok(TxtrFormat::Rgb565),
+            "rgb5a3" => Ok(TxtrFormat::Rgb5A3),
+            "rgba8" => Ok(TxtrFormat::Rgba8),
+            "cmpr" => Ok(TxtrFormat::Cmpr),
+            _ => Err(()),
+        }
+    }
+}
+
+pub trait TxtrPaletteFormatExt {
+    fn color_type(&self) -> ColorType;
+    fn decode_color(&self, color: &[u8], pixel: &mut [u8]);
+    fn encode_color(&self, pixel: &[u8]) -> [u8; 2];
+    fn palette_indices(&self, palette: &[u8]) -> HashMap<[u8; 2], usize>;
+}
+
+impl TxtrPaletteFormatExt for TxtrPaletteFormat {
+    fn color_type(&self) -> ColorType {
+        match self {
+            TxtrPaletteFormat::Ia8 => ColorType::La8,
+            TxtrPaletteFormat::Rgb565 => ColorType::Rgb8,
+            TxtrPaletteFormat::Rgb5A3 => ColorType::Rgba8,
+        }
+    }
+
+    fn decode_color(&self, color: &[u8], pixel: &mut [u8]) {
+        let color = color.try_into().unwrap();
+        match self {
+            TxtrPaletteFormat::Ia8 => pixel.copy_from_slice(&decode_ia8(color)[..]),
+            TxtrPaletteFormat::Rgb565 => pixel.copy_from_slice(&decode_rgb565(color)[..]),
+            TxtrPaletteFormat::Rgb5A3 => pixel.copy_from_slice(&decode_rgb5a3(color)[..]),
+        }
+    }
+
+    fn encode_color(&self, pixel: &[u8]) -> [u8; 2] {
+        match self {
+            TxtrPaletteFormat::Ia8 => encode_ia8(pixel.try_into().unwrap()),
+            TxtrPaletteFormat::Rgb565 => encode_rgb565(pixel.try_into().unwrap()),
+            TxtrPaletteFormat::Rgb5A3 => encode_rgb5a3(pixel.try_into().unwrap()),
+        }
+    }
+
+    /// Maps each color of the palette to the first index it appears at
+    fn palette_indices(&self, palette: &[u8]) -> HashMap<[u8; 2], usize> {
+        let mut map = HashMap::with_capacity(palette.len() / 2);
+        for (i, color) in palette.chunks(2).enumerate() {
+            map.entry([color[0], color[1]]).or_insert(i);
+        }
+        map
+    }
+}
+
+/// Decodes one of a TXTR's mipmaps into rows of pixels, top row first, using the color type
+/// given by `TxtrFormatExt::color_type`
+pub fn decode_txtr_mipmap(txtr: &Txtr, mipmap: usize) -> Vec<u8> {
+    let w = txtr.width as usize >> mipmap;
+    let h = txtr.height as usize >> mipmap;
+
+    let mipmap_data: Vec<u8> = txtr
+        .pixel_data
+        .iter()
+        .nth(mipmap)
+        .unwrap()
+        .iter()
+        .map(|b| *b)
+        .collect();
+
+    let channel_count = txtr.format.color_type().channel_count() as usize;
+    let mut decompressed_pixels = vec![0u8; w * h * channel_count];
+
+    let (block_w, block_h) = txtr.format.block_dimensions();
+
+    let mut decoded_block = vec![0u8; channel_count * block_w * block_h];
+    for (i, block) in mipmap_data
+        .chunks(txtr.format.bytes_per_block())
+        .enumerate()
+    {
+        txtr.format.decode_block(block, &mut decoded_block[..]);
+        let outer_x = (i % (w / block_w)) * block_w;
+        let outer_y = (i / (w / block_w)) * block_h;
+        for inner_y in 0..block_h {
+            for inner_x in 0..block_w {
+                let decoded_start = (inner_y * block_w + inner_x) * channel_count;
+                let pixels_start = if txtr.format.flipped() {
+                    ((h - 1 - (outer_y + inner_y)) * w + outer_x + inner_x) * channel_count
+                } else {
+                    ((outer_y + inner_y) * w + outer_x + inner_x) * channel_count
+                };
+                decompressed_pixels[pixels_start..pixels_start + channel_count]
+                    .copy_from_slice(&decoded_block[decoded_start..decoded_start + channel_count]);
+            }
+        }
+    }
+
+    decompressed_pixels
+}
+
+/// Encodes rows of RGBA pixels, top row first, into RGB5A3 texels. Unlike the TXTRs drawn on
+/// models, the disc banner and memory card images are copied as-is and so aren't flipped.
+pub fn encode_rgb5a3_texels(pixels: &[u8], width: usize, height: usize) -> Vec<u8> {
+    let format = TxtrFormat::Rgb5A3;
+    let (block_w, block_h) = format.block_dimensions();
+    let mut texels = vec![0u8; width * height * 2];
+    let mut block_pixels = vec![0u8; block_w * block_h * 4];
+    for (i, block) in texels.chunks_mut(format.bytes_per_block()).enumerate() {
+        let outer_x = (i % (width / block_w)) * block_w;
+        let outer_y = (i / (width / block_w)) * block_h;
+        for inner_y in 0..block_h {
+            let start = ((outer_y + inner_y) * width + outer_x) * 4;
+            block_pixels[inner_y * block_w * 4..(inner_y + 1) * block_w * 4]
+                .copy_from_slice(&pixels[start..start + block_w * 4]);
+        }
+        // The quality only matters to CMPR
+        format.encode_block(block, &block_pixels, CmprQuality::Best);
+    }
+    texels
+}
+
+/// Encodes rows of pixels, top row first and using the color type given by
+/// `TxtrFormatExt::color_type`, into a TXTR. Without a `mipmap_count`, as many mipmaps as the
+/// format's blocks allow are made. Without a `filter`, each mipmap averages 2x2 pixels of the
+/// previous one.
+pub fn encode_txtr<'r>(
+    pixels: &[u8],
+    w: usize,
+    h: usize,
+    mut format: TxtrFormat,
+    mipmap_count: Option<u8>,
+    filter: Option<FilterType>,
+    quality: CmprQuality,
+) -> Result<Txtr<'r>, String> {
+    let color_type = format.color_type();
+    let channel_count = color_type.channel_count() as usize;
+    let (block_w, block_h) = format.block_dimensions();
+    if w == 0 || h == 0 || w % block_w != 0 || h % block_h != 0 {
+        Err(format!(
+            "The images width and height ({}, {}) must be a multiple of the chosen format's \
+             block dimensions ({}, {})",
+            w, h, block_w, block_h
+        ))?
+    }
+    if w > u16::MAX as usize || h > u16::MAX as usize {
+        Err(format!("The image is too large ({}x{})", w, h))?
+    }
+
+    format
+        .compute_palette(pixels)
+        .map_err(|()| "Image contains too many colors for choosen paletted format")?;
+
+    let max_mipmaps_for_fomat = match format {
+        TxtrFormat::C4(_, _) | TxtrFormat::C8(_, _) | TxtrFormat::C14x2(_, _) => 1,
+        _ => {
+            let mut i = 1;
+            while w >> i != 0 && (w >> i) % block_w == 0 && (h >> i) % block_h == 0 {
+                i += 1
+            }
+            i
+        }
+    };
+    let max_mipmaps = mipmap_count.unwrap_or(max_mipmaps_for_fomat);
+    if max_mipmaps > max_mipmaps_for_fomat {
+        Err(format!(
+            "Specified format supports a max of {} mipmaps for this image",
+            max_mipmaps_for_fomat
+        ))?
+    }
+
+    let mut mipmaps = vec![];
+    let mut uncompressed_pixels = pixels.to_vec();
+
+    // Temporary buffer to store the pixels for 1 block so we can pass it to the encode function
+    let mut block_pixels = vec![0u8; block_w * block_h * channel_count];
+    for mipmap in 0..max_mipmaps {
+        let mip_w = w >> mipmap;
+        let mip_h = h >> mipmap;
+
+        let mut blocks = vec![0u8; mip_w * mip_h * format.bytes_per_block() / (block_w * block_h)];
+        for (i, block) in blocks.chunks_mut(format.bytes_per_block()).enumerate() {
+            let outer_x = (i % (mip_w / block_w)) * block_w;
+            let outer_y = (i / (mip_w / block_w)) * block_h;
+            for inner_y in 0..block_h {
+                let row = if format.flipped() {
+                    mip_h - 1 - (outer_y + inner_y)
+                } else {
+                    outer_y + inner_y
+                };
+                let start = (row * mip_w + outer_x) * channel_count;
+                block_pixels
+                    [inner_y * block_w * channel_count..(inner_y + 1) * block_w * channel_count]
+                    .copy_from_slice(&uncompressed_pixels[start..start + block_w * channel_count]);
+            }
+            format.encode_block(block, &block_pixels[..], quality);
+        }
+        mipmaps.push(blocks.into());
+
+        if mipmap + 1 == max_mipmaps {
+            break;
+        }
+        let discretize_alpha = matches!(format, TxtrFormat::Cmpr);
+        uncompressed_pixels = match filter {
+            // Each mipmap is resampled from the full size image rather than the previous mipmap
+            Some(filter) => resize_pixels(
+                pixels,
+                (w, h),
+                (mip_w / 2, mip_h / 2),
+                color_type,
+                filter,
+                discretize_alpha,
+            ),
+            None => box_filter_pixels(
+                &uncompressed_pixels[..],
+                mip_w,
+                mip_h,
+                channel_count,
+                discretize_alpha,
+            ),
+        };
+    }
+
+    Ok(Txtr {
+        format,
+        width: w as u16,
+        height: h as u16,
+        pixel_data: mipmaps.into(),
+    })
+}
+
+fn box_filter_pixels(
+    pixels: &[u8],
+    w: usize,
+    h: usize,
+    chan_count: usize,
+    discretize_alpha: bool,
+) -> Vec<u8> {
+    let mut output = Vec::with_capacity(w * h * chan_count / 4);
+    for iy in 0..h / 2 {
+        for ix in 0..w / 2 {
+            let y = iy * 2;
+            let x = ix * 2;
+            for c in 0..chan_count {
+                output.push(
+                    (((pixels[(y * w + x) * chan_count + c] as u16)
+                        + (pixels[(y * w + x + 1) * chan_count + c] as u16)
+                        + (pixels[((y + 1) * w + x) * chan_count + c] as u16)
+                        + (pixels[((y + 1) * w + x + 1) * chan_count + c] as u16))
+                        / 4) as u8,
+                );
+                if discretize_alpha && c == chan_count - 1 {
+                    let last = output.last_mut().unwrap();
+                    if *last > 0 {
+                        *last = 0xff;
+                    }
+                }
+            }
+        }
+    }
+    output
+}
+
+fn resize_pixels(
+    pixels: &[u8],
+    (w, h): (usize, usize),
+    (new_w, new_h): (usize, usize),
+    color_type: ColorType,
+    filter: FilterType,
+    discretize_alpha: bool,
+) -> Vec<u8> {
+    let (w, h) = (w as u32, h as u32);
+    let pixels = pixels.to_vec();
+    let image = match color_type {
+        ColorType::L8 => DynamicImage::ImageLuma8(ImageBuffer::from_raw(w, h, pixels).unwrap()),
+        ColorType::La8 => DynamicImage::ImageLumaA8(ImageBuffer::from_raw(w, h, pixels).unwrap()),
+        ColorType::Rgb8 => DynamicImage::ImageRgb8(ImageBuffer::from_raw(w, h, pixels).unwrap()),
+        _ => DynamicImage::ImageRgba8(ImageBuffer::from_raw(w, h, pixels).unwrap()),
+    };
+    let mut output = image
+        .resize_exact(new_w as u32, new_h as u32, filter)
+        .into_bytes();
+    if discretize_alpha {
+        // Unlike the box filter, these filters can ring, so only keep mostly opaque pixels
+        for pixel in output.chunks_exact_mut(4) {
+            pixel[3] = if pixel[3] >= 0x80 { 0xff } else { 0 };
+        }
+    }
+    output
+}
+
+/// Converts a PNG into a mipmapped CMPR TXTR, the format used by most model and room textures.
+/// The image is expected the same way up as `decode_txtr_mipmap` exports it.
+pub fn png_to_cmpr_txtr<'r>(png: &[u8], quality: CmprQuality) -> Result<Txtr<'r>, String> {
+    let image = image::load_from_memory_with_format(png, ImageFormat::Png)
+        .map_err(|e| format!("Failed to decode PNG: {}", e))?
+        .into_rgba8();
+    let (block_w, block_h) = TxtrFormat::Cmpr.block_dimensions();
+    let (w, h) = (image.width() as usize, image.height() as usize);
+    if !w.is_power_of_two() || !h.is_power_of_two() || w < block_w || h < block_h {
+        Err(format!(
+            "The image must be a power of two of at least {}x{} pixels, but is {}x{}",
+            block_w, block_h, w, h
+        ))?
+    }
+    encode_txtr(
+        &image.into_raw(),
+        w,
+        h,
+        TxtrFormat::Cmpr,
+        None,
+        None,
+        quality,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use image::codecs::png::PngEncoder;
+
+    use super::*;
+
+    fn gradient(w: usize, h: usize) -> Vec<u8> {
+        (0..w * h)
+            .flat_map(|i| [(i % w * 8) as u8, (i / w * 8) as u8, 0x40, 0xff])
+            .collect()
+    }
+
+    fn png(pixels: &[u8], w: usize, h: usize) -> Vec<u8> {
+        let mut png = vec![];
+        PngEncoder::new(&mut png)
+            .encode(pixels, w as u32, h as u32, ColorType::Rgba8)
+            .unwrap();
+        png
+    }
+
+    #[test]
+    fn txtrs_decode_the_same_way_up() {
+        let pixels = gradient(8, 16);
+        let txtr = encode_txtr(
+            &pixels,
+            8,
+            16,
+            TxtrFormat::Rgba8,
+            None,
+            None,
+            CmprQuality::Fast,
+        )
+        .unwrap();
+
+        assert_eq!((txtr.width, txtr.height), (8, 16));
+        assert_eq!(txtr.pixel_data.len(), 2);
+        assert_eq!(decode_txtr_mipmap(&txtr, 0), pixels);
+        // The second mipmap averages each 2x2 square
+        assert_eq!(
+            decode_txtr_mipmap(&txtr, 1)[..8],
+            [4, 4, 0x40, 0xff, 20, 4, 0x40, 0xff]
+        );
+    }
+
+    #[test]
+    fn cmpr_txtrs_have_mipmaps_down_to_a_block() {
+        let pixels: Vec<u8> = [0x80, 0x40, 0x20, 0xff].repeat(32 * 16);
+        let txtr = png_to_cmpr_txtr(&png(&pixels, 32, 16), CmprQuality::Normal).unwrap();
+
+        assert!(matches!(txtr.format, TxtrFormat::Cmpr));
+        let mipmap_sizes: Vec<usize> = txtr.pixel_data.iter().map(|m| m.len()).collect();
+        assert_eq!(mipmap_sizes, [256, 64]);
+        for (mipmap, size) in [(0, 32 * 16), (1, 16 * 8)] {
+            let decoded = decode_txtr_mipmap(&txtr, mipmap);
+            assert_eq!(decoded.len(), size * 4);
+            for pixel in decoded.chunks(4) {
+                assert!(pixel.iter().zip(&pixels).all(|(a, b)| a.abs_diff(*b) <= 8));
+            }
+        }
+    }
+
+    #[test]
+    fn hsv_round_trips() {
+        for rgb in [
+            [0, 0, 0],
+            [255, 255, 255],
+            [128, 128, 128],
+            [255, 0, 0],
+            [0, 255, 0],
+            [0, 0, 255],
+            [255, 0, 255],
+            [12, 200, 97],
+            [250, 180, 3],
+            [40, 10, 160],
+        ] {
+            let rgb = rgb.map(|c: u8| c as f32 / 255.0);
+            let [h, s, v] = rgb_to_hsv(rgb);
+            assert!(
+                (0.0..1.0).contains(&h) && (0.0..=1.0).contains(&s) && (0.0..=1.0).contains(&v)
+            );
+            for (a, b) in hsv_to_rgb([h, s, v]).iter().zip(rgb) {
+                assert!((a - b).abs() < 1e-5, "{:?}", rgb);
+            }
+        }
+    }
+
+    #[test]
+    fn default_recolor_keeps_colors() {
+        let params = RecolorParams::default();
+        for rgb in [[0, 0, 0], [255, 255, 255], [12, 200, 97], [250, 180, 3]] {
+            assert_eq!(recolor_color(&params, rgb, [1.0, 0.0, 0.0]), rgb);
+            assert_eq!(recolor_color(&params, rgb, [0.3, 0.3, 0.4]), rgb);
+        }
+    }
+
+    #[test]
+    fn regions_take_the_hue_of_their_target() {
+        let params = RecolorParams {
+            regions: [Some([0, 0, 255]), None, Some([0, 255, 0])],
+            ..Default::default()
+        };
+        assert_eq!(
+            recolor_color(&params, [200, 0, 0], [1.0, 0.0, 0.0]),
+            [0, 0, 200]
+        );
+        assert_eq!(
+            recolor_color(&params, [200, 0, 0], [0.0, 0.0, 1.0]),
+            [0, 200, 0]
+        );
+        // The secondary region has no target
+        assert_eq!(
+            recolor_color(&params, [200, 0, 0], [0.0, 1.0, 0.0]),
+            [200, 0, 0]
+        );
+        // Grays stay gray
+        assert_eq!(
+            recolor_color(&params, [90, 90, 90], [1.0, 0.0, 0.0]),
+            [90, 90, 90]
+        );
+
+        let darker = RecolorParams {
+            brightness: 0.5,
+            ..Default::default()
+        };
+        assert_eq!(
+            recolor_color(&darker, [200, 100, 0], [1.0, 0.0, 0.0]),
+            [100, 50, 0]
+        );
+    }
+
+    #[test]
+    fn incandescent_suit_textures_are_glow() {
+        let tables = [
+            POWER_SUIT_TEXTURES,
+            VARIA_SUIT_TEXTURES,
+            GRAVITY_SUIT_TEXTURES,
+            PHAZON_SUIT_TEXTURES,
+            FUSION_POWER_SUIT_TEXTURES,
+            FUSION_VARIA_SUIT_TEXTURES,
+            FUSION_GRAVITY_SUIT_TEXTURES,
+            FUSION_PHAZON_SUIT_TEXTURES,
+            SHIP_TEXTURES,
+        ];
+        for texture in tables.iter().flat_map(|table| table.iter()) {
+            if texture.info.long_name.contains("incan") {
+                assert_eq!(texture.region, RECOLOR_GLOW, "{}", texture.info.long_name);
+            }
+        }
+    }
+
+    #[test]
+    fn bad_sizes_are_rejected() {
+        let pixels = gradient(24, 8);
+        assert!(png_to_cmpr_txtr(&png(&pixels, 24, 8), CmprQuality::Fast).is_err());
+        assert!(png_to_cmpr_txtr(&png(&pixels[..4 * 4 * 4], 4, 4), CmprQuality::Fast).is_err());
+        assert!(png_to_cmpr_txtr(b"not a png", CmprQuality::Fast).is_err());
+        let too_many_mipmaps = encode_txtr(
+            &pixels,
+            24,
+            8,
+            TxtrFormat::Rgb5A3,
+            Some(4),
+            None,
+            CmprQuality::Fast,
+        );
+        assert!(too_many_mipmaps.is_err());
+    }
+}
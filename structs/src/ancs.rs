@@ -51,9 +51,10 @@ pub struct CharacterInfo<'r> {
     pub cskr: ResId<CSKR>,
     pub cinf: ResId<CINF>,
 
+    #[auto_struct(derive = animation_names.len() as u32)]
     pub animation_count: u32,
     #[auto_struct(init = (animation_count as usize, info_type_count))]
-    pub animation_names: RoArray<'r, AnimationName<'r>>,
+    pub animation_names: LazyArray<'r, AnimationName<'r>>,
 
     pub pas_database: PasDatabase<'r>,
     #[auto_struct(init = info_type_count)]
@@ -66,14 +67,16 @@ pub struct CharacterInfo<'r> {
     pub unknown2: Option<u32>,
 
     #[auto_struct(init = bool_to_opt(info_type_count > 1))]
+    #[auto_struct(derive = animation_aabbs.as_ref().map(|a| a.len() as u32))]
     pub animation_aabb_count: Option<u32>,
     #[auto_struct(init = animation_aabb_count.map(|i| (i as usize, ())))]
-    pub animation_aabbs: Option<RoArray<'r, AnimationAABB<'r>>>,
+    pub animation_aabbs: Option<LazyArray<'r, AnimationAABB<'r>>>,
 
     #[auto_struct(init = bool_to_opt(info_type_count > 1))]
+    #[auto_struct(derive = effects.as_ref().map(|a| a.len() as u32))]
     pub effect_count: Option<u32>,
     #[auto_struct(init = effect_count.map(|i| (i as usize, ())))]
-    pub effects: Option<RoArray<'r, Effect<'r>>>,
+    pub effects: Option<LazyArray<'r, Effect<'r>>>,
 
     #[auto_struct(init = bool_to_opt(info_type_count > 3))]
     pub overlay_cmdl: Option<ResId<CMDL>>,
@@ -81,9 +84,10 @@ pub struct CharacterInfo<'r> {
     pub overlay_cskr: Option<ResId<CSKR>>,
 
     #[auto_struct(init = bool_to_opt(info_type_count > 4))]
+    #[auto_struct(derive = animation_indices.as_ref().map(|a| a.len() as u32))]
     pub animation_index_count: Option<u32>,
     #[auto_struct(init = animation_index_count.map(|i| (i as usize, ())))]
-    pub animation_indices: Option<RoArray<'r, u32>>,
+    pub animation_indices: Option<LazyArray<'r, u32>>,
 
     #[auto_struct(init = bool_to_opt(info_type_count > 9))]
     pub unknown3: Option<u32>,
@@ -91,9 +95,10 @@ pub struct CharacterInfo<'r> {
     pub unknown4: Option<u8>,
 
     #[auto_struct(init = bool_to_opt(info_type_count > 9))]
+    #[auto_struct(derive = animation_indexed_aabbs.as_ref().map(|a| a.len() as u32))]
     pub animation_indexed_aabb_count: Option<u32>,
     #[auto_struct(init = animation_indexed_aabb_count.map(|i| (i as usize, ())))]
-    pub animation_indexed_aabbs: Option<RoArray<'r, AnimationIndexedAABB>>,
+    pub animation_indexed_aabbs: Option<LazyArray<'r, AnimationIndexedAABB>>,
 }
 
 #[auto_struct(Readable, Writable)]
@@ -114,10 +119,11 @@ pub struct PasDatabase<'r> {
     #[auto_struct(expect = FourCC::from_bytes(b"PAS4"))]
     magic: FourCC,
 
+    #[auto_struct(derive = anim_states.len() as u32)]
     pub anim_state_count: u32,
     pub default_state: u32,
     #[auto_struct(init = (anim_state_count as usize, ()))]
-    pub anim_states: RoArray<'r, PasAnimState<'r>>,
+    pub anim_states: LazyArray<'r, PasAnimState<'r>>,
 }
 
 // PasDatabase inner details {{{
@@ -186,17 +192,18 @@ pub struct ParticleResData<'r> {
     #[auto_struct(derive = swhc_assets.len() as u32)]
     pub swhc_asset_count: u32,
     #[auto_struct(init = (swhc_asset_count as usize, ()))]
-    pub swhc_assets: RoArray<'r, ResId<SHWC>>,
+    pub swhc_assets: LazyArray<'r, ResId<SHWC>>,
 
     #[auto_struct(derive = unknowns.len() as u32)]
     pub unknown_count: u32,
     #[auto_struct(init = (unknown_count as usize, ()))]
-    pub unknowns: RoArray<'r, u32>,
+    pub unknowns: LazyArray<'r, u32>,
 
     #[auto_struct(init = bool_to_opt(info_type_count > 5))]
+    #[auto_struct(derive = elsc_assets.as_ref().map(|a| a.len() as u32))]
     pub elsc_count: Option<u32>,
     #[auto_struct(init = elsc_count.map(|i| (i as usize, ())))]
-    pub elsc_assets: Option<RoArray<'r, ResId<ELSC>>>,
+    pub elsc_assets: Option<LazyArray<'r, ResId<ELSC>>>,
 }
 
 #[auto_struct(Readable, Writable)]
@@ -219,7 +226,7 @@ pub struct Effect<'r> {
     pub name: CStr<'r>,
     pub component_count: u32,
     #[auto_struct(init = (component_count as usize, ()))]
-    pub components: RoArray<'r, EffectComponent<'r>>,
+    pub components: LazyArray<'r, EffectComponent<'r>>,
 }
 
 #[auto_struct(Readable, Writable)]
@@ -244,23 +251,26 @@ pub struct AnimationSet<'r> {
     #[auto_struct(init = (animation_count as usize, ()))]
     pub animations: LazyArray<'r, Animation<'r>>,
 
+    #[auto_struct(derive = transitions.len() as u32)]
     pub transition_count: u32,
     #[auto_struct(init = (transition_count as usize, ()))]
-    pub transitions: RoArray<'r, Transition<'r>>,
+    pub transitions: LazyArray<'r, Transition<'r>>,
     pub default_transition: MetaTransition<'r>,
 
+    #[auto_struct(derive = additive_animations.len() as u32)]
     pub additive_animation_count: u32,
     #[auto_struct(init = (additive_animation_count as usize, ()))]
-    pub additive_animations: RoArray<'r, AdditiveAnimation>,
+    pub additive_animations: LazyArray<'r, AdditiveAnimation>,
 
     // Defalut AddaptiveAnimation data
     pub fade_in: f32,
     pub fade_out: f32,
 
     #[auto_struct(init = bool_to_opt(info_count > 2))]
+    #[auto_struct(derive = half_transitions.as_ref().map(|a| a.len() as u32))]
     pub half_transition_count: Option<u32>,
     #[auto_struct(init = half_transition_count.map(|i| (i as usize, ())))]
-    pub half_transitions: Option<RoArray<'r, HalfTransition<'r>>>,
+    pub half_transitions: Option<LazyArray<'r, HalfTransition<'r>>>,
 
     #[auto_struct(init = bool_to_opt(info_count > 3))]
     #[auto_struct(derive = animation_resources.as_ref().map(|a| a.len() as u32))]
@@ -1,12 +1,29 @@
+use std::io;
+
 use auto_struct_macros::auto_struct;
 use reader_writer::{
-    generic_array::GenericArray, typenum::*, IteratorArray, LazyArray, RoArray, RoArrayIter,
+    generic_array::GenericArray, typenum::*, IteratorArray, LCow, LazyArray, Readable, Reader,
+    RoArray, RoArrayIter, Writable,
 };
 
 use crate::{res_id::*, ResId};
 
-// We don't need to modify CMDLs, so most of the details are left out.
-// We only actually care about reading out the TXTR file ids.
+/// Normals are stored as `i16`s scaled by 1/0x8000 rather than as `f32`s
+pub const CMDL_FLAG_SHORT_NORMALS: u32 = 0x2;
+/// There is an extra section of texture coordinates stored as `i16`s scaled by 1/0x8000
+pub const CMDL_FLAG_SHORT_UVS: u32 = 0x4;
+
+pub const MATERIAL_FLAG_KONST_COLORS: u32 = 0x8;
+pub const MATERIAL_FLAG_INDIRECT_TEXTURE: u32 = 0x400;
+
+fn bool_to_opt(b: bool) -> Option<()> {
+    if b {
+        Some(())
+    } else {
+        None
+    }
+}
+
 #[auto_struct(Readable, Writable)]
 #[derive(Debug, Clone)]
 pub struct Cmdl<'r> {
@@ -20,14 +37,19 @@ pub struct Cmdl<'r> {
 
     pub maab: GenericArray<f32, U6>,
 
-    pub data_section_count: u32,
-    pub material_set_count: u32,
+    #[auto_struct(derive = (material_sets.len() + data_sections.len()) as u32)]
+    data_section_count: u32,
+    #[auto_struct(derive = material_sets.len() as u32)]
+    material_set_count: u32,
 
-    // TODO: Iter derive
+    #[auto_struct(derive_from_iter = material_sets.iter()
+            .map(&|i: LCow<CmdlMaterialSet>| i.size() as u32))]
     #[auto_struct(init = (material_set_count as usize, ()))]
-    pub material_set_sizes: RoArray<'r, u32>,
+    material_set_sizes: RoArray<'r, u32>,
+    #[auto_struct(derive_from_iter = data_sections.iter()
+            .map(&|i: LCow<CmdlDataSection>| i.size() as u32))]
     #[auto_struct(init = ((data_section_count - material_set_count) as usize, ()))]
-    pub data_section_sizes: RoArray<'r, u32>,
+    data_section_sizes: RoArray<'r, u32>,
 
     #[auto_struct(pad_align = 32)]
     _pad: (),
@@ -38,19 +60,149 @@ pub struct Cmdl<'r> {
     pub data_sections: IteratorArray<'r, CmdlDataSection<'r>, RoArrayIter<'r, u32>>,
 }
 
+impl Cmdl<'_> {
+    /// Decodes the vertex attributes and surfaces held in the data sections
+    pub fn geometry(&self) -> CmdlGeometry {
+        let sections: Vec<Vec<u8>> = self
+            .data_sections
+            .iter()
+            .map(|section| section.data.iter().map(|b| *b).collect())
+            .collect();
+        CmdlGeometry::read_sections(self.flags, sections.iter().map(|s| &s[..]))
+    }
+
+    /// Replaces the data sections with `geometry`, updating the flags describing its encoding
+    pub fn set_geometry(&mut self, geometry: &CmdlGeometry) {
+        self.flags &= !(CMDL_FLAG_SHORT_NORMALS | CMDL_FLAG_SHORT_UVS);
+        self.flags |= geometry.flags();
+        *self.data_sections.as_mut_vec() = geometry
+            .write_sections()
+            .into_iter()
+            .map(|data| CmdlDataSection { data: data.into() })
+            .collect();
+    }
+}
+
 #[auto_struct(Readable, Writable)]
 #[derive(Debug, Clone)]
 pub struct CmdlMaterialSet<'r> {
-    #[auto_struct(args)]
-    size: u32,
+    #[auto_struct(args = _)]
+    _size: u32,
 
     #[auto_struct(derive = texture_ids.len() as u32)]
     pub texture_count: u32,
     #[auto_struct(init = (texture_count as usize, ()))]
     pub texture_ids: LazyArray<'r, ResId<TXTR>>,
 
-    #[auto_struct(init = (size as usize - 4 - texture_ids.size(), ()))]
-    pub remainder: RoArray<'r, u8>,
+    #[auto_struct(derive = materials.len() as u32)]
+    material_count: u32,
+    // Relative to the start of the first material
+    #[auto_struct(derive_from_iter = materials.iter()
+            .scan(0, |end, material: LCow<CmdlMaterial>| {
+                *end += material.size() as u32;
+                Some(*end)
+            }))]
+    #[auto_struct(init = (material_count as usize, ()))]
+    material_end_offsets: RoArray<'r, u32>,
+    #[auto_struct(init = (material_count as usize, ()))]
+    pub materials: LazyArray<'r, CmdlMaterial<'r>>,
+
+    #[auto_struct(pad_align = 32)]
+    _pad: (),
+}
+
+#[auto_struct(Readable, Writable)]
+#[derive(Debug, Clone)]
+pub struct CmdlMaterial<'r> {
+    pub flags: u32,
+
+    // Indices into the material set's texture ids
+    #[auto_struct(derive = textures.len() as u32)]
+    texture_count: u32,
+    #[auto_struct(init = (texture_count as usize, ()))]
+    pub textures: LazyArray<'r, u32>,
+
+    // The GX vertex descriptor used by surfaces with this material, see `CmdlVertexAttributes`
+    pub vertex_attributes: u32,
+    pub group_index: u32,
+
+    // Present when flags contains MATERIAL_FLAG_KONST_COLORS
+    #[auto_struct(derive = konst_colors.as_ref().map(|colors| colors.len() as u32))]
+    #[auto_struct(init = bool_to_opt(flags & MATERIAL_FLAG_KONST_COLORS != 0))]
+    konst_color_count: Option<u32>,
+    #[auto_struct(init = konst_color_count.map(|i| (i as usize, ())))]
+    pub konst_colors: Option<LazyArray<'r, u32>>,
+
+    pub blend_dst_factor: u16,
+    pub blend_src_factor: u16,
+
+    // Present when flags contains MATERIAL_FLAG_INDIRECT_TEXTURE
+    #[auto_struct(init = bool_to_opt(flags & MATERIAL_FLAG_INDIRECT_TEXTURE != 0))]
+    pub indirect_texture: Option<u32>,
+
+    #[auto_struct(derive = color_channels.len() as u32)]
+    color_channel_count: u32,
+    #[auto_struct(init = (color_channel_count as usize, ()))]
+    pub color_channels: LazyArray<'r, u32>,
+
+    #[auto_struct(derive = tev_stages.len() as u32)]
+    tev_stage_count: u32,
+    #[auto_struct(init = (tev_stage_count as usize, ()))]
+    pub tev_stages: LazyArray<'r, CmdlTevStage>,
+    #[auto_struct(init = (tev_stage_count as usize, ()))]
+    pub tev_stage_inputs: LazyArray<'r, CmdlTevStageInput>,
+
+    #[auto_struct(derive = tex_gens.len() as u32)]
+    tex_gen_count: u32,
+    #[auto_struct(init = (tex_gen_count as usize, ()))]
+    pub tex_gens: LazyArray<'r, u32>,
+
+    // Counts the animation count field as well as the animations
+    #[auto_struct(derive = (4 + uv_animations.iter().map(|i| i.size()).sum::<usize>()) as u32)]
+    uv_animations_size: u32,
+    #[auto_struct(derive = uv_animations.len() as u32)]
+    uv_animation_count: u32,
+    #[auto_struct(init = (uv_animation_count as usize, ()))]
+    pub uv_animations: LazyArray<'r, CmdlUvAnimation<'r>>,
+}
+
+#[auto_struct(Readable, Writable, FixedSize)]
+#[derive(Debug, Clone, PartialEq)]
+pub struct CmdlTevStage {
+    pub color_input_flags: u32,
+    pub alpha_input_flags: u32,
+    pub color_combine_flags: u32,
+    pub alpha_combine_flags: u32,
+    pub padding: u8,
+    pub konst_alpha_input: u8,
+    pub konst_color_input: u8,
+    pub rasterized_color_input: u8,
+}
+
+#[auto_struct(Readable, Writable, FixedSize)]
+#[derive(Debug, Clone, PartialEq)]
+pub struct CmdlTevStageInput {
+    pub padding: u16,
+    pub texture: u8,
+    pub tex_coord: u8,
+}
+
+fn uv_animation_param_count(mode: u32) -> usize {
+    match mode {
+        0 | 1 | 6 => 0, // Inverse modelview (with and without translation), model matrix
+        2 | 4 | 5 => 4, // Scroll, horizontal and vertical filmstrips
+        3 | 7 => 2,     // Rotation, cylinder environment
+        8 => 9,
+        _ => panic!("Unknown CMDL UV animation mode {}", mode),
+    }
+}
+
+#[auto_struct(Readable, Writable)]
+#[derive(Debug, Clone)]
+pub struct CmdlUvAnimation<'r> {
+    pub mode: u32,
+    #[auto_struct(init = (uv_animation_param_count(mode), ()))]
+    pub params: LazyArray<'r, f32>,
 }
 
 #[auto_struct(Readable, Writable)]
@@ -60,5 +212,497 @@ pub struct CmdlDataSection<'r> {
     size: u32,
 
     #[auto_struct(init = (size as usize, ()))]
-    pub remainder: RoArray<'r, u8>,
+    pub data: LazyArray<'r, u8>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CmdlNormals {
+    Float(Vec<[f32; 3]>),
+    Short(Vec<[i16; 3]>),
+}
+
+impl Default for CmdlNormals {
+    fn default() -> Self {
+        CmdlNormals::Float(vec![])
+    }
+}
+
+/// The contents of a model's data sections. World geometry in an MREA uses the same layout for
+/// each of its models.
+///
+/// Every section is padded to 32 bytes, so the attribute arrays can end with zeroed entries that
+/// no surface uses.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CmdlGeometry {
+    pub positions: Vec<[f32; 3]>,
+    pub normals: CmdlNormals,
+    pub colors: Vec<u32>,
+    pub tex_coords: Vec<[f32; 2]>,
+    pub short_tex_coords: Option<Vec<[i16; 2]>>,
+    pub surfaces: Vec<CmdlSurface>,
+}
+
+fn read_array<'r, T, const N: usize>(reader: &mut Reader<'r>) -> [T; N]
+where
+    T: Readable<'r, Args = ()> + Default + Copy,
+{
+    let mut array = [T::default(); N];
+    for item in array.iter_mut() {
+        *item = reader.read(());
+    }
+    array
+}
+
+fn read_section<'r, T, const N: usize>(data: &'r [u8]) -> Vec<[T; N]>
+where
+    T: Readable<'r, Args = ()> + Default + Copy,
+{
+    let count = data.len() / (T::fixed_size().unwrap() * N);
+    let mut reader = Reader::new(data);
+    (0..count).map(|_| read_array(&mut reader)).collect()
+}
+
+fn write_section<T: Writable, const N: usize>(items: &[[T; N]]) -> Vec<u8> {
+    let mut data = vec![];
+    for item in items.iter().flatten() {
+        item.write_to(&mut data).unwrap();
+    }
+    pad_section(&mut data);
+    data
+}
+
+fn read_bytes(reader: &mut Reader, len: usize) -> Vec<u8> {
+    let bytes = reader.truncated(len).to_vec();
+    reader.advance(len);
+    bytes
+}
+
+fn pad_section(data: &mut Vec<u8>) {
+    data.resize((data.len() + 31) & !31, 0);
+}
+
+impl CmdlGeometry {
+    pub fn read_sections<'a, I>(flags: u32, sections: I) -> CmdlGeometry
+    where
+        I: IntoIterator<Item = &'a [u8]>,
+    {
+        let mut sections = sections.into_iter();
+        let mut next = || sections.next().expect("Missing CMDL data section");
+
+        let positions = read_section(next());
+        let normals = if flags & CMDL_FLAG_SHORT_NORMALS != 0 {
+            CmdlNormals::Short(read_section(next()))
+        } else {
+            CmdlNormals::Float(read_section(next()))
+        };
+        let colors = read_section::<u32, 1>(next())
+            .into_iter()
+            .map(|[color]| color)
+            .collect();
+        let tex_coords = read_section(next());
+        let short_tex_coords = if flags & CMDL_FLAG_SHORT_UVS != 0 {
+            Some(read_section(next()))
+        } else {
+            None
+        };
+
+        let mut reader = Reader::new(next());
+        let surface_count: u32 = reader.read(());
+        let surfaces = (0..surface_count)
+            .map(|_| Reader::new(next()).read(()))
+            .collect();
+
+        CmdlGeometry {
+            positions,
+            normals,
+            colors,
+            tex_coords,
+            short_tex_coords,
+            surfaces,
+        }
+    }
+
+    pub fn write_sections(&self) -> Vec<Vec<u8>> {
+        let mut sections = vec![write_section(&self.positions)];
+        sections.push(match &self.normals {
+            CmdlNormals::Float(normals) => write_section(normals),
+            CmdlNormals::Short(normals) => write_section(normals),
+        });
+        let colors: Vec<[u32; 1]> = self.colors.iter().map(|color| [*color]).collect();
+        sections.push(write_section(&colors));
+        sections.push(write_section(&self.tex_coords));
+        if let Some(short_tex_coords) = &self.short_tex_coords {
+            sections.push(write_section(short_tex_coords));
+        }
+
+        let surfaces: Vec<Vec<u8>> = self
+            .surfaces
+            .iter()
+            .map(|surface| {
+                let mut data = vec![];
+                surface.write_to(&mut data).unwrap();
+                data
+            })
+            .collect();
+
+        // The surface offsets section lists where each surface ends, relative to the first one
+        let mut offsets = vec![];
+        (surfaces.len() as u32).write_to(&mut offsets).unwrap();
+        let mut end = 0;
+        for surface in surfaces.iter() {
+            end += surface.len() as u32;
+            end.write_to(&mut offsets).unwrap();
+        }
+        pad_section(&mut offsets);
+        sections.push(offsets);
+
+        sections.extend(surfaces);
+        sections
+    }
+
+    /// The CMDL flags describing how this geometry is encoded
+    pub fn flags(&self) -> u32 {
+        let mut flags = 0;
+        if let CmdlNormals::Short(_) = self.normals {
+            flags |= CMDL_FLAG_SHORT_NORMALS;
+        }
+        if self.short_tex_coords.is_some() {
+            flags |= CMDL_FLAG_SHORT_UVS;
+        }
+        flags
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CmdlSurface {
+    pub center: [f32; 3],
+    pub material_index: u32,
+    pub mantissa: u16,
+    // Filled in by the game when the model is loaded, always 0 on disc
+    pub parent_model_pointer: u32,
+    pub next_surface_pointer: u32,
+    pub normal: [f32; 3],
+    pub extra_data: Vec<u8>,
+    /// Raw GX display list, see `primitives` and `set_primitives`
+    pub display_list: Vec<u8>,
+}
+
+impl<'r> Readable<'r> for CmdlSurface {
+    type Args = ();
+    fn read_from(reader: &mut Reader<'r>, (): ()) -> Self {
+        let start_len = reader.len();
+        let center = read_array(reader);
+        let material_index = reader.read(());
+        let mantissa = reader.read(());
+        let display_list_size: u16 = reader.read(());
+        let parent_model_pointer = reader.read(());
+        let next_surface_pointer = reader.read(());
+        let extra_data_size: u32 = reader.read(());
+        let normal = read_array(reader);
+        let extra_data = read_bytes(reader, extra_data_size as usize);
+        let header_len = start_len - reader.len();
+        reader.advance(reader_writer::pad_bytes_count(32, header_len));
+        let display_list = read_bytes(reader, display_list_size as usize);
+
+        CmdlSurface {
+            center,
+            material_index,
+            mantissa,
+            parent_model_pointer,
+            next_surface_pointer,
+            normal,
+            extra_data,
+            display_list,
+        }
+    }
+
+    fn size(&self) -> usize {
+        let header_len = 0x2C + self.extra_data.len();
+        reader_writer::align_byte_count(32, header_len)
+            + reader_writer::align_byte_count(32, self.display_list.len())
+    }
+}
+
+impl Writable for CmdlSurface {
+    fn write_to<W: io::Write>(&self, writer: &mut W) -> io::Result<u64> {
+        let mut data = vec![];
+        for f in self.center.iter() {
+            f.write_to(&mut data)?;
+        }
+        self.material_index.write_to(&mut data)?;
+        self.mantissa.write_to(&mut data)?;
+        let display_list_size = reader_writer::align_byte_count(32, self.display_list.len());
+        (display_list_size as u16).write_to(&mut data)?;
+        self.parent_model_pointer.write_to(&mut data)?;
+        self.next_surface_pointer.write_to(&mut data)?;
+        (self.extra_data.len() as u32).write_to(&mut data)?;
+        for f in self.normal.iter() {
+            f.write_to(&mut data)?;
+        }
+        data.extend_from_slice(&self.extra_data);
+        pad_section(&mut data);
+        data.extend_from_slice(&self.display_list);
+        pad_section(&mut data);
+
+        writer.write_all(&data)?;
+        Ok(data.len() as u64)
+    }
+}
+
+impl CmdlSurface {
+    /// Decodes the display list. `vertex_attributes` must be those of the surface's material.
+    pub fn primitives(&self, vertex_attributes: u32) -> Result<Vec<CmdlPrimitive>, String> {
+        let attributes = CmdlVertexAttributes(vertex_attributes);
+        attributes.validate()?;
+
+        let mut reader = Reader::new(&self.display_list);
+        let mut primitives = vec![];
+        while !reader.is_empty() {
+            let opcode: u8 = reader.read(());
+            if opcode == 0 {
+                // NOPs pad the list out to 32 bytes
+                continue;
+            }
+            let kind = GxPrimitiveKind::from_opcode(opcode)
+                .ok_or_else(|| format!("Unknown GX display list opcode 0x{:X}", opcode))?;
+            if reader.len() < 2 {
+                return Err("Truncated GX display list".to_string());
+            }
+            let vertex_count: u16 = reader.read(());
+            if reader.len() < vertex_count as usize * attributes.vertex_size() {
+                return Err("Truncated GX display list".to_string());
+            }
+            let vertices = (0..vertex_count)
+                .map(|_| attributes.read_vertex(&mut reader))
+                .collect();
+            primitives.push(CmdlPrimitive {
+                kind,
+                vertex_format: opcode & 0x7,
+                vertices,
+            });
+        }
+
+        Ok(primitives)
+    }
+
+    /// Encodes `primitives` as the display list. `vertex_attributes` must be those of the
+    /// surface's material.
+    pub fn set_primitives(
+        &mut self,
+        vertex_attributes: u32,
+        primitives: &[CmdlPrimitive],
+    ) -> Result<(), String> {
+        let attributes = CmdlVertexAttributes(vertex_attributes);
+        attributes.validate()?;
+
+        let mut data = vec![];
+        for primitive in primitives {
+            if primitive.vertices.len() > u16::MAX as usize {
+                return Err(format!(
+                    "Primitive has {} vertices, the most a display list can hold is {}",
+                    primitive.vertices.len(),
+                    u16::MAX
+                ));
+            }
+            (primitive.kind as u8 | (primitive.vertex_format & 0x7))
+                .write_to(&mut data)
+                .unwrap();
+            (primitive.vertices.len() as u16)
+                .write_to(&mut data)
+                .unwrap();
+            for vertex in primitive.vertices.iter() {
+                attributes.write_vertex(vertex, &mut data)?;
+            }
+        }
+        pad_section(&mut data);
+        if data.len() > u16::MAX as usize {
+            return Err(format!("Display list is too large ({} bytes)", data.len()));
+        }
+
+        self.display_list = data;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum GxPrimitiveKind {
+    Quads = 0x80,
+    Triangles = 0x90,
+    TriangleStrip = 0x98,
+    TriangleFan = 0xA0,
+    Lines = 0xA8,
+    LineStrip = 0xB0,
+    Points = 0xB8,
+}
+
+impl GxPrimitiveKind {
+    pub fn from_opcode(opcode: u8) -> Option<Self> {
+        Some(match opcode & 0xF8 {
+            0x80 => GxPrimitiveKind::Quads,
+            0x90 => GxPrimitiveKind::Triangles,
+            0x98 => GxPrimitiveKind::TriangleStrip,
+            0xA0 => GxPrimitiveKind::TriangleFan,
+            0xA8 => GxPrimitiveKind::Lines,
+            0xB0 => GxPrimitiveKind::LineStrip,
+            0xB8 => GxPrimitiveKind::Points,
+            _ => return None,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CmdlPrimitive {
+    pub kind: GxPrimitiveKind,
+    pub vertex_format: u8,
+    pub vertices: Vec<CmdlVertex>,
+}
+
+impl CmdlPrimitive {
+    /// Splits the primitive into triangles, preserving the winding order. Lines and points
+    /// yield nothing.
+    pub fn triangles(&self) -> Vec<[&CmdlVertex; 3]> {
+        let v = &self.vertices;
+        match self.kind {
+            GxPrimitiveKind::Triangles => {
+                v.chunks_exact(3).map(|t| [&t[0], &t[1], &t[2]]).collect()
+            }
+            GxPrimitiveKind::TriangleStrip => (2..v.len())
+                .map(|i| {
+                    if i % 2 == 0 {
+                        [&v[i - 2], &v[i - 1], &v[i]]
+                    } else {
+                        [&v[i - 1], &v[i - 2], &v[i]]
+                    }
+                })
+                .collect(),
+            GxPrimitiveKind::TriangleFan => {
+                (2..v.len()).map(|i| [&v[0], &v[i - 1], &v[i]]).collect()
+            }
+            GxPrimitiveKind::Quads => v
+                .chunks_exact(4)
+                .flat_map(|q| [[&q[0], &q[1], &q[2]], [&q[0], &q[2], &q[3]]])
+                .collect(),
+            _ => vec![],
+        }
+    }
+}
+
+/// A vertex in a display list. Each attribute is an index into the matching array of the
+/// model's geometry, except for the matrix indices which are stored directly.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CmdlVertex {
+    pub matrix_index: Option<u8>,
+    pub tex_matrix_indices: [Option<u8>; 7],
+    pub position: Option<u16>,
+    pub normal: Option<u16>,
+    pub colors: [Option<u16>; 2],
+    pub tex_coords: [Option<u16>; 8],
+}
+
+/// A material's vertex descriptor. Bits 0-23 hold two bits for each of the position, normal,
+/// 2 colors and 8 texture coordinates: 0 when absent, 2 for an 8-bit index and 3 for a 16-bit
+/// index. Bit 24 enables the position matrix index, and bits 25-31 the texture matrix indices.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CmdlVertexAttributes(pub u32);
+
+impl CmdlVertexAttributes {
+    const INDEXED_ATTRIBUTE_COUNT: usize = 12;
+
+    fn index_size(&self, attribute: usize) -> usize {
+        match (self.0 >> (attribute * 2)) & 3 {
+            0 => 0,
+            2 => 1,
+            3 => 2,
+            _ => unreachable!(),
+        }
+    }
+
+    fn has_matrix_index(&self, index: usize) -> bool {
+        self.0 & (1 << (24 + index)) != 0
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        for attribute in 0..Self::INDEXED_ATTRIBUTE_COUNT {
+            if (self.0 >> (attribute * 2)) & 3 == 1 {
+                return Err(format!(
+                    "Vertex attributes 0x{:X} use direct data, which CMDLs don't support",
+                    self.0
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn vertex_size(&self) -> usize {
+        let indices: usize = (0..Self::INDEXED_ATTRIBUTE_COUNT)
+            .map(|attribute| self.index_size(attribute))
+            .sum();
+        indices + (0..8).filter(|i| self.has_matrix_index(*i)).count()
+    }
+
+    fn read_vertex(&self, reader: &mut Reader) -> CmdlVertex {
+        let mut matrix_indices = [None; 8];
+        for (i, matrix_index) in matrix_indices.iter_mut().enumerate() {
+            if self.has_matrix_index(i) {
+                *matrix_index = Some(reader.read::<u8>(()));
+            }
+        }
+
+        let mut indices = [None; Self::INDEXED_ATTRIBUTE_COUNT];
+        for (attribute, index) in indices.iter_mut().enumerate() {
+            *index = match self.index_size(attribute) {
+                1 => Some(reader.read::<u8>(()) as u16),
+                2 => Some(reader.read::<u16>(())),
+                _ => None,
+            };
+        }
+
+        let mut tex_matrix_indices = [None; 7];
+        tex_matrix_indices.copy_from_slice(&matrix_indices[1..]);
+        let mut tex_coords = [None; 8];
+        tex_coords.copy_from_slice(&indices[4..]);
+        CmdlVertex {
+            matrix_index: matrix_indices[0],
+            tex_matrix_indices,
+            position: indices[0],
+            normal: indices[1],
+            colors: [indices[2], indices[3]],
+            tex_coords,
+        }
+    }
+
+    fn write_vertex(&self, vertex: &CmdlVertex, data: &mut Vec<u8>) -> Result<(), String> {
+        let matrix_indices = [vertex.matrix_index]
+            .into_iter()
+            .chain(vertex.tex_matrix_indices.iter().copied());
+        for (i, matrix_index) in matrix_indices.enumerate() {
+            match (self.has_matrix_index(i), matrix_index) {
+                (true, Some(matrix_index)) => matrix_index.write_to(data).unwrap(),
+                (false, None) => 0,
+                _ => return Err(format!("Vertex {:?} doesn't match its attributes", vertex)),
+            };
+        }
+
+        let indices = [
+            vertex.position,
+            vertex.normal,
+            vertex.colors[0],
+            vertex.colors[1],
+        ]
+        .into_iter()
+        .chain(vertex.tex_coords.iter().copied());
+        for (attribute, index) in indices.enumerate() {
+            match (self.index_size(attribute), index) {
+                (0, None) => 0,
+                (1, Some(index)) if index <= u8::MAX as u16 => {
+                    (index as u8).write_to(data).unwrap()
+                }
+                (2, Some(index)) => index.write_to(data).unwrap(),
+                _ => return Err(format!("Vertex {:?} doesn't match its attributes", vertex)),
+            };
+        }
+
+        Ok(())
+    }
 }
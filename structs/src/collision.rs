@@ -0,0 +1,333 @@
+use auto_struct_macros::auto_struct;
+use reader_writer::{generic_array::GenericArray, typenum::*, Readable, RoArray};
+
+// Collision material flags
+pub const COLLISION_MATERIAL_FLIPPED: u32 = 1 << 25;
+pub const COLLISION_MATERIAL_CEILING: u32 = 1 << 29;
+pub const COLLISION_MATERIAL_WALL: u32 = 1 << 30;
+pub const COLLISION_MATERIAL_FLOOR: u32 = 1 << 31;
+
+const OCTREE_NODE_EMPTY: u32 = 0;
+const OCTREE_NODE_BRANCH: u32 = 1;
+const OCTREE_NODE_LEAF: u32 = 2;
+
+#[auto_struct(Readable, Writable)]
+#[derive(Debug, Clone)]
+pub struct AreaCollision<'r> {
+    pub unknown: u32,
+    pub size: u32,
+
+    #[auto_struct(expect = 0xDEAFBABE)]
+    magic: u32,
+    #[auto_struct(expect = 3)]
+    version: u32,
+
+    pub bounding_box: GenericArray<f32, U6>,
+    pub octree_root_type: u32,
+
+    #[auto_struct(derive = octree.len() as u32)]
+    octree_size: u32,
+    #[auto_struct(init = (octree_size as usize, ()))]
+    pub octree: RoArray<'r, u8>,
+
+    #[auto_struct(derive = materials.len() as u32)]
+    material_count: u32,
+    #[auto_struct(init = (material_count as usize, ()))]
+    pub materials: RoArray<'r, u32>,
+
+    #[auto_struct(derive = vertex_materials.len() as u32)]
+    vertex_material_count: u32,
+    #[auto_struct(init = (vertex_material_count as usize, ()))]
+    pub vertex_materials: RoArray<'r, u8>,
+
+    #[auto_struct(derive = edge_materials.len() as u32)]
+    edge_material_count: u32,
+    #[auto_struct(init = (edge_material_count as usize, ()))]
+    pub edge_materials: RoArray<'r, u8>,
+
+    #[auto_struct(derive = triangle_materials.len() as u32)]
+    triangle_material_count: u32,
+    #[auto_struct(init = (triangle_material_count as usize, ()))]
+    pub triangle_materials: RoArray<'r, u8>,
+
+    #[auto_struct(derive = edges.len() as u32)]
+    edge_count: u32,
+    #[auto_struct(init = (edge_count as usize, ()))]
+    pub edges: RoArray<'r, GenericArray<u16, U2>>,
+
+    // Three edge indices per triangle
+    #[auto_struct(derive = triangles.len() as u32 * 3)]
+    triangle_edge_count: u32,
+    #[auto_struct(init = (triangle_edge_count as usize / 3, ()))]
+    pub triangles: RoArray<'r, GenericArray<u16, U3>>,
+
+    #[auto_struct(derive = vertices.len() as u32)]
+    vertex_count: u32,
+    #[auto_struct(init = (vertex_count as usize, ()))]
+    pub vertices: RoArray<'r, GenericArray<f32, U3>>,
+
+    #[auto_struct(pad_align = 32)]
+    _pad: (),
+}
+
+#[derive(Debug, Clone)]
+pub enum OctreeNode {
+    Empty,
+    Branch {
+        bounds: [f32; 6],
+        // Indexed by octant: bit 0 picks the upper half in X, bit 1 in Y and bit 2 in Z
+        children: Vec<OctreeNode>,
+    },
+    Leaf {
+        bounds: [f32; 6],
+        triangles: Vec<u16>,
+    },
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct CollisionTriangle {
+    pub vertices: [[f32; 3]; 3],
+    pub material: u32,
+}
+
+/// The triangles of an area's collision, in world space, ready to be queried
+#[derive(Debug, Clone)]
+pub struct CollisionMesh {
+    pub bounds: [f32; 6],
+    pub triangles: Vec<CollisionTriangle>,
+}
+
+impl AreaCollision<'_> {
+    /// Parses the AABB tree. Branches only store the layout of their children, their bounds are
+    /// the octant of the parent's bounds.
+    pub fn octree(&self) -> Result<OctreeNode, String> {
+        let bytes: Vec<u8> = self.octree.iter().collect();
+        read_octree_node(&bytes, 0, self.octree_root_type, self.bounding_box.into())
+    }
+
+    pub fn mesh(&self) -> Result<CollisionMesh, String> {
+        let materials: Vec<u32> = self.materials.iter().collect();
+        let edges: Vec<_> = self.edges.iter().collect();
+        let vertices: Vec<[f32; 3]> = self.vertices.iter().map(Into::into).collect();
+        let vertex = |i: u16| {
+            vertices
+                .get(i as usize)
+                .copied()
+                .ok_or_else(|| format!("Collision vertex {} is out of range", i))
+        };
+        let edge = |i: u16| {
+            edges
+                .get(i as usize)
+                .map(|e| (e[0], e[1]))
+                .ok_or_else(|| format!("Collision edge {} is out of range", i))
+        };
+
+        let mut triangles = Vec::with_capacity(self.triangles.len());
+        for (i, (tri, material)) in self
+            .triangles
+            .iter()
+            .zip(self.triangle_materials.iter())
+            .enumerate()
+        {
+            let material = *materials
+                .get(material as usize)
+                .ok_or_else(|| format!("Collision triangle {} has no material", i))?;
+            let e0 = edge(tri[0])?;
+            let e1 = edge(tri[1])?;
+            let third = if e1.0 != e0.0 && e1.0 != e0.1 {
+                e1.0
+            } else {
+                e1.1
+            };
+
+            // Flipped triangles list their first edge the other way around
+            let (a, b) = if material & COLLISION_MATERIAL_FLIPPED != 0 {
+                (e0.0, e0.1)
+            } else {
+                (e0.1, e0.0)
+            };
+            triangles.push(CollisionTriangle {
+                vertices: [vertex(a)?, vertex(b)?, vertex(third)?],
+                material,
+            });
+        }
+
+        Ok(CollisionMesh {
+            bounds: self.bounding_box.into(),
+            triangles,
+        })
+    }
+}
+
+fn read_u16(bytes: &[u8], offset: usize) -> Result<u16, String> {
+    bytes
+        .get(offset..offset + 2)
+        .map(|b| u16::from_be_bytes([b[0], b[1]]))
+        .ok_or_else(|| format!("Collision octree ends before offset 0x{:X}", offset))
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> Result<u32, String> {
+    bytes
+        .get(offset..offset + 4)
+        .map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+        .ok_or_else(|| format!("Collision octree ends before offset 0x{:X}", offset))
+}
+
+fn read_octree_node(
+    bytes: &[u8],
+    offset: usize,
+    type_: u32,
+    bounds: [f32; 6],
+) -> Result<OctreeNode, String> {
+    match type_ {
+        OCTREE_NODE_EMPTY => Ok(OctreeNode::Empty),
+        OCTREE_NODE_BRANCH => {
+            // u16 child types, u16 padding, then one offset per child from the end of this header
+            let child_types = read_u16(bytes, offset)?;
+            let mut children = Vec::with_capacity(8);
+            for i in 0..8 {
+                let child_type = (child_types as u32 >> (i * 2)) & 3;
+                let child_offset = offset + 36 + read_u32(bytes, offset + 4 + i * 4)? as usize;
+                children.push(read_octree_node(
+                    bytes,
+                    child_offset,
+                    child_type,
+                    octant(&bounds, i),
+                )?);
+            }
+            Ok(OctreeNode::Branch { bounds, children })
+        }
+        OCTREE_NODE_LEAF => {
+            let mut leaf_bounds = [0.0; 6];
+            for (i, bound) in leaf_bounds.iter_mut().enumerate() {
+                *bound = f32::from_bits(read_u32(bytes, offset + i * 4)?);
+            }
+            let count = read_u16(bytes, offset + 24)? as usize;
+            let triangles = (0..count)
+                .map(|i| read_u16(bytes, offset + 26 + i * 2))
+                .collect::<Result<_, _>>()?;
+            Ok(OctreeNode::Leaf {
+                bounds: leaf_bounds,
+                triangles,
+            })
+        }
+        _ => Err(format!("Unknown collision octree node type {}", type_)),
+    }
+}
+
+fn octant(bounds: &[f32; 6], index: usize) -> [f32; 6] {
+    let mut result = *bounds;
+    for axis in 0..3 {
+        let mid = (bounds[axis] + bounds[axis + 3]) / 2.0;
+        if index & (1 << axis) != 0 {
+            result[axis] = mid;
+        } else {
+            result[axis + 3] = mid;
+        }
+    }
+    result
+}
+
+fn sub(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+fn dot(a: [f32; 3], b: [f32; 3]) -> f32 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn cross(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+impl CollisionTriangle {
+    /// Not normalized. Points away from the solid side of the surface.
+    pub fn normal(&self) -> [f32; 3] {
+        let [a, b, c] = self.vertices;
+        cross(sub(b, a), sub(c, a))
+    }
+
+    /// Distance along `direction` at which the ray hits this triangle (Möller–Trumbore)
+    pub fn intersect(&self, origin: [f32; 3], direction: [f32; 3]) -> Option<f32> {
+        let [a, b, c] = self.vertices;
+        let ab = sub(b, a);
+        let ac = sub(c, a);
+        let p = cross(direction, ac);
+        let det = dot(ab, p);
+        if det.abs() < 1e-8 {
+            return None;
+        }
+
+        let t_vec = sub(origin, a);
+        let u = dot(t_vec, p) / det;
+        if !(0.0..=1.0).contains(&u) {
+            return None;
+        }
+        let q = cross(t_vec, ab);
+        let v = dot(direction, q) / det;
+        if v < 0.0 || u + v > 1.0 {
+            return None;
+        }
+
+        let t = dot(ac, q) / det;
+        (t > 1e-4).then_some(t)
+    }
+}
+
+// Slightly skewed so rays don't run exactly along axis-aligned walls and edges
+const PROBE_DIRECTIONS: [[f32; 3]; 6] = [
+    [1.0, 0.013, 0.021],
+    [-1.0, -0.017, 0.011],
+    [0.019, 1.0, -0.014],
+    [-0.012, -1.0, 0.016],
+    [0.015, -0.011, 1.0],
+    [-0.018, 0.014, -1.0],
+];
+
+impl CollisionMesh {
+    pub fn contains(&self, point: [f32; 3]) -> bool {
+        (0..3).all(|i| self.bounds[i] <= point[i] && point[i] <= self.bounds[i + 3])
+    }
+
+    /// The closest triangle hit by the ray, with the distance to it
+    pub fn raycast(
+        &self,
+        origin: [f32; 3],
+        direction: [f32; 3],
+    ) -> Option<(f32, &CollisionTriangle)> {
+        self.triangles
+            .iter()
+            .filter_map(|tri| tri.intersect(origin, direction).map(|t| (t, tri)))
+            .min_by(|(a, _), (b, _)| a.total_cmp(b))
+    }
+
+    /// The height of the closest floor below `point`, ignoring the undersides of surfaces
+    pub fn floor_below(&self, point: [f32; 3]) -> Option<f32> {
+        self.triangles
+            .iter()
+            .filter(|tri| tri.normal()[2] > 0.0)
+            .filter_map(|tri| tri.intersect(point, [0.0, 0.0, -1.0]))
+            .min_by(|a, b| a.total_cmp(b))
+            .map(|t| point[2] - t)
+    }
+
+    /// Whether `point` is inside solid geometry. Rays are cast in several directions and the point
+    /// counts as inside when most of the rays that hit something hit the back of a surface. Rays
+    /// escaping through door frames and other openings don't vote.
+    pub fn is_inside_geometry(&self, point: [f32; 3]) -> bool {
+        let mut inside = 0;
+        let mut outside = 0;
+        for direction in PROBE_DIRECTIONS {
+            match self.raycast(point, direction) {
+                Some((_, tri)) if dot(tri.normal(), direction) > 0.0 => inside += 1,
+                Some(_) => outside += 1,
+                None => (),
+            }
+        }
+        inside > outside
+    }
+}
@@ -0,0 +1,305 @@
+use std::{borrow::Cow, collections::HashSet};
+
+use reader_writer::{FourCC, Reader};
+
+use crate::{
+    Ancs, Cmdl, Dependency, Evnt, Font, Frme, FrmeWidgetKind, Kssm, ParticleSystem, Scan, Strg,
+};
+
+fn dep(asset_id: u32, asset_type: &[u8; 4]) -> Option<Dependency> {
+    if asset_id == 0 || asset_id == 0xFFFFFFFF {
+        return None;
+    }
+    Some(Dependency {
+        asset_id,
+        asset_type: asset_type.into(),
+    })
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    data.get(offset..offset + 4)
+        .map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+}
+
+// The particle formats are a stream of four character property tags followed by their values.
+// DPSC, and any particle `ParticleSystem` can't parse, is scanned for the properties that hold
+// asset ids instead:
+//   <tag> CNST <id>              for child generators, models, swooshes, electric effects, etc
+//   <tag> CNST|ATEX CNST <id>    for textures
+struct ParticleRefs {
+    assets: &'static [(&'static [u8; 4], &'static [u8; 4])],
+    textures: &'static [&'static [u8; 4]],
+}
+
+const PART_REFS: ParticleRefs = ParticleRefs {
+    assets: &[
+        (b"ICTS", b"PART"),
+        (b"IDTS", b"PART"),
+        (b"IITS", b"PART"),
+        (b"PMDL", b"CMDL"),
+        (b"SSWH", b"SWHC"),
+        (b"SELC", b"ELSC"),
+    ],
+    textures: &[b"TEXR", b"TIND"],
+};
+
+const ELSC_REFS: ParticleRefs = ParticleRefs {
+    assets: &[(b"SSWH", b"SWHC"), (b"GPSM", b"PART"), (b"EPSM", b"PART")],
+    textures: &[],
+};
+
+const SWHC_REFS: ParticleRefs = ParticleRefs {
+    assets: &[],
+    textures: &[b"TEXR"],
+};
+
+const WPSC_REFS: ParticleRefs = ParticleRefs {
+    assets: &[
+        (b"APSM", b"PART"),
+        (b"APS2", b"PART"),
+        (b"ASW1", b"SWHC"),
+        (b"ASW2", b"SWHC"),
+        (b"ASW3", b"SWHC"),
+        (b"OHEF", b"CMDL"),
+        (b"COLR", b"CRSC"),
+    ],
+    textures: &[],
+};
+
+const DPSC_REFS: ParticleRefs = ParticleRefs {
+    assets: &[(b"DMDL", b"CMDL")],
+    textures: &[b"1TEX", b"2TEX"],
+};
+
+fn particle_dependencies(data: &[u8], refs: &ParticleRefs, deps: &mut Vec<Dependency>) {
+    for i in 0..data.len().saturating_sub(8) {
+        let tag = &data[i..i + 4];
+        let class = &data[i + 4..i + 8];
+
+        if tag == b"KSSM" && class == b"CNST" {
+            let kssm: Kssm = Reader::new(&data[i + 8..]).read(());
+            for list in kssm.lists.iter() {
+                for item in list.items.iter() {
+                    deps.extend(dep(item.part.to_u32(), b"PART"));
+                }
+            }
+        } else if refs.textures.iter().any(|t| &t[..] == tag) {
+            if (class == b"CNST" || class == b"ATEX")
+                && data.get(i + 8..i + 12) == Some(&b"CNST"[..])
+            {
+                if let Some(id) = read_u32(data, i + 12) {
+                    deps.extend(dep(id, b"TXTR"));
+                }
+            }
+        } else if let Some((_, fourcc)) = refs.assets.iter().find(|(t, _)| &t[..] == tag) {
+            if class == b"CNST" {
+                if let Some(id) = read_u32(data, i + 8) {
+                    deps.extend(dep(id, fourcc));
+                }
+            }
+        }
+    }
+}
+
+// CRSC maps every collision material to a generator, decal or sound. Sounds aren't assets and
+// decals are the only generators that aren't PARTs.
+fn crsc_dependencies(data: &[u8], deps: &mut Vec<Dependency>) {
+    for i in 0..data.len().saturating_sub(12) {
+        let tag = &data[i..i + 4];
+        if &data[i + 4..i + 8] != b"CNST" || !tag.iter().all(|c| c.is_ascii_alphanumeric()) {
+            continue;
+        }
+        if tag.ends_with(b"SFX") || tag == b"RNGE" || tag == b"FOFF" {
+            continue;
+        }
+
+        let fourcc = if tag.ends_with(b"DL") || matches!(tag, b"3MUD" | b"3LAV" | b"3SAN") {
+            b"DPSC"
+        } else {
+            b"PART"
+        };
+        if let Some(id) = read_u32(data, i + 8) {
+            deps.extend(dep(id, fourcc));
+        }
+    }
+}
+
+// Text can embed images and fonts, e.g. "&image=SI,0.70,0.68,46434ED3;" or "&font=C29C51F1;"
+fn strg_dependencies(strg: &Strg, deps: &mut Vec<Dependency>) {
+    for table in strg.string_tables.iter() {
+        for string in table.strings.iter() {
+            let string: String = string.chars().collect();
+            for (tag, fourcc) in [("&image=", b"TXTR"), ("&font=", b"FONT")] {
+                for (start, _) in string.match_indices(tag) {
+                    let args = &string[start + tag.len()..];
+                    let args = &args[..args.find(';').unwrap_or(args.len())];
+                    for arg in args.split(',') {
+                        if arg.len() != 8 {
+                            continue;
+                        }
+                        if let Ok(id) = u32::from_str_radix(arg, 16) {
+                            deps.extend(dep(id, fourcc));
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn ancs_dependencies(ancs: &Ancs, deps: &mut Vec<Dependency>) {
+    for char_info in ancs.char_set.char_info.iter() {
+        deps.extend(dep(char_info.cmdl.to_u32(), b"CMDL"));
+        deps.extend(dep(char_info.cskr.to_u32(), b"CSKR"));
+        deps.extend(dep(char_info.cinf.to_u32(), b"CINF"));
+        for part in char_info.particles.part_assets.iter() {
+            deps.extend(dep(*part, b"PART"));
+        }
+        for swhc in char_info.particles.swhc_assets.iter() {
+            deps.extend(dep(swhc.to_u32(), b"SWHC"));
+        }
+        for elsc in char_info
+            .particles
+            .elsc_assets
+            .iter()
+            .flat_map(|a| a.iter())
+        {
+            deps.extend(dep(elsc.to_u32(), b"ELSC"));
+        }
+        for effect in char_info.effects.iter().flat_map(|e| e.iter()) {
+            for comp in effect.components.iter() {
+                deps.extend(dep(comp.file_id, comp.type_.as_bytes()));
+            }
+        }
+        if let Some(cmdl) = char_info.overlay_cmdl {
+            deps.extend(dep(cmdl.to_u32(), b"CMDL"));
+        }
+        if let Some(cskr) = char_info.overlay_cskr {
+            deps.extend(dep(cskr.to_u32(), b"CSKR"));
+        }
+    }
+    for anim_resource in ancs
+        .anim_set
+        .animation_resources
+        .iter()
+        .flat_map(|a| a.iter())
+    {
+        deps.extend(dep(anim_resource.anim.to_u32(), b"ANIM"));
+        deps.extend(dep(anim_resource.evnt.to_u32(), b"EVNT"));
+    }
+}
+
+/// Lists the resources directly referenced by a resource. `data` must be the decompressed
+/// contents of a well-formed resource: like any other `Reader::read`, parsing a truncated or
+/// corrupt CMDL, ANCS, EVNT, SCAN, STRG, FRME or FONT panics. Particles are the exception, as
+/// anything `ParticleSystem` can't parse is scanned instead. Formats that can't reference other
+/// resources yield nothing.
+pub fn direct_dependencies(fourcc: FourCC, data: &[u8]) -> Vec<Dependency> {
+    let mut deps = vec![];
+    match fourcc.as_bytes() {
+        b"CMDL" => {
+            let cmdl: Cmdl = Reader::new(data).read(());
+            for material_set in cmdl.material_sets.iter() {
+                for id in material_set.texture_ids.iter() {
+                    deps.extend(dep(id.to_u32(), b"TXTR"));
+                }
+            }
+        }
+        b"ANCS" => ancs_dependencies(&Reader::new(data).read(()), &mut deps),
+        b"EVNT" => {
+            let evnt: Evnt = Reader::new(data).read(());
+            for effect in evnt.effect_events.iter() {
+                deps.extend(dep(effect.effect_file_id, effect.effect_type.as_bytes()));
+            }
+        }
+        b"PART" | b"ELSC" | b"SWHC" | b"WPSC" | b"CRSC" => match ParticleSystem::parse(data) {
+            Ok(system) => deps.extend(system.dependencies()),
+            Err(_) => match fourcc.as_bytes() {
+                b"PART" => particle_dependencies(data, &PART_REFS, &mut deps),
+                b"ELSC" => particle_dependencies(data, &ELSC_REFS, &mut deps),
+                b"SWHC" => particle_dependencies(data, &SWHC_REFS, &mut deps),
+                b"WPSC" => particle_dependencies(data, &WPSC_REFS, &mut deps),
+                _ => crsc_dependencies(data, &mut deps),
+            },
+        },
+        b"DPSC" => particle_dependencies(data, &DPSC_REFS, &mut deps),
+        b"SCAN" => {
+            let scan: Scan = Reader::new(data).read(());
+            deps.extend(dep(scan.frme.to_u32(), b"FRME"));
+            deps.extend(dep(scan.strg.to_u32(), b"STRG"));
+            for image in scan.images.iter() {
+                deps.extend(dep(image.txtr.to_u32(), b"TXTR"));
+            }
+        }
+        b"STRG" => strg_dependencies(&Reader::new(data).read(()), &mut deps),
+        b"FRME" => {
+            let frme: Frme = Reader::new(data).read(());
+            for widget in frme.widgets.iter() {
+                match &widget.kind {
+                    FrmeWidgetKind::Model(model) => deps.extend(dep(model.model.to_u32(), b"CMDL")),
+                    FrmeWidgetKind::Energy(energy) => {
+                        deps.extend(dep(energy.txtr.to_u32(), b"TXTR"))
+                    }
+                    FrmeWidgetKind::Image(image) => {
+                        deps.extend(dep(image.texture.to_u32(), b"TXTR"))
+                    }
+                    FrmeWidgetKind::TextPane(text_pane) => {
+                        deps.extend(dep(text_pane.font.to_u32(), b"FONT"));
+                        if let Some(jpn_font) = text_pane.jpn_font {
+                            deps.extend(dep(jpn_font.to_u32(), b"FONT"));
+                        }
+                    }
+                    _ => (),
+                }
+            }
+        }
+        b"FONT" => {
+            let font: Font = Reader::new(data).read(());
+            deps.extend(dep(font.txtr.to_u32(), b"TXTR"));
+        }
+        _ => (),
+    }
+
+    let mut seen = HashSet::new();
+    deps.retain(|d| seen.insert((d.asset_id, d.asset_type)));
+    deps
+}
+
+/// Computes every resource needed to load `roots`, including the roots themselves. `data` is
+/// asked for the decompressed contents of each resource found, which must be well-formed (see
+/// `direct_dependencies`). Resources it returns `None` for are still part of the closure, but
+/// aren't searched any further.
+///
+/// Dependencies are listed before the resources that need them, which is the order the game's
+/// own MLVL dependency layers use.
+pub fn dependency_closure<'a, I, F>(roots: I, mut data: F) -> Vec<Dependency>
+where
+    I: IntoIterator<Item = Dependency>,
+    F: FnMut(&Dependency) -> Option<Cow<'a, [u8]>>,
+{
+    fn visit<'a, F>(
+        dep: Dependency,
+        data: &mut F,
+        seen: &mut HashSet<(u32, FourCC)>,
+        closure: &mut Vec<Dependency>,
+    ) where
+        F: FnMut(&Dependency) -> Option<Cow<'a, [u8]>>,
+    {
+        if !seen.insert((dep.asset_id, dep.asset_type)) {
+            return;
+        }
+        if let Some(bytes) = data(&dep) {
+            for child in direct_dependencies(dep.asset_type, &bytes) {
+                visit(child, data, seen, closure);
+            }
+        }
+        closure.push(dep);
+    }
+
+    let mut seen = HashSet::new();
+    let mut closure = vec![];
+    for root in roots {
+        visit(root, &mut data, &mut seen, &mut closure);
+    }
+    closure
+}
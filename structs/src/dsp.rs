@@ -0,0 +1,25 @@
+use auto_struct_macros::auto_struct;
+use reader_writer::generic_array::{typenum::*, GenericArray};
+
+// Header of a standard .dsp file, followed by the ADPCM frames of a single channel. Loop and
+// current addresses are in nibbles, counting the header byte of each frame.
+#[auto_struct(Readable, Writable, FixedSize)]
+#[derive(Debug, Clone)]
+pub struct DspHeader {
+    pub sample_count: u32,
+    pub nibble_count: u32,
+    pub sample_rate: u32,
+    pub loop_flag: u16,
+    #[auto_struct(expect = 0)]
+    format: u16,
+    pub loop_start_address: u32,
+    pub loop_end_address: u32,
+    pub current_address: u32,
+    pub coefs: GenericArray<i16, U16>,
+    pub gain: u16,
+    pub initial_predictor_scale: u16,
+    pub initial_history: GenericArray<i16, U2>,
+    pub loop_predictor_scale: u16,
+    pub loop_history: GenericArray<i16, U2>,
+    pub padding: GenericArray<u16, U11>,
+}
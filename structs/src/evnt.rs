@@ -15,7 +15,7 @@ pub struct Evnt<'r> {
     #[auto_struct(derive = user_events.len() as u32)]
     pub user_event_count: u32,
     #[auto_struct(init = (user_event_count as usize, ()))]
-    pub user_events: RoArray<'r, UserEvent<'r>>,
+    pub user_events: LazyArray<'r, UserEvent<'r>>,
 
     #[auto_struct(derive = effect_events.len() as u32)]
     pub effect_event_count: u32,
@@ -26,7 +26,7 @@ pub struct Evnt<'r> {
     #[auto_struct(derive = sound_events.as_ref().map(|a| a.len() as u32))]
     pub sound_event_count: Option<u32>,
     #[auto_struct(init = sound_event_count.map(|i| (i as usize, ())))]
-    pub sound_events: Option<RoArray<'r, SoundEvent<'r>>>,
+    pub sound_events: Option<LazyArray<'r, SoundEvent<'r>>>,
 
     #[auto_struct(pad_align = 32)]
     _pad: (),
@@ -1,5 +1,5 @@
 use auto_struct_macros::auto_struct;
-use reader_writer::{CStr, FourCC, RoArray};
+use reader_writer::{CStr, FourCC, LazyArray};
 
 use crate::{res_id::*, ResId};
 
@@ -23,40 +23,42 @@ pub struct Font<'r> {
 
     pub name: CStr<'r>,
     pub txtr: ResId<TXTR>,
+    // How the texture's texel values are split into layers, 0 for a single layer with only a fill
+    // and 1 for a single layer with a fill and an outline
     pub txtr_fmt: u32,
 
     #[auto_struct(derive = glyphs.len() as u32)]
     glyph_count: u32,
     #[auto_struct(init = (glyph_count as usize, ()))]
-    glyphs: RoArray<'r, FontGlyph>,
+    pub glyphs: LazyArray<'r, FontGlyph>,
 
     #[auto_struct(derive = kernings.len() as u32)]
     kerning_count: u32,
     #[auto_struct(init = (kerning_count as usize, ()))]
-    kernings: RoArray<'r, FontKerning>,
+    pub kernings: LazyArray<'r, FontKerning>,
 }
 
 #[auto_struct(Readable, Writable, FixedSize)]
 #[derive(Debug, Clone)]
 pub struct FontGlyph {
-    utf16_char: u16,
-    left_uv_coordinate: f32,
-    top_uv_coordinate: f32,
-    right_uv_coordinate: f32,
-    bottom_uv_coordinate: f32,
-    left_padding: u32,
-    print_head_advance: u32,
-    right_padding: u32,
-    width: u32,
-    height: u32,
-    vertical_offset: u32,
-    kerning_start_index: u32,
+    pub utf16_char: u16,
+    pub left_uv_coordinate: f32,
+    pub top_uv_coordinate: f32,
+    pub right_uv_coordinate: f32,
+    pub bottom_uv_coordinate: f32,
+    pub left_padding: u32,
+    pub print_head_advance: u32,
+    pub right_padding: u32,
+    pub width: u32,
+    pub height: u32,
+    pub vertical_offset: u32,
+    pub kerning_start_index: u32,
 }
 
 #[auto_struct(Readable, Writable, FixedSize)]
 #[derive(Debug, Clone)]
 pub struct FontKerning {
-    char1: u16,
-    char2: u16,
-    kerning_adjust: i32,
+    pub char1: u16,
+    pub char2: u16,
+    pub kerning_adjust: i32,
 }
@@ -5,8 +5,11 @@ mod ancs;
 mod anim;
 mod bnr;
 mod cmdl;
+mod collision;
 mod ctwk;
+mod dependencies;
 mod dol;
+mod dsp;
 mod dumb;
 mod evnt;
 mod font;
@@ -210,8 +213,11 @@ pub use ancs::*;
 pub use anim::*;
 pub use bnr::*;
 pub use cmdl::*;
+pub use collision::*;
 pub use ctwk::*;
+pub use dependencies::*;
 pub use dol::*;
+pub use dsp::*;
 pub use dumb::*;
 pub use evnt::*;
 pub use font::*;
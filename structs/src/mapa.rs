@@ -1,7 +1,7 @@
 use std::str::FromStr;
 
 use auto_struct_macros::auto_struct;
-use reader_writer::{generic_array::GenericArray, typenum::*, LazyArray, RoArray};
+use reader_writer::{generic_array::GenericArray, typenum::*, LazyArray, Readable, RoArray};
 use serde::{Deserialize, Serialize};
 
 #[auto_struct(Readable, Writable)]
@@ -38,7 +38,7 @@ pub struct Mapa<'r> {
     _pad: (),
 }
 
-#[derive(Debug, Clone)]
+#[derive(Serialize, Deserialize, PartialEq, Debug, Copy, Clone)]
 pub enum MapaObjectType {
     DoorNormal = 0,
     DoorShield = 1,
@@ -127,6 +127,24 @@ impl MapaObject {
     pub fn is_vertical(&self) -> bool {
         self.type_ < 16 && self.type_ > 6
     }
+
+    /// Changes the color of a door icon. Ceiling and floor icons keep their orientation when the
+    /// new color has one (ice, wave and plasma).
+    pub fn set_door_type(&mut self, door_type: u32) {
+        const ICE: u32 = MapaObjectType::DoorIce as u32;
+        const PLASMA: u32 = MapaObjectType::DoorPlasma as u32;
+
+        self.type_ = if self.is_vertical() && (ICE..=PLASMA).contains(&door_type) {
+            // Ceiling and floor icons are grouped by color, floor2 icons follow them
+            let color = door_type - ICE;
+            match self.type_ {
+                7..=12 => 7 + color * 2 + (self.type_ - 7) % 2,
+                _ => 13 + color,
+            }
+        } else {
+            door_type
+        };
+    }
 }
 
 #[auto_struct(Readable, Writable, FixedSize)]
@@ -144,12 +162,12 @@ pub struct MapaSurface<'r> {
     #[auto_struct(derive = primitives.len() as u32)]
     pub primitive_count: u32,
     #[auto_struct(init = (primitive_count as usize, ()))]
-    pub primitives: RoArray<'r, MapaPrimitive<'r>>,
+    pub primitives: LazyArray<'r, MapaPrimitive<'r>>,
 
     #[auto_struct(derive = borders.len() as u32)]
     pub border_count: u32,
     #[auto_struct(init = (border_count as usize, ()))]
-    pub borders: RoArray<'r, MapaBorder<'r>>,
+    pub borders: LazyArray<'r, MapaBorder<'r>>,
 }
 
 #[auto_struct(Readable, Writable)]
@@ -178,17 +196,72 @@ pub struct MapaBorder<'r> {
 }
 
 impl Mapa<'_> {
-    fn update_offsets(&mut self) {
-        // update the table start offsets
-        for i in 0..self.surfaces.len() {
-            let surface_header = &mut self.surface_headers.as_mut_vec()[i];
-            surface_header.primitive_table_start += 80;
-            surface_header.border_table_start += 80;
+    // Surfaces are located by absolute offsets, which move whenever the data before them grows
+    // or shrinks
+    fn shift_surface_offsets(&mut self, from_surface: usize, delta: i64) {
+        for surface_header in self.surface_headers.as_mut_vec()[from_surface..].iter_mut() {
+            surface_header.primitive_table_start =
+                (surface_header.primitive_table_start as i64 + delta) as u32;
+            surface_header.border_table_start =
+                (surface_header.border_table_start as i64 + delta) as u32;
         }
     }
 
+    pub fn add_object(
+        &mut self,
+        type_: u32,
+        visibility_mode: MapaObjectVisibilityMode,
+        editor_id: u32,
+        transform_matrix: [f32; 12],
+    ) {
+        self.objects.as_mut_vec().push(MapaObject {
+            type_,
+            visibility_mode: visibility_mode as u32,
+            editor_id,
+            seed1: 0xFFFFFFFF,
+            transform_matrix: transform_matrix.into(),
+            seek2: [0xFFFFFFFF, 0xFFFFFFFF, 0xFFFFFFFF, 0xFFFFFFFF].into(),
+        });
+
+        // fix offsets else it crashes
+        self.shift_surface_offsets(0, MapaObject::fixed_size().unwrap() as i64);
+    }
+
+    /// Removes every object `f` returns true for, returning how many were removed
+    pub fn remove_objects<F>(&mut self, mut f: F) -> usize
+    where
+        F: FnMut(&MapaObject) -> bool,
+    {
+        let objects = self.objects.as_mut_vec();
+        let count = objects.len();
+        objects.retain(|obj| !f(obj));
+        let removed = count - objects.len();
+
+        self.shift_surface_offsets(0, -((removed * MapaObject::fixed_size().unwrap()) as i64));
+        removed
+    }
+
+    /// Empties a surface's geometry and outline so it is no longer drawn
+    pub fn hide_surface(&mut self, index: usize) -> Result<(), String> {
+        let surface_count = self.surfaces.len();
+        let surface = self.surfaces.as_mut_vec().get_mut(index).ok_or_else(|| {
+            format!(
+                "Map surface {} does not exist, the room has {} surfaces",
+                index, surface_count
+            )
+        })?;
+        let old_size = surface.size();
+        surface.primitives = vec![].into();
+        surface.borders = vec![].into();
+        let delta = surface.size() as i64 - old_size as i64;
+
+        let surface_header = &mut self.surface_headers.as_mut_vec()[index];
+        surface_header.border_table_start = surface_header.primitive_table_start + 4;
+        self.shift_surface_offsets(index + 1, delta);
+        Ok(())
+    }
+
     pub fn add_pickup(&mut self, editor_id: u32, pickup_pos: [f32; 3]) {
-        let mappable_objects = &mut self.objects;
         let transform_matrix = [
             1.0f32,
             0.0f32,
@@ -202,18 +275,12 @@ impl Mapa<'_> {
             0.0f32,
             1.0f32,
             pickup_pos[2],
-        ]
-        .into();
-        mappable_objects.as_mut_vec().push(MapaObject {
-            type_: MapaObjectType::Pickup as u32,
-            visibility_mode: MapaObjectVisibilityMode::Always as u32,
+        ];
+        self.add_object(
+            MapaObjectType::Pickup as u32,
+            MapaObjectVisibilityMode::Always,
             editor_id,
-            seed1: 0xFFFFFFFF,
             transform_matrix,
-            seek2: [0xFFFFFFFF, 0xFFFFFFFF, 0xFFFFFFFF, 0xFFFFFFFF].into(),
-        });
-
-        // fix offsets else it crashes
-        self.update_offsets()
+        );
     }
 }
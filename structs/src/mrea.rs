@@ -6,7 +6,7 @@ use reader_writer::{
     RoArray, RoArrayIter, Writable,
 };
 
-use crate::scly::Scly;
+use crate::{cmdl::CmdlMaterialSet, collision::AreaCollision, scly::Scly};
 
 #[auto_struct(Readable, Writable)]
 #[derive(Clone, Debug)]
@@ -89,6 +89,24 @@ impl<'r> Mrea<'r> {
     pub fn lights_section_mut(&mut self) -> &mut Lights<'r> {
         self.sections.as_mut_vec()[self.lights_section_idx as usize].convert_to_lights()
     }
+
+    /// The textures and materials of the area's world geometry
+    pub fn material_set_section_mut(&mut self) -> &mut CmdlMaterialSet<'r> {
+        self.sections.as_mut_vec()[self.world_geometry_section_idx as usize]
+            .convert_to_material_set()
+    }
+
+    pub fn collision_section(&self) -> AreaCollision<'r> {
+        let section = self
+            .sections
+            .iter()
+            .nth(self.collision_section_idx as usize)
+            .unwrap();
+        match *section {
+            MreaSection::Unknown(ref reader) => reader.clone().read(()),
+            _ => panic!(),
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -96,6 +114,7 @@ pub enum MreaSection<'r> {
     Unknown(Reader<'r>),
     Scly(Scly<'r>),
     Lights(Lights<'r>),
+    MaterialSet(CmdlMaterialSet<'r>),
 }
 
 impl<'r> MreaSection<'r> {
@@ -111,6 +130,20 @@ impl<'r> MreaSection<'r> {
         }
     }
 
+    pub fn convert_to_material_set(&mut self) -> &mut CmdlMaterialSet<'r> {
+        *self = match *self {
+            MreaSection::Unknown(ref reader) => {
+                MreaSection::MaterialSet(reader.clone().read(reader.len() as u32))
+            }
+            MreaSection::MaterialSet(ref mut material_set) => return material_set,
+            _ => panic!(),
+        };
+        match *self {
+            MreaSection::MaterialSet(ref mut material_set) => material_set,
+            _ => panic!(),
+        }
+    }
+
     pub fn convert_to_lights(&mut self) -> &mut Lights<'r> {
         *self = match *self {
             MreaSection::Unknown(ref reader) => MreaSection::Lights(reader.clone().read(())),
@@ -137,6 +170,7 @@ impl<'r> Readable<'r> for MreaSection<'r> {
             MreaSection::Unknown(ref reader) => reader.len(),
             MreaSection::Scly(ref scly) => scly.size(),
             MreaSection::Lights(ref lights) => lights.size(),
+            MreaSection::MaterialSet(ref material_set) => material_set.size(),
         }
     }
 }
@@ -150,6 +184,7 @@ impl Writable for MreaSection<'_> {
             }
             MreaSection::Scly(ref scly) => scly.write_to(writer),
             MreaSection::Lights(ref lights) => lights.write_to(writer),
+            MreaSection::MaterialSet(ref material_set) => material_set.write_to(writer),
         }
     }
 }
@@ -165,6 +200,12 @@ pub struct Lights<'r> {
     #[auto_struct(init = (lights_count as usize, ()))]
     pub light_layers: LazyArray<'r, LightLayer>,
 
+    // The game keeps a second, separate set of lights for each area
+    #[auto_struct(derive = light_layers_b.len() as u32)]
+    pub lights_b_count: u32,
+    #[auto_struct(init = (lights_b_count as usize, ()))]
+    pub light_layers_b: LazyArray<'r, LightLayer>,
+
     #[auto_struct(pad_align = 32)]
     _pad: (),
 }
@@ -7,7 +7,7 @@ use reader_writer::{
 
 use crate::{
     ctwk::*, dumb::Dumb, evnt::Evnt, frme::Frme, hint::Hint, mapa::Mapa, mapw::Mapw, mlvl::Mlvl,
-    mrea::Mrea, savw::Savw, scan::Scan, strg::Strg,
+    mrea::Mrea, part::*, savw::Savw, scan::Scan, strg::Strg,
 };
 
 #[auto_struct(Readable, Writable)]
@@ -570,7 +570,7 @@ macro_rules! build_resource_data {
                 }
             )*
 
-            fn size(&self) -> usize
+            pub fn size(&self) -> usize
             {
                 match *self {
                     ResourceKind::Unknown(ref data, _) => data.len(),
@@ -579,7 +579,7 @@ macro_rules! build_resource_data {
                 }
             }
 
-            fn write_to<W: io::Write>(&self, writer: &mut W) -> io::Result<u64>
+            pub fn write_to<W: io::Write>(&self, writer: &mut W) -> io::Result<u64>
             {
                 match *self {
                     ResourceKind::Unknown(ref data, _) => {
@@ -646,4 +646,24 @@ build_resource_data!(
     b"CTWK",
     as_ctwk,
     as_ctwk_mut,
+    Part,
+    b"PART",
+    as_part,
+    as_part_mut,
+    Elsc,
+    b"ELSC",
+    as_elsc,
+    as_elsc_mut,
+    Swhc,
+    b"SWHC",
+    as_swhc,
+    as_swhc_mut,
+    Wpsc,
+    b"WPSC",
+    as_wpsc,
+    as_wpsc_mut,
+    Crsc,
+    b"CRSC",
+    as_crsc,
+    as_crsc_mut,
 );
@@ -1,32 +1,694 @@
+use std::io;
+
 use auto_struct_macros::auto_struct;
-// This is intentionally _very_ incomplete. The particle format is huge, so only some of
-// the substructures are implemented.
-use reader_writer::RoArray;
+use reader_writer::{FourCC, LazyArray, Readable, Reader, Writable};
+
+use crate::{res_id::*, Dependency, ResId};
+
+// The particle formats (PART, ELSC, SWHC, WPSC and CRSC) are a magic, followed by properties
+// until "_END". Each property is a four character tag and an element. Elements are a class
+// FourCC followed by arguments that depend on the class, which are mostly elements themselves,
+// e.g. a real element "MULT" takes two real elements to multiply. "NONE" is the empty element
+// of any kind.
+//
+// The tags of each format and the arguments of each element class follow the game's
+// CParticleDataFactory and the constructors of the element classes.
+
+pub type Part<'r> = ParticleSystem<'r>;
+pub type Elsc<'r> = ParticleSystem<'r>;
+pub type Swhc<'r> = ParticleSystem<'r>;
+pub type Wpsc<'r> = ParticleSystem<'r>;
+pub type Crsc<'r> = ParticleSystem<'r>;
+
+/// What an element evaluates to, which decides the classes it can be and their arguments
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ParticleElementKind {
+    Real,
+    Int,
+    Vector,
+    Color,
+    ModVector,
+    Emitter,
+    Uv,
+    Bool,
+    Asset(&'static [u8; 4]),
+    Sound,
+    SpawnKeyframes,
+}
+
+#[derive(Clone, Copy)]
+enum ArgKind {
+    Element(ParticleElementKind),
+    Real,
+    Int,
+    Bool,
+    Asset,
+    Tag(&'static [u8; 4]),
+    Keyframes(ParticleElementKind),
+    SpawnKeyframes,
+}
+
+#[derive(Clone, Debug)]
+pub struct ParticleSystem<'r> {
+    // GPSM, ELSM, SWSM, WPSM or CRSM
+    pub magic: FourCC,
+    pub properties: Vec<ParticleProperty<'r>>,
+}
+
+#[derive(Clone, Debug)]
+pub struct ParticleProperty<'r> {
+    pub tag: FourCC,
+    pub value: ParticleElement<'r>,
+}
+
+#[derive(Clone, Debug)]
+pub struct ParticleElement<'r> {
+    pub class: FourCC,
+    pub args: Vec<ParticleArg<'r>>,
+}
+
+#[derive(Clone, Debug)]
+pub enum ParticleArg<'r> {
+    Element(ParticleElement<'r>),
+    Real(f32),
+    Int(u32),
+    Bool(bool),
+    Asset(u32),
+    // A fixed tag between arguments, like the ILOC and IVEC of SETR
+    Tag(FourCC),
+    Keyframes(ParticleKeyframes),
+    SpawnKeyframes(Kssm<'r>),
+}
+
+/// The values of a KEYE/KEYP element, one key per frame (KEYE) or per percent of the
+/// particle's lifetime (KEYP)
+#[derive(Clone, Debug)]
+pub struct ParticleKeyframes {
+    pub percent: u32,
+    pub unknown0: u32,
+    pub looping: bool,
+    pub unknown1: bool,
+    pub loop_end: u32,
+    pub loop_start: u32,
+    pub keys: ParticleKeys,
+}
+
+#[derive(Clone, Debug)]
+pub enum ParticleKeys {
+    Real(Vec<f32>),
+    Int(Vec<u32>),
+    Vector(Vec<[f32; 3]>),
+    Color(Vec<[f32; 4]>),
+}
+
+fn property_kind(magic: &[u8; 4], tag: &[u8; 4]) -> Option<ParticleElementKind> {
+    use ParticleElementKind::*;
+    let kind = match (magic, tag) {
+        (b"GPSM", b"PSIV" | b"PSOV" | b"POFS" | b"SEPO" | b"SSPO" | b"PMSC" | b"PMOP")
+        | (b"GPSM", b"PMRT" | b"LOFF" | b"LDIR" | b"ILOC" | b"IVEC") => Vector,
+        (b"GPSM", b"PSVM" | b"VEL1" | b"VEL2" | b"VEL3" | b"VEL4") => ModVector,
+        (b"GPSM", b"PSTS" | b"GRTE" | b"LENG" | b"WIDT" | b"SIZE" | b"ROTA" | b"LINT")
+        | (b"GPSM", b"LFOR" | b"LSLA" | b"ADV1" | b"ADV2" | b"ADV3" | b"ADV4" | b"ADV5")
+        | (b"GPSM", b"ADV6" | b"ADV7" | b"ADV8") => Real,
+        (b"GPSM", b"PSLT" | b"PSWT" | b"SEED" | b"MAXP" | b"LTME" | b"MBSP" | b"CSSD")
+        | (b"GPSM", b"NCSY" | b"NDSY" | b"PISY" | b"SISY" | b"SSSD" | b"SESD" | b"LTYP")
+        | (b"GPSM", b"LFOT") => Int,
+        (b"GPSM", b"COLR" | b"PMCL" | b"LCLR") => Color,
+        (b"GPSM", b"EMTR") => Emitter,
+        (b"GPSM", b"TEXR" | b"TIND") => Uv,
+        (b"GPSM", b"LINE" | b"FXLL" | b"AAPH" | b"ZBUF" | b"SORT" | b"LIT_" | b"MBLR")
+        | (b"GPSM", b"PMAB" | b"PMUS" | b"PMOO" | b"VMD1" | b"VMD2" | b"VMD3" | b"VMD4")
+        | (b"GPSM", b"CIND" | b"OPTS" | b"ORNT" | b"RSOP") => Bool,
+        (b"GPSM", b"ICTS" | b"IDTS" | b"IITS") => Asset(b"PART"),
+        (b"GPSM", b"PMDL") => Asset(b"CMDL"),
+        (b"GPSM", b"SSWH") => Asset(b"SWHC"),
+        (b"GPSM", b"SELC") => Asset(b"ELSC"),
+        (b"GPSM", b"KSSM") => SpawnKeyframes,
+
+        (b"ELSM", b"LIFE" | b"SLIF" | b"SCNT" | b"SSEG") => Int,
+        (b"ELSM", b"GRAT" | b"AMPL" | b"AMPD" | b"LWD1" | b"LWD2" | b"LWD3") => Real,
+        (b"ELSM", b"COLR" | b"LCL1" | b"LCL2" | b"LCL3") => Color,
+        (b"ELSM", b"IEMT" | b"FEMT") => Emitter,
+        (b"ELSM", b"ZERY") => Bool,
+        (b"ELSM", b"SSWH") => Asset(b"SWHC"),
+        (b"ELSM", b"GPSM" | b"EPSM") => Asset(b"PART"),
+
+        (b"SWSM", b"PSLT" | b"LENG" | b"SIDE" | b"SPLN" | b"TSPN") => Int,
+        (b"SWSM", b"TIME" | b"LRAD" | b"RRAD" | b"IROT" | b"ROTM") => Real,
+        (b"SWSM", b"COLR") => Color,
+        (b"SWSM", b"POFS" | b"IVEL" | b"NPOS") => Vector,
+        (b"SWSM", b"VELM" | b"VLM2") => ModVector,
+        (b"SWSM", b"TEXR") => Uv,
+        (b"SWSM", b"LLRD" | b"CROS" | b"VLS1" | b"VLS2" | b"SROT" | b"WIRE" | b"TEXW")
+        | (b"SWSM", b"AALP" | b"ZBUF" | b"ORNT" | b"CRND") => Bool,
+
+        (b"WPSM", b"IORN" | b"IVEC" | b"PSOV" | b"PSCL" | b"POFS" | b"OFST") => Vector,
+        (b"WPSM", b"PSVM") => ModVector,
+        (b"WPSM", b"PSLT") => Int,
+        (b"WPSM", b"TRAT" | b"RNGE" | b"FOFF") => Real,
+        (b"WPSM", b"PCOL") => Color,
+        (b"WPSM", b"VMD2" | b"APSO" | b"HOMG" | b"AP11" | b"AP21" | b"AS11" | b"AS12")
+        | (b"WPSM", b"AS13" | b"EWTR" | b"LWTR" | b"SWTR") => Bool,
+        (b"WPSM", b"APSM" | b"APS2") => Asset(b"PART"),
+        (b"WPSM", b"ASW1" | b"ASW2" | b"ASW3") => Asset(b"SWHC"),
+        (b"WPSM", b"OHEF") => Asset(b"CMDL"),
+        (b"WPSM", b"COLR") => Asset(b"CRSC"),
+        (b"WPSM", b"PJFX") => Sound,
+
+        // CRSC maps every collision material to a generator, decal or sound
+        (b"CRSM", b"RNGE" | b"FOFF") => Real,
+        (b"CRSM", tag) if tag.ends_with(b"SFX") => Sound,
+        (b"CRSM", tag) if tag.ends_with(b"DL") || matches!(tag, b"3MUD" | b"3LAV" | b"3SAN") => {
+            Asset(b"DPSC")
+        }
+        (b"CRSM", tag) if tag.iter().all(|c| c.is_ascii_alphanumeric()) => Asset(b"PART"),
+        _ => return None,
+    };
+    Some(kind)
+}
+
+fn class_args(kind: ParticleElementKind, class: &[u8; 4]) -> Option<&'static [ArgKind]> {
+    use ArgKind::*;
+    use ParticleElementKind as K;
+
+    const R: ArgKind = Element(K::Real);
+    const I: ArgKind = Element(K::Int);
+    const V: ArgKind = Element(K::Vector);
+    const C: ArgKind = Element(K::Color);
+    const MV: ArgKind = Element(K::ModVector);
+    const B: ArgKind = Element(K::Bool);
+    const TEX: ArgKind = Element(K::Asset(b"TXTR"));
+
+    let args: &'static [ArgKind] = match (kind, class) {
+        (_, b"NONE") => &[],
+
+        (K::Real, b"CNST") => &[Real],
+        (K::Real, b"KEYE" | b"KEYP") => &[Keyframes(K::Real)],
+        (K::Real, b"LFTW" | b"ADD_" | b"SUB_" | b"MULT" | b"IRND" | b"RAND" | b"ISWT") => &[R, R],
+        (K::Real, b"CHAN") => &[R, R, I],
+        (K::Real, b"CLMP" | b"SINE") => &[R, R, R],
+        (K::Real, b"CLTN" | b"CEQL") => &[R, R, R, R],
+        (K::Real, b"CRNG") => &[R, R, R, R, R],
+        (K::Real, b"DOTP") => &[V, V],
+        (K::Real, b"PULS") => &[I, I, R, R],
+        (K::Real, b"VMAG" | b"VXTR" | b"VYTR" | b"VZTR") => &[V],
+        (K::Real, b"CEXT") => &[I],
+        (K::Real, b"ITRL") => &[I, R],
+        (K::Real, b"GTCR" | b"GTCG" | b"GTCB" | b"GTCA") => &[C],
+        (K::Real, b"RLPT" | b"SCAL") => &[R],
+        (K::Real, b"PAP1" | b"PAP2" | b"PAP3" | b"PAP4" | b"PAP5" | b"PAP6" | b"PAP7")
+        | (K::Real, b"PAP8" | b"PSLL" | b"PRLW") => &[],
+
+        (K::Int, b"CNST") => &[Int],
+        (K::Int, b"KEYE" | b"KEYP") => &[Keyframes(K::Int)],
+        (K::Int, b"DETH" | b"ADD_" | b"SUB_" | b"MULT" | b"MODU" | b"RAND" | b"IRND") => &[I, I],
+        (K::Int, b"CLMP" | b"CHAN" | b"SPAH") => &[I, I, I],
+        (K::Int, b"PULS") => &[I, I, I, I],
+        (K::Int, b"IMPL" | b"ILPT") => &[I],
+        (K::Int, b"TSCL") => &[R],
+        (K::Int, b"RTOI") => &[R, R],
+        (K::Int, b"GAPC" | b"GEMT" | b"GTCP") => &[],
+
+        (K::Vector, b"CNST") => &[R, R, R],
+        (K::Vector, b"KEYE" | b"KEYP") => &[Keyframes(K::Vector)],
+        (K::Vector, b"CONE") => &[V, R],
+        (K::Vector, b"CHAN") => &[V, V, I],
+        (K::Vector, b"ANGC") => &[R, R, R, R, R],
+        (K::Vector, b"ADD_" | b"SUB_" | b"MULT") => &[V, V],
+        (K::Vector, b"CCLU") => &[V, V, I, R],
+        (K::Vector, b"CIRC") => &[V, V, R, R, R],
+        (K::Vector, b"PULS") => &[I, I, V, V],
+        (K::Vector, b"RTOV") => &[R],
+        (K::Vector, b"CCTV") => &[C],
+        (K::Vector, b"PVEL" | b"PLCO" | b"PLOC" | b"PSOF" | b"PSOU" | b"PSOR" | b"PSTR") => &[],
+
+        (K::Color, b"CNST") => &[R, R, R, R],
+        (K::Color, b"KEYE" | b"KEYP") => &[Keyframes(K::Color)],
+        (K::Color, b"CHAN") => &[C, C, I],
+        (K::Color, b"CFDE") => &[C, C, R, R],
+        (K::Color, b"FADE") => &[C, C, R],
+        (K::Color, b"PULS") => &[I, I, C, C],
+        (K::Color, b"PCOL") => &[],
+
+        (K::ModVector, b"CNST") => &[R, R, R],
+        (K::ModVector, b"IMPL" | b"EMPL" | b"LMPL") => &[V, R, R, R, B],
+        (K::ModVector, b"CHAN") => &[MV, MV, I],
+        (K::ModVector, b"BNCE") => &[V, V, R, R, B],
+        (K::ModVector, b"GRAV" | b"SPOS") => &[V],
+        (K::ModVector, b"EXPL") => &[R, R],
+        (K::ModVector, b"PULS") => &[I, I, MV, MV],
+        (K::ModVector, b"WIND") => &[V, R],
+        (K::ModVector, b"SWRL") => &[V, V, R, R],
+
+        (K::Emitter, b"SETR") => &[Tag(b"ILOC"), V, Tag(b"IVEC"), V],
+        (K::Emitter, b"SEMR") => &[V, V],
+        (K::Emitter, b"SPHE") => &[V, R, R],
+        (K::Emitter, b"ASPH") => &[V, R, R, R, R, R, R],
+
+        (K::Uv, b"CNST") => &[TEX],
+        (K::Uv, b"ATEX") => &[TEX, I, I, I, I, I, B],
+
+        (K::Bool, b"CNST") => &[Bool],
+        (K::Asset(_), b"CNST") => &[Asset],
+        (K::Sound, b"CNST") => &[Int],
+        (K::SpawnKeyframes, b"CNST") => &[SpawnKeyframes],
+        _ => return None,
+    };
+    Some(args)
+}
+
+fn fourcc_str(fourcc: FourCC) -> String {
+    String::from_utf8_lossy(fourcc.as_bytes()).into_owned()
+}
+
+struct ParticleReader<'r> {
+    data: &'r [u8],
+    pos: usize,
+}
+
+impl<'r> ParticleReader<'r> {
+    fn bytes<const N: usize>(&mut self) -> Result<[u8; N], String> {
+        let bytes = self
+            .data
+            .get(self.pos..self.pos + N)
+            .ok_or_else(|| format!("Unexpected end of data at {:#x}", self.pos))?;
+        self.pos += N;
+        Ok(bytes.try_into().unwrap())
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        self.bytes().map(u32::from_be_bytes)
+    }
+
+    fn f32(&mut self) -> Result<f32, String> {
+        self.bytes().map(f32::from_be_bytes)
+    }
+
+    fn bool(&mut self) -> Result<bool, String> {
+        self.bytes::<1>().map(|b| b[0] != 0)
+    }
+
+    fn fourcc(&mut self) -> Result<FourCC, String> {
+        self.bytes::<4>().map(|b| FourCC::from_bytes(&b))
+    }
+
+    fn keyframes(&mut self, kind: ParticleElementKind) -> Result<ParticleKeyframes, String> {
+        let percent = self.u32()?;
+        let unknown0 = self.u32()?;
+        let looping = self.bool()?;
+        let unknown1 = self.bool()?;
+        let loop_end = self.u32()?;
+        let loop_start = self.u32()?;
+        let count = self.u32()? as usize;
+        // Don't trust the count with an allocation before knowing the keys are there
+        let component_count = match kind {
+            ParticleElementKind::Vector => 3,
+            ParticleElementKind::Color => 4,
+            _ => 1,
+        };
+        if self.data.len() - self.pos < count * component_count * 4 {
+            return Err(format!("Keyframes at {:#x} are truncated", self.pos));
+        }
+        let keys = match kind {
+            ParticleElementKind::Real => {
+                ParticleKeys::Real((0..count).map(|_| self.f32()).collect::<Result<_, _>>()?)
+            }
+            ParticleElementKind::Int => {
+                ParticleKeys::Int((0..count).map(|_| self.u32()).collect::<Result<_, _>>()?)
+            }
+            ParticleElementKind::Vector => ParticleKeys::Vector(
+                (0..count)
+                    .map(|_| Ok([self.f32()?, self.f32()?, self.f32()?]))
+                    .collect::<Result<_, String>>()?,
+            ),
+            _ => ParticleKeys::Color(
+                (0..count)
+                    .map(|_| Ok([self.f32()?, self.f32()?, self.f32()?, self.f32()?]))
+                    .collect::<Result<_, String>>()?,
+            ),
+        };
+        Ok(ParticleKeyframes {
+            percent,
+            unknown0,
+            looping,
+            unknown1,
+            loop_end,
+            loop_start,
+            keys,
+        })
+    }
+
+    fn spawn_keyframes(&mut self) -> Result<Kssm<'r>, String> {
+        // Walk the counts to find the size before handing the data to the panicking reader
+        let start = self.pos;
+        self.pos += 16;
+        let list_count = self.u32()?;
+        for _ in 0..list_count {
+            self.u32()?;
+            let item_count = self.u32()? as usize;
+            self.pos += item_count * 16;
+        }
+        if self.pos > self.data.len() {
+            return Err(format!("KSSM at {:#x} is truncated", start));
+        }
+        Ok(Reader::new(&self.data[start..self.pos]).read(()))
+    }
+
+    fn element(&mut self, kind: ParticleElementKind) -> Result<ParticleElement<'r>, String> {
+        let class = self.fourcc()?;
+        let arg_kinds = class_args(kind, class.as_bytes()).ok_or_else(|| {
+            format!(
+                "Unknown {:?} element class {} at {:#x}",
+                kind,
+                fourcc_str(class),
+                self.pos - 4
+            )
+        })?;
+        let mut args = Vec::with_capacity(arg_kinds.len());
+        for arg_kind in arg_kinds {
+            args.push(match *arg_kind {
+                ArgKind::Element(kind) => ParticleArg::Element(self.element(kind)?),
+                ArgKind::Real => ParticleArg::Real(self.f32()?),
+                ArgKind::Int => ParticleArg::Int(self.u32()?),
+                ArgKind::Bool => ParticleArg::Bool(self.bool()?),
+                ArgKind::Asset => ParticleArg::Asset(self.u32()?),
+                ArgKind::Tag(expected) => {
+                    let tag = self.fourcc()?;
+                    if tag.as_bytes() != expected {
+                        return Err(format!(
+                            "Expected {} in {} at {:#x}",
+                            fourcc_str(expected.into()),
+                            fourcc_str(class),
+                            self.pos - 4
+                        ));
+                    }
+                    ParticleArg::Tag(tag)
+                }
+                ArgKind::Keyframes(kind) => ParticleArg::Keyframes(self.keyframes(kind)?),
+                ArgKind::SpawnKeyframes => ParticleArg::SpawnKeyframes(self.spawn_keyframes()?),
+            });
+        }
+        Ok(ParticleElement { class, args })
+    }
+}
+
+impl<'r> ParticleSystem<'r> {
+    /// Reads a PART, ELSC, SWHC, WPSC or CRSC, failing on tags and element classes that aren't
+    /// known rather than panicking like `Readable::read_from`
+    pub fn parse(data: &'r [u8]) -> Result<Self, String> {
+        let mut reader = ParticleReader { data, pos: 0 };
+        let magic = reader.fourcc()?;
+        if !matches!(
+            magic.as_bytes(),
+            b"GPSM" | b"ELSM" | b"SWSM" | b"WPSM" | b"CRSM"
+        ) {
+            return Err(format!("Unknown particle magic {}", fourcc_str(magic)));
+        }
+
+        let mut properties = vec![];
+        loop {
+            let tag = reader.fourcc()?;
+            if tag == b"_END".into() {
+                break;
+            }
+            let kind = property_kind(magic.as_bytes(), tag.as_bytes()).ok_or_else(|| {
+                format!(
+                    "Unknown {} property {} at {:#x}",
+                    fourcc_str(magic),
+                    fourcc_str(tag),
+                    reader.pos - 4
+                )
+            })?;
+            let value = reader.element(kind)?;
+            properties.push(ParticleProperty { tag, value });
+        }
+
+        Ok(ParticleSystem { magic, properties })
+    }
+
+    pub fn property(&self, tag: &[u8; 4]) -> Option<&ParticleElement<'r>> {
+        self.properties
+            .iter()
+            .find(|p| p.tag.as_bytes() == tag)
+            .map(|p| &p.value)
+    }
+
+    pub fn property_mut(&mut self, tag: &[u8; 4]) -> Option<&mut ParticleElement<'r>> {
+        self.properties
+            .iter_mut()
+            .find(|p| p.tag.as_bytes() == tag)
+            .map(|p| &mut p.value)
+    }
 
-use crate::{res_id::*, ResId};
+    /// Calls `f` with every element of the system and its kind, innermost elements first
+    pub fn for_each_element_mut<F>(&mut self, mut f: F)
+    where
+        F: FnMut(ParticleElementKind, &mut ParticleElement<'r>),
+    {
+        fn visit<'r, F>(kind: ParticleElementKind, element: &mut ParticleElement<'r>, f: &mut F)
+        where
+            F: FnMut(ParticleElementKind, &mut ParticleElement<'r>),
+        {
+            let arg_kinds = class_args(kind, element.class.as_bytes()).unwrap_or(&[]);
+            for (arg_kind, arg) in arg_kinds.iter().zip(element.args.iter_mut()) {
+                if let (ArgKind::Element(kind), ParticleArg::Element(element)) = (arg_kind, arg) {
+                    visit(*kind, element, f);
+                }
+            }
+            f(kind, element);
+        }
+
+        let magic = *self.magic.as_bytes();
+        for property in &mut self.properties {
+            if let Some(kind) = property_kind(&magic, property.tag.as_bytes()) {
+                visit(kind, &mut property.value, &mut f);
+            }
+        }
+    }
+
+    /// Calls `f` with the type and id of every asset the system references, which it may
+    /// replace
+    pub fn for_each_asset_mut<F>(&mut self, mut f: F)
+    where
+        F: FnMut(FourCC, &mut u32),
+    {
+        self.for_each_element_mut(|kind, element| match (kind, &mut element.args[..]) {
+            (ParticleElementKind::Asset(fourcc), [ParticleArg::Asset(id)]) => f(fourcc.into(), id),
+            (ParticleElementKind::SpawnKeyframes, [ParticleArg::SpawnKeyframes(kssm)]) => {
+                for list in kssm.lists.as_mut_vec() {
+                    for item in list.items.as_mut_vec() {
+                        let mut id = item.part.to_u32();
+                        f(b"PART".into(), &mut id);
+                        item.part = ResId::new(id);
+                    }
+                }
+            }
+            _ => (),
+        });
+    }
+
+    /// Lists every asset the system references
+    pub fn dependencies(&self) -> Vec<Dependency> {
+        let mut deps = vec![];
+        self.clone().for_each_asset_mut(|asset_type, asset_id| {
+            if *asset_id != 0 && *asset_id != 0xFFFFFFFF {
+                deps.push(Dependency {
+                    asset_id: *asset_id,
+                    asset_type,
+                });
+            }
+        });
+        deps
+    }
+
+    /// Maps the RGBA of every constant color and color keyframe
+    pub fn map_colors<F>(&mut self, mut f: F)
+    where
+        F: FnMut([f32; 4]) -> [f32; 4],
+    {
+        fn constant<'a>(arg: &'a mut ParticleArg) -> Option<&'a mut f32> {
+            match arg {
+                ParticleArg::Element(ParticleElement { class, args })
+                    if *class == b"CNST".into() =>
+                {
+                    match &mut args[..] {
+                        [ParticleArg::Real(value)] => Some(value),
+                        _ => None,
+                    }
+                }
+                _ => None,
+            }
+        }
+
+        self.for_each_element_mut(|kind, element| {
+            if kind != ParticleElementKind::Color {
+                return;
+            }
+            if element.class == b"CNST".into() {
+                let components: Option<Vec<&mut f32>> =
+                    element.args.iter_mut().map(constant).collect();
+                // Colors computed from other elements are left alone
+                if let Some(mut components) = components.filter(|c| c.len() == 4) {
+                    let color = f([
+                        *components[0],
+                        *components[1],
+                        *components[2],
+                        *components[3],
+                    ]);
+                    for (component, value) in components.iter_mut().zip(color) {
+                        **component = value;
+                    }
+                }
+            } else if let [ParticleArg::Keyframes(keyframes)] = &mut element.args[..] {
+                if let ParticleKeys::Color(keys) = &mut keyframes.keys {
+                    for key in keys {
+                        *key = f(*key);
+                    }
+                }
+            }
+        });
+    }
+}
+
+impl ParticleKeyframes {
+    fn size(&self) -> usize {
+        let key_size = match &self.keys {
+            ParticleKeys::Real(keys) => keys.len() * 4,
+            ParticleKeys::Int(keys) => keys.len() * 4,
+            ParticleKeys::Vector(keys) => keys.len() * 12,
+            ParticleKeys::Color(keys) => keys.len() * 16,
+        };
+        22 + key_size
+    }
+}
+
+impl Writable for ParticleKeyframes {
+    fn write_to<W: io::Write>(&self, writer: &mut W) -> io::Result<u64> {
+        let mut s = self.percent.write_to(writer)?;
+        s += self.unknown0.write_to(writer)?;
+        s += (self.looping as u8).write_to(writer)?;
+        s += (self.unknown1 as u8).write_to(writer)?;
+        s += self.loop_end.write_to(writer)?;
+        s += self.loop_start.write_to(writer)?;
+        match &self.keys {
+            ParticleKeys::Real(keys) => {
+                s += (keys.len() as u32).write_to(writer)?;
+                for key in keys {
+                    s += key.write_to(writer)?;
+                }
+            }
+            ParticleKeys::Int(keys) => {
+                s += (keys.len() as u32).write_to(writer)?;
+                for key in keys {
+                    s += key.write_to(writer)?;
+                }
+            }
+            ParticleKeys::Vector(keys) => {
+                s += (keys.len() as u32).write_to(writer)?;
+                for key in keys.iter().flatten() {
+                    s += key.write_to(writer)?;
+                }
+            }
+            ParticleKeys::Color(keys) => {
+                s += (keys.len() as u32).write_to(writer)?;
+                for key in keys.iter().flatten() {
+                    s += key.write_to(writer)?;
+                }
+            }
+        }
+        Ok(s)
+    }
+}
+
+impl ParticleElement<'_> {
+    fn size(&self) -> usize {
+        4 + self
+            .args
+            .iter()
+            .map(|arg| match arg {
+                ParticleArg::Element(element) => element.size(),
+                ParticleArg::Real(_) | ParticleArg::Int(_) | ParticleArg::Asset(_) => 4,
+                ParticleArg::Bool(_) => 1,
+                ParticleArg::Tag(_) => 4,
+                ParticleArg::Keyframes(keyframes) => keyframes.size(),
+                ParticleArg::SpawnKeyframes(kssm) => kssm.size(),
+            })
+            .sum::<usize>()
+    }
+}
+
+impl Writable for ParticleElement<'_> {
+    fn write_to<W: io::Write>(&self, writer: &mut W) -> io::Result<u64> {
+        let mut s = self.class.write_to(writer)?;
+        for arg in &self.args {
+            s += match arg {
+                ParticleArg::Element(element) => element.write_to(writer)?,
+                ParticleArg::Real(value) => value.write_to(writer)?,
+                ParticleArg::Int(value) | ParticleArg::Asset(value) => value.write_to(writer)?,
+                ParticleArg::Bool(value) => (*value as u8).write_to(writer)?,
+                ParticleArg::Tag(tag) => tag.write_to(writer)?,
+                ParticleArg::Keyframes(keyframes) => keyframes.write_to(writer)?,
+                ParticleArg::SpawnKeyframes(kssm) => kssm.write_to(writer)?,
+            };
+        }
+        Ok(s)
+    }
+}
+
+impl<'r> Readable<'r> for ParticleSystem<'r> {
+    type Args = ();
+    fn read_from(reader: &mut Reader<'r>, (): ()) -> Self {
+        let system = ParticleSystem::parse(reader.as_slice())
+            .unwrap_or_else(|e| panic!("Failed to read particle system: {}", e));
+        reader.advance(system.size());
+        system
+    }
+
+    fn size(&self) -> usize {
+        self.properties
+            .iter()
+            .map(|p| 4 + p.value.size())
+            .sum::<usize>()
+            + 8
+    }
+}
+
+impl Writable for ParticleSystem<'_> {
+    fn write_to<W: io::Write>(&self, writer: &mut W) -> io::Result<u64> {
+        let mut s = self.magic.write_to(writer)?;
+        for property in &self.properties {
+            s += property.tag.write_to(writer)?;
+            s += property.value.write_to(writer)?;
+        }
+        s += FourCC::from_bytes(b"_END").write_to(writer)?;
+        Ok(s)
+    }
+}
 
-#[auto_struct(Readable)]
+#[auto_struct(Readable, Writable)]
 #[derive(Debug, Clone)]
 pub struct Kssm<'r> {
     pub unknown0: u32,
     pub unknown1: u32,
     pub end_frame: u32,
     pub unknown2: u32,
-    pub list_count: u32,
+    #[auto_struct(derive = lists.len() as u32)]
+    list_count: u32,
     #[auto_struct(init = (list_count as usize, ()))]
-    pub lists: RoArray<'r, KssmFrameInfo<'r>>,
+    pub lists: LazyArray<'r, KssmFrameInfo<'r>>,
 }
 
-#[auto_struct(Readable)]
+#[auto_struct(Readable, Writable)]
 #[derive(Debug, Clone)]
 pub struct KssmFrameInfo<'r> {
     pub frame: u32,
-    pub item_count: u32,
+    #[auto_struct(derive = items.len() as u32)]
+    item_count: u32,
     #[auto_struct(init = (item_count as usize, ()))]
-    pub items: RoArray<'r, KssmFrameInfoItem>,
+    pub items: LazyArray<'r, KssmFrameInfoItem>,
 }
 
-#[auto_struct(Readable)]
+#[auto_struct(Readable, Writable, FixedSize)]
 #[derive(Debug, Clone)]
 pub struct KssmFrameInfoItem {
     pub part: ResId<PART>,
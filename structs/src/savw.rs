@@ -22,12 +22,12 @@ pub struct Savw<'r> {
     #[auto_struct(derive = memory_relay_array.len() as u32)]
     memory_relay_count: u32,
     #[auto_struct(init = (memory_relay_count as usize, ()))]
-    pub memory_relay_array: RoArray<'r, u32>,
+    pub memory_relay_array: LazyArray<'r, u32>,
 
     #[auto_struct(derive = layer_toggle_array.len() as u32)]
     layer_toggle_count: u32,
     #[auto_struct(init = (layer_toggle_count as usize, ()))]
-    pub layer_toggle_array: RoArray<'r, LayerToggle>,
+    pub layer_toggle_array: LazyArray<'r, LayerToggle>,
 
     #[auto_struct(derive = door_array.len() as u32)]
     door_count: u32,
@@ -315,6 +315,17 @@ macro_rules! build_scly_property {
                 }
             }
 
+            /// The name of the object's type, or "Unknown" for types without a definition
+            pub fn object_type_name(&self) -> &'static str
+            {
+                let object_type = self.object_type();
+                #[allow(unreachable_patterns)] // ridley throws a warning because we have both PAL and NTSC ridley definitions
+                match object_type {
+                    $(<scly_props::$name as SclyPropertyData>::OBJECT_TYPE => stringify!($name),)*
+                    _ => "Unknown",
+                }
+            }
+
             /* Name */
 
             pub fn get_name(&self) -> CStr
@@ -3,14 +3,14 @@ use reader_writer::{
     FourCC, IteratorArray, LCow, LazyArray, LazyUtf16beStr, Readable, RoArray, RoArrayIter,
 };
 
-static SUPPORTED_LANGUAGES: &[&[u8; 4]] = &[
+pub static SUPPORTED_LANGUAGES: &[&[u8; 4]] = &[
     b"ENGL", b"DUTC", b"FREN", b"GERM", b"ITAL", b"JAPN", b"SPAN",
 ];
 
 pub static NON_JPN_LANGUAGES: &[&[u8; 4]] = &[b"ENGL", b"DUTC", b"FREN", b"GERM", b"ITAL", b"SPAN"];
 
 const EMPTY_STRING: &str = "\u{0}";
-const JPN_FONT_PREFIX: &str = "&line-extra-space=4;&font=C29C51F1;";
+pub const JPN_FONT_PREFIX: &str = "&line-extra-space=4;&font=C29C51F1;";
 
 pub enum Languages {
     All,
@@ -92,6 +92,26 @@ impl<'r> Strg<'r> {
         }
     }
 
+    // Like add_strings with a single string, which can differ between the selected languages
+    pub fn add_localized_string(
+        &mut self,
+        string: impl Fn(FourCC) -> String,
+        languages: Languages,
+    ) {
+        let languages = match languages {
+            Languages::All => SUPPORTED_LANGUAGES,
+            Languages::Some(value) => value,
+        };
+
+        for table in self.string_tables.as_mut_vec().iter_mut() {
+            let string = match languages.contains(&table.lang.as_bytes()) {
+                true => string(table.lang),
+                false => EMPTY_STRING.to_string(),
+            };
+            table.strings.as_mut_vec().push(string.into());
+        }
+    }
+
     pub fn edit_strings(&mut self, (from, to): (String, String), languages: Languages) {
         let languages = match languages {
             Languages::All => SUPPORTED_LANGUAGES,
@@ -168,6 +188,23 @@ impl<'r> Strg<'r> {
         }
     }
 
+    pub fn from_string_tables(tables: Vec<(FourCC, Vec<String>)>) -> Strg<'r> {
+        Strg {
+            string_tables: tables
+                .into_iter()
+                .map(|(lang, strings)| StrgStringTable {
+                    lang,
+                    strings: strings
+                        .into_iter()
+                        .map(|i| i.into())
+                        .collect::<Vec<_>>()
+                        .into(),
+                })
+                .collect::<Vec<_>>()
+                .into(),
+        }
+    }
+
     pub fn from_strings_jpn(strings: Vec<String>) -> Strg<'r> {
         let strings: LazyArray<LazyUtf16beStr> = strings
             .into_iter()
@@ -1,7 +1,7 @@
 use auto_struct_macros::auto_struct;
 use reader_writer::{
     generic_array::{
-        typenum::{U32, U512},
+        typenum::{U32, U32768, U512},
         GenericArray,
     },
     IteratorArray, LazyArray, Reader, RoArray,
@@ -80,6 +80,7 @@ pub enum TxtrFormat {
     Ia8,
     C4(TxtrPaletteFormat, Box<GenericArray<u8, U32>>),
     C8(TxtrPaletteFormat, Box<GenericArray<u8, U512>>),
+    C14x2(TxtrPaletteFormat, Box<GenericArray<u8, U32768>>),
     Rgb565,
     Rgb5A3,
     Rgba8,
@@ -107,6 +108,15 @@ impl TxtrFormat {
                     Box::new(palette.color_data.iter().collect()),
                 )
             }
+            0x6 => {
+                // Palettes with fewer than the 16384 addressable colors are padded with zeros
+                let palette = palette.as_ref().unwrap();
+                let mut color_data: Box<GenericArray<u8, U32768>> = Box::default();
+                for (dst, src) in color_data.iter_mut().zip(palette.color_data.iter()) {
+                    *dst = src;
+                }
+                TxtrFormat::C14x2(TxtrPaletteFormat::from_u32(palette.format), color_data)
+            }
             0x7 => TxtrFormat::Rgb565,
             0x8 => TxtrFormat::Rgb5A3,
             0x9 => TxtrFormat::Rgba8,
@@ -123,6 +133,7 @@ impl TxtrFormat {
             TxtrFormat::Ia8 => 0x3,
             TxtrFormat::C4(_, _) => 0x4,
             TxtrFormat::C8(_, _) => 0x5,
+            TxtrFormat::C14x2(_, _) => 0x6,
             TxtrFormat::Rgb565 => 0x7,
             TxtrFormat::Rgb5A3 => 0x8,
             TxtrFormat::Rgba8 => 0x9,
@@ -134,6 +145,7 @@ impl TxtrFormat {
         let (format, bytes, width, height) = match self {
             TxtrFormat::C4(fmt, bytes) => (fmt, &bytes[..], 1, 16),
             TxtrFormat::C8(fmt, bytes) => (fmt, &bytes[..], 256, 1),
+            TxtrFormat::C14x2(fmt, bytes) => (fmt, &bytes[..], 256, 64),
             _ => return None,
         };
         Some(TxtrPalette {
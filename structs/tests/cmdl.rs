@@ -0,0 +1,338 @@
+use reader_writer::{generic_array::GenericArray, Reader, Writable};
+use structs::{
+    Cmdl, CmdlGeometry, CmdlMaterial, CmdlMaterialSet, CmdlNormals, CmdlPrimitive, CmdlSurface,
+    CmdlTevStage, CmdlTevStageInput, CmdlUvAnimation, CmdlVertex, GxPrimitiveKind, ResId,
+    CMDL_FLAG_SHORT_NORMALS, CMDL_FLAG_SHORT_UVS, MATERIAL_FLAG_INDIRECT_TEXTURE,
+    MATERIAL_FLAG_KONST_COLORS,
+};
+
+// Position, normal and texture coordinate 0 as 16-bit indices, plus the position matrix index
+const VERTEX_ATTRIBUTES: u32 = 0x3 | (0x3 << 2) | (0x3 << 8) | (0x1 << 24);
+// Position as an 8-bit index and color 0 as a 16-bit index
+const SMALL_VERTEX_ATTRIBUTES: u32 = 0x2 | (0x3 << 4);
+
+fn vertex(position: u16, matrix_index: u8) -> CmdlVertex {
+    CmdlVertex {
+        matrix_index: Some(matrix_index),
+        position: Some(position),
+        normal: Some(position),
+        tex_coords: [Some(position), None, None, None, None, None, None, None],
+        ..CmdlVertex::default()
+    }
+}
+
+fn material<'r>(flags: u32, vertex_attributes: u32) -> CmdlMaterial<'r> {
+    CmdlMaterial {
+        flags,
+        textures: vec![0, 1].into(),
+        vertex_attributes,
+        group_index: 0,
+        konst_colors: (flags & MATERIAL_FLAG_KONST_COLORS != 0)
+            .then(|| vec![0xFF0000FF, 0x00FF00FF].into()),
+        blend_dst_factor: 0,
+        blend_src_factor: 1,
+        indirect_texture: (flags & MATERIAL_FLAG_INDIRECT_TEXTURE != 0).then_some(1),
+        color_channels: vec![0x1].into(),
+        tev_stages: vec![
+            CmdlTevStage {
+                color_input_flags: 0xC7FF8,
+                alpha_input_flags: 0x1F3E0,
+                color_combine_flags: 0x100,
+                alpha_combine_flags: 0x100,
+                padding: 0,
+                konst_alpha_input: 0,
+                konst_color_input: 0xC,
+                rasterized_color_input: 0,
+            };
+            2
+        ]
+        .into(),
+        tev_stage_inputs: vec![
+            CmdlTevStageInput {
+                padding: 0xFFFF,
+                texture: 0,
+                tex_coord: 0,
+            },
+            CmdlTevStageInput {
+                padding: 0xFFFF,
+                texture: 1,
+                tex_coord: 1,
+            },
+        ]
+        .into(),
+        tex_gens: vec![0x4B24, 0x4B27].into(),
+        uv_animations: vec![
+            CmdlUvAnimation {
+                mode: 2,
+                params: vec![0.0, 0.5, 1.0, 0.25].into(),
+            },
+            CmdlUvAnimation {
+                mode: 0,
+                params: vec![].into(),
+            },
+        ]
+        .into(),
+    }
+}
+
+fn surface(material_index: u32, center: f32) -> CmdlSurface {
+    CmdlSurface {
+        center: [center, 0.0, 0.0],
+        material_index,
+        mantissa: 0x8000,
+        parent_model_pointer: 0,
+        next_surface_pointer: 0,
+        normal: [0.0, 0.0, 1.0],
+        extra_data: vec![],
+        display_list: vec![],
+    }
+}
+
+fn test_model<'r>() -> Cmdl<'r> {
+    let mut first = surface(0, 0.5);
+    first
+        .set_primitives(
+            VERTEX_ATTRIBUTES,
+            &[
+                CmdlPrimitive {
+                    kind: GxPrimitiveKind::TriangleStrip,
+                    vertex_format: 0,
+                    vertices: (0..4).map(|i| vertex(i, 0)).collect(),
+                },
+                CmdlPrimitive {
+                    kind: GxPrimitiveKind::Triangles,
+                    vertex_format: 0,
+                    vertices: vec![vertex(0, 3), vertex(2, 3), vertex(1, 3)],
+                },
+            ],
+        )
+        .unwrap();
+
+    let mut second = surface(1, -0.5);
+    second.extra_data = vec![1, 2, 3, 4, 5];
+    second
+        .set_primitives(
+            SMALL_VERTEX_ATTRIBUTES,
+            &[CmdlPrimitive {
+                kind: GxPrimitiveKind::Quads,
+                vertex_format: 1,
+                vertices: (0..4)
+                    .map(|i| CmdlVertex {
+                        position: Some(i),
+                        colors: [Some(300 + i), None],
+                        ..CmdlVertex::default()
+                    })
+                    .collect(),
+            }],
+        )
+        .unwrap();
+
+    let geometry = CmdlGeometry {
+        positions: vec![
+            [0.0, 0.0, 0.0],
+            [1.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0],
+            [1.0, 1.0, 0.0],
+        ],
+        normals: CmdlNormals::Short(vec![[0, 0, 0x7FFF], [0, 0, 0x7FFF], [0, 0, -0x8000]]),
+        colors: vec![0xFFFFFFFF, 0x000000FF],
+        tex_coords: vec![[0.0, 0.0], [1.0, 0.0], [0.0, 1.0], [1.0, 1.0]],
+        short_tex_coords: Some(vec![[0, 0], [0x7FFF, 0x7FFF]]),
+        surfaces: vec![first, second],
+    };
+
+    let mut cmdl = Cmdl {
+        flags: 0,
+        maab: GenericArray::clone_from_slice(&[-1.0, -1.0, -1.0, 1.0, 1.0, 1.0]),
+        material_sets: vec![CmdlMaterialSet {
+            texture_ids: vec![ResId::new(0x12345678), ResId::new(0x9ABCDEF0)].into(),
+            materials: vec![
+                material(
+                    MATERIAL_FLAG_KONST_COLORS | MATERIAL_FLAG_INDIRECT_TEXTURE,
+                    VERTEX_ATTRIBUTES,
+                ),
+                material(0x80, SMALL_VERTEX_ATTRIBUTES),
+            ]
+            .into(),
+        }]
+        .into(),
+        data_sections: vec![].into(),
+    };
+    cmdl.set_geometry(&geometry);
+    cmdl
+}
+
+fn to_bytes(cmdl: &Cmdl) -> Vec<u8> {
+    let mut bytes = vec![];
+    cmdl.write_to(&mut bytes).unwrap();
+    bytes
+}
+
+#[test]
+fn test_cmdl_round_trip() {
+    let original = test_model();
+    let bytes = to_bytes(&original);
+    assert_eq!(bytes.len() % 32, 0);
+
+    let cmdl: Cmdl = Reader::new(&bytes).read(());
+    assert_eq!(cmdl.flags, CMDL_FLAG_SHORT_NORMALS | CMDL_FLAG_SHORT_UVS);
+    assert_eq!(to_bytes(&cmdl), bytes);
+
+    let material_set = cmdl.material_sets.iter().next().unwrap();
+    let texture_ids: Vec<u32> = material_set
+        .texture_ids
+        .iter()
+        .map(|id| id.to_u32())
+        .collect();
+    assert_eq!(texture_ids, vec![0x12345678, 0x9ABCDEF0]);
+
+    let materials: Vec<CmdlMaterial> = material_set
+        .materials
+        .iter()
+        .map(|m| m.into_owned())
+        .collect();
+    assert_eq!(materials.len(), 2);
+    let konst_colors: Vec<u32> = materials[0]
+        .konst_colors
+        .as_ref()
+        .unwrap()
+        .iter()
+        .map(|c| *c)
+        .collect();
+    assert_eq!(konst_colors, vec![0xFF0000FF, 0x00FF00FF]);
+    assert_eq!(materials[0].indirect_texture, Some(1));
+    assert!(materials[1].konst_colors.is_none());
+    assert!(materials[1].indirect_texture.is_none());
+    assert_eq!(materials[1].tev_stages.len(), 2);
+    assert_eq!(
+        materials[1].tev_stage_inputs.iter().nth(1).unwrap().texture,
+        1
+    );
+    let animations: Vec<CmdlUvAnimation> = materials[1]
+        .uv_animations
+        .iter()
+        .map(|a| a.into_owned())
+        .collect();
+    assert_eq!(animations[0].mode, 2);
+    let params: Vec<f32> = animations[0].params.iter().map(|p| *p).collect();
+    assert_eq!(params, vec![0.0, 0.5, 1.0, 0.25]);
+    assert_eq!(animations[1].params.len(), 0);
+
+    assert_eq!(cmdl.geometry(), original.geometry());
+}
+
+#[test]
+fn test_cmdl_geometry() {
+    let cmdl = test_model();
+    let bytes = to_bytes(&cmdl);
+    let cmdl: Cmdl = Reader::new(&bytes).read(());
+    let geometry = cmdl.geometry();
+
+    // Sections are padded to 32 bytes, so the 3 short normals (18 bytes) gain 2 zeroed entries
+    // and the 4 positions (48 bytes) gain 1
+    assert_eq!(geometry.positions.len(), 5);
+    assert_eq!(geometry.positions[3], [1.0, 1.0, 0.0]);
+    assert_eq!(geometry.positions[4], [0.0, 0.0, 0.0]);
+    match &geometry.normals {
+        CmdlNormals::Short(normals) => {
+            assert_eq!(normals.len(), 5);
+            assert_eq!(normals[2], [0, 0, -0x8000]);
+        }
+        CmdlNormals::Float(_) => panic!("Expected short normals"),
+    }
+    assert_eq!(&geometry.colors[..2], &[0xFFFFFFFF, 0x000000FF]);
+    assert_eq!(
+        geometry.short_tex_coords.as_ref().unwrap()[1],
+        [0x7FFF, 0x7FFF]
+    );
+
+    assert_eq!(geometry.surfaces.len(), 2);
+    let first = &geometry.surfaces[0];
+    assert_eq!(first.center, [0.5, 0.0, 0.0]);
+    assert_eq!(first.display_list.len() % 32, 0);
+    let primitives = first.primitives(VERTEX_ATTRIBUTES).unwrap();
+    assert_eq!(primitives.len(), 2);
+    assert_eq!(primitives[0].kind, GxPrimitiveKind::TriangleStrip);
+    assert_eq!(primitives[0].vertices[3], vertex(3, 0));
+    assert_eq!(primitives[1].vertices[1], vertex(2, 3));
+
+    let second = &geometry.surfaces[1];
+    assert_eq!(second.material_index, 1);
+    assert_eq!(second.extra_data, vec![1, 2, 3, 4, 5]);
+    let primitives = second.primitives(SMALL_VERTEX_ATTRIBUTES).unwrap();
+    assert_eq!(primitives[0].kind, GxPrimitiveKind::Quads);
+    assert_eq!(primitives[0].vertex_format, 1);
+    assert_eq!(primitives[0].vertices[2].colors[0], Some(302));
+    assert_eq!(primitives[0].vertices[2].normal, None);
+}
+
+#[test]
+fn test_cmdl_edit_geometry() {
+    let cmdl = test_model();
+    let bytes = to_bytes(&cmdl);
+    let mut cmdl: Cmdl = Reader::new(&bytes).read(());
+
+    let mut geometry = cmdl.geometry();
+    geometry.normals = CmdlNormals::Float(vec![[0.0, 0.0, 1.0]]);
+    geometry.short_tex_coords = None;
+    geometry.positions[0] = [2.0, 3.0, 4.0];
+    geometry.surfaces.truncate(1);
+    cmdl.set_geometry(&geometry);
+
+    // Editing the geometry must keep the section sizes in the header in sync
+    let bytes = to_bytes(&cmdl);
+    let cmdl: Cmdl = Reader::new(&bytes).read(());
+    assert_eq!(cmdl.flags, 0);
+    assert_eq!(cmdl.data_sections.len(), 6);
+    let edited = cmdl.geometry();
+    assert_eq!(edited.positions[0], [2.0, 3.0, 4.0]);
+    assert_eq!(edited.surfaces, geometry.surfaces);
+    assert!(edited.short_tex_coords.is_none());
+}
+
+#[test]
+fn test_cmdl_primitive_errors() {
+    let mut surface = surface(0, 0.0);
+    let primitive = CmdlPrimitive {
+        kind: GxPrimitiveKind::Points,
+        vertex_format: 0,
+        vertices: vec![CmdlVertex {
+            position: Some(0x100),
+            ..CmdlVertex::default()
+        }],
+    };
+
+    // An 8-bit position index can't hold 0x100, and a missing position matrix index is an error
+    assert!(surface
+        .set_primitives(SMALL_VERTEX_ATTRIBUTES, &[primitive.clone()])
+        .is_err());
+    assert!(surface
+        .set_primitives(VERTEX_ATTRIBUTES, &[primitive.clone()])
+        .is_err());
+    // Direct attributes aren't supported
+    assert!(surface.set_primitives(0x1, &[primitive]).is_err());
+
+    surface.display_list = vec![0x90, 0x00, 0x03, 0x00];
+    assert!(surface.primitives(VERTEX_ATTRIBUTES).is_err());
+}
+
+#[test]
+fn test_cmdl_triangles() {
+    let primitive = CmdlPrimitive {
+        kind: GxPrimitiveKind::TriangleStrip,
+        vertex_format: 0,
+        vertices: (0..5).map(|i| vertex(i, 0)).collect(),
+    };
+    let triangles: Vec<[u16; 3]> = primitive
+        .triangles()
+        .iter()
+        .map(|t| t.map(|v| v.position.unwrap()))
+        .collect();
+    assert_eq!(triangles, vec![[0, 1, 2], [2, 1, 3], [2, 3, 4]]);
+
+    let primitive = CmdlPrimitive {
+        kind: GxPrimitiveKind::Quads,
+        ..primitive
+    };
+    assert_eq!(primitive.triangles().len(), 2);
+}
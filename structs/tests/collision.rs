@@ -0,0 +1,108 @@
+use reader_writer::{Reader, Writable};
+use structs::{AreaCollision, OctreeNode};
+
+// A closed 10x10x10 room with its surfaces facing inwards, in a single octree leaf
+fn collision_bytes() -> Vec<u8> {
+    let corner = |i: usize| {
+        [
+            (i & 1) as f32 * 10.0,
+            ((i >> 1) & 1) as f32 * 10.0,
+            ((i >> 2) & 1) as f32 * 10.0,
+        ]
+    };
+    let mut triangles: Vec<[usize; 3]> = Vec::new();
+    for axis in 0..3 {
+        for side in 0..2 {
+            let quad: Vec<usize> = (0..8).filter(|i| (i >> axis) & 1 == side).collect();
+            for mut tri in [[quad[0], quad[1], quad[3]], [quad[0], quad[3], quad[2]]] {
+                let [a, b, c] = tri.map(corner);
+                let ab = [b[0] - a[0], b[1] - a[1], b[2] - a[2]];
+                let ac = [c[0] - a[0], c[1] - a[1], c[2] - a[2]];
+                let normal = ab[(axis + 1) % 3] * ac[(axis + 2) % 3]
+                    - ab[(axis + 2) % 3] * ac[(axis + 1) % 3];
+                if (normal > 0.0) == (side == 1) {
+                    tri.swap(1, 2);
+                }
+                triangles.push(tri);
+            }
+        }
+    }
+
+    let mut bytes = vec![];
+    let u32s = |bytes: &mut Vec<u8>, words: &[u32]| {
+        bytes.extend(words.iter().flat_map(|w| w.to_be_bytes()));
+    };
+    u32s(&mut bytes, &[0x01000000, 0, 0xDEAFBABE, 3]);
+    u32s(
+        &mut bytes,
+        &[0.0f32, 0.0, 0.0, 10.0, 10.0, 10.0].map(f32::to_bits),
+    );
+
+    let mut octree: Vec<u8> = [0.0f32, 0.0, 0.0, 10.0, 10.0, 10.0]
+        .iter()
+        .flat_map(|f| f.to_bits().to_be_bytes())
+        .collect();
+    octree.extend((triangles.len() as u16).to_be_bytes());
+    octree.extend((0..triangles.len() as u16).flat_map(u16::to_be_bytes));
+    u32s(&mut bytes, &[2, octree.len() as u32]);
+    bytes.extend(octree);
+
+    // One material, then the vertex, edge and triangle material indices
+    u32s(&mut bytes, &[1, 0, 0, 0]);
+    u32s(&mut bytes, &[triangles.len() as u32]);
+    bytes.extend(vec![0; triangles.len()]);
+
+    // Each triangle gets its own edges, the first one listed backwards
+    u32s(&mut bytes, &[triangles.len() as u32 * 3]);
+    for [a, b, c] in &triangles {
+        for (v0, v1) in [(b, a), (b, c), (c, a)] {
+            bytes.extend((*v0 as u16).to_be_bytes());
+            bytes.extend((*v1 as u16).to_be_bytes());
+        }
+    }
+    u32s(&mut bytes, &[triangles.len() as u32 * 3]);
+    bytes.extend((0..triangles.len() as u16 * 3).flat_map(u16::to_be_bytes));
+
+    u32s(&mut bytes, &[8]);
+    for i in 0..8 {
+        u32s(&mut bytes, &corner(i).map(f32::to_bits));
+    }
+
+    bytes.resize((bytes.len() + 31) & !31, 0);
+    bytes
+}
+
+#[test]
+fn collision_round_trip() {
+    let bytes = collision_bytes();
+    let collision: AreaCollision = Reader::new(&bytes).read(());
+    let mut written = vec![];
+    collision.write_to(&mut written).unwrap();
+    assert_eq!(written, bytes);
+
+    match collision.octree().unwrap() {
+        OctreeNode::Leaf { triangles, .. } => assert_eq!(triangles.len(), 12),
+        node => panic!("Expected a leaf, got {:?}", node),
+    }
+}
+
+#[test]
+fn collision_point_queries() {
+    let bytes = collision_bytes();
+    let collision: AreaCollision = Reader::new(&bytes).read(());
+    let mesh = collision.mesh().unwrap();
+    assert_eq!(mesh.triangles.len(), 12);
+
+    let inside = [3.0, 4.0, 5.0];
+    assert!(mesh.contains(inside));
+    assert!(!mesh.is_inside_geometry(inside));
+    assert_eq!(mesh.floor_below(inside), Some(0.0));
+
+    let outside = [15.0, 4.0, 5.0];
+    assert!(!mesh.contains(outside));
+    assert!(mesh.is_inside_geometry(outside));
+    assert_eq!(mesh.floor_below(outside), None);
+
+    let (distance, _) = mesh.raycast(inside, [1.0, 0.0, 0.0]).unwrap();
+    assert!((distance - 7.0).abs() < 1e-4);
+}
@@ -0,0 +1,116 @@
+use std::{borrow::Cow, ffi::CString};
+
+use reader_writer::{Readable, Reader, Writable};
+use structs::{Evnt, SoundEvent};
+
+fn base_bytes(name: &str, event_type: u16, timestamp: f32, index: u32) -> Vec<u8> {
+    let mut bytes = vec![];
+    bytes.extend(2u16.to_be_bytes());
+    bytes.extend(name.as_bytes());
+    bytes.push(0);
+    bytes.extend(event_type.to_be_bytes());
+    bytes.extend(timestamp.to_be_bytes());
+    bytes.extend(0u32.to_be_bytes());
+    bytes.extend(index.to_be_bytes());
+    bytes.push(1);
+    bytes.extend(1.0f32.to_be_bytes());
+    bytes.extend((-1i32).to_be_bytes());
+    bytes.extend(0u32.to_be_bytes());
+    bytes
+}
+
+// One event of each kind, without the sound count of version 1 EVNTs when `sound_id` is None
+fn evnt_bytes(sound_id: Option<u32>) -> Vec<u8> {
+    let mut bytes = vec![];
+    bytes.extend(if sound_id.is_some() { 2u32 } else { 1 }.to_be_bytes());
+
+    bytes.extend(1u32.to_be_bytes());
+    bytes.extend(base_bytes("loop", 1, 0.25, 0));
+    bytes.push(1);
+
+    bytes.extend(1u32.to_be_bytes());
+    bytes.extend(base_bytes("user", 6, 0.5, 0));
+    bytes.extend(3u32.to_be_bytes());
+    bytes.extend(b"Head\0");
+
+    bytes.extend(1u32.to_be_bytes());
+    bytes.extend(base_bytes("effect", 5, 0.75, 0));
+    bytes.extend(12u32.to_be_bytes());
+    bytes.extend(b"PART");
+    bytes.extend(0x12345678u32.to_be_bytes());
+    bytes.extend(b"L_hand\0");
+    bytes.extend(1.0f32.to_be_bytes());
+    bytes.extend(1u32.to_be_bytes());
+
+    if let Some(sound_id) = sound_id {
+        bytes.extend(1u32.to_be_bytes());
+        bytes.extend(base_bytes("sound", 8, 1.0, 0));
+        bytes.extend(sound_id.to_be_bytes());
+        bytes.extend(1.0f32.to_be_bytes());
+        bytes.extend(0.5f32.to_be_bytes());
+    }
+
+    bytes.resize(bytes.len().next_multiple_of(32), 0);
+    bytes
+}
+
+#[test]
+fn evnt_round_trips() {
+    let bytes = evnt_bytes(Some(0x0123));
+    let evnt: Evnt = Reader::new(&bytes[..]).read(());
+
+    assert_eq!(evnt.loop_events.len(), 1);
+    let user = evnt.user_events.iter().next().unwrap();
+    assert_eq!(user.base.name.to_str().unwrap(), "user");
+    assert_eq!(user.event_type, 3);
+    assert_eq!(user.bone_name.to_str().unwrap(), "Head");
+    let effect = evnt.effect_events.iter().next().unwrap();
+    assert_eq!(effect.base.timestamp.timestamp, 0.75);
+    assert_eq!(effect.effect_type, b"PART".into());
+    assert_eq!(effect.effect_file_id, 0x12345678);
+    assert_eq!(effect.bone_name.to_str().unwrap(), "L_hand");
+    let sound = evnt.sound_events.as_ref().unwrap().iter().next().unwrap();
+    assert_eq!(sound.sound_id, 0x0123);
+    assert_eq!(sound.reference_distance, 0.5);
+
+    assert_eq!(evnt.size(), bytes.len());
+    let mut written = vec![];
+    evnt.write_to(&mut written).unwrap();
+    assert_eq!(written, bytes);
+}
+
+#[test]
+fn version_1_evnts_have_no_sounds() {
+    let bytes = evnt_bytes(None);
+    let evnt: Evnt = Reader::new(&bytes[..]).read(());
+
+    assert!(evnt.sound_events.is_none());
+    assert_eq!(evnt.effect_events.len(), 1);
+    let mut written = vec![];
+    evnt.write_to(&mut written).unwrap();
+    assert_eq!(written, bytes);
+}
+
+#[test]
+fn added_sounds_make_a_version_2_evnt() {
+    let bytes = evnt_bytes(None);
+    let mut evnt: Evnt = Reader::new(&bytes[..]).read(());
+
+    let mut base = evnt.user_events.iter().next().unwrap().base.clone();
+    base.name = Cow::Owned(CString::new("sound").unwrap());
+    base.event_type = 8;
+    base.timestamp.timestamp = 1.0;
+    evnt.sound_events = Some(
+        vec![SoundEvent {
+            base,
+            sound_id: 0x0123,
+            reference_amplitude: 1.0,
+            reference_distance: 0.5,
+        }]
+        .into(),
+    );
+
+    let mut written = vec![];
+    evnt.write_to(&mut written).unwrap();
+    assert_eq!(written, evnt_bytes(Some(0x0123)));
+}
@@ -0,0 +1,70 @@
+use reader_writer::{Readable, Reader, Writable};
+use structs::{LightLayer, Lights};
+
+fn light_bytes(light_type: u32, brightness: f32) -> Vec<u8> {
+    let mut bytes = vec![];
+    bytes.extend(light_type.to_be_bytes());
+    for value in [
+        1.0f32, 0.5, 0.25, 1.0, 2.0, 3.0, 0.0, -1.0, 0.0, brightness, 30.0, 0.0,
+    ] {
+        bytes.extend(value.to_be_bytes());
+    }
+    bytes.push(1);
+    bytes.extend(0.0f32.to_be_bytes());
+    bytes.extend(2u32.to_be_bytes());
+    bytes.extend(0.0f32.to_be_bytes());
+    bytes
+}
+
+// One light in the first layer and two in the second
+fn lights_bytes() -> Vec<u8> {
+    let mut bytes = vec![];
+    bytes.extend(0xBABEDEADu32.to_be_bytes());
+    bytes.extend(1u32.to_be_bytes());
+    bytes.extend(light_bytes(0, 1.0));
+    bytes.extend(2u32.to_be_bytes());
+    bytes.extend(light_bytes(1, 2.0));
+    bytes.extend(light_bytes(2, 3.0));
+    bytes.resize(bytes.len().next_multiple_of(32), 0);
+    bytes
+}
+
+fn brightnesses(layer: &reader_writer::LazyArray<LightLayer>) -> Vec<f32> {
+    layer.iter().map(|light| light.brightness).collect()
+}
+
+#[test]
+fn both_light_layers_round_trip() {
+    let bytes = lights_bytes();
+    let lights: Lights = Reader::new(&bytes[..]).read(());
+
+    assert_eq!(brightnesses(&lights.light_layers), [1.0]);
+    assert_eq!(brightnesses(&lights.light_layers_b), [2.0, 3.0]);
+    let light = lights.light_layers_b.iter().nth(1).unwrap();
+    assert_eq!(light.light_type, 2);
+    assert_eq!(&light.position[..], [1.0, 2.0, 3.0]);
+    assert_eq!(light.falloff_type, 2);
+
+    assert_eq!(lights.size(), bytes.len());
+    let mut written = vec![];
+    lights.write_to(&mut written).unwrap();
+    assert_eq!(written, bytes);
+}
+
+#[test]
+fn edited_light_layers_are_counted_and_padded() {
+    let bytes = lights_bytes();
+    let mut lights: Lights = Reader::new(&bytes[..]).read(());
+
+    let light = lights.light_layers.iter().next().unwrap().into_owned();
+    lights.light_layers.as_mut_vec().clear();
+    lights.light_layers_b.as_mut_vec().push(light);
+
+    let mut written = vec![];
+    lights.write_to(&mut written).unwrap();
+    assert_eq!(written.len() % 32, 0);
+
+    let lights: Lights = Reader::new(&written[..]).read(());
+    assert_eq!(brightnesses(&lights.light_layers), [] as [f32; 0]);
+    assert_eq!(brightnesses(&lights.light_layers_b), [2.0, 3.0, 1.0]);
+}
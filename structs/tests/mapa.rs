@@ -0,0 +1,109 @@
+use reader_writer::{Readable, Reader, Writable};
+use structs::{Mapa, MapaObject, MapaObjectType, MapaObjectVisibilityMode};
+
+// A room with one door, a triangle and two surfaces each drawing and outlining it
+fn mapa_bytes() -> Vec<u8> {
+    let mut words: Vec<u32> = vec![0xDEADD00D, 2, 0, 0];
+    words.extend([0.0f32, 0.0, 0.0, 10.0, 10.0, 10.0].map(f32::to_bits));
+    words.extend([1, 3, 2]);
+
+    // Door
+    words.extend([MapaObjectType::DoorIceFloor as u32, 1, 0x100, 0xFFFFFFFF]);
+    words.extend(
+        [
+            1.0f32, 0.0, 0.0, 5.0, 0.0, 1.0, 0.0, 5.0, 0.0, 0.0, 1.0, 0.0,
+        ]
+        .map(f32::to_bits),
+    );
+    words.extend([0xFFFFFFFF; 4]);
+
+    // Vertices
+    words.extend([0.0f32, 0.0, 0.0, 10.0, 0.0, 0.0, 0.0, 10.0, 0.0].map(f32::to_bits));
+
+    // Surface headers, followed by the surfaces at 232 and 260
+    for start in [232, 260] {
+        words.extend([0.0f32; 6].map(f32::to_bits));
+        words.extend([start, start + 16]);
+    }
+
+    let mut bytes: Vec<u8> = words.iter().flat_map(|w| w.to_be_bytes()).collect();
+    for _ in 0..2 {
+        // One triangle strip and one outline
+        bytes.extend([0, 0, 0, 1, 0, 0, 0, 4, 0, 0, 0, 3, 0, 1, 2, 0]);
+        bytes.extend([0, 0, 0, 1, 0, 0, 0, 2, 0, 1, 0, 0]);
+    }
+    bytes
+}
+
+// Every surface offset must point at where its data actually ends up
+fn assert_offsets_match_layout(mapa: &Mapa) {
+    let mut offset =
+        52 + mapa.objects.len() * 80 + mapa.vertices.len() * 12 + mapa.surface_headers.len() * 32;
+    for (header, surface) in mapa.surface_headers.iter().zip(mapa.surfaces.iter()) {
+        assert_eq!(header.primitive_table_start as usize, offset);
+        assert_eq!(
+            header.border_table_start as usize,
+            offset + 4 + surface.primitives.size()
+        );
+        offset += surface.size();
+    }
+}
+
+fn round_trip(mapa: &Mapa) -> Vec<u8> {
+    let mut written = vec![];
+    mapa.write_to(&mut written).unwrap();
+    written
+}
+
+#[test]
+fn mapa_round_trip() {
+    let bytes = mapa_bytes();
+    let mapa: Mapa = Reader::new(&bytes).read(());
+    assert_offsets_match_layout(&mapa);
+    assert_eq!(round_trip(&mapa), bytes);
+}
+
+#[test]
+fn mapa_edits_keep_offsets_valid() {
+    let bytes = mapa_bytes();
+    let mut mapa: Mapa = Reader::new(&bytes).read(());
+
+    mapa.add_pickup(0x200, [1.0, 2.0, 3.0]);
+    mapa.add_object(
+        MapaObjectType::SaveStation as u32,
+        MapaObjectVisibilityMode::Always,
+        0xFFFFFFFF,
+        [1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0],
+    );
+    assert_eq!(mapa.remove_objects(|obj| obj.editor_id == 0x100), 1);
+    mapa.hide_surface(0).unwrap();
+    assert!(mapa.hide_surface(2).is_err());
+
+    let written = round_trip(&mapa);
+    let mapa: Mapa = Reader::new(&written).read(());
+    assert_offsets_match_layout(&mapa);
+    assert_eq!(mapa.objects.len(), 2);
+    assert_eq!(mapa.surfaces.iter().next().unwrap().primitives.len(), 0);
+    assert_eq!(mapa.surfaces.iter().nth(1).unwrap().primitives.len(), 1);
+}
+
+#[test]
+fn door_icons_keep_their_orientation() {
+    let bytes = mapa_bytes();
+    let mapa: Mapa = Reader::new(&bytes).read(());
+    let door: MapaObject = mapa.objects.iter().next().unwrap().into_owned();
+
+    let recolor = |type_: MapaObjectType| {
+        let mut door = door.clone();
+        door.set_door_type(type_ as u32);
+        door.type_
+    };
+    assert_eq!(
+        recolor(MapaObjectType::DoorPlasma),
+        MapaObjectType::DoorPlasmaFloor as u32
+    );
+    assert_eq!(
+        recolor(MapaObjectType::DoorShield),
+        MapaObjectType::DoorShield as u32
+    );
+}
@@ -0,0 +1,225 @@
+use reader_writer::{FourCC, Reader, Writable};
+use structs::{Dependency, ParticleSystem};
+
+// Builds particle data out of FourCCs and big endian values
+#[derive(Default)]
+struct Bytes(Vec<u8>);
+
+impl Bytes {
+    fn tag(mut self, tag: &[u8; 4]) -> Self {
+        self.0.extend_from_slice(tag);
+        self
+    }
+
+    fn u32(mut self, value: u32) -> Self {
+        self.0.extend_from_slice(&value.to_be_bytes());
+        self
+    }
+
+    fn f32(mut self, value: f32) -> Self {
+        self.0.extend_from_slice(&value.to_be_bytes());
+        self
+    }
+
+    fn u8(mut self, value: u8) -> Self {
+        self.0.push(value);
+        self
+    }
+
+    fn real(self, value: f32) -> Self {
+        self.tag(b"CNST").f32(value)
+    }
+
+    fn color(self, rgba: [f32; 4]) -> Self {
+        rgba.iter()
+            .fold(self.tag(b"CNST"), |bytes, &value| bytes.real(value))
+    }
+}
+
+fn dep(asset_id: u32, asset_type: &[u8; 4]) -> Dependency {
+    Dependency {
+        asset_id,
+        asset_type: asset_type.into(),
+    }
+}
+
+fn part_bytes() -> Vec<u8> {
+    Bytes::default()
+        .tag(b"GPSM")
+        // Lifetime
+        .tag(b"PSLT")
+        .tag(b"CNST")
+        .u32(60)
+        // Emitter with nested vector and real elements
+        .tag(b"EMTR")
+        .tag(b"SETR")
+        .tag(b"ILOC")
+        .tag(b"CNST")
+        .real(0.0)
+        .real(0.0)
+        .real(1.0)
+        .tag(b"IVEC")
+        .tag(b"CONE")
+        .tag(b"NONE")
+        .tag(b"MULT")
+        .real(2.0)
+        .tag(b"PAP1")
+        // Color keyframes
+        .tag(b"COLR")
+        .tag(b"KEYP")
+        .u32(1)
+        .u32(0)
+        .u8(0)
+        .u8(0)
+        .u32(1)
+        .u32(0)
+        .u32(2)
+        .f32(1.0)
+        .f32(0.5)
+        .f32(0.25)
+        .f32(1.0)
+        .f32(0.0)
+        .f32(0.0)
+        .f32(0.0)
+        .f32(0.0)
+        // Constant model color
+        .tag(b"PMCL")
+        .color([0.5, 0.5, 0.5, 1.0])
+        // Animated texture
+        .tag(b"TEXR")
+        .tag(b"ATEX")
+        .tag(b"CNST")
+        .u32(0x11111111)
+        .tag(b"CNST")
+        .u32(16)
+        .tag(b"CNST")
+        .u32(16)
+        .tag(b"CNST")
+        .u32(64)
+        .tag(b"CNST")
+        .u32(64)
+        .tag(b"CNST")
+        .u32(10)
+        .tag(b"CNST")
+        .u8(1)
+        .tag(b"TIND")
+        .tag(b"NONE")
+        .tag(b"ICTS")
+        .tag(b"CNST")
+        .u32(0x22222222)
+        .tag(b"IDTS")
+        .tag(b"NONE")
+        .tag(b"KSSM")
+        .tag(b"CNST")
+        .u32(0)
+        .u32(0)
+        .u32(30)
+        .u32(0)
+        .u32(1)
+        .u32(15)
+        .u32(1)
+        .u32(0x33333333)
+        .u32(0)
+        .u32(0)
+        .u32(0)
+        .tag(b"ZBUF")
+        .tag(b"CNST")
+        .u8(1)
+        .tag(b"_END")
+        .0
+}
+
+#[test]
+fn part_round_trip() {
+    let bytes = part_bytes();
+    let part: ParticleSystem = Reader::new(&bytes).read(());
+    assert_eq!(part.properties.len(), 10);
+
+    let mut written = vec![];
+    part.write_to(&mut written).unwrap();
+    assert_eq!(written, bytes);
+}
+
+#[test]
+fn part_dependencies() {
+    let bytes = part_bytes();
+    let part = ParticleSystem::parse(&bytes).unwrap();
+    assert_eq!(
+        part.dependencies(),
+        vec![
+            dep(0x11111111, b"TXTR"),
+            dep(0x22222222, b"PART"),
+            dep(0x33333333, b"PART"),
+        ]
+    );
+}
+
+#[test]
+fn part_recolor_and_swap_texture() {
+    let bytes = part_bytes();
+    let mut part = ParticleSystem::parse(&bytes).unwrap();
+    part.map_colors(|[r, g, b, a]| [b, g, r, a]);
+    part.for_each_asset_mut(|asset_type, id| {
+        if asset_type == FourCC::from_bytes(b"TXTR") {
+            *id = 0x44444444;
+        }
+    });
+
+    let mut written = vec![];
+    part.write_to(&mut written).unwrap();
+    let part = ParticleSystem::parse(&written).unwrap();
+    assert_eq!(part.dependencies()[0], dep(0x44444444, b"TXTR"));
+
+    let mut colors = vec![];
+    part.clone().map_colors(|color| {
+        colors.push(color);
+        color
+    });
+    assert_eq!(
+        colors,
+        vec![
+            [0.25, 0.5, 1.0, 1.0],
+            [0.0, 0.0, 0.0, 0.0],
+            [0.5, 0.5, 0.5, 1.0],
+        ]
+    );
+}
+
+#[test]
+fn wpsc_vectors_are_not_assets() {
+    let bytes = Bytes::default()
+        .tag(b"WPSM")
+        .tag(b"IORN")
+        .tag(b"CNST")
+        .real(0.0)
+        .real(0.0)
+        .real(0.0)
+        .tag(b"APSM")
+        .tag(b"CNST")
+        .u32(0x55555555)
+        .tag(b"COLR")
+        .tag(b"CNST")
+        .u32(0x66666666)
+        .tag(b"PJFX")
+        .tag(b"CNST")
+        .u32(0x1234)
+        .tag(b"_END")
+        .0;
+
+    let deps = structs::direct_dependencies(b"WPSC".into(), &bytes);
+    assert_eq!(
+        deps,
+        vec![dep(0x55555555, b"PART"), dep(0x66666666, b"CRSC")]
+    );
+}
+
+#[test]
+fn unknown_class_is_an_error() {
+    let bytes = Bytes::default()
+        .tag(b"GPSM")
+        .tag(b"SIZE")
+        .tag(b"XXXX")
+        .tag(b"_END")
+        .0;
+    assert!(ParticleSystem::parse(&bytes).is_err());
+}